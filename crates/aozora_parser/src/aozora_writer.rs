@@ -0,0 +1,475 @@
+//! Serializes parsed documents back into Aozora Bunko notation.
+//!
+//! Writing a parsed text and parsing the result again yields the same structure,
+//! so the writer can be used for programmatic transformations and as a formatter.
+//! Notation that has several equivalent forms is normalized
+//! (e.g. `第一章［＃「第一章」は大見出し］` is written as `［＃大見出し］第一章［＃大見出し終わり］`).
+//! Annotations the parser does not recognize are written back as they were, and
+//! characters of the notation in the text (《, 》, ｜, ［＃) are escaped as ※［＃…］.
+
+use crate::block_parser::{AozoraBlock, BlockElement};
+use crate::parser::{self, AozoraDocument, COMMENT_SEPARATOR, Colophon, AozoraMetadata, HeaderNote, NOTATION_SYMBOLS, ParsedItem, SpecialCharacter};
+use crate::tokenizer::command::{
+    Alignment, Bousen, Bouten, Command, CommandBegin, CommandEnd, Midashi, MidashiSize, MidashiType,
+    SingleCommand,
+};
+
+/// Writes a whole document: the header, the 注記説明 block and the body.
+pub fn write_document(doc: &AozoraDocument) -> String {
+    let mut out = write_header(&doc.metadata);
+
+    // The blank line closing the header is kept as the first item of the body
+    let mut items = doc.items.as_slice();
+    if let Some((ParsedItem::Newline(_), rest)) = items.split_first() {
+        out.push('\n');
+        items = rest;
+    }
+    if !doc.metadata.notes.is_empty() {
+        write_notes(&mut out, &doc.metadata.notes);
+    }
+    write_item_list(&mut out, items);
+    if let Some(colophon) = &doc.colophon {
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+        write_colophon(&mut out, colophon);
+    }
+    out
+}
+
+/// Writes a block tree (the output of `parse_blocks`) as body text.
+pub fn write_block(block: &AozoraBlock) -> String {
+    let mut out = String::new();
+    write_block_into(&mut out, block);
+    out
+}
+
+/// Writes a flat list of parsed items as body text.
+pub fn write_items(items: &[ParsedItem]) -> String {
+    let mut out = String::new();
+    write_item_list(&mut out, items);
+    out
+}
+
+/// Writes the header lines (作品名, 副題, 原題, 著者名, 翻訳者名), each followed by a newline.
+pub fn write_header(metadata: &AozoraMetadata) -> String {
+    let mut lines = vec![metadata.title.as_str()];
+    lines.extend(metadata.subtitle.as_deref());
+    lines.extend(metadata.original_title.as_deref());
+    lines.push(metadata.author.as_str());
+
+    let mut out = lines.iter().map(|l| format!("{}\n", l)).collect::<String>();
+    if let Some(translator) = &metadata.translator {
+        out.push_str(&format!("{}訳\n", translator));
+    }
+    out
+}
+
+fn write_notes(out: &mut String, notes: &[HeaderNote]) {
+    out.push_str(COMMENT_SEPARATOR);
+    out.push('\n');
+    for note in notes {
+        match note {
+            HeaderNote::Heading(heading) => out.push_str(&format!("【{}】\n", heading)),
+            HeaderNote::Annotation { symbol, description, examples } => {
+                out.push_str(&format!("{}：{}\n", symbol, description));
+                for example in examples {
+                    out.push_str(&format!("（例）{}\n", example));
+                }
+            }
+            HeaderNote::Text(text) => {
+                out.push_str(text);
+                out.push('\n');
+            }
+        }
+    }
+    out.push_str(COMMENT_SEPARATOR);
+    out.push('\n');
+}
+
+fn write_colophon(out: &mut String, colophon: &Colophon) {
+    // Continuation lines of 底本 are indented
+    let indented = |text: &str| text.replace('\n', "\n　　　");
+
+    out.push_str(&format!("底本：{}\n", indented(&colophon.source)));
+    if let Some(parent) = &colophon.parent_source {
+        out.push_str(&format!("底本の親本：{}\n", indented(parent)));
+    }
+    if let Some(inputter) = &colophon.inputter {
+        out.push_str(&format!("入力：{}\n", inputter));
+    }
+    if let Some(proofreader) = &colophon.proofreader {
+        out.push_str(&format!("校正：{}\n", proofreader));
+    }
+    if let Some(published) = &colophon.published {
+        out.push_str(&format!("{}公開\n", published));
+    }
+    for revised in &colophon.revised {
+        out.push_str(&format!("{}修正\n", revised));
+    }
+    for note in &colophon.notes {
+        out.push_str(note);
+        out.push('\n');
+    }
+}
+
+fn write_block_into(out: &mut String, block: &AozoraBlock) {
+    if let Some(begin) = &block.decoration
+        && let Some(notation) = begin_notation(begin)
+    {
+        push_command(out, &notation);
+    }
+    for element in &block.elements {
+        match element {
+            BlockElement::Item(item) => write_item(out, item),
+            BlockElement::Block(child) => write_block_into(out, child),
+        }
+    }
+    if let Some(begin) = &block.decoration
+        && let Some(notation) = closing_notation(begin)
+    {
+        push_command(out, &notation);
+    }
+}
+
+fn write_item_list(out: &mut String, items: &[ParsedItem]) {
+    for item in items {
+        write_item(out, item);
+    }
+}
+
+fn write_item(out: &mut String, item: &ParsedItem) {
+    match item {
+        ParsedItem::Text(dt) => match &dt.ruby {
+            Some(ruby) => {
+                if needs_ruby_separator(out, &dt.text) {
+                    out.push('｜');
+                }
+                push_text(out, &dt.text);
+                out.push_str(&format!("《{}》", ruby));
+            }
+            None => push_text(out, &dt.text),
+        },
+        ParsedItem::Command { cmd, .. } => {
+            // The text of 縦中横 was taken out of the text before the annotation
+            if let Command::SingleCommand(SingleCommand::Tatechuyoko(content)) = cmd {
+                out.push_str(content);
+            }
+            if let Some(notation) = command_notation(cmd) {
+                push_command(out, &notation);
+            }
+        }
+        ParsedItem::UnknownCommand { content, .. } => push_command(out, content),
+        ParsedItem::Newline(_) => out.push('\n'),
+        ParsedItem::SpecialCharacter { kind, .. } => match kind {
+            SpecialCharacter::Odoriji => out.push_str("／＼"),
+            SpecialCharacter::DakutenOdoriji => out.push_str("／″＼"),
+            SpecialCharacter::Gaiji(gaiji) => {
+                out.push('※');
+                push_command(out, &gaiji.annotation);
+            }
+        },
+    }
+}
+
+/// Push text, escaping the characters that would be read as notation. ［ and ］ are
+/// only notation as part of ［＃…］, so only ［ before ＃ is escaped.
+fn push_text(out: &mut String, text: &str) {
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let escaped = match c {
+            '《' | '》' | '｜' => true,
+            '［' => chars.peek() == Some(&'＃'),
+            _ => false,
+        };
+        match NOTATION_SYMBOLS.iter().find(|(symbol, _)| *symbol == c) {
+            Some((_, name)) if escaped => {
+                out.push('※');
+                push_command(out, name);
+            }
+            _ => out.push(c),
+        }
+    }
+}
+
+fn push_command(out: &mut String, notation: &str) {
+    out.push_str(&format!("［＃{}］", notation));
+}
+
+/// Whether a ruby base must be marked with ｜.
+///
+/// Without ｜ the ruby covers the run of same-script characters before it, so the
+/// separator can only be omitted when that run is exactly the base text.
+pub(crate) fn needs_ruby_separator(out: &str, base: &str) -> bool {
+    let Some(first) = base.chars().next() else {
+        return true;
+    };
+    let script = parser::script_of(first);
+    if base.chars().any(|c| parser::script_of(c) != script) {
+        return true;
+    }
+    out.chars().last().is_some_and(|c| parser::script_of(c) == script)
+}
+
+fn to_full_width_digits(n: usize) -> String {
+    n.to_string()
+        .chars()
+        .map(|c| char::from_u32(c as u32 - '0' as u32 + '０' as u32).unwrap())
+        .collect()
+}
+
+fn midashi_notation(m: &Midashi) -> String {
+    let kind = match m.kind {
+        MidashiType::Normal => "",
+        MidashiType::Dogyo => "同行",
+        MidashiType::Mado => "窓",
+    };
+    let size = match m.size {
+        MidashiSize::Large => "大",
+        MidashiSize::Middle => "中",
+        MidashiSize::Small => "小",
+    };
+    format!("{}{}見出し", kind, size)
+}
+
+fn bouten_notation(b: &Bouten) -> &'static str {
+    match b {
+        Bouten::Sirogoma => "傍点",
+        Bouten::BlackCircle => "丸傍点",
+        Bouten::WhiteCircle => "白丸傍点",
+        Bouten::BlackTriangle => "黒三角傍点",
+        Bouten::WhiteTriangle => "白三角傍点",
+        Bouten::DoubleCircle => "二重丸傍点",
+        Bouten::Hebinome => "蛇の目傍点",
+        Bouten::Cross => "ばつ傍点",
+    }
+}
+
+fn bousen_notation(b: &Bousen) -> &'static str {
+    match b {
+        Bousen::Bousen => "傍線",
+        Bousen::Double => "二重傍線",
+        Bousen::Chain => "鎖線",
+        Bousen::Dashed => "破線",
+        Bousen::Wavy => "波線",
+    }
+}
+
+fn alignment_notation(a: &Alignment) -> String {
+    if a.is_upper {
+        format!("{}字下げ", to_full_width_digits(a.space))
+    } else if a.space == 0 {
+        "地付き".to_string()
+    } else {
+        format!("地から{}字上げ", to_full_width_digits(a.space))
+    }
+}
+
+/// Notation of a single command item (without ［＃ and ］).
+fn command_notation(cmd: &Command) -> Option<String> {
+    match cmd {
+        Command::CommandBegin(begin) => begin_notation(begin),
+        Command::CommandEnd(end) => end_notation(end),
+        Command::SingleCommand(single) => Some(single_notation(single)),
+    }
+}
+
+fn single_notation(cmd: &SingleCommand) -> String {
+    match cmd {
+        SingleCommand::Midashi((m, content)) => format!("「{}」は{}", content, midashi_notation(m)),
+        SingleCommand::Alignment(a) => alignment_notation(a),
+        SingleCommand::Kaicho => "改丁".to_string(),
+        SingleCommand::Kaimihiraki => "改見開き".to_string(),
+        SingleCommand::Kaipage => "改ページ".to_string(),
+        SingleCommand::Kaidan => "改段".to_string(),
+        SingleCommand::Bouten((b, content)) => format!("「{}」に{}", content, bouten_notation(b)),
+        SingleCommand::Bousen((b, content)) => format!("「{}」に{}", content, bousen_notation(b)),
+        SingleCommand::Bold(content) => format!("「{}」は太字", content),
+        SingleCommand::Italic(content) => format!("「{}」は斜体", content),
+        SingleCommand::Tatechuyoko(content) => format!("「{}」は縦中横", content),
+        SingleCommand::Mama(content) => format!("「{}」に「ママ」の注記", content),
+        SingleCommand::SourceNote { visible, original } => {
+            format!("「{}」は底本では「{}」", visible, original)
+        }
+        SingleCommand::Note { target, note } => format!("「{}」に「{}」の注記", target, note),
+        SingleCommand::Image { description, src, caption, size } => {
+            let caption = caption.as_ref().map(|c| format!("「{}」のキャプション付きの", c)).unwrap_or_default();
+            let size = size.map(|(w, h)| format!("、横{}×縦{}", w, h)).unwrap_or_default();
+            format!("{}{}（{}{}）入る", caption, description, src, size)
+        }
+    }
+}
+
+pub(crate) fn begin_notation(begin: &CommandBegin) -> Option<String> {
+    Some(match begin {
+        CommandBegin::Midashi(m) => midashi_notation(m),
+        CommandBegin::Alignment(a) => format!("ここから{}", alignment_notation(a)),
+        CommandBegin::Bouten(b) => bouten_notation(b).to_string(),
+        CommandBegin::Bousen(b) => bousen_notation(b).to_string(),
+        CommandBegin::Bold => "太字".to_string(),
+        CommandBegin::Italic => "斜体".to_string(),
+        CommandBegin::Kakomikei => "ここから罫囲み".to_string(),
+        CommandBegin::Yokogumi => "ここから横組み".to_string(),
+        CommandBegin::Jitsume(n) => format!("ここから{}字詰め", to_full_width_digits(*n)),
+        CommandBegin::PageCenter => "ページの左右中央".to_string(),
+        CommandBegin::Dangumi(n) => format!("ここから{}段組み", to_full_width_digits(*n)),
+    })
+}
+
+/// Notation of a standalone end command. Ends carry no parameters, so the
+/// generic form is used (e.g. 傍点終わり for every kind of 傍点).
+pub(crate) fn end_notation(end: &CommandEnd) -> Option<String> {
+    Some(match end {
+        CommandEnd::Midashi(m) => format!("{}終わり", midashi_notation(m)),
+        CommandEnd::Alignment => "ここで字下げ終わり".to_string(),
+        CommandEnd::Bouten => "傍点終わり".to_string(),
+        CommandEnd::Bousen => "傍線終わり".to_string(),
+        CommandEnd::Bold => "太字終わり".to_string(),
+        CommandEnd::Italic => "斜体終わり".to_string(),
+        CommandEnd::Kakomikei => "ここで罫囲み終わり".to_string(),
+        CommandEnd::Yokogumi => "ここで横組み終わり".to_string(),
+        CommandEnd::Jitsume => "ここで字詰め終わり".to_string(),
+        // ページの左右中央 has no closing annotation; it ends at the next page break
+        CommandEnd::PageCenter => return None,
+        CommandEnd::Dangumi => "ここで段組み終わり".to_string(),
+    })
+}
+
+/// Notation closing a block, matching the form of its begin command.
+fn closing_notation(begin: &CommandBegin) -> Option<String> {
+    match begin {
+        CommandBegin::Midashi(m) => end_notation(&CommandEnd::Midashi(m.clone())),
+        CommandBegin::Alignment(a) if !a.is_upper => Some(if a.space == 0 {
+            "ここで地付き終わり".to_string()
+        } else {
+            "ここで字上げ終わり".to_string()
+        }),
+        CommandBegin::Alignment(_) => end_notation(&CommandEnd::Alignment),
+        CommandBegin::Bouten(b) => Some(format!("{}終わり", bouten_notation(b))),
+        CommandBegin::Bousen(b) => Some(format!("{}終わり", bousen_notation(b))),
+        CommandBegin::Bold => end_notation(&CommandEnd::Bold),
+        CommandBegin::Italic => end_notation(&CommandEnd::Italic),
+        CommandBegin::Kakomikei => end_notation(&CommandEnd::Kakomikei),
+        CommandBegin::Yokogumi => end_notation(&CommandEnd::Yokogumi),
+        CommandBegin::Jitsume(_) => end_notation(&CommandEnd::Jitsume),
+        CommandBegin::PageCenter => end_notation(&CommandEnd::PageCenter),
+        CommandBegin::Dangumi(_) => end_notation(&CommandEnd::Dangumi),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_parser::parse_blocks;
+    use crate::parser::{parse, parse_with, DecoratedText, HeaderMode, ParseOptions};
+    use crate::tokenizer::{parse_aozora, Span};
+
+    fn round_trip(text: &str) -> String {
+        write_document(&parse(parse_aozora(text.to_string()).unwrap()))
+    }
+
+    #[test]
+    fn test_round_trip_document() {
+        let text = "桜桃\n太宰治\n\n-------------------------------------------------------\n【テキスト中に現れる記号について】\n《》：ルビ\n（例）目を挙《あ》ぐ。\n-------------------------------------------------------\n［＃ここから２字下げ］\n青空《あおぞら》文庫の漢字《かんじ》／＼\n［＃ここで字下げ終わり］\n［＃改ページ］\n";
+        assert_eq!(round_trip(text), text);
+    }
+
+    #[test]
+    fn test_round_trip_colophon() {
+        let text = "T\nA\n\n本文\n\n\n底本：角川文庫「人間失格・桜桃」角川書店\n　　　1989（平成元）年4月10日初版発行\n入力：高橋美奈子\n校正：瀬戸さえ子\n1999年4月8日公開\n2004年2月23日修正\n青空文庫作成ファイル：\n";
+        assert_eq!(round_trip(text), text);
+    }
+
+    #[test]
+    fn test_round_trip_gaiji() {
+        let text = "T\nA\n\n※［＃「木＋吶のつくり」、第3水準1-85-54］と※［＃「口＋世」、U+546D、190-下-5］\n";
+        assert_eq!(round_trip(text), text);
+    }
+
+    #[test]
+    fn test_round_trip_tatechuyoko() {
+        let text = "T\nA\n\n第12［＃「12」は縦中横］話!?［＃「!?」は縦中横］\n";
+        assert_eq!(round_trip(text), text);
+    }
+
+    #[test]
+    fn test_round_trip_unknown_command() {
+        let text = "T\nA\n\n本文［＃未知の注記］と［＃「無い」は縦中横］\n";
+        let doc = parse(parse_aozora(text.to_string()).unwrap());
+        assert!(matches!(&doc.items[2], ParsedItem::UnknownCommand { content, .. } if content == "未知の注記"));
+        assert_eq!(write_document(&doc), text);
+    }
+
+    #[test]
+    fn test_notation_characters_are_escaped() {
+        let items = vec![ParsedItem::Text(DecoratedText {
+            text: "《》｜［＃］［".to_string(),
+            ruby: None,
+            span: Default::default(),
+        })];
+        let written = write_items(&items);
+        assert_eq!(
+            written,
+            "※［＃始め二重山括弧、1-1-52］※［＃終わり二重山括弧、1-1-53］※［＃縦線、1-1-35］※［＃始め角括弧、1-1-46］＃］［"
+        );
+
+        // The escapes are read back as the characters, in a single text
+        let doc = parse_with(parse_aozora(written.clone()).unwrap(), &ParseOptions { header: HeaderMode::BodyOnly });
+        assert_eq!(doc.items.len(), 1);
+        assert!(matches!(&doc.items[0], ParsedItem::Text(dt) if dt.text == "《》｜［＃］［" && dt.span == Span::new(0, written.chars().count())));
+        assert_eq!(write_items(&doc.items), written);
+    }
+
+    #[test]
+    fn test_round_trip_image() {
+        let text = "T\nA\n\n［＃挿絵（fig1_01.png、横320×縦240）入る］\n［＃「第一図」のキャプション付きの図（fig2.png）入る］\n";
+        assert_eq!(round_trip(text), text);
+    }
+
+    #[test]
+    fn test_round_trip_extended_header() {
+        let text = "罪と罰\n第一部\nドストエフスキー\n米川正夫訳\n\n本文\n";
+        assert_eq!(round_trip(text), text);
+    }
+
+    #[test]
+    fn test_midashi_reference_is_normalized() {
+        let text = "T\nA\n\n第一章［＃「第一章」は大見出し］\n";
+        let written = round_trip(text);
+        assert_eq!(written, "T\nA\n\n［＃大見出し］第一章［＃大見出し終わり］\n");
+        // Writing is stable once normalized
+        assert_eq!(round_trip(&written), written);
+    }
+
+    #[test]
+    fn test_redundant_ruby_separator_is_dropped() {
+        let text = "T\nA\n\n｜青空《あおぞら》\n";
+        assert_eq!(round_trip(text), "T\nA\n\n青空《あおぞら》\n");
+    }
+
+    #[test]
+    fn test_ruby_separator_added_when_needed() {
+        // The ruby base would otherwise absorb the preceding kanji
+        let text = "T\nA\n\n漢｜字《じ》と字《じ》\n";
+        assert_eq!(round_trip(text), text);
+    }
+
+    #[test]
+    fn test_write_block() {
+        let text = "T\nA\n\n［＃ここから２段組み］\n一［＃改段］\n二\n［＃ここで段組み終わり］\n";
+        let doc = parse(parse_aozora(text.to_string()).unwrap());
+        let block = parse_blocks(doc.items.clone()).unwrap();
+        assert_eq!(write_block(&block), write_items(&doc.items));
+        assert_eq!(write_block(&block), "\n［＃ここから２段組み］\n一［＃改段］\n二\n［＃ここで段組み終わり］\n");
+    }
+
+    #[test]
+    fn test_closing_notation_matches_begin() {
+        let block = AozoraBlock {
+            decoration: Some(CommandBegin::Bouten(Bouten::WhiteCircle)),
+            elements: Vec::new(),
+            span: Default::default(),
+        };
+        assert_eq!(write_block(&block), "［＃白丸傍点］［＃白丸傍点終わり］");
+    }
+}
@@ -0,0 +1,246 @@
+//! Project configuration, read from karp.toml (or kartana.toml) in the directory of the
+//! input or one of its ancestors.
+//!
+//! ```toml
+//! [build]
+//! out-dir = "dist"
+//! writing-mode = "horizontal"   # or "vertical"
+//! theme = "theme.css"           # appended to the built-in stylesheet
+//! cover = "cover.jpg"
+//! okuzuke = true                # add a colophon page (奥付) at the end
+//!
+//! [lint]
+//! disable = ["missing-paragraph-indent"]
+//! preferred-width = "half"      # or "full"
+//! max-paragraph-length = 400
+//! max-sentence-length = 120
+//!
+//! [lint.severity]
+//! odd-ellipsis = "error"        # "error", "warning" or "info"
+//!
+//! [metadata]
+//! title = "桜桃"
+//! author = "太宰治"
+//! subtitle = "…"
+//! lang = "ja"
+//! description = "…"
+//! publisher = "…"
+//! date = "1948-05-01"
+//! subjects = ["小説"]
+//! rights = "…"
+//! ```
+//!
+//! Relative paths are resolved against the directory of the configuration file.
+
+use aozora_parser::{CharacterWidth, EpubMetadata, LintConfig, Severity, WritingMode, XhtmlGeneratorOptions};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// Names of the configuration file, in order of preference.
+pub const CONFIG_FILES: [&str; 2] = ["karp.toml", "kartana.toml"];
+
+/// Settings read from a configuration file. The default applies when there is none.
+#[derive(Debug, Default)]
+pub struct Config {
+    pub lint: LintConfig,
+    pub out_dir: Option<PathBuf>,
+    pub writing_mode: Option<WritingMode>,
+    /// Contents of the theme stylesheet
+    pub theme: Option<String>,
+    pub cover: Option<PathBuf>,
+    /// Whether to add a colophon page
+    pub okuzuke: bool,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subtitle: Option<String>,
+    pub lang: Option<String>,
+    pub metadata: EpubMetadata,
+    /// What happens to warning-level diagnostics (`--allow`/`--deny warnings`)
+    pub warnings: WarningsLevel,
+}
+
+/// Level of the `warnings` group, which covers every diagnostic of warning severity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WarningsLevel {
+    /// Drop them
+    Allow,
+    #[default]
+    Warn,
+    /// Report them as errors, failing the build
+    Deny,
+}
+
+impl Config {
+    /// Load the configuration file that applies to `input`, or the default if there is none.
+    pub fn for_input(input: &Path) -> Result<Self, String> {
+        match find(input) {
+            Some(path) => Self::load(&path).map_err(|e| format!("{}: {}", path.display(), e)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let table: Table = text.parse().map_err(|e: toml::de::Error| e.message().to_string())?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        let mut config = Config::default();
+
+        check_keys(&table, "", &["build", "lint", "metadata"])?;
+        if let Some(build) = section(&table, "build")? {
+            check_keys(build, "build", &["out-dir", "writing-mode", "theme", "cover", "okuzuke"])?;
+            config.out_dir = string(build, "build", "out-dir")?.map(|dir| base.join(dir));
+            config.writing_mode = match string(build, "build", "writing-mode")?.as_deref() {
+                None => None,
+                Some("vertical") => Some(WritingMode::VerticalRl),
+                Some("horizontal") => Some(WritingMode::HorizontalTb),
+                Some(other) => {
+                    return Err(format!("`build.writing-mode` must be \"vertical\" or \"horizontal\", not \"{}\"", other));
+                }
+            };
+            if let Some(theme) = string(build, "build", "theme")? {
+                let theme = base.join(theme);
+                config.theme = Some(
+                    fs::read_to_string(&theme).map_err(|e| format!("could not read {}: {}", theme.display(), e))?,
+                );
+            }
+            if let Some(cover) = string(build, "build", "cover")? {
+                let cover = base.join(cover);
+                if !cover.is_file() {
+                    return Err(format!("cover image {} does not exist", cover.display()));
+                }
+                config.cover = Some(cover);
+            }
+            config.okuzuke = boolean(build, "build", "okuzuke")?.unwrap_or(false);
+        }
+
+        if let Some(lint) = section(&table, "lint")? {
+            check_keys(
+                lint,
+                "lint",
+                &["disable", "severity", "preferred-width", "max-paragraph-length", "max-sentence-length"],
+            )?;
+            for rule in strings(lint, "lint", "disable")? {
+                config.lint.disable(rule);
+            }
+            if let Some(severities) = section(lint, "severity")? {
+                for (rule, severity) in severities {
+                    let severity = match severity.as_str() {
+                        Some("error") => Severity::Error,
+                        Some("warning") => Severity::Warning,
+                        Some("info") => Severity::Info,
+                        _ => return Err(format!("`lint.severity.{}` must be \"error\", \"warning\" or \"info\"", rule)),
+                    };
+                    config.lint.set_severity(rule.as_str(), severity);
+                }
+            }
+            match string(lint, "lint", "preferred-width")?.as_deref() {
+                None => {}
+                Some("full") => config.lint.preferred_width = CharacterWidth::FullWidth,
+                Some("half") => config.lint.preferred_width = CharacterWidth::HalfWidth,
+                Some(other) => {
+                    return Err(format!("`lint.preferred-width` must be \"full\" or \"half\", not \"{}\"", other));
+                }
+            }
+            if let Some(length) = integer(lint, "lint", "max-paragraph-length")? {
+                config.lint.max_paragraph_length = length;
+            }
+            if let Some(length) = integer(lint, "lint", "max-sentence-length")? {
+                config.lint.max_sentence_length = length;
+            }
+        }
+
+        if let Some(metadata) = section(&table, "metadata")? {
+            check_keys(
+                metadata,
+                "metadata",
+                &["title", "author", "subtitle", "lang", "description", "publisher", "date", "subjects", "rights"],
+            )?;
+            config.title = string(metadata, "metadata", "title")?;
+            config.author = string(metadata, "metadata", "author")?;
+            config.subtitle = string(metadata, "metadata", "subtitle")?;
+            config.lang = string(metadata, "metadata", "lang")?;
+            config.metadata.description = string(metadata, "metadata", "description")?;
+            config.metadata.publisher = string(metadata, "metadata", "publisher")?;
+            config.metadata.date = string(metadata, "metadata", "date")?;
+            config.metadata.subjects = strings(metadata, "metadata", "subjects")?;
+            config.metadata.rights = string(metadata, "metadata", "rights")?;
+        }
+        Ok(config)
+    }
+
+    /// Options of the XHTML generator, with the language and writing mode applied.
+    pub fn xhtml_options(&self) -> XhtmlGeneratorOptions {
+        let mut options = XhtmlGeneratorOptions::default();
+        if let Some(lang) = &self.lang {
+            options.lang = lang.clone();
+        }
+        if let Some(writing_mode) = self.writing_mode {
+            options.writing_mode = writing_mode;
+        }
+        options
+    }
+}
+
+/// The configuration file in the directory of `input` (or `input` itself if it is a
+/// directory) or the nearest of its ancestors.
+fn find(input: &Path) -> Option<PathBuf> {
+    let dir = if input.is_dir() { input } else { input.parent().unwrap_or(Path::new("")) };
+    let dir = fs::canonicalize(if dir.as_os_str().is_empty() { Path::new(".") } else { dir }).ok()?;
+    dir.ancestors()
+        .flat_map(|dir| CONFIG_FILES.iter().map(move |name| dir.join(name)))
+        .find(|path| path.is_file())
+}
+
+/// Reject keys that are not known, which are most likely typos.
+fn check_keys(table: &Table, section: &str, known: &[&str]) -> Result<(), String> {
+    match table.keys().find(|key| !known.contains(&key.as_str())) {
+        Some(key) if section.is_empty() => Err(format!("unknown section `{}`", key)),
+        Some(key) => Err(format!("unknown key `{}.{}`", section, key)),
+        None => Ok(()),
+    }
+}
+
+fn section<'a>(table: &'a Table, key: &str) -> Result<Option<&'a Table>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Table(section)) => Ok(Some(section)),
+        Some(_) => Err(format!("`{}` must be a table", key)),
+    }
+}
+
+fn string(table: &Table, section: &str, key: &str) -> Result<Option<String>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(_) => Err(format!("`{}.{}` must be a string", section, key)),
+    }
+}
+
+fn strings(table: &Table, section: &str, key: &str) -> Result<Vec<String>, String> {
+    match table.get(key) {
+        None => Ok(Vec::new()),
+        Some(Value::Array(values)) => values
+            .iter()
+            .map(|value| value.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| format!("`{}.{}` must be an array of strings", section, key)),
+        Some(_) => Err(format!("`{}.{}` must be an array of strings", section, key)),
+    }
+}
+
+fn boolean(table: &Table, section: &str, key: &str) -> Result<Option<bool>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Boolean(b)) => Ok(Some(*b)),
+        Some(_) => Err(format!("`{}.{}` must be true or false", section, key)),
+    }
+}
+
+fn integer(table: &Table, section: &str, key: &str) -> Result<Option<usize>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Integer(n)) if *n > 0 => Ok(Some(*n as usize)),
+        Some(_) => Err(format!("`{}.{}` must be a positive integer", section, key)),
+    }
+}
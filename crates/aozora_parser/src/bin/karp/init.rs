@@ -0,0 +1,119 @@
+//! `karp init`: scaffold a project that both karp and the Kartana GUI can work with.
+//!
+//! ```text
+//! <name>/
+//!   karp.toml
+//!   data/series/<name>/series.toml   (the series as the GUI stores it)
+//!   data/series/<name>/<chapter>.txt (Shift_JIS, as the GUI saves it)
+//! ```
+//!
+//! Running the GUI in `<name>/` shows the series, and `karp build data/series/<name>`
+//! picks up karp.toml from the project root.
+
+use encoding_rs::SHIFT_JIS;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Title of the first chapter.
+pub const FIRST_CHAPTER: &str = "第一章";
+
+const CONFIG_TEMPLATE: &str = r#"[build]
+out-dir = "dist"
+# writing-mode = "horizontal"
+# theme = "theme.css"
+# cover = "cover.jpg"
+
+[lint]
+# disable = ["missing-paragraph-indent"]
+
+[metadata]
+# publisher = ""
+# subjects = []
+"#;
+
+/// The usual note on notation, following the header (title and author), and an indented
+/// empty paragraph to start writing in.
+const TEXT_AFTER_HEADER: &str = "
+-------------------------------------------------------
+【テキスト中に現れる記号について】
+
+《》：ルビ
+（例）青空《あおぞら》
+
+｜：ルビの付く文字列の始まりを特定する記号
+（例）青空｜文庫《ぶんこ》
+
+［＃］：入力者注　主に外字の説明や、傍点の位置の指定
+（例）［＃「青空」に傍点］
+-------------------------------------------------------
+
+　
+";
+
+/// Create the project directory `dir`. Fails if it exists and is not empty.
+/// Returns the path of the manuscript.
+pub fn init_project(dir: &Path, author: &str) -> Result<PathBuf, String> {
+    if fs::read_dir(dir).is_ok_and(|mut entries| entries.next().is_some()) {
+        return Err(format!("destination `{}` already exists and is not empty", dir.display()));
+    }
+    let title = dir
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| format!("`{}` is not a valid project name", dir.display()))?;
+
+    // Nothing is written unless the manuscript can be
+    let text = format!("{}\n{}\n{}", title, author, TEXT_AFTER_HEADER);
+    let (bytes, _, had_errors) = SHIFT_JIS.encode(&text);
+    if had_errors {
+        return Err("the title or author cannot be encoded as Shift_JIS".to_string());
+    }
+
+    let series_dir = dir.join("data").join("series").join(title);
+    fs::create_dir_all(&series_dir).map_err(|e| e.to_string())?;
+
+    let config = CONFIG_TEMPLATE.replace("[metadata]\n", &format!("[metadata]\ntitle = {}\n", toml_string(title)));
+    fs::write(dir.join("karp.toml"), config).map_err(|e| e.to_string())?;
+
+    let series = format!(
+        "title = {}\nis_favourite = false\n\n[[chapters]]\ntitle = {}\ncreated_at = {}\n",
+        toml_string(title),
+        toml_string(FIRST_CHAPTER),
+        toml_string(&chrono::Local::now().format("%Y-%m-%d").to_string())
+    );
+    fs::write(series_dir.join("series.toml"), series).map_err(|e| e.to_string())?;
+
+    let manuscript = series_dir.join(format!("{}.txt", FIRST_CHAPTER));
+    fs::write(&manuscript, bytes).map_err(|e| e.to_string())?;
+    Ok(manuscript)
+}
+
+fn toml_string(s: &str) -> String {
+    toml::Value::String(s.to_string()).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_project(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("karp_init_{}", std::process::id())).join(name)
+    }
+
+    #[test]
+    fn test_unencodable_author() {
+        let dir = temp_project("unencodable");
+        assert!(init_project(&dir, "𠮷田").is_err());
+        // No half-created project is left behind
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_braces_in_title() {
+        let dir = temp_project("{author}");
+        let manuscript = init_project(&dir, "太宰治").unwrap();
+        let bytes = fs::read(&manuscript).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        let (text, _, _) = SHIFT_JIS.decode(&bytes);
+        assert!(text.starts_with("{author}\n太宰治\n\n-----"));
+    }
+}
@@ -0,0 +1,1381 @@
+//! karp - Aozora Bunko text to EPUB compiler
+//!
+//! Usage:
+//!   karp build <path>  - Compile text file to EPUB
+//!   karp build -o <file> <path>  - Compile to the given EPUB file
+//!   karp build --out-dir <dir> <path>  - Compile into the given directory
+//!   karp build <dir|glob>  - Compile every .txt file in a directory or matching a glob,
+//!     several at once (`-j <n>` to limit the number of threads)
+//!   karp check <path>  - Check for warnings/errors without generating EPUB
+//!   karp check --fix <path>  - Check and apply automatic fixes to the file
+//!   karp serve <path>  - Preview in the browser, reloading when the file changes
+//!   karp toc <path>  - Print the heading outline with the location of each heading
+//!   karp inspect <book.epub>  - List the files, metadata, spine and TOC of an EPUB
+//!   karp inspect --text <book.epub>  - Print the text of an EPUB in reading order
+//!   karp init <name>  - Create a project with a config file and a template manuscript
+//!   karp convert <path> --to <format>  - Convert to XHTML, HTML, text, Markdown, LaTeX or
+//!     JSON, writing to stdout or `-o <file>`
+//!   karp completions <shell>  - Print a completion script for bash, zsh, fish, PowerShell
+//!     or elvish
+//!   karp --message-format=json <command>  - Print one JSON object per diagnostic, like
+//!     cargo (requires the `serde` feature)
+//!
+//! A path of `-` reads the manuscript from stdin: `karp build - -o <file>` builds it and
+//! `karp check --fix -` prints the fixed text to stdout instead of the diagnostics.
+//!
+//! `build` takes `--title`, `--author`, `--language`, `--publisher` and `--cover`, which
+//! override the header of the text and the configuration file. `--title`, `--author` and
+//! `--cover` are refused when building several files.
+//!
+//! `build` and `check` take clippy-style lint levels: `-A/--allow <rule>` silences a rule,
+//! `-W/--warn <rule>` reports it as a warning and `-D/--deny <rule>` as an error. The
+//! group `warnings` stands for every warning, so `-D warnings` fails on any of them.
+//!
+//! Both commands take `--encoding {auto,sjis,utf8}`; `auto` (the default) looks at the
+//! BOM and at which encoding the bytes are valid in, and `check --fix` refuses a file it
+//! could only partly decode. Settings for a project are read from
+//! karp.toml or kartana.toml (see [`config`]).
+
+mod config;
+mod init;
+mod serve;
+
+use aozora_parser::{
+    parse_aozora, parse, parse_blocks, parse_blocks_with, lint_with_diagnostics, apply_fixes, lint_report, to_markdown, default_css,
+    extract_epub_text, inspect_epub, text_to_epub_generator, EpubInfo, BlockParseOptions, ParserDiagnostics,
+    LatexGenerator, LatexGeneratorOptions, LintWarning,
+    LintWarningKind, Severity,
+    ConversionError, TextGenerator, TextGeneratorOptions, TocEntry, XhtmlGenerator,
+};
+use config::{Config, WarningsLevel};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use encoding_rs::{SHIFT_JIS, UTF_8};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+#[derive(Parser)]
+#[command(name = "karp")]
+#[command(author, version, about = "Aozora Bunko text to EPUB compiler")]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+    /// Format of the diagnostics of `build` and `check`
+    #[arg(long, global = true, value_enum, default_value_t = MessageFormat::Human)]
+    message_format: MessageFormat,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Compile text file to EPUB
+    Build {
+        /// Path to the input text file, or a directory or glob of text files (`-` for stdin)
+        path: PathBuf,
+        /// Path of the EPUB file to write
+        #[arg(short, long, conflicts_with = "out_dir")]
+        output: Option<PathBuf>,
+        /// Directory to write the EPUB file to (created if missing)
+        #[arg(long)]
+        out_dir: Option<PathBuf>,
+        /// Number of files to build at once when building several (defaults to the
+        /// number of CPUs)
+        #[arg(short, long, value_parser = clap::value_parser!(u16).range(1..))]
+        jobs: Option<u16>,
+        /// Encoding of the input text
+        #[arg(long, value_enum, default_value_t = TextEncoding::Auto)]
+        encoding: TextEncoding,
+        #[command(flatten)]
+        levels: LintLevels,
+        #[command(flatten)]
+        metadata: MetadataOverrides,
+    },
+    /// Convert a text file to another format
+    Convert {
+        /// Path to the input text file (`-` for stdin)
+        path: PathBuf,
+        /// Format to convert to
+        #[arg(long, value_enum)]
+        to: ConvertFormat,
+        /// File to write (stdout if omitted or `-`)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Encoding of the input text
+        #[arg(long, value_enum, default_value_t = TextEncoding::Auto)]
+        encoding: TextEncoding,
+    },
+    /// Preview a text file in the browser, reloading when it changes
+    Serve {
+        /// Path to the input text file
+        path: PathBuf,
+        /// Port to listen on (on localhost)
+        #[arg(long, default_value_t = 8000)]
+        port: u16,
+        /// Encoding of the input text
+        #[arg(long, value_enum, default_value_t = TextEncoding::Auto)]
+        encoding: TextEncoding,
+    },
+    /// Print the heading outline without generating EPUB
+    Toc {
+        /// Path to the input text file (`-` for stdin)
+        path: PathBuf,
+        /// Output format of the outline
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        format: OutputFormat,
+        /// Encoding of the input text
+        #[arg(long, value_enum, default_value_t = TextEncoding::Auto)]
+        encoding: TextEncoding,
+    },
+    /// Show what is inside an EPUB file
+    Inspect {
+        /// Path to the EPUB file (`-` for stdin)
+        path: PathBuf,
+        /// Print the text of the content documents in reading order instead
+        #[arg(long)]
+        text: bool,
+        /// Output format of the listing
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human, conflicts_with = "text")]
+        format: OutputFormat,
+    },
+    /// Create a project with karp.toml and a template manuscript, laid out like the data
+    /// directory of the Kartana GUI
+    Init {
+        /// Directory to create; its name becomes the title
+        name: PathBuf,
+        /// Author written in the header of the manuscript
+        #[arg(long, default_value = "著者名")]
+        author: String,
+    },
+    /// Print a shell completion script, e.g. `karp completions bash > /etc/bash_completion.d/karp`
+    Completions {
+        shell: Shell,
+    },
+    /// Check for warnings/errors without generating EPUB
+    Check {
+        /// Path to the input text file (`-` for stdin)
+        path: PathBuf,
+        /// Apply automatic fixes to the file (with stdin, print the fixed text to stdout)
+        #[arg(long)]
+        fix: bool,
+        /// Encoding of the input text
+        #[arg(long, value_enum, default_value_t = TextEncoding::Auto)]
+        encoding: TextEncoding,
+        #[command(flatten)]
+        levels: LintLevels,
+    },
+}
+
+/// Lint levels given on the command line, overriding the configuration file. A rule
+/// given to several flags gets the strictest level.
+#[derive(Args)]
+struct LintLevels {
+    /// Silence a rule (or `warnings` for every warning)
+    #[arg(short = 'A', long = "allow", value_name = "RULE")]
+    allow: Vec<String>,
+    /// Report a rule as a warning
+    #[arg(short = 'W', long = "warn", value_name = "RULE")]
+    warn: Vec<String>,
+    /// Report a rule as an error (or `warnings` to fail on every warning)
+    #[arg(short = 'D', long = "deny", value_name = "RULE")]
+    deny: Vec<String>,
+}
+
+/// Metadata given on the command line, overriding the header and the configuration file.
+#[derive(Args)]
+struct MetadataOverrides {
+    /// Title of the book
+    #[arg(long)]
+    title: Option<String>,
+    /// Author of the book
+    #[arg(long)]
+    author: Option<String>,
+    /// Language of the book (e.g. `ja`)
+    #[arg(long)]
+    language: Option<String>,
+    /// Publisher of the book
+    #[arg(long)]
+    publisher: Option<String>,
+    /// Cover image (JPEG, PNG, GIF or SVG)
+    #[arg(long)]
+    cover: Option<PathBuf>,
+}
+
+impl MetadataOverrides {
+    /// Whether a value is given that belongs to a single book, unlike the language or
+    /// publisher that a whole series can share.
+    fn is_per_book(&self) -> bool {
+        self.title.is_some() || self.author.is_some() || self.cover.is_some()
+    }
+
+    fn apply(&self, config: &mut Config) -> Result<(), String> {
+        if let Some(cover) = &self.cover {
+            if !cover.is_file() {
+                return Err(format!("cover image {} does not exist", cover.display()));
+            }
+            config.cover = Some(cover.clone());
+        }
+        config.title = self.title.clone().or(config.title.take());
+        config.author = self.author.clone().or(config.author.take());
+        config.lang = self.language.clone().or(config.lang.take());
+        config.metadata.publisher = self.publisher.clone().or(config.metadata.publisher.take());
+        Ok(())
+    }
+}
+
+impl LintLevels {
+    fn apply(&self, config: &mut Config) -> Result<(), String> {
+        for (rules, level) in [
+            (&self.allow, WarningsLevel::Allow),
+            (&self.warn, WarningsLevel::Warn),
+            (&self.deny, WarningsLevel::Deny),
+        ] {
+            for rule in rules {
+                if rule == "warnings" {
+                    config.warnings = level;
+                    continue;
+                }
+                if !LintWarningKind::RULE_IDS.contains(&rule.as_str()) {
+                    return Err(format!("unknown lint rule `{}`", rule));
+                }
+                match level {
+                    WarningsLevel::Allow => config.lint.disable(rule.as_str()),
+                    WarningsLevel::Warn => {
+                        config.lint.enable(rule);
+                        config.lint.set_severity(rule.as_str(), Severity::Warning);
+                    }
+                    WarningsLevel::Deny => {
+                        config.lint.enable(rule);
+                        config.lint.set_severity(rule.as_str(), Severity::Error);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum OutputFormat {
+    /// Colored messages for the terminal
+    Human,
+    /// JSON
+    #[cfg(feature = "serde")]
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum ConvertFormat {
+    /// A standalone XHTML document
+    Xhtml,
+    /// An HTML5 fragment to embed in a page
+    Html,
+    /// Plain text without ruby and annotations
+    Txt,
+    /// Markdown
+    Md,
+    /// A LaTeX document (jlreq)
+    Latex,
+    /// The parsed document
+    #[cfg(feature = "serde")]
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum MessageFormat {
+    /// Colored messages for the terminal
+    Human,
+    /// One JSON object per line: a `diagnostic` for each warning (rule id, severity,
+    /// message, span, line/column and fix), an `error` for other failures and a
+    /// `build-finished` for each built file
+    #[cfg(feature = "serde")]
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum TextEncoding {
+    /// Detect from the BOM and the content
+    Auto,
+    /// Shift_JIS, the encoding of Aozora Bunko texts
+    Sjis,
+    Utf8,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let message_format = cli.message_format;
+    let input = match &cli.command {
+        Commands::Build { path, .. }
+        | Commands::Check { path, .. }
+        | Commands::Convert { path, .. }
+        | Commands::Serve { path, .. }
+        | Commands::Toc { path, .. } => path,
+        Commands::Init { name, author } => return init_command(name, author),
+        Commands::Inspect { path, text, format } => return inspect_command(path, *text, *format),
+        Commands::Completions { shell } => {
+            clap_complete::generate(*shell, &mut Cli::command(), "karp", &mut io::stdout());
+            return ExitCode::SUCCESS;
+        }
+    };
+    let mut config = match Config::for_input(input) {
+        Ok(config) => config,
+        Err(e) => {
+            print_error(&format!("invalid configuration: {}", e));
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Commands::Build { levels, .. } | Commands::Check { levels, .. } = &cli.command
+        && let Err(e) = levels.apply(&mut config)
+    {
+        print_error(&e);
+        return ExitCode::FAILURE;
+    }
+    if let Commands::Build { metadata, .. } = &cli.command
+        && let Err(e) = metadata.apply(&mut config)
+    {
+        print_error(&e);
+        return ExitCode::FAILURE;
+    }
+
+    match cli.command {
+        Commands::Build { path, output, out_dir, jobs, encoding, metadata, .. } => match find_sources(&path) {
+            Ok(None) if is_stdio(&path) && output.is_none() => {
+                print_error("reading from stdin requires --output");
+                ExitCode::FAILURE
+            }
+            Ok(None) => {
+                let out_dir = out_dir.or_else(|| config.out_dir.clone());
+                let output_path = output_path(&path, output.as_deref(), out_dir.as_deref());
+                let success = build_command(&path, &output_path, encoding, message_format, &config);
+                #[cfg(feature = "serde")]
+                if message_format == MessageFormat::Json {
+                    print_json_build_finished(&path, &output_path, success);
+                }
+                if success { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+            }
+            Ok(Some(_)) if output.is_some() => {
+                print_error("--output cannot be used when building several files; use --out-dir");
+                ExitCode::FAILURE
+            }
+            Ok(Some(_)) if metadata.is_per_book() => {
+                print_error("--title, --author and --cover cannot be used when building several files");
+                ExitCode::FAILURE
+            }
+            Ok(Some(sources)) => {
+                let out_dir = out_dir.or_else(|| config.out_dir.clone());
+                let jobs = jobs.map_or_else(
+                    || thread::available_parallelism().map_or(1, |n| n.get()),
+                    usize::from,
+                );
+                batch_command(&path, &sources, out_dir.as_deref(), jobs, encoding, message_format, &config)
+            }
+            Err(e) => {
+                print_error(&e);
+                ExitCode::FAILURE
+            }
+        },
+        Commands::Check { path, fix, encoding, .. } => check_command(&path, fix, encoding, message_format, &config),
+        Commands::Convert { path, to, output, encoding } => {
+            convert_command(&path, to, output.as_deref(), encoding, &config)
+        }
+        Commands::Serve { path, port, encoding } => serve_command(&path, port, encoding, &config),
+        Commands::Toc { path, format, encoding } => toc_command(&path, format, encoding),
+        Commands::Init { .. } | Commands::Inspect { .. } | Commands::Completions { .. } => {
+            unreachable!("handled before loading the configuration")
+        }
+    }
+}
+
+fn convert_command(
+    path: &Path,
+    to: ConvertFormat,
+    output: Option<&Path>,
+    encoding: TextEncoding,
+    config: &Config,
+) -> ExitCode {
+    let text = match read_aozora_file(path, encoding) {
+        Ok((t, _)) => t,
+        Err(e) => {
+            report_error(&format!("could not read file: {}", e), path, MessageFormat::Human);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut converted = match convert(text, to, config) {
+        Ok(converted) => converted,
+        Err(e) => {
+            report_error(&e.to_string(), path, MessageFormat::Human);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if !converted.ends_with('\n') {
+        converted.push('\n');
+    }
+    let result = match output {
+        None => io::stdout().write_all(converted.as_bytes()),
+        Some(output) if is_stdio(output) => io::stdout().write_all(converted.as_bytes()),
+        Some(output) => match output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            Some(dir) => fs::create_dir_all(dir).and_then(|()| fs::write(output, &converted)),
+            None => fs::write(output, &converted),
+        },
+    };
+    if let Err(e) = result {
+        print_error(&format!("could not write output: {}", e));
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+/// Render the text in `to`, with the title, author, language and writing mode of the
+/// configuration file applied.
+fn convert(text: String, to: ConvertFormat, config: &Config) -> Result<String, ConversionError> {
+    let doc = parse(parse_aozora(text)?);
+    #[cfg(feature = "serde")]
+    if to == ConvertFormat::Json {
+        return Ok(document_json(&doc));
+    }
+    let title = config.title.clone().unwrap_or(doc.metadata.title);
+    let author = config.author.clone().unwrap_or(doc.metadata.author);
+    let blocks = parse_blocks(doc.items)?;
+
+    let options = config.xhtml_options();
+    Ok(match to {
+        ConvertFormat::Xhtml => XhtmlGenerator::generate_with(&blocks, &title, &options).0,
+        ConvertFormat::Html => XhtmlGenerator::generate_fragment(&blocks, &options),
+        ConvertFormat::Txt => TextGenerator::generate_with(&blocks, &TextGeneratorOptions::default()),
+        ConvertFormat::Md => to_markdown(&blocks),
+        ConvertFormat::Latex => {
+            let latex_options = LatexGeneratorOptions { writing_mode: options.writing_mode, ..Default::default() };
+            LatexGenerator::generate_with(&blocks, &title, &author, &latex_options)
+        }
+        #[cfg(feature = "serde")]
+        ConvertFormat::Json => unreachable!("serialized before building blocks"),
+    })
+}
+
+fn serve_command(path: &Path, port: u16, encoding: TextEncoding, config: &Config) -> ExitCode {
+    if is_stdio(path) {
+        print_error("serve needs a file to watch, not stdin");
+        return ExitCode::FAILURE;
+    }
+    if !path.is_file() {
+        print_error(&format!("{} is not a file", path.display()));
+        return ExitCode::FAILURE;
+    }
+    let mut stylesheet = default_css();
+    if let Some(theme) = &config.theme {
+        stylesheet.push('\n');
+        stylesheet.push_str(theme);
+    }
+    let render = || {
+        let (text, _) = read_aozora_file(path, encoding).map_err(|e| format!("could not read file: {}", e))?;
+        preview(text, config).map_err(|e| e.to_string())
+    };
+    match serve::serve(path, port, &stylesheet, render) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            print_error(&e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Render the text as an HTML fragment for `karp serve`, with the settings of the
+/// configuration file applied as in [`convert`].
+fn preview(text: String, config: &Config) -> Result<serve::Preview, ConversionError> {
+    let doc = parse(parse_aozora(text)?);
+    let title = config.title.clone().unwrap_or(doc.metadata.title);
+    let blocks = parse_blocks(doc.items)?;
+    let options = config.xhtml_options();
+    Ok(serve::Preview {
+        title,
+        root_class: options.writing_mode.class_name().to_string(),
+        html: XhtmlGenerator::generate_fragment(&blocks, &options),
+    })
+}
+
+fn toc_command(path: &Path, format: OutputFormat, encoding: TextEncoding) -> ExitCode {
+    let text = match read_aozora_file(path, encoding) {
+        Ok((t, _)) => t,
+        Err(e) => {
+            report_error(&format!("could not read file: {}", e), path, MessageFormat::Human);
+            return ExitCode::FAILURE;
+        }
+    };
+    let toc = match headings(&text) {
+        Ok(toc) => toc,
+        Err(e) => {
+            report_error(&e.to_string(), path, MessageFormat::Human);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match format {
+        OutputFormat::Human => {}
+        #[cfg(feature = "serde")]
+        OutputFormat::Json => {
+            print_json_toc(&text, &toc);
+            return ExitCode::SUCCESS;
+        }
+    }
+    if toc.is_empty() {
+        println!("no headings in {}", path.display());
+    }
+    for entry in &toc {
+        let (line, column) = line_column(&text, entry.span.start);
+        let size = match entry.level {
+            2 => "大",
+            3 => "中",
+            _ => "小",
+        };
+        println!(
+            "{}\x1b[1;32m{}\x1b[0m {}{}  \x1b[1;34m-->\x1b[0m {}:{}:{}",
+            "  ".repeat(entry.level.saturating_sub(2) as usize),
+            size,
+            entry.text,
+            if entry.is_window { " (窓)" } else { "" },
+            path.display(),
+            line,
+            column
+        );
+    }
+    ExitCode::SUCCESS
+}
+
+/// The headings of the text, as collected for the table of contents of the EPUB.
+fn headings(text: &str) -> Result<Vec<TocEntry>, ConversionError> {
+    let doc = parse(parse_aozora(text.to_string())?);
+    let blocks = parse_blocks(doc.items)?;
+    let (_, toc) = XhtmlGenerator::generate(&blocks, &doc.metadata.title);
+    Ok(toc)
+}
+
+/// Line and column of character offset `pos` in `text`, both starting at 1.
+fn line_column(text: &str, pos: usize) -> (usize, usize) {
+    let before: Vec<char> = text.chars().take(pos).collect();
+    let line_start = before.iter().rposition(|&c| c == '\n').map_or(0, |i| i + 1);
+    let line = before.iter().filter(|&&c| c == '\n').count() + 1;
+    (line, pos - line_start + 1)
+}
+
+fn inspect_command(path: &Path, text: bool, format: OutputFormat) -> ExitCode {
+    let bytes = if is_stdio(path) {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes).map(|_| bytes)
+    } else {
+        fs::read(path)
+    };
+    let bytes = match bytes {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            report_error(&format!("could not read file: {}", e), path, MessageFormat::Human);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if text {
+        return match extract_epub_text(&bytes) {
+            Ok(text) => match io::stdout().write_all(text.as_bytes()) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    print_error(&format!("could not write output: {}", e));
+                    ExitCode::FAILURE
+                }
+            },
+            Err(e) => {
+                report_error(&format!("not a readable EPUB: {}", e), path, MessageFormat::Human);
+                ExitCode::FAILURE
+            }
+        };
+    }
+    let info = match inspect_epub(&bytes) {
+        Ok(info) => info,
+        Err(e) => {
+            report_error(&format!("not a readable EPUB: {}", e), path, MessageFormat::Human);
+            return ExitCode::FAILURE;
+        }
+    };
+    match format {
+        OutputFormat::Human => print_epub_info(&info),
+        #[cfg(feature = "serde")]
+        OutputFormat::Json => print_json_epub_info(&info),
+    }
+    ExitCode::SUCCESS
+}
+
+fn print_epub_info(info: &EpubInfo) {
+    let heading = |title: &str| println!("\x1b[1;32m{}\x1b[0m", title);
+
+    heading("Package");
+    println!("    {}", info.package);
+
+    heading("Metadata");
+    let width = info.metadata.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    for (key, value) in &info.metadata {
+        println!("    {:<width$}  {}", key, value, width = width);
+    }
+
+    heading(&format!("Files ({})", info.files.len()));
+    let width = info.files.iter().map(|f| f.size.to_string().len()).max().unwrap_or(0);
+    for file in &info.files {
+        println!(
+            "    {:>width$}  {}{}",
+            file.size,
+            file.path,
+            file.media_type.as_ref().map_or(String::new(), |t| format!("  \x1b[2m{}\x1b[0m", t)),
+            width = width
+        );
+    }
+
+    heading("Spine");
+    let width = info.spine.len().to_string().len();
+    for (i, path) in info.spine.iter().enumerate() {
+        println!("    {:>width$}. {}", i + 1, path, width = width);
+    }
+
+    heading("Table of contents");
+    if info.toc.is_empty() {
+        println!("    (none)");
+    }
+    for entry in &info.toc {
+        println!(
+            "    {}{}  \x1b[1;34m-->\x1b[0m {}",
+            "  ".repeat(entry.depth.saturating_sub(1)),
+            entry.text,
+            entry.href
+        );
+    }
+}
+
+fn init_command(name: &Path, author: &str) -> ExitCode {
+    match init::init_project(name, author) {
+        Ok(manuscript) => {
+            println!("     \x1b[1;32mCreated\x1b[0m project `{}`", name.display());
+            println!("        \x1b[1;32mEdit\x1b[0m {}", manuscript.display());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            print_error(&e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Where to write the EPUB: `output` as given, the source file name in `out_dir`, or next
+/// to the source.
+fn output_path(path: &Path, output: Option<&Path>, out_dir: Option<&Path>) -> PathBuf {
+    match (output, out_dir) {
+        (Some(output), _) => output.to_path_buf(),
+        (None, Some(dir)) => dir.join(path.with_extension("epub").file_name().unwrap_or_default()),
+        (None, None) => path.with_extension("epub"),
+    }
+}
+
+/// Build one file, printing its diagnostics. Returns whether the EPUB was written.
+fn build_command(
+    path: &Path,
+    output_path: &Path,
+    encoding: TextEncoding,
+    message_format: MessageFormat,
+    config: &Config,
+) -> bool {
+    let human = message_format == MessageFormat::Human;
+    if human {
+        println!("   \x1b[1;32mCompiling\x1b[0m {}", path.display());
+    }
+
+    // Read and decode file
+    let text = match read_aozora_file(path, encoding) {
+        Ok((t, _)) => t,
+        Err(e) => {
+            report_error(&format!("could not read file: {}", e), path, message_format);
+            return false;
+        }
+    };
+
+    // Run linter and collect warnings
+    let warnings = match run_lint(&text, config) {
+        Ok(w) => w,
+        Err(e) => {
+            report_error(&e.to_string(), path, message_format);
+            return false;
+        }
+    };
+
+    // Print warnings
+    let error_count = warnings.iter().filter(|w| w.severity == Severity::Error).count();
+    match message_format {
+        MessageFormat::Human => {
+            print_warnings(&text, &warnings, path);
+        }
+        #[cfg(feature = "serde")]
+        MessageFormat::Json => print_json_diagnostics(&text, &warnings, path),
+    }
+
+    if error_count > 0 {
+        if human {
+            print_summary(error_count, warnings.len() - error_count, true);
+        }
+        return false;
+    }
+
+    // Generate EPUB
+    if let Some(dir) = output_path.parent().filter(|dir| !dir.as_os_str().is_empty())
+        && let Err(e) = fs::create_dir_all(dir)
+    {
+        report_error(&format!("could not create directory {}: {}", dir.display(), e), path, message_format);
+        return false;
+    }
+    match build_epub(text, output_path, config) {
+        Ok(()) => {
+            if human {
+                if !warnings.is_empty() {
+                    print_summary(0, warnings.len(), false);
+                }
+                println!("    \x1b[1;32mFinished\x1b[0m {}", output_path.display());
+            }
+            true
+        }
+        Err(e) => {
+            report_error(&e.to_string(), path, message_format);
+            false
+        }
+    }
+}
+
+/// Text files to build when `path` is a directory (searched recursively) or a glob, or
+/// `None` for a single file.
+fn find_sources(path: &Path) -> Result<Option<Vec<PathBuf>>, String> {
+    let mut sources = Vec::new();
+    if path.is_dir() {
+        collect_texts(path, &mut sources).map_err(|e| format!("could not read directory: {}", e))?;
+    } else if !path.exists() && path.to_string_lossy().contains(['*', '?', '[']) {
+        let pattern = path.to_string_lossy();
+        let paths = glob::glob(&pattern).map_err(|e| format!("invalid glob `{}`: {}", pattern, e))?;
+        sources.extend(paths.filter_map(Result::ok).filter(|p| p.is_file()));
+    } else {
+        return Ok(None);
+    }
+    if sources.is_empty() {
+        return Err(format!("no text files found in {}", path.display()));
+    }
+    Ok(Some(sources))
+}
+
+fn collect_texts(dir: &Path, sources: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.map(|e| e.map(|e| e.path())).collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            collect_texts(&entry, sources)?;
+        } else if entry.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("txt")) {
+            sources.push(entry);
+        }
+    }
+    Ok(())
+}
+
+/// Outcome of building one file of a batch.
+enum BuildStatus {
+    Built { warnings: usize },
+    Failed(String),
+}
+
+/// A file of a batch, built on a worker thread and reported by the main thread.
+struct BuiltFile {
+    /// Index in the sources
+    index: usize,
+    output_path: PathBuf,
+    status: BuildStatus,
+    /// The text and its warnings, for JSON diagnostics; `None` if it could not be linted
+    #[cfg_attr(not(feature = "serde"), allow(dead_code))]
+    lint: Option<(String, Vec<LintWarning>)>,
+}
+
+/// Build several files on `jobs` threads, printing a line per file as it finishes
+/// instead of the full diagnostics (or, with JSON messages, the diagnostics and a
+/// `build-finished` message for each file), and the failures again at the end.
+/// With `out_dir`, files found in a directory keep their relative paths under it.
+fn batch_command(
+    root: &Path,
+    sources: &[PathBuf],
+    out_dir: Option<&Path>,
+    jobs: usize,
+    encoding: TextEncoding,
+    message_format: MessageFormat,
+    config: &Config,
+) -> ExitCode {
+    let human = message_format == MessageFormat::Human;
+    let jobs = jobs.clamp(1, sources.len().max(1));
+    if human {
+        println!(
+            "    \x1b[1;32mBuilding\x1b[0m {} file{} ({} job{})",
+            sources.len(),
+            if sources.len() == 1 { "" } else { "s" },
+            jobs,
+            if jobs == 1 { "" } else { "s" }
+        );
+    }
+
+    // Workers take the next file until none is left and send back what they built
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    let mut failures = Vec::new();
+    let mut warning_count = 0;
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let (sender, next) = (sender.clone(), &next);
+            scope.spawn(move || {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(source) = sources.get(index) else { break };
+                    let output_path = match (out_dir, source.strip_prefix(root)) {
+                        (Some(dir), Ok(relative)) => dir.join(relative).with_extension("epub"),
+                        _ => output_path(source, None, out_dir),
+                    };
+                    let (status, lint) = build_quietly(source, &output_path, encoding, config);
+                    if sender.send(BuiltFile { index, output_path, status, lint }).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        for (done, file) in receiver.iter().enumerate() {
+            let source = &sources[file.index];
+            #[cfg(feature = "serde")]
+            if message_format == MessageFormat::Json {
+                if let Some((text, warnings)) = &file.lint {
+                    print_json_diagnostics(text, warnings, source);
+                }
+                if let BuildStatus::Failed(reason) = &file.status {
+                    print_json_error(reason, source);
+                }
+                print_json_build_finished(source, &file.output_path, matches!(file.status, BuildStatus::Built { .. }));
+            }
+            let progress = format!("[{:>width$}/{}]", done + 1, sources.len(), width = sources.len().to_string().len());
+            match file.status {
+                BuildStatus::Built { warnings } => {
+                    warning_count += warnings;
+                    if !human {
+                        continue;
+                    }
+                    let status = if warnings > 0 {
+                        format!("\x1b[1;33m{:>8}\x1b[0m", format!("{} warn", warnings))
+                    } else {
+                        format!("\x1b[1;32m{:>8}\x1b[0m", "ok")
+                    };
+                    println!("{} {} {} -> {}", status, progress, source.display(), file.output_path.display());
+                }
+                BuildStatus::Failed(reason) => {
+                    if human {
+                        println!("\x1b[1;31m{:>8}\x1b[0m {} {}: {}", "failed", progress, source.display(), reason);
+                    }
+                    failures.push((file.index, reason));
+                }
+            }
+        }
+    });
+
+    let failed = failures.len();
+    if !human {
+        return if failed > 0 { ExitCode::FAILURE } else { ExitCode::SUCCESS };
+    }
+    if failed > 0 {
+        // Lines of failed files are easy to miss among many others
+        failures.sort_by_key(|(index, _)| *index);
+        println!("\n\x1b[1;31mfailures\x1b[0m:");
+        for (index, reason) in &failures {
+            println!("    {}: {}", sources[*index].display(), reason);
+        }
+        println!();
+    }
+    println!(
+        "    \x1b[1;32mFinished\x1b[0m {} of {} file{} built{}{}",
+        sources.len() - failed,
+        sources.len(),
+        if sources.len() == 1 { "" } else { "s" },
+        if failed > 0 { format!(", {} failed", failed) } else { String::new() },
+        if warning_count > 0 {
+            format!(", {} warning{}", warning_count, if warning_count == 1 { "" } else { "s" })
+        } else {
+            String::new()
+        }
+    );
+    if failed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Build one file of a batch without printing anything. Returns the outcome together with
+/// the text and its warnings, once it got as far as linting.
+fn build_quietly(
+    path: &Path,
+    output_path: &Path,
+    encoding: TextEncoding,
+    config: &Config,
+) -> (BuildStatus, Option<(String, Vec<LintWarning>)>) {
+    let text = match read_aozora_file(path, encoding) {
+        Ok((t, _)) => t,
+        Err(e) => return (BuildStatus::Failed(format!("could not read file: {}", e)), None),
+    };
+    let warnings = match run_lint(&text, config) {
+        Ok(w) => w,
+        Err(e) => return (BuildStatus::Failed(e.to_string()), None),
+    };
+    let errors = warnings.iter().filter(|w| w.severity == Severity::Error).count();
+    let status = if errors > 0 {
+        BuildStatus::Failed(format!(
+            "{} error{} (run `karp check` for details)",
+            errors,
+            if errors == 1 { "" } else { "s" }
+        ))
+    } else if let Some(dir) = output_path.parent().filter(|dir| !dir.as_os_str().is_empty())
+        && let Err(e) = fs::create_dir_all(dir)
+    {
+        BuildStatus::Failed(format!("could not create directory {}: {}", dir.display(), e))
+    } else {
+        match build_epub(text.clone(), output_path, config) {
+            Ok(()) => BuildStatus::Built { warnings: warnings.len() },
+            Err(e) => BuildStatus::Failed(e.to_string()),
+        }
+    };
+    (status, Some((text, warnings)))
+}
+
+fn check_command(
+    path: &Path,
+    fix: bool,
+    encoding: TextEncoding,
+    message_format: MessageFormat,
+    config: &Config,
+) -> ExitCode {
+    // Fixing stdin prints the fixed text, which leaves no room for the diagnostics
+    let to_stdout = fix && is_stdio(path);
+    let human = message_format == MessageFormat::Human && !to_stdout;
+    if to_stdout && message_format != MessageFormat::Human {
+        print_error("JSON output cannot be combined with fixing stdin");
+        return ExitCode::FAILURE;
+    }
+    if human {
+        println!("    \x1b[1;32mChecking\x1b[0m {}", path.display());
+    }
+
+    // Read and decode file
+    let (mut text, encoding, lossy) = match read_aozora_file_lossy(path, encoding) {
+        Ok(t) => t,
+        Err(e) => {
+            report_error(&format!("could not read file: {}", e), path, message_format);
+            return ExitCode::FAILURE;
+        }
+    };
+    // Writing the fixes would replace the undecodable bytes for good
+    if fix && lossy {
+        report_error(
+            "cannot fix a file with bytes that could not be decoded; pass --encoding to read it strictly",
+            path,
+            message_format,
+        );
+        return ExitCode::FAILURE;
+    }
+
+    // Run linter and collect warnings
+    let mut warnings = match run_lint(&text, config) {
+        Ok(w) => w,
+        Err(e) => {
+            report_error(&e.to_string(), path, message_format);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if fix {
+        let fixed_count = warnings.iter().filter(|w| w.fix.is_some()).count();
+        // The text is passed on even if there is nothing to fix
+        if fixed_count > 0 || to_stdout {
+            let fixed = apply_fixes(&text, &warnings);
+            if let Err(e) = write_aozora_file(path, &fixed, encoding) {
+                report_error(&format!("could not write file: {}", e), path, message_format);
+                return ExitCode::FAILURE;
+            }
+            if human {
+                println!(
+                    "       \x1b[1;32mFixed\x1b[0m {} issue{}",
+                    fixed_count,
+                    if fixed_count == 1 { "" } else { "s" }
+                );
+            }
+            // Report what is left after fixing
+            warnings = match run_lint(&fixed, config) {
+                Ok(w) => w,
+                Err(e) => {
+                    report_error(&e.to_string(), path, message_format);
+                    return ExitCode::FAILURE;
+                }
+            };
+            text = fixed;
+        }
+    }
+
+    let error_count = warnings.iter().filter(|w| w.severity == Severity::Error).count();
+    match message_format {
+        // Only the exit status tells about what is left
+        _ if to_stdout => {}
+        MessageFormat::Human => {
+            print_warnings(&text, &warnings, path);
+            print_summary(error_count, warnings.len() - error_count, error_count > 0);
+        }
+        #[cfg(feature = "serde")]
+        MessageFormat::Json => print_json_diagnostics(&text, &warnings, path),
+    }
+
+    if error_count > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Read and decode a text file. Returns the text and the encoding it was read in, never
+/// `Auto`. A detected encoding decodes invalid bytes as U+FFFD; an explicit one rejects them.
+fn read_aozora_file(path: &Path, encoding: TextEncoding) -> Result<(String, TextEncoding), String> {
+    read_aozora_file_lossy(path, encoding).map(|(text, encoding, _)| (text, encoding))
+}
+
+/// Like [`read_aozora_file`], also telling whether invalid bytes were replaced with U+FFFD.
+fn read_aozora_file_lossy(path: &Path, encoding: TextEncoding) -> Result<(String, TextEncoding, bool), String> {
+    let bytes = if is_stdio(path) {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+        bytes
+    } else {
+        fs::read(path).map_err(|e| e.to_string())?
+    };
+    let (encoding, strict) = match encoding {
+        TextEncoding::Auto => (detect_encoding(&bytes), false),
+        encoding => (encoding, true),
+    };
+    let (text, had_errors) = match encoding {
+        TextEncoding::Sjis => SHIFT_JIS.decode_without_bom_handling(&bytes),
+        _ => UTF_8.decode_with_bom_removal(&bytes),
+    };
+    if had_errors && strict {
+        return Err(match encoding {
+            TextEncoding::Sjis => "file is not valid Shift_JIS".to_string(),
+            _ => "file is not valid UTF-8".to_string(),
+        });
+    }
+    Ok((text.into_owned(), encoding, had_errors))
+}
+
+/// Guess the encoding of a text. A UTF-8 BOM or valid UTF-8 means UTF-8: Shift_JIS
+/// Japanese text is practically never valid UTF-8, since its lead bytes are UTF-8
+/// continuation bytes. Otherwise the encoding that decodes with fewer errors wins, with
+/// Shift_JIS on a tie.
+fn detect_encoding(bytes: &[u8]) -> TextEncoding {
+    if bytes.starts_with(b"\xEF\xBB\xBF") || std::str::from_utf8(bytes).is_ok() {
+        return TextEncoding::Utf8;
+    }
+    let utf8_errors = bytes.utf8_chunks().filter(|chunk| !chunk.invalid().is_empty()).count();
+    let (sjis, _) = SHIFT_JIS.decode_without_bom_handling(bytes);
+    let sjis_errors = sjis.matches('\u{FFFD}').count();
+    if utf8_errors < sjis_errors {
+        TextEncoding::Utf8
+    } else {
+        TextEncoding::Sjis
+    }
+}
+
+/// Write text back in the encoding it was read in, keeping a UTF-8 BOM if the file had one.
+/// Text read from stdin goes to stdout.
+fn write_aozora_file(path: &Path, text: &str, encoding: TextEncoding) -> Result<(), String> {
+    let bytes = if encoding == TextEncoding::Sjis {
+        let (cow, _, had_errors) = SHIFT_JIS.encode(text);
+        if had_errors {
+            return Err("text cannot be encoded as Shift_JIS".to_string());
+        }
+        cow.into_owned()
+    } else if is_stdio(path) {
+        text.as_bytes().to_vec()
+    } else {
+        let original = fs::read(path).map_err(|e| e.to_string())?;
+        let bom: &[u8] = if original.starts_with(b"\xEF\xBB\xBF") { b"\xEF\xBB\xBF" } else { b"" };
+        [bom, text.as_bytes()].concat()
+    };
+    if is_stdio(path) {
+        return io::stdout().write_all(&bytes).map_err(|e| e.to_string());
+    }
+    fs::write(path, bytes).map_err(|e| e.to_string())
+}
+
+/// Whether `path` stands for stdin (or stdout, as an output).
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+fn run_lint(text: &str, config: &Config) -> Result<Vec<LintWarning>, ConversionError> {
+    let tokens = parse_aozora(text.to_string())?;
+    let doc = parse(tokens);
+    let output = parse_blocks_with(doc.items, &BlockParseOptions::default())?;
+    let diagnostics = ParserDiagnostics { parse: &doc.diagnostics, blocks: &output.diagnostics };
+    let mut warnings = lint_with_diagnostics(output.block, text, diagnostics, &config.lint).warnings;
+    match config.warnings {
+        WarningsLevel::Allow => warnings.retain(|w| w.severity != Severity::Warning),
+        WarningsLevel::Warn => {}
+        WarningsLevel::Deny => {
+            for w in warnings.iter_mut().filter(|w| w.severity == Severity::Warning) {
+                w.severity = Severity::Error;
+            }
+        }
+    }
+    Ok(warnings)
+}
+
+/// Convert to EPUB as `text_to_epub` does, applying the settings of the configuration file.
+fn build_epub(text: String, output_path: &Path, config: &Config) -> Result<(), ConversionError> {
+    let mut generator = text_to_epub_generator(text)?
+        .with_metadata(config.metadata.clone())
+        .with_xhtml_options(config.xhtml_options())
+        .with_okuzuke(config.okuzuke);
+    if let Some(title) = &config.title {
+        generator = generator.with_title(title);
+    }
+    if let Some(author) = &config.author {
+        generator = generator.with_creator(author);
+    }
+    if let Some(subtitle) = &config.subtitle {
+        generator = generator.with_subtitle(subtitle);
+    }
+    if let Some(theme) = &config.theme {
+        generator = generator.with_stylesheet(theme.clone());
+    }
+    if let Some(cover) = &config.cover {
+        generator = generator.with_cover_image(cover);
+    }
+    generator.write_to_file(output_path)?;
+    Ok(())
+}
+
+fn print_warnings(text: &str, warnings: &[LintWarning], path: &Path) -> usize {
+    let mut error_count = 0;
+    let lines: Vec<&str> = text.split('\n').collect();
+
+    for (w, entry) in warnings.iter().zip(lint_report(text, warnings)) {
+        let (color, label) = match w.severity {
+            Severity::Error => {
+                error_count += 1;
+                ("\x1b[1;31m", "error")
+            }
+            Severity::Warning => ("\x1b[1;33m", "warning"),
+            Severity::Info => ("\x1b[1;36m", "info"),
+        };
+
+        println!(
+            "{}{}\x1b[0m: {}",
+            color, label, w.message
+        );
+        println!(
+            "  \x1b[1;34m-->\x1b[0m {}:{}:{}",
+            path.display(),
+            entry.line,
+            entry.column
+        );
+        if let Some(line) = lines.get(entry.line - 1) {
+            // Spans reaching past the end of the line are underlined up to it
+            let len = (w.span.end - w.span.start).max(1);
+            print_code_frame(entry.line, line, entry.column - 1, len, color);
+        }
+        println!();
+    }
+
+    error_count
+}
+
+/// Print the line containing a warning with the span underlined, in the style of rustc.
+/// `start` and `len` are in characters.
+fn print_code_frame(line_number: usize, line: &str, start: usize, len: usize, color: &str) {
+    let line = line.trim_end_matches('\r');
+    let gutter = " ".repeat(line_number.to_string().len());
+    let prefix: usize = line.chars().take(start).map(display_width).sum();
+    let underline: usize = line.chars().skip(start).take(len).map(display_width).sum();
+
+    println!("{} \x1b[1;34m|\x1b[0m", gutter);
+    println!("\x1b[1;34m{} |\x1b[0m {}", line_number, line);
+    println!(
+        "{} \x1b[1;34m|\x1b[0m {}{}{}\x1b[0m",
+        gutter,
+        " ".repeat(prefix),
+        color,
+        "^".repeat(underline.max(1))
+    );
+}
+
+/// Terminal columns taken by a character: full-width characters take two.
+fn display_width(c: char) -> usize {
+    match c {
+        c if c.is_ascii() => 1,
+        // Half-width katakana and symbols
+        '\u{FF61}'..='\u{FF9F}' => 1,
+        _ => 2,
+    }
+}
+
+/// Print one `diagnostic` message per warning, for `--message-format=json`.
+#[cfg(feature = "serde")]
+fn print_json_diagnostics(text: &str, warnings: &[LintWarning], path: &Path) {
+    for entry in lint_report(text, warnings) {
+        let mut message = serde_json::to_value(entry).expect("lint report is always serializable");
+        message["reason"] = "diagnostic".into();
+        message["file"] = path.display().to_string().into();
+        println!("{}", message);
+    }
+}
+
+#[cfg(feature = "serde")]
+fn print_json_error(message: &str, path: &Path) {
+    println!("{}", serde_json::json!({ "reason": "error", "file": path.display().to_string(), "message": message }));
+}
+
+#[cfg(feature = "serde")]
+fn print_json_build_finished(path: &Path, output_path: &Path, success: bool) {
+    println!(
+        "{}",
+        serde_json::json!({
+            "reason": "build-finished",
+            "file": path.display().to_string(),
+            "output": output_path.display().to_string(),
+            "success": success,
+        })
+    );
+}
+
+/// Print the headings as a JSON array with their level, text, anchor id, span and
+/// line/column.
+#[cfg(feature = "serde")]
+fn print_json_toc(text: &str, toc: &[TocEntry]) {
+    let entries: Vec<serde_json::Value> = toc
+        .iter()
+        .map(|entry| {
+            let (line, column) = line_column(text, entry.span.start);
+            let mut value = serde_json::to_value(entry).expect("TOC entries are always serializable");
+            value["line"] = line.into();
+            value["column"] = column.into();
+            value
+        })
+        .collect();
+    println!("{}", serde_json::to_string_pretty(&entries).expect("TOC entries are always serializable"));
+}
+
+#[cfg(feature = "serde")]
+fn print_json_epub_info(info: &EpubInfo) {
+    println!("{}", serde_json::to_string_pretty(info).expect("EPUB info is always serializable"));
+}
+
+#[cfg(feature = "serde")]
+fn document_json(doc: &aozora_parser::AozoraDocument) -> String {
+    let mut json = serde_json::to_string_pretty(doc).expect("documents are always serializable");
+    json.push('\n');
+    json
+}
+
+fn print_summary(errors: usize, warnings: usize, is_error: bool) {
+    if is_error {
+        print!("\x1b[1;31merror\x1b[0m: ");
+        println!(
+            "aborting due to {} error{}{}",
+            errors,
+            if errors == 1 { "" } else { "s" },
+            if warnings > 0 {
+                format!("; {} warning{} emitted", warnings, if warnings == 1 { "" } else { "s" })
+            } else {
+                String::new()
+            }
+        );
+    } else if warnings > 0 {
+        println!(
+            "\x1b[1;33mwarning\x1b[0m: {} warning{} emitted",
+            warnings,
+            if warnings == 1 { "" } else { "s" }
+        );
+    }
+}
+
+/// Errors go to stderr, so that they do not end up in output written to stdout.
+fn print_error(msg: &str) {
+    eprintln!("\x1b[1;31merror\x1b[0m: {}", msg);
+}
+
+/// Print an error that is not a lint warning, such as a file that cannot be read.
+fn report_error(message: &str, path: &Path, message_format: MessageFormat) {
+    match message_format {
+        MessageFormat::Human => {
+            print_error(message);
+            eprintln!("  \x1b[1;34m-->\x1b[0m {}", path.display());
+        }
+        #[cfg(feature = "serde")]
+        MessageFormat::Json => print_json_error(message, path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Read `bytes` back through a temporary file.
+    fn read_bytes(name: &str, bytes: &[u8], encoding: TextEncoding) -> Result<(String, TextEncoding), String> {
+        let path = std::env::temp_dir().join(format!("karp_test_{}_{}.txt", std::process::id(), name));
+        fs::write(&path, bytes).unwrap();
+        let result = read_aozora_file(&path, encoding);
+        let _ = fs::remove_file(&path);
+        result
+    }
+
+    #[test]
+    fn test_read_utf8_with_bom() {
+        let bytes = [b"\xEF\xBB\xBF".as_slice(), "青空文庫".as_bytes()].concat();
+        assert_eq!(detect_encoding(&bytes), TextEncoding::Utf8);
+        // The BOM is not part of the text
+        assert_eq!(read_bytes("bom", &bytes, TextEncoding::Auto), Ok(("青空文庫".to_string(), TextEncoding::Utf8)));
+    }
+
+    #[test]
+    fn test_read_utf8_without_bom() {
+        let bytes = "青空文庫\n".as_bytes();
+        assert_eq!(detect_encoding(bytes), TextEncoding::Utf8);
+        assert_eq!(read_bytes("utf8", bytes, TextEncoding::Auto), Ok(("青空文庫\n".to_string(), TextEncoding::Utf8)));
+    }
+
+    #[test]
+    fn test_read_shift_jis() {
+        let (bytes, _, _) = SHIFT_JIS.encode("青空文庫《あおぞらぶんこ》\n");
+        assert_eq!(detect_encoding(&bytes), TextEncoding::Sjis);
+        assert_eq!(
+            read_bytes("sjis", &bytes, TextEncoding::Auto),
+            Ok(("青空文庫《あおぞらぶんこ》\n".to_string(), TextEncoding::Sjis))
+        );
+        // Read as UTF-8 when asked to, which it is not
+        assert_eq!(read_bytes("sjis_as_utf8", &bytes, TextEncoding::Utf8), Err("file is not valid UTF-8".to_string()));
+    }
+
+    #[test]
+    fn test_read_invalid_bytes() {
+        let bytes = [b"\xFF\xFF".as_slice(), "本文".as_bytes()].concat();
+        // A detected encoding replaces what it cannot decode
+        let (text, _) = read_bytes("invalid_auto", &bytes, TextEncoding::Auto).unwrap();
+        assert!(text.contains('\u{FFFD}'));
+        // ...and says so, so that `check --fix` does not write the replacements back
+        let path = std::env::temp_dir().join(format!("karp_test_{}_lossy.txt", std::process::id()));
+        fs::write(&path, &bytes).unwrap();
+        let lossy = read_aozora_file_lossy(&path, TextEncoding::Auto).map(|(_, _, lossy)| lossy);
+        let _ = fs::remove_file(&path);
+        assert_eq!(lossy, Ok(true));
+        // An explicit one rejects the file
+        assert_eq!(read_bytes("invalid_sjis", &bytes, TextEncoding::Sjis), Err("file is not valid Shift_JIS".to_string()));
+        assert_eq!(read_bytes("invalid_utf8", &bytes, TextEncoding::Utf8), Err("file is not valid UTF-8".to_string()));
+    }
+}
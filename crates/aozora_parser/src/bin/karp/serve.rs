@@ -0,0 +1,241 @@
+//! `karp serve`: preview a manuscript in the browser.
+//!
+//! A tiny HTTP server on localhost renders the text to HTML on every request, so the page
+//! always shows the file as it is on disk. The page polls `/__karp/version` (the
+//! modification time of the file) and reloads itself when the file changes. Other paths
+//! are served from the directory of the manuscript, for the images it refers to.
+
+use aozora_parser::escape_html;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// A rendered manuscript.
+pub struct Preview {
+    pub title: String,
+    /// Class of the writing mode (`vrtl` or `hltr`), set on the root so that the whole
+    /// page scrolls in the direction of the text
+    pub root_class: String,
+    /// The body as an HTML fragment
+    pub html: String,
+}
+
+const VERSION_PATH: &str = "/__karp/version";
+
+/// How long a connection may take to send its request before it is dropped.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Layout of the preview page on top of the book stylesheet.
+const PAGE_STYLE: &str = "body { margin: 0; padding: 2em; box-sizing: border-box; }
+.vrtl body { height: 100vh; }
+.karp-error { color: #b00; white-space: pre-wrap; writing-mode: horizontal-tb; }
+";
+
+/// Reload the page when the version reported by the server changes.
+const RELOAD_SCRIPT: &str = "<script>
+const version = \"{version}\";
+setInterval(async () => {
+  try {
+    const response = await fetch(\"{version_path}\");
+    if (await response.text() !== version) location.reload();
+  } catch (e) {}
+}, 1000);
+</script>";
+
+/// Serve `path` on `127.0.0.1:port` until the process is killed. Each connection is
+/// handled on its own thread, and `render` is called for every page request.
+pub fn serve(
+    path: &Path,
+    port: u16,
+    stylesheet: &str,
+    render: impl Fn() -> Result<Preview, String> + Sync,
+) -> Result<(), String> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|e| format!("could not listen on port {}: {}", port, e))?;
+    let root = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    println!("     \x1b[1;32mServing\x1b[0m {} at http://127.0.0.1:{}/", path.display(), port);
+    println!("             Press Ctrl-C to stop");
+
+    let render = &render;
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            // A connection that stays silent would otherwise keep its thread forever
+            if stream.set_read_timeout(Some(READ_TIMEOUT)).is_err() {
+                continue;
+            }
+            // A browser that goes away mid-response is not worth stopping the server for
+            scope.spawn(move || handle(stream, path, root, stylesheet, render));
+        }
+    });
+    Ok(())
+}
+
+fn handle(
+    mut stream: TcpStream,
+    path: &Path,
+    root: &Path,
+    stylesheet: &str,
+    render: &impl Fn() -> Result<Preview, String>,
+) -> std::io::Result<()> {
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(&stream);
+    reader.read_line(&mut request_line)?;
+    // The headers are not needed, but have to be read before responding
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or("/"));
+    let target = percent_decode(target.split(['?', '#']).next().unwrap_or("/"));
+    if method != "GET" {
+        return respond(&mut stream, "405 Method Not Allowed", "text/plain", b"method not allowed");
+    }
+
+    match target.as_str() {
+        "/" => {
+            let page = page(path, stylesheet, render);
+            respond(&mut stream, "200 OK", "text/html; charset=utf-8", page.as_bytes())
+        }
+        VERSION_PATH => respond(&mut stream, "200 OK", "text/plain", version(path).as_bytes()),
+        _ => match asset(root, &target) {
+            Some((bytes, content_type)) => respond(&mut stream, "200 OK", content_type, &bytes),
+            None => respond(&mut stream, "404 Not Found", "text/plain", b"not found"),
+        },
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+/// The preview page, or a page showing why the text could not be rendered.
+fn page(path: &Path, stylesheet: &str, render: &impl Fn() -> Result<Preview, String>) -> String {
+    // Taken before rendering, so that a change during rendering triggers another reload
+    let version = version(path);
+    let preview = render().unwrap_or_else(|e| {
+        eprintln!("\x1b[1;31merror\x1b[0m: {}", e);
+        Preview {
+            title: path.display().to_string(),
+            root_class: "hltr".to_string(),
+            html: format!("<p class=\"karp-error\">{}</p>", escape_html(&e)),
+        }
+    });
+    format!(
+        "<!DOCTYPE html>\n<html class=\"{}\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}\n{}</style>\n</head>\n<body>\n{}\n{}\n</body>\n</html>\n",
+        preview.root_class,
+        escape_html(&preview.title),
+        stylesheet,
+        PAGE_STYLE,
+        preview.html,
+        RELOAD_SCRIPT.replace("{version}", &version).replace("{version_path}", VERSION_PATH)
+    )
+}
+
+/// Modification time of the file, which changes whenever it is saved.
+fn version(path: &Path) -> String {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or_else(|| "missing".to_string(), |time| time.as_nanos().to_string())
+}
+
+/// A file below `root`, with its content type. Paths leaving `root` are refused.
+fn asset(root: &Path, target: &str) -> Option<(Vec<u8>, &'static str)> {
+    let relative = PathBuf::from(target.trim_start_matches('/'));
+    if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return None;
+    }
+    let file = root.join(&relative);
+    let content_type = match file.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "css" => "text/css",
+        _ => return None,
+    };
+    Some((fs::read(file).ok()?, content_type))
+}
+
+/// Decode `%XX` escapes, which browsers use for non-ASCII file names.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match (bytes[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `target` as the server resolves it below `root`.
+    fn request(root: &Path, target: &str) -> Option<Vec<u8>> {
+        asset(root, &percent_decode(target)).map(|(bytes, _)| bytes)
+    }
+
+    #[test]
+    fn test_asset_stays_below_root() {
+        let base = std::env::temp_dir().join(format!("karp_serve_{}", std::process::id()));
+        let root = base.join("root");
+        fs::create_dir_all(root.join("css")).unwrap();
+        fs::write(root.join("css/theme.css"), "p {}").unwrap();
+        fs::write(base.join("x.css"), "secret").unwrap();
+        let outside = base.join("x.css").to_string_lossy().into_owned();
+
+        let served = request(&root, "/css/theme.css");
+        let refused = [
+            request(&root, "/../x.css"),
+            request(&root, "../x.css"),
+            request(&root, "/%2e%2e/x.css"),
+            request(&root, "/css%2F..%2F..%2Fx.css"),
+            request(&root, "%2F../x.css"),
+            request(&root, &outside),
+        ];
+        let _ = fs::remove_dir_all(&base);
+
+        assert_eq!(served.as_deref(), Some(b"p {}".as_slice()));
+        assert!(refused.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("/%E6%8C%BF%E7%B5%B5.png"), "/挿絵.png");
+        assert_eq!(percent_decode("%2e%2E/x"), "../x");
+        // Malformed escapes are kept as they are
+        assert_eq!(percent_decode("%"), "%");
+        assert_eq!(percent_decode("a%4"), "a%4");
+        assert_eq!(percent_decode("%zz%"), "%zz%");
+        assert_eq!(percent_decode("%あ"), "%あ");
+        // A cut multi-byte sequence does not panic
+        assert_eq!(percent_decode("%E6%8C"), "\u{FFFD}");
+    }
+}
@@ -1,14 +1,16 @@
 use crate::parser::ParsedItem;
-use crate::tokenizer::command::{Command, CommandBegin, CommandEnd};
+use crate::tokenizer::command::{Command, CommandBegin, CommandEnd, Midashi, SingleCommand};
 use crate::tokenizer::Span;
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BlockElement {
     Item(ParsedItem),
     Block(AozoraBlock),
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AozoraBlock {
     pub decoration: Option<CommandBegin>, // None for Root
     pub elements: Vec<BlockElement>,
@@ -19,16 +21,179 @@ pub struct AozoraBlock {
 pub enum BlockParseError {
     UnexpectedEnd { end: CommandEnd, span: Span },
     UnclosedBlock { begin: CommandBegin, span: Span },
+    /// The end command does not match the innermost open block (strict mode only)
+    MismatchedBlockTags { begin: CommandBegin, begin_span: Span, end: CommandEnd, span: Span },
+}
+
+impl std::fmt::Display for BlockParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlockParseError::UnexpectedEnd { end, span } => {
+                write!(f, "{:?} at character {} closes no block", end, span.start)
+            }
+            BlockParseError::UnclosedBlock { begin, span } => {
+                write!(f, "{:?} at character {} is never closed", begin, span.start)
+            }
+            BlockParseError::MismatchedBlockTags { begin, begin_span, end, span } => write!(
+                f,
+                "{:?} at character {} is closed by {:?} at character {}",
+                begin, begin_span.start, end, span.start
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BlockParseError {}
+
+/// A recoverable problem found while building the block structure.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BlockDiagnostic {
+    /// The end command does not match the innermost open block.
+    /// `begin` is the block that was open, `end` the command that tried to close it.
+    MismatchedBlockTags { begin: CommandBegin, begin_span: Span, end: CommandEnd, span: Span },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BlockParseOptions {
+    /// Return an error instead of recovering from malformed block structure
+    pub strict: bool,
+}
+
+/// Block structure together with the problems recovered from while building it.
+#[derive(Debug, Clone)]
+pub struct BlockParseOutput {
+    pub block: AozoraBlock,
+    pub diagnostics: Vec<BlockDiagnostic>,
+}
+
+impl AozoraBlock {
+    /// Shift the spans of this block and everything in it by `offset` characters.
+    fn shift_spans(&mut self, offset: usize) {
+        self.span = self.span.shift(offset);
+        for elem in &mut self.elements {
+            match elem {
+                BlockElement::Item(item) => item.shift_spans(offset),
+                BlockElement::Block(block) => block.shift_spans(offset),
+            }
+        }
+    }
+
+    /// Append another block, e.g. the next chapter of a work.
+    ///
+    /// `source_len` is the length of the source text this block was parsed from
+    /// ([`AozoraDocument::end`](crate::parser::AozoraDocument::end)), header and trailer
+    /// included. The spans of `other` are shifted by it, as [`AozoraDocument::append`]
+    /// does, and the two are separated by a line break. A root block (no decoration) is
+    /// merged element by element; a decorated block is added as a nested block.
+    ///
+    /// [`AozoraDocument::append`]: crate::parser::AozoraDocument::append
+    pub fn append(&mut self, mut other: AozoraBlock, source_len: usize) {
+        let offset = source_len;
+        if let Some(BlockElement::Item(item)) = self.elements.last()
+            && !matches!(item, ParsedItem::Newline(_))
+        {
+            self.elements.push(BlockElement::Item(ParsedItem::Newline(Span::new(offset, offset))));
+        }
+        other.shift_spans(offset);
+        self.span = self.span.merge(&other.span);
+        if other.decoration.is_none() {
+            self.elements.append(&mut other.elements);
+        } else {
+            self.elements.push(BlockElement::Block(other));
+        }
+    }
+}
+
+/// Depth-first iterator over a block and all blocks nested in it.
+pub struct Blocks<'a> {
+    stack: Vec<&'a AozoraBlock>,
+}
+
+impl<'a> Iterator for Blocks<'a> {
+    type Item = &'a AozoraBlock;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let block = self.stack.pop()?;
+        // Push children in reverse so they are visited in document order
+        self.stack.extend(block.elements.iter().rev().filter_map(|e| match e {
+            BlockElement::Block(b) => Some(b),
+            BlockElement::Item(_) => None,
+        }));
+        Some(block)
+    }
+}
+
+/// Iterator over all items in a block, including those in nested blocks, in document order.
+pub struct Items<'a> {
+    stack: Vec<std::slice::Iter<'a, BlockElement>>,
+}
+
+impl<'a> Iterator for Items<'a> {
+    type Item = &'a ParsedItem;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.stack.last_mut()?.next() {
+                Some(BlockElement::Item(item)) => return Some(item),
+                Some(BlockElement::Block(block)) => self.stack.push(block.elements.iter()),
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+impl AozoraBlock {
+    /// This block and all blocks nested in it, depth-first in document order.
+    pub fn blocks(&self) -> Blocks<'_> {
+        Blocks { stack: vec![self] }
+    }
+
+    /// Blocks (including this one) whose decoration matches the predicate.
+    ///
+    /// ```ignore
+    /// let boxes = block.find_by_decoration(|d| matches!(d, CommandBegin::Kakomikei));
+    /// ```
+    pub fn find_by_decoration<P>(&self, predicate: P) -> impl Iterator<Item = &AozoraBlock>
+    where
+        P: Fn(&CommandBegin) -> bool,
+    {
+        self.blocks()
+            .filter(move |b| b.decoration.as_ref().is_some_and(&predicate))
+    }
+
+    /// All items in this block and nested blocks, in document order.
+    pub fn iter_items(&self) -> Items<'_> {
+        Items { stack: vec![self.elements.iter()] }
+    }
+
+    /// Heading blocks (見出し) in document order, with their heading type.
+    pub fn headings(&self) -> impl Iterator<Item = (&Midashi, &AozoraBlock)> {
+        self.blocks().filter_map(|b| match &b.decoration {
+            Some(CommandBegin::Midashi(m)) => Some((m, b)),
+            _ => None,
+        })
+    }
+
+    /// Plain text of this block (ruby and commands are left out, except the text of 縦中横).
+    pub fn text(&self) -> String {
+        self.iter_items()
+            .filter_map(|item| match item {
+                ParsedItem::Text(dt) => Some(dt.text.as_str()),
+                ParsedItem::Command { cmd: Command::SingleCommand(SingleCommand::Tatechuyoko(content)), .. } => {
+                    Some(content.as_str())
+                }
+                _ => None,
+            })
+            .collect()
+    }
 }
 
 /// Helper to get span from ParsedItem
 fn item_span(item: &ParsedItem) -> Span {
-    match item {
-        ParsedItem::Text(dt) => dt.span,
-        ParsedItem::Command { span, .. } => *span,
-        ParsedItem::Newline(span) => *span,
-        ParsedItem::SpecialCharacter { span, .. } => *span,
-    }
+    item.span()
 }
 
 /// Helper to get span from BlockElement
@@ -40,6 +205,29 @@ fn element_span(elem: &BlockElement) -> Span {
 }
 
 pub fn parse_blocks(items: Vec<ParsedItem>) -> Result<AozoraBlock, BlockParseError> {
+    parse_blocks_with(items, &BlockParseOptions::default()).map(|output| output.block)
+}
+
+/// Close the innermost open block with the given end span and add it to its parent.
+fn close_block(stack: &mut Vec<AozoraBlock>, end_span: Option<&Span>) {
+    let mut finished_block = stack.pop().unwrap();
+    if let Some(span) = end_span {
+        // Update span to include end command
+        finished_block.span = finished_block.span.merge(span);
+    }
+    if let Some(parent) = stack.last_mut() {
+        parent.elements.push(BlockElement::Block(finished_block));
+    }
+}
+
+/// Build the block structure with options.
+///
+/// In the default (lenient) mode, an end command that does not match the innermost
+/// open block closes the nearest matching block instead, and is reported in
+/// `BlockParseOutput::diagnostics`, and blocks left open at the end of the text are
+/// closed automatically. In strict mode both are errors.
+pub fn parse_blocks_with(items: Vec<ParsedItem>, options: &BlockParseOptions) -> Result<BlockParseOutput, BlockParseError> {
+    let mut diagnostics = Vec::new();
     let mut stack: Vec<AozoraBlock> = Vec::new();
     // Root block
     stack.push(AozoraBlock {
@@ -64,15 +252,37 @@ pub fn parse_blocks(items: Vec<ParsedItem>) -> Result<AozoraBlock, BlockParseErr
                 return Err(BlockParseError::UnexpectedEnd { end: end.clone(), span: *span });
             }
 
-            let mut finished_block = stack.pop().unwrap();
-            
-            // Update span to include end command
-            finished_block.span = finished_block.span.merge(span);
-            
-            // Add to parent
-            if let Some(parent) = stack.last_mut() {
-                parent.elements.push(BlockElement::Block(finished_block));
+            let top = stack.last().unwrap();
+            let begin = top.decoration.as_ref().unwrap();
+            if !begin.is_closed_by(end) {
+                if options.strict {
+                    return Err(BlockParseError::MismatchedBlockTags {
+                        begin: begin.clone(),
+                        begin_span: top.span,
+                        end: end.clone(),
+                        span: *span,
+                    });
+                }
+                diagnostics.push(BlockDiagnostic::MismatchedBlockTags {
+                    begin: begin.clone(),
+                    begin_span: top.span,
+                    end: end.clone(),
+                    span: *span,
+                });
+
+                // Close up to the nearest block this command matches; ignore it if there is none
+                let Some(depth) = stack
+                    .iter()
+                    .rposition(|b| b.decoration.as_ref().is_some_and(|d| d.is_closed_by(end)))
+                else {
+                    continue;
+                };
+                while stack.len() > depth + 1 {
+                    close_block(&mut stack, None);
+                }
             }
+
+            close_block(&mut stack, Some(span));
         } else {
             // Standard item
             if let Some(current_block) = stack.last_mut() {
@@ -82,11 +292,17 @@ pub fn parse_blocks(items: Vec<ParsedItem>) -> Result<AozoraBlock, BlockParseErr
     }
 
     // Auto-close any unclosed blocks (some Aozora documents don't explicitly close all blocks)
+    if options.strict
+        && let Some(unclosed) = stack.get(1)
+    {
+        // Report the outermost one, which was opened first
+        return Err(BlockParseError::UnclosedBlock {
+            begin: unclosed.decoration.clone().unwrap(),
+            span: unclosed.span,
+        });
+    }
     while stack.len() > 1 {
-        let finished_block = stack.pop().unwrap();
-        if let Some(parent) = stack.last_mut() {
-            parent.elements.push(BlockElement::Block(finished_block));
-        }
+        close_block(&mut stack, None);
     }
 
     // Calculate root span from elements
@@ -97,17 +313,14 @@ pub fn parse_blocks(items: Vec<ParsedItem>) -> Result<AozoraBlock, BlockParseErr
         root.span = first_span.merge(&last_span);
     }
 
-    Ok(root)
+    Ok(BlockParseOutput { block: root, diagnostics })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::parser::{DecoratedText, SpecialCharacter};
-    use crate::tokenizer::command::{
-        Alignment, Midashi, MidashiSize, MidashiType, SingleCommand,
-    };
-    use crate::tokenizer::{AozoraToken, TextToken, TextKind};
+    use crate::parser::DecoratedText;
+    use crate::tokenizer::command::{Alignment, Midashi, MidashiSize, MidashiType};
 
     fn make_text(s: &str) -> ParsedItem {
         ParsedItem::Text(DecoratedText {
@@ -117,6 +330,65 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_append_blocks() {
+        let first = parse_blocks(vec![ParsedItem::Text(DecoratedText {
+            text: "第一章".to_string(),
+            ruby: None,
+            span: Span::new(0, 3),
+        })]).unwrap();
+        let second = parse_blocks(vec![
+            ParsedItem::Command { cmd: Command::CommandBegin(CommandBegin::Kakomikei), span: Span::new(0, 9) },
+            ParsedItem::Text(DecoratedText { text: "第二章".to_string(), ruby: None, span: Span::new(9, 12) }),
+            ParsedItem::Command { cmd: Command::CommandEnd(CommandEnd::Kakomikei), span: Span::new(12, 23) },
+        ]).unwrap();
+
+        let mut merged = first;
+        merged.append(second, 3);
+
+        assert_eq!(merged.span, Span::new(0, 26));
+        assert_eq!(merged.elements.len(), 3);
+        assert_eq!(merged.elements[1], BlockElement::Item(ParsedItem::Newline(Span::new(3, 3))));
+        match &merged.elements[2] {
+            BlockElement::Block(b) => {
+                assert_eq!(b.span, Span::new(3, 26));
+                assert!(matches!(&b.elements[0], BlockElement::Item(ParsedItem::Text(t)) if t.span == Span::new(12, 15)));
+            }
+            _ => panic!("Expected nested block"),
+        }
+    }
+
+    #[test]
+    fn test_query_helpers() {
+        let large = Midashi { size: MidashiSize::Large, kind: MidashiType::Normal };
+        let items = vec![
+            ParsedItem::Command { cmd: Command::CommandBegin(CommandBegin::Midashi(large.clone())), span: Span::new(0, 5) },
+            make_text("第一章"),
+            ParsedItem::Command { cmd: Command::CommandEnd(CommandEnd::Midashi(large.clone())), span: Span::new(8, 15) },
+            ParsedItem::Command { cmd: Command::CommandBegin(CommandBegin::Kakomikei), span: Span::new(15, 20) },
+            make_text("本文"),
+            ParsedItem::Command { cmd: Command::CommandBegin(CommandBegin::Bold), span: Span::new(22, 25) },
+            make_text("強調"),
+            ParsedItem::Command { cmd: Command::CommandEnd(CommandEnd::Bold), span: Span::new(27, 30) },
+            ParsedItem::Command { cmd: Command::CommandEnd(CommandEnd::Kakomikei), span: Span::new(30, 35) },
+        ];
+        let root = parse_blocks(items).unwrap();
+
+        assert_eq!(root.blocks().count(), 4);
+        let texts: Vec<String> = root.iter_items().filter_map(|i| match i {
+            ParsedItem::Text(t) => Some(t.text.clone()),
+            _ => None,
+        }).collect();
+        assert_eq!(texts, vec!["第一章", "本文", "強調"]);
+
+        let boxes: Vec<_> = root.find_by_decoration(|d| matches!(d, CommandBegin::Kakomikei)).collect();
+        assert_eq!(boxes.len(), 1);
+        assert_eq!(boxes[0].text(), "本文強調");
+
+        let headings: Vec<_> = root.headings().map(|(m, b)| (m.size, b.text())).collect();
+        assert_eq!(headings, vec![(MidashiSize::Large, "第一章".to_string())]);
+    }
+
     #[test]
     fn test_simple_items() {
         let items = vec![make_text("abc"), make_text("def")];
@@ -195,6 +467,24 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_unclosed_block_strict() {
+        let items = vec![
+            ParsedItem::Command { cmd: Command::CommandBegin(CommandBegin::Yokogumi), span: Span::new(0, 5) },
+            ParsedItem::Command { cmd: Command::CommandBegin(CommandBegin::Kakomikei), span: Span::new(5, 10) },
+            make_text("oops"),
+        ];
+        let strict = BlockParseOptions { strict: true };
+        let res = parse_blocks_with(items.clone(), &strict);
+        assert_eq!(res.unwrap_err(), BlockParseError::UnclosedBlock {
+            begin: CommandBegin::Yokogumi,
+            span: Span::new(0, 5),
+        });
+
+        // Lenient mode still auto-closes
+        assert!(parse_blocks_with(items, &BlockParseOptions::default()).is_ok());
+    }
+
     #[test]
     fn test_unexpected_end_error() {
          let items = vec![
@@ -203,4 +493,68 @@ mod tests {
         let res = parse_blocks(items);
         assert!(matches!(res, Err(BlockParseError::UnexpectedEnd { .. })));
     }
+
+    #[test]
+    fn test_mismatched_end_closes_matching_block() {
+        // ［＃ここから罫囲み］［＃ここから横組み］... ［＃ここで罫囲み終わり］
+        let items = vec![
+            ParsedItem::Command { cmd: Command::CommandBegin(CommandBegin::Kakomikei), span: Span::new(0, 5) },
+            ParsedItem::Command { cmd: Command::CommandBegin(CommandBegin::Yokogumi), span: Span::new(5, 10) },
+            make_text("text"),
+            ParsedItem::Command { cmd: Command::CommandEnd(CommandEnd::Kakomikei), span: Span::new(14, 20) },
+            make_text("after"),
+        ];
+
+        let output = parse_blocks_with(items, &BlockParseOptions::default()).unwrap();
+        assert_eq!(output.diagnostics, vec![BlockDiagnostic::MismatchedBlockTags {
+            begin: CommandBegin::Yokogumi,
+            begin_span: Span::new(5, 10),
+            end: CommandEnd::Kakomikei,
+            span: Span::new(14, 20),
+        }]);
+
+        // The 罫囲み block is closed, with the 横組み block nested inside it
+        let root = output.block;
+        assert_eq!(root.elements.len(), 2);
+        match &root.elements[0] {
+            BlockElement::Block(b) => {
+                assert_eq!(b.decoration, Some(CommandBegin::Kakomikei));
+                assert_eq!(b.span, Span::new(0, 20));
+                assert!(matches!(&b.elements[0], BlockElement::Block(inner) if inner.decoration == Some(CommandBegin::Yokogumi)));
+            }
+            _ => panic!("Expected block"),
+        }
+    }
+
+    #[test]
+    fn test_mismatched_end_without_matching_block() {
+        let items = vec![
+            ParsedItem::Command { cmd: Command::CommandBegin(CommandBegin::Kakomikei), span: Span::new(0, 5) },
+            make_text("text"),
+            ParsedItem::Command { cmd: Command::CommandEnd(CommandEnd::Alignment), span: Span::new(9, 15) },
+            ParsedItem::Command { cmd: Command::CommandEnd(CommandEnd::Kakomikei), span: Span::new(15, 20) },
+        ];
+
+        let output = parse_blocks_with(items.clone(), &BlockParseOptions::default()).unwrap();
+        // The stray end is ignored and the block is closed by its own end
+        assert_eq!(output.diagnostics.len(), 1);
+        assert_eq!(output.block.elements.len(), 1);
+        assert!(matches!(&output.block.elements[0], BlockElement::Block(b) if b.span == Span::new(0, 20)));
+
+        let strict = BlockParseOptions { strict: true };
+        assert_eq!(parse_blocks_with(items, &strict).unwrap_err(), BlockParseError::MismatchedBlockTags {
+            begin: CommandBegin::Kakomikei,
+            begin_span: Span::new(0, 5),
+            end: CommandEnd::Alignment,
+            span: Span::new(9, 15),
+        });
+    }
+
+    #[test]
+    fn test_midashi_end_must_match_size() {
+        let midashi = |size| Midashi { size, kind: MidashiType::Normal };
+        assert!(CommandBegin::Midashi(midashi(MidashiSize::Large)).is_closed_by(&CommandEnd::Midashi(midashi(MidashiSize::Large))));
+        assert!(!CommandBegin::Midashi(midashi(MidashiSize::Large)).is_closed_by(&CommandEnd::Midashi(midashi(MidashiSize::Small))));
+        assert!(!CommandBegin::Alignment(Alignment { is_upper: true, space: 2 }).is_closed_by(&CommandEnd::Kakomikei));
+    }
 }
@@ -4,17 +4,17 @@ pub fn default_css() -> String {
 
     // Import order matches book-style.css imports
     css.push_str(include_str!("epub_template/css/style-reset.css"));
-    css.push_str("\n");
+    css.push('\n');
     css.push_str(include_str!("epub_template/css/style-standard.css"));
-    css.push_str("\n");
+    css.push('\n');
     css.push_str(include_str!("epub_template/css/style-advance.css"));
-    css.push_str("\n");
+    css.push('\n');
     css.push_str(include_str!("epub_template/css/aozora.css"));
-    css.push_str("\n");
+    css.push('\n');
     css.push_str(include_str!("epub_template/css/font.css"));
-    css.push_str("\n");
+    css.push('\n');
     css.push_str(include_str!("epub_template/css/text.css"));
-    css.push_str("\n");
+    css.push('\n');
     
     // book-style.css contains customizations. We should include it but remove the @imports
     // because we just inlined them. 
@@ -25,3 +25,107 @@ pub fn default_css() -> String {
     
     css
 }
+
+/// The default stylesheet with every class selector prefixed, matching the markup
+/// generated with `XhtmlGeneratorOptions::class_prefix` (e.g. `.jisage-2` becomes
+/// `.aozora-jisage-2` for the prefix `aozora-`).
+pub fn default_css_with_prefix(prefix: &str) -> String {
+    prefix_css_classes(&default_css(), prefix)
+}
+
+/// Prefix every class selector in a stylesheet.
+///
+/// Only selectors are rewritten; declarations, at-rule preludes (`@media (…)`),
+/// strings and comments are copied as they are.
+pub fn prefix_css_classes(css: &str, prefix: &str) -> String {
+    // Whether each open block holds rules (@media and the like) rather than declarations
+    let mut holds_rules: Vec<bool> = Vec::new();
+    let mut prelude = String::new();
+    let mut out = String::with_capacity(css.len());
+    let mut chars = css.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let in_declarations = holds_rules.last().is_some_and(|rules| !rules);
+        match c {
+            '/' if chars.peek() == Some(&'*') => {
+                out.push(c);
+                while let Some(c) = chars.next() {
+                    out.push(c);
+                    if c == '*' && chars.peek() == Some(&'/') {
+                        out.push(chars.next().unwrap());
+                        break;
+                    }
+                }
+            }
+            '"' | '\'' => {
+                out.push(c);
+                while let Some(d) = chars.next() {
+                    out.push(d);
+                    if d == '\\' {
+                        if let Some(escaped) = chars.next() {
+                            out.push(escaped);
+                        }
+                    } else if d == c {
+                        break;
+                    }
+                }
+            }
+            '{' => {
+                let at_rule = prelude.trim_start();
+                holds_rules.push(
+                    ["@media", "@supports", "@layer", "@container", "@document"]
+                        .iter()
+                        .any(|name| at_rule.starts_with(name)),
+                );
+                prelude.clear();
+                out.push(c);
+            }
+            '}' => {
+                holds_rules.pop();
+                prelude.clear();
+                out.push(c);
+            }
+            ';' if !in_declarations => {
+                prelude.clear();
+                out.push(c);
+            }
+            '.' if !in_declarations
+                && !prelude.trim_start().starts_with('@')
+                && chars.peek().is_some_and(|&n| n.is_alphabetic() || n == '_' || n == '-' || !n.is_ascii()) =>
+            {
+                prelude.push(c);
+                out.push(c);
+                out.push_str(prefix);
+            }
+            _ => {
+                if !in_declarations {
+                    prelude.push(c);
+                }
+                out.push(c);
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_css_classes() {
+        let css = "@import \"a.css\";\n/* .comment */\ndiv.jisage-2, .vrtl p:not(.em) { margin: 0.5em; }\n@media (min-width: 10.5em) { .hltr .main { font: 1em \"x.y\"; } }\n[title=\".x\"] { color: red }";
+        assert_eq!(
+            prefix_css_classes(css, "ao-"),
+            "@import \"a.css\";\n/* .comment */\ndiv.ao-jisage-2, .ao-vrtl p:not(.ao-em) { margin: 0.5em; }\n@media (min-width: 10.5em) { .ao-hltr .ao-main { font: 1em \"x.y\"; } }\n[title=\".x\"] { color: red }"
+        );
+    }
+
+    #[test]
+    fn test_default_css_with_prefix() {
+        let css = default_css_with_prefix("aozora-");
+        assert!(css.contains(".aozora-vrtl"));
+        assert!(!css.contains("\n.vrtl"));
+        assert_eq!(default_css_with_prefix(""), default_css());
+    }
+}
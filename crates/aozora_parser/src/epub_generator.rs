@@ -1,119 +1,935 @@
-use crate::block_parser::AozoraBlock;
-use crate::xhtml_generator::{XhtmlGenerator, TocEntry};
+use crate::block_parser::{parse_blocks, AozoraBlock, BlockParseError};
+use crate::epub_validator::{validate_epub, EpubValidationError};
+use crate::parser::{AozoraDocument, Colophon, ParsedItem};
+use crate::tokenizer::command::{Command, SingleCommand};
+use crate::xhtml_generator::{
+    escape_html, fill_template, NotePlacement, TocEntry, TocNode, TocTree, WritingMode, XhtmlGenerator, XhtmlGeneratorOptions,
+};
+use chrono::{DateTime, Datelike, Timelike, Utc};
 use std::fmt::Write as FmtWrite;
 use std::fs::File;
-use std::io::Write;
-use std::path::Path;
+use std::io::{Cursor, Seek, Write};
+use std::path::{Component, Path, PathBuf};
 use zip::write::SimpleFileOptions;
 use zip::ZipWriter;
 use uuid::Uuid;
 
-pub struct EpubGenerator {
+/// Optional Dublin Core metadata written to the OPF in addition to the title, creator and
+/// language.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EpubMetadata {
+    pub description: Option<String>,
+    pub publisher: Option<String>,
+    /// Publication date (`dc:date`), e.g. `1948-05-01`
+    pub date: Option<String>,
+    /// Subjects or keywords, one `dc:subject` each
+    pub subjects: Vec<String>,
+    pub rights: Option<String>,
+    /// Other `dc:` elements as (element name without the prefix, value),
+    /// e.g. `("contributor", "入力者名")`
+    pub custom: Vec<(String, String)>,
+}
+
+impl EpubMetadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn with_publisher(mut self, publisher: impl Into<String>) -> Self {
+        self.publisher = Some(publisher.into());
+        self
+    }
+
+    pub fn with_date(mut self, date: impl Into<String>) -> Self {
+        self.date = Some(date.into());
+        self
+    }
+
+    /// Add a subject. Can be called more than once.
+    pub fn with_subject(mut self, subject: impl Into<String>) -> Self {
+        self.subjects.push(subject.into());
+        self
+    }
+
+    pub fn with_rights(mut self, rights: impl Into<String>) -> Self {
+        self.rights = Some(rights.into());
+        self
+    }
+
+    /// Add an arbitrary `dc:` element, e.g. `with_dc("contributor", "入力者名")`.
+    /// Names that are not valid element names are ignored when writing the OPF.
+    pub fn with_dc(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.custom.push((name.into(), value.into()));
+        self
+    }
+
+    /// Render the metadata as `dc:` elements for the OPF `<metadata>` section.
+    fn to_opf(&self) -> String {
+        let mut elements: Vec<(&str, &str)> = Vec::new();
+        if let Some(description) = &self.description {
+            elements.push(("description", description));
+        }
+        if let Some(publisher) = &self.publisher {
+            elements.push(("publisher", publisher));
+        }
+        if let Some(date) = &self.date {
+            elements.push(("date", date));
+        }
+        for subject in &self.subjects {
+            elements.push(("subject", subject));
+        }
+        if let Some(rights) = &self.rights {
+            elements.push(("rights", rights));
+        }
+        for (name, value) in &self.custom {
+            let valid = name.starts_with(|c: char| c.is_ascii_alphabetic())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if valid {
+                elements.push((name, value));
+            }
+        }
+
+        let mut out = String::new();
+        for (name, value) in elements {
+            writeln!(out, "\t\t<dc:{0}>{1}</dc:{0}>", name, escape_html(value)).unwrap();
+        }
+        out
+    }
+}
+
+/// How a class of files is stored in the archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EpubCompression {
+    /// No compression; fastest, and sensible for already compressed files such as images
+    Stored,
+    /// Deflate, with a level from 0 (fastest) to 9 (smallest); `None` is the default level
+    Deflated(Option<i64>),
+}
+
+impl Default for EpubCompression {
+    fn default() -> Self {
+        EpubCompression::Deflated(None)
+    }
+}
+
+impl EpubCompression {
+    fn file_options(self, timestamp: zip::DateTime) -> SimpleFileOptions {
+        let (method, level) = match self {
+            EpubCompression::Stored => (zip::CompressionMethod::Stored, None),
+            EpubCompression::Deflated(level) => (zip::CompressionMethod::Deflated, level),
+        };
+        SimpleFileOptions::default()
+            .compression_method(method)
+            .compression_level(level)
+            .last_modified_time(timestamp)
+            .unix_permissions(0o755)
+    }
+}
+
+/// Compression of each class of files in the EPUB. The mimetype file is always stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EpubCompressionOptions {
+    /// XHTML, the package document, the NCX and container.xml
+    pub documents: EpubCompression,
+    /// Stylesheets
+    pub styles: EpubCompression,
+    /// Images (挿絵)
+    pub images: EpubCompression,
+}
+
+/// One work in the book: a plain book has one, an anthology one per document.
+struct Work {
     title: String,
     creator: String,
     blocks: AozoraBlock,
-    uuid: String,
+}
+
+pub struct EpubGenerator {
+    title: String,
+    creator: String,
+    works: Vec<Work>,
+    /// Whether the works are listed separately (title pages and TOC entries per work)
+    anthology: bool,
+    identifier: String,
+    modified: DateTime<Utc>,
+    xhtml_options: XhtmlGeneratorOptions,
+    metadata: EpubMetadata,
+    image_dir: Option<PathBuf>,
+    cover_image: Option<PathBuf>,
+    stylesheet: Option<String>,
+    title_page: bool,
+    subtitle: Option<String>,
+    okuzuke: bool,
+    okuzuke_template: Option<String>,
+    colophon: Option<Colophon>,
+    compression: EpubCompressionOptions,
 }
 
 impl EpubGenerator {
     pub fn new(title: String, creator: String, blocks: AozoraBlock) -> Self {
+        let work = Work { title: title.clone(), creator: creator.clone(), blocks };
+        Self::with_works(title, creator, vec![work], false)
+    }
+
+    /// Build an anthology (作品集) of several works. Each document becomes its own section
+    /// with a title page and a TOC entry carrying its title and author; `title` and
+    /// `creator` (e.g. the editor) go on the combined title page and in the metadata.
+    pub fn anthology(
+        title: String,
+        creator: String,
+        documents: Vec<AozoraDocument>,
+    ) -> Result<Self, BlockParseError> {
+        let works = documents
+            .into_iter()
+            .map(|doc| {
+                Ok(Work {
+                    title: doc.metadata.title,
+                    creator: doc.metadata.author,
+                    blocks: parse_blocks(doc.items)?,
+                })
+            })
+            .collect::<Result<Vec<_>, BlockParseError>>()?;
+        Ok(Self::with_works(title, creator, works, true))
+    }
+
+    fn with_works(title: String, creator: String, works: Vec<Work>, anthology: bool) -> Self {
         EpubGenerator {
             title,
             creator,
-            blocks,
-            uuid: Uuid::new_v4().to_string(),
+            works,
+            anthology,
+            identifier: format!("urn:uuid:{}", Uuid::new_v4()),
+            modified: Utc::now(),
+            xhtml_options: XhtmlGeneratorOptions::default(),
+            metadata: EpubMetadata::default(),
+            image_dir: None,
+            cover_image: None,
+            stylesheet: None,
+            title_page: true,
+            subtitle: None,
+            okuzuke: false,
+            okuzuke_template: None,
+            colophon: None,
+            compression: EpubCompressionOptions::default(),
+        }
+    }
+
+    /// Set the title of the book, replacing the one it was created with.
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        if !self.anthology {
+            self.works[0].title = self.title.clone();
+        }
+        self
+    }
+
+    /// Set the author of the book, replacing the one it was created with.
+    pub fn with_creator(mut self, creator: impl Into<String>) -> Self {
+        self.creator = creator.into();
+        if !self.anthology {
+            self.works[0].creator = self.creator.clone();
+        }
+        self
+    }
+
+    /// Set how each class of files is compressed, trading file size against packaging
+    /// speed (everything is deflated at the default level by default).
+    pub fn with_compression(mut self, compression: EpubCompressionOptions) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Whether to add a colophon page (奥付, item/xhtml/okuzuke.xhtml) at the end of the
+    /// spine (off by default).
+    pub fn with_okuzuke(mut self, enabled: bool) -> Self {
+        self.okuzuke = enabled;
+        self
+    }
+
+    /// 底本 information shown on the colophon page, usually `AozoraDocument::colophon`.
+    pub fn with_colophon(mut self, colophon: Colophon) -> Self {
+        self.colophon = Some(colophon);
+        self
+    }
+
+    /// XHTML of the colophon page, replacing the built-in one. The slots `{lang}`,
+    /// `{title}`, `{creator}`, `{writing_mode}`, `{source}` (the 底本 lines),
+    /// `{date}` (the generation date) and `{generator}` are filled in.
+    pub fn with_okuzuke_template(mut self, template: impl Into<String>) -> Self {
+        self.okuzuke_template = Some(template.into());
+        self
+    }
+
+    /// Whether to include the title page (扉) at the start of the spine (on by default).
+    pub fn with_title_page(mut self, enabled: bool) -> Self {
+        self.title_page = enabled;
+        self
+    }
+
+    /// Subtitle shown under the title on the title page.
+    pub fn with_subtitle(mut self, subtitle: impl Into<String>) -> Self {
+        self.subtitle = Some(subtitle.into());
+        self
+    }
+
+    /// Embed the images (挿絵) referenced by the text, reading them from `dir` (usually the
+    /// directory of the source text). They are copied into item/image/ and added to the
+    /// manifest. Without this, image references are left pointing at their original paths.
+    pub fn with_image_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.image_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Use the image at `path` as the cover. It is copied into the package and marked with
+    /// the `cover-image` property (and the EPUB 2 `cover` meta), which readers show in
+    /// their library.
+    pub fn with_cover_image<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.cover_image = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Append CSS to book-style.css, after the built-in rules, so that a theme can override
+    /// them.
+    pub fn with_stylesheet(mut self, css: impl Into<String>) -> Self {
+        self.stylesheet = Some(css.into());
+        self
+    }
+
+    /// Set the description, publisher, date, subjects and other metadata written to the OPF.
+    pub fn with_metadata(mut self, metadata: EpubMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Set the unique identifier of the book (`dc:identifier`), e.g. `urn:isbn:…`.
+    /// A random `urn:uuid:` is used by default; fix it, together with `with_modified`,
+    /// to get byte-identical output across builds.
+    pub fn with_identifier(mut self, identifier: impl Into<String>) -> Self {
+        self.identifier = identifier.into();
+        self
+    }
+
+    /// Set the modification time (`dcterms:modified`, the colophon date and the archive
+    /// timestamps). The time the generator was created is used by default.
+    pub fn with_modified(mut self, modified: DateTime<Utc>) -> Self {
+        self.modified = modified;
+        self
+    }
+
+    /// Set the options used to generate the body XHTML.
+    /// The writing mode also applies to the title page, nav and spine direction.
+    pub fn with_xhtml_options(mut self, options: XhtmlGeneratorOptions) -> Self {
+        self.xhtml_options = options;
+        self
+    }
+
+    /// Set the writing mode (vertical by default).
+    pub fn with_writing_mode(mut self, mode: WritingMode) -> Self {
+        self.xhtml_options.writing_mode = mode;
+        self
+    }
+
+    /// Collect notes into a separate notes page (item/xhtml/notes.xhtml) instead of
+    /// the end of the body.
+    pub fn with_endnotes(mut self) -> Self {
+        self.xhtml_options.notes = NotePlacement::Endnote {
+            notes_href: String::new(),
+            body_href: String::new(),
+        };
+        self
+    }
+
+    /// XHTML options for the body of the `index`-th work, with endnote links pointing at
+    /// the files in the book.
+    fn body_options(&self, index: usize) -> XhtmlGeneratorOptions {
+        let mut options = self.xhtml_options.clone();
+        if let NotePlacement::Endnote { .. } = options.notes {
+            options.notes = NotePlacement::Endnote {
+                notes_href: notes_file(index),
+                body_href: body_file(index),
+            };
+        }
+        if self.image_dir.is_some() {
+            options.image_href_prefix = "../image/".to_string();
+        }
+        options.first_page = Some(self.first_page(index));
+        options
+    }
+
+    /// schema.org accessibility metadata, describing what the works actually contain.
+    fn accessibility_metadata(&self) -> String {
+        let items = || self.works.iter().flat_map(|work| work.blocks.iter_items());
+        let has_ruby = items().any(|item| matches!(item, ParsedItem::Text(dt) if dt.ruby.is_some()));
+        let has_images = items()
+            .any(|item| matches!(item, ParsedItem::Command { cmd: Command::SingleCommand(SingleCommand::Image { .. }), .. }));
+        let has_headings = self.works.iter().any(|work| work.blocks.headings().next().is_some())
+            || items().any(|item| {
+                matches!(item, ParsedItem::Command { cmd: Command::SingleCommand(SingleCommand::Midashi(_)), .. })
+            });
+        let has_pages = self.works.iter().any(|work| page_breaks(&work.blocks) > 0);
+
+        let mut properties = vec![("accessMode", "textual")];
+        if has_images {
+            // 挿絵 only carry a short description such as "挿絵", which is not a real
+            // text alternative
+            properties.push(("accessMode", "visual"));
+            properties.push(("accessModeSufficient", "textual,visual"));
+        } else {
+            properties.push(("accessModeSufficient", "textual"));
+        }
+        properties.push(("accessibilityFeature", "tableOfContents"));
+        properties.push(("accessibilityFeature", "readingOrder"));
+        if has_headings {
+            properties.push(("accessibilityFeature", "structuralNavigation"));
+        }
+        if has_ruby {
+            properties.push(("accessibilityFeature", "rubyAnnotations"));
+        }
+        if has_pages {
+            properties.push(("accessibilityFeature", "pageBreakMarkers"));
+            properties.push(("accessibilityFeature", "pageNavigation"));
+        }
+        properties.push(("accessibilityHazard", "none"));
+
+        let mut out = String::new();
+        for (property, value) in properties {
+            writeln!(out, "\t\t<meta property=\"schema:{}\">{}</meta>", property, value).unwrap();
+        }
+        out
+    }
+
+    /// Page number the work at `index` starts on, counting every 改ページ, 改丁 and 改見開き
+    /// of the works before it as a new page.
+    fn first_page(&self, index: usize) -> usize {
+        1 + self.works[..index].iter().map(|work| page_breaks(&work.blocks) + 1).sum::<usize>()
+    }
+
+    /// Paths of the images to embed, in order of first appearance.
+    /// Empty unless an image directory is set.
+    fn images(&self) -> Vec<&str> {
+        if self.image_dir.is_none() {
+            return Vec::new();
+        }
+        let mut images = Vec::new();
+        for item in self.works.iter().flat_map(|work| work.blocks.iter_items()) {
+            if let ParsedItem::Command { cmd: Command::SingleCommand(SingleCommand::Image { src, .. }), .. } = item
+                && !images.contains(&src.as_str())
+            {
+                images.push(src.as_str());
+            }
         }
+        images
     }
 
     pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
-        let file = File::create(path)?;
-        let mut zip = ZipWriter::new(file);
+        self.write_to(File::create(path)?)?;
+        Ok(())
+    }
+
+    /// Build the package in memory and check it for common epubcheck failures
+    /// (see [`validate_epub`]). Returns the problems found; an empty list means the book
+    /// passed every check.
+    pub fn validate(&self) -> std::io::Result<Vec<EpubValidationError>> {
+        let bytes = self.write_to(Cursor::new(Vec::new()))?.into_inner();
+        validate_epub(&bytes)
+    }
+
+    /// Write the package into `writer`, returning the writer once the archive is finished.
+    pub fn write_to<W: Write + Seek>(&self, writer: W) -> std::io::Result<W> {
+        let mut zip = ZipWriter::new(writer);
 
-        let options = SimpleFileOptions::default()
-            .compression_method(zip::CompressionMethod::Stored)
-            .unix_permissions(0o755);
+        // Timestamps of the entries follow the modification time, for reproducible archives
+        let timestamp = zip::DateTime::from_date_and_time(
+            self.modified.year().try_into().unwrap_or(1980),
+            self.modified.month() as u8,
+            self.modified.day() as u8,
+            self.modified.hour() as u8,
+            self.modified.minute() as u8,
+            self.modified.second() as u8,
+        )
+        .unwrap_or_default();
 
         // mimetype (must be first, uncompressed)
-        zip.start_file("mimetype", options)?;
+        zip.start_file("mimetype", EpubCompression::Stored.file_options(timestamp))?;
         zip.write_all(b"application/epub+zip")?;
 
-        let options_deflate = SimpleFileOptions::default()
-            .compression_method(zip::CompressionMethod::Deflated)
-            .unix_permissions(0o755);
+        let options_documents = self.compression.documents.file_options(timestamp);
+        let options_styles = self.compression.styles.file_options(timestamp);
+        let options_images = self.compression.images.file_options(timestamp);
 
-        // Generate content first to get TOC
-        let (body_content, toc_entries) = XhtmlGenerator::generate(&self.blocks, &self.title);
+        // item/xhtml/0001.xhtml, ... (main content, one per work), streamed into the archive
+        // first to get the TOC
+        zip.add_directory("item/xhtml", options_documents)?;
+        let mut tocs = Vec::new();
+        let mut notes = Vec::new();
+        for (i, work) in self.works.iter().enumerate() {
+            zip.start_file(format!("item/xhtml/{}", body_file(i)), options_documents)?;
+            let (toc_entries, work_notes) =
+                XhtmlGenerator::write_io_with(&work.blocks, &work.title, &self.body_options(i), &mut zip)?;
+            tocs.push(toc_entries);
+            notes.push(work_notes);
+        }
+        let has_notes: Vec<bool> = notes.iter().map(Option::is_some).collect();
 
         // META-INF/container.xml
-        zip.start_file("META-INF/container.xml", options_deflate)?;
+        zip.start_file("META-INF/container.xml", options_documents)?;
         zip.write_all(self.generate_container().as_bytes())?;
 
         // item/standard.opf
-        zip.start_file("item/standard.opf", options_deflate)?;
-        zip.write_all(self.generate_opf().as_bytes())?;
+        zip.start_file("item/standard.opf", options_documents)?;
+        zip.write_all(self.generate_opf(&has_notes).as_bytes())?;
 
         // item/nav.xhtml
-        zip.start_file("item/nav.xhtml", options_deflate)?;
-        zip.write_all(self.generate_nav(&toc_entries).as_bytes())?;
+        zip.start_file("item/nav.xhtml", options_documents)?;
+        zip.write_all(self.generate_nav(&tocs).as_bytes())?;
+
+        // item/toc.ncx (for EPUB 2 readers)
+        zip.start_file("item/toc.ncx", options_documents)?;
+        zip.write_all(self.generate_ncx(&tocs).as_bytes())?;
         
         // Copy CSS files from reference directory
-        zip.add_directory("item/style", options_deflate)?;
+        zip.add_directory("item/style", options_styles)?;
         let css_files = self.get_css_contents();
         for (filename, content) in &css_files {
-            zip.start_file(format!("item/style/{}", filename), options_deflate)?;
+            zip.start_file(format!("item/style/{}", filename), options_styles)?;
             zip.write_all(content.as_bytes())?;
         }
 
         // item/xhtml/title.xhtml (title page)
-        zip.add_directory("item/xhtml", options_deflate)?;
-        zip.start_file("item/xhtml/title.xhtml", options_deflate)?;
-        zip.write_all(self.generate_title_page().as_bytes())?;
+        if self.title_page {
+            zip.start_file("item/xhtml/title.xhtml", options_documents)?;
+            zip.write_all(self.generate_title_page().as_bytes())?;
+            if self.anthology {
+                for (i, work) in self.works.iter().enumerate() {
+                    zip.start_file(format!("item/xhtml/{}", work_title_file(i)), options_documents)?;
+                    zip.write_all(self.generate_work_title_page(work).as_bytes())?;
+                }
+            }
+        }
 
-        // item/xhtml/0001.xhtml (main content)
-        zip.start_file("item/xhtml/0001.xhtml", options_deflate)?;
-        zip.write_all(body_content.as_bytes())?;
+        // item/image/ (images referenced by the text)
+        if let Some(dir) = &self.image_dir {
+            for src in self.images() {
+                let relative = Path::new(src);
+                if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("image path must be relative to the image directory: {}", src),
+                    ));
+                }
+                let content = std::fs::read(dir.join(relative))?;
+                zip.start_file(format!("item/image/{}", src), options_images)?;
+                zip.write_all(&content)?;
+            }
+        }
 
-        zip.finish()?;
-        Ok(())
+        // item/cover.* (cover image)
+        if let (Some(path), Some(href)) = (&self.cover_image, self.cover_href()) {
+            let content = std::fs::read(path)?;
+            zip.start_file(format!("item/{}", href), options_images)?;
+            zip.write_all(&content)?;
+        }
+
+        // item/xhtml/okuzuke.xhtml (colophon)
+        if self.okuzuke {
+            zip.start_file("item/xhtml/okuzuke.xhtml", options_documents)?;
+            zip.write_all(self.generate_okuzuke().as_bytes())?;
+        }
+
+        // item/xhtml/notes.xhtml, ... (endnotes)
+        for (i, notes) in notes.iter().enumerate() {
+            if let Some(notes) = notes {
+                zip.start_file(format!("item/xhtml/{}", notes_file(i)), options_documents)?;
+                zip.write_all(notes.as_bytes())?;
+            }
+        }
+
+        Ok(zip.finish()?)
     }
 
     fn generate_container(&self) -> String {
         include_str!("epub_template/container.xml").to_string()
     }
 
-    fn generate_opf(&self) -> String {
-        include_str!("epub_template/standard.opf")
-            .replace("{title}", &self.title)
-            .replace("{creator}", &self.creator)
-            .replace("{uuid}", &self.uuid)
-            .replace("{modified}", &chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string())
+    /// `has_notes` tells, per work, whether it has an endnotes page.
+    fn generate_opf(&self, has_notes: &[bool]) -> String {
+        let mut body_items = String::new();
+        let mut body_itemrefs = String::new();
+        for i in 0..self.works.len() {
+            if self.anthology && self.title_page {
+                let id = format!("title-page{:04}", i + 1);
+                writeln!(
+                    body_items,
+                    "\t\t<item id=\"{}\" href=\"xhtml/{}\" media-type=\"application/xhtml+xml\"/>",
+                    id,
+                    work_title_file(i)
+                )
+                .unwrap();
+                writeln!(body_itemrefs, "\t\t<itemref linear=\"yes\" idref=\"{}\"/>", id).unwrap();
+            }
+            let id = format!("sec{:04}", i + 1);
+            writeln!(
+                body_items,
+                "\t\t<item id=\"{}\" href=\"xhtml/{}\" media-type=\"application/xhtml+xml\"/>",
+                id,
+                body_file(i)
+            )
+            .unwrap();
+            writeln!(body_itemrefs, "\t\t<itemref linear=\"yes\" idref=\"{}\"/>", id).unwrap();
+            if has_notes.get(i).copied().unwrap_or(false) {
+                let id = if i == 0 { "notes".to_string() } else { format!("notes{:04}", i + 1) };
+                writeln!(
+                    body_items,
+                    "\t\t<item id=\"{}\" href=\"xhtml/{}\" media-type=\"application/xhtml+xml\"/>",
+                    id,
+                    notes_file(i)
+                )
+                .unwrap();
+                writeln!(body_itemrefs, "\t\t<itemref linear=\"yes\" idref=\"{}\"/>", id).unwrap();
+            }
+        }
+
+        // Authors of the works in an anthology, after the book's creator
+        let mut metadata = String::new();
+        let mut creators = vec![self.creator.as_str()];
+        for work in &self.works {
+            if !creators.contains(&work.creator.as_str()) && !work.creator.is_empty() {
+                creators.push(&work.creator);
+                writeln!(
+                    metadata,
+                    "\t\t<dc:creator id=\"creator{:02}\">{}</dc:creator>",
+                    creators.len(),
+                    escape_html(&work.creator)
+                )
+                .unwrap();
+            }
+        }
+        metadata.push_str(&self.metadata.to_opf());
+        if self.cover_image.is_some() {
+            metadata.push_str("\t\t<meta name=\"cover\" content=\"cover-image\"/>\n");
+        }
+
+        let (title_page_item, title_page_itemref) = if self.title_page {
+            (
+                "\t\t<item id=\"title-page\" href=\"xhtml/title.xhtml\" media-type=\"application/xhtml+xml\"/>\n",
+                "\t\t<itemref idref=\"title-page\" linear=\"yes\"/>\n",
+            )
+        } else {
+            ("", "")
+        };
+        let (okuzuke_item, okuzuke_itemref) = if self.okuzuke {
+            (
+                "\t\t<item id=\"okuzuke\" href=\"xhtml/okuzuke.xhtml\" media-type=\"application/xhtml+xml\"/>\n",
+                "\t\t<itemref linear=\"yes\" idref=\"okuzuke\"/>\n",
+            )
+        } else {
+            ("", "")
+        };
+        fill_template(
+            include_str!("epub_template/standard.opf"),
+            &[
+                ("okuzuke_item", okuzuke_item),
+                ("okuzuke_itemref", okuzuke_itemref),
+                ("title_page_item", title_page_item),
+                ("title_page_itemref", title_page_itemref),
+                ("lang", &escape_html(&self.xhtml_options.lang)),
+                ("body_items", &body_items),
+                ("body_itemrefs", &body_itemrefs),
+                ("metadata", &metadata),
+                ("accessibility", &self.accessibility_metadata()),
+                ("image_items", &self.image_items()),
+                ("title", &escape_html(&self.title)),
+                ("creator", &escape_html(&self.creator)),
+                ("identifier", &escape_html(&self.identifier)),
+                ("page_progression_direction", self.xhtml_options.writing_mode.page_progression_direction()),
+                ("modified", &self.modified.format("%Y-%m-%dT%H:%M:%SZ").to_string()),
+            ],
+        )
+    }
+
+    fn image_items(&self) -> String {
+        let mut items = String::new();
+        if let Some(href) = self.cover_href() {
+            writeln!(
+                items,
+                "\t\t<item id=\"cover-image\" href=\"{}\" media-type=\"{}\" properties=\"cover-image\"/>",
+                escape_html(&href),
+                image_media_type(&href)
+            )
+            .unwrap();
+        }
+        for (i, src) in self.images().into_iter().enumerate() {
+            writeln!(
+                items,
+                "\t\t<item id=\"image{:04}\" href=\"image/{}\" media-type=\"{}\"/>",
+                i + 1,
+                escape_html(src),
+                image_media_type(src)
+            )
+            .unwrap();
+        }
+        items
+    }
+
+    /// Path of the cover image in the package, relative to item/. Kept out of item/image/
+    /// so that it cannot collide with the images of the text.
+    fn cover_href(&self) -> Option<String> {
+        let extension = self.cover_image.as_ref()?.extension().and_then(|e| e.to_str()).unwrap_or("png");
+        Some(format!("cover.{}", extension.to_ascii_lowercase()))
     }
 
     fn generate_title_page(&self) -> String {
-        include_str!("epub_template/title.xhtml")
-            .replace("{title}", &self.title)
-            .replace("{creator}", &self.creator)
+        let subtitle = match &self.subtitle {
+            Some(subtitle) => format!(
+                "            <div class=\"book-title-after\">\n                <p>{}</p>\n            </div>\n",
+                escape_html(subtitle)
+            ),
+            None => String::new(),
+        };
+        // An anthology lists its works on the combined title page
+        let mut works = String::new();
+        if self.anthology {
+            works.push_str("        <div class=\"works\">\n");
+            for work in &self.works {
+                writeln!(works, "            <p>{}</p>", escape_html(&work_label(work))).unwrap();
+            }
+            works.push_str("        </div>\n");
+        }
+        self.title_page_xhtml(&self.title, &self.creator, &subtitle, &works)
+    }
+
+    /// Title page (中扉) of one work in an anthology.
+    fn generate_work_title_page(&self, work: &Work) -> String {
+        self.title_page_xhtml(&work.title, &work.creator, "", "")
+    }
+
+    fn title_page_xhtml(&self, title: &str, creator: &str, subtitle: &str, works: &str) -> String {
+        fill_template(
+            include_str!("epub_template/title.xhtml"),
+            &[
+                ("subtitle", subtitle),
+                ("works", works),
+                ("lang", &escape_html(&self.xhtml_options.lang)),
+                ("title", &escape_html(title)),
+                ("creator", &escape_html(creator)),
+                ("writing_mode", self.xhtml_options.writing_mode.class_name()),
+            ],
+        )
+    }
+
+    fn generate_okuzuke(&self) -> String {
+        let mut source = String::new();
+        if let Some(colophon) = &self.colophon {
+            let mut lines = Vec::new();
+            if !colophon.source.is_empty() {
+                lines.push(format!("底本：{}", colophon.source));
+            }
+            if let Some(parent) = &colophon.parent_source {
+                lines.push(format!("底本の親本：{}", parent));
+            }
+            if let Some(inputter) = &colophon.inputter {
+                lines.push(format!("入力：{}", inputter));
+            }
+            if let Some(proofreader) = &colophon.proofreader {
+                lines.push(format!("校正：{}", proofreader));
+            }
+            if let Some(published) = &colophon.published {
+                lines.push(format!("{}公開", published));
+            }
+            for revised in &colophon.revised {
+                lines.push(format!("{}修正", revised));
+            }
+            if !lines.is_empty() {
+                source.push_str("        <div class=\"original-books\">\n");
+                for line in lines {
+                    // Continuation lines of the 底本 are kept as line breaks
+                    writeln!(source, "            <p>{}</p>", escape_html(&line).replace('\n', "<br />")).unwrap();
+                }
+                source.push_str("        </div>\n");
+            }
+        }
+
+        let date = self.modified.format("%Y年%-m月%-d日").to_string();
+        let generator = concat!("aozora_parser ", env!("CARGO_PKG_VERSION"));
+        let template = self
+            .okuzuke_template
+            .as_deref()
+            .unwrap_or(include_str!("epub_template/okuzuke.xhtml"));
+        fill_template(
+            template,
+            &[
+                ("lang", &escape_html(&self.xhtml_options.lang)),
+                ("title", &escape_html(&self.title)),
+                ("creator", &escape_html(&self.creator)),
+                ("writing_mode", self.xhtml_options.writing_mode.class_name()),
+                ("source", &source),
+                ("date", &escape_html(&date)),
+                ("generator", &escape_html(generator)),
+            ],
+        )
     }
 
-    fn generate_nav(&self, toc: &[TocEntry]) -> String {
+    /// Href of the first page of a work in an anthology, relative to the package.
+    fn work_href(&self, index: usize) -> String {
+        if self.title_page {
+            format!("xhtml/{}", work_title_file(index))
+        } else {
+            format!("xhtml/{}", body_file(index))
+        }
+    }
+
+    /// `tocs` holds the TOC entries of each work.
+    fn generate_nav(&self, tocs: &[Vec<TocEntry>]) -> String {
         let mut toc_items = String::new();
-        
-        // Add title page link first
-        writeln!(toc_items, "\t\t\t<li><a href=\"xhtml/title.xhtml\">{}</a>", self.title).unwrap();
-        
-        // Add heading links
-        if !toc.is_empty() {
-            toc_items.push_str("\t\t<ol>\n");
-            for entry in toc {
-                writeln!(toc_items, "\t\t\t<li><a href=\"xhtml/0001.xhtml#{}\">　{}</a></li>", entry.id, entry.text).unwrap();
+        // Headings nested by level; in an anthology, nested under an entry per work
+        let headings = if self.anthology {
+            let mut ol = String::from("<ol>");
+            for (i, work) in self.works.iter().enumerate() {
+                let toc = tocs.get(i).map(Vec::as_slice).unwrap_or_default();
+                write!(
+                    ol,
+                    "<li><a href=\"{}\">{}</a>{}</li>",
+                    self.work_href(i),
+                    escape_html(&work_label(work)),
+                    TocTree::from_entries(toc).to_ol(&format!("xhtml/{}", body_file(i)))
+                )
+                .unwrap();
+            }
+            ol.push_str("</ol>");
+            ol
+        } else {
+            let toc = tocs.first().map(Vec::as_slice).unwrap_or_default();
+            TocTree::from_entries(toc).to_ol("xhtml/0001.xhtml")
+        };
+
+        if self.title_page {
+            // Add title page link first
+            writeln!(toc_items, "\t\t\t<li><a href=\"xhtml/title.xhtml\">{}</a>", escape_html(&self.title)).unwrap();
+
+            // Add heading links
+            if !headings.is_empty() {
+                writeln!(toc_items, "\t\t\t{}", headings).unwrap();
+            }
+            toc_items.push_str("\t\t</li>");
+        } else if headings.is_empty() {
+            // The toc nav must not be empty; link the body instead
+            write!(toc_items, "<li><a href=\"xhtml/0001.xhtml\">{}</a></li>", escape_html(&self.title)).unwrap();
+        } else {
+            // The headings go directly into the <ol> of the template
+            toc_items.push_str(&headings["<ol>".len()..headings.len() - "</ol>".len()]);
+        }
+
+        // Print-equivalent pages start at each work and at each page break
+        let mut page_list = String::new();
+        if self.works.iter().any(|work| page_breaks(&work.blocks) > 0) {
+            page_list.push_str("    <nav epub:type=\"page-list\" id=\"page-list\" hidden=\"\">\n        <ol>\n");
+            for (i, work) in self.works.iter().enumerate() {
+                let first = self.first_page(i);
+                writeln!(page_list, "            <li><a href=\"xhtml/{}\">{}</a></li>", body_file(i), first).unwrap();
+                for page in first + 1..=first + page_breaks(&work.blocks) {
+                    writeln!(page_list, "            <li><a href=\"xhtml/{}#page-{1}\">{1}</a></li>", body_file(i), page)
+                        .unwrap();
+                }
             }
-            toc_items.push_str("\t\t</ol>\n");
+            page_list.push_str("        </ol>\n    </nav>\n");
         }
-        toc_items.push_str("\t\t</li>");
 
-        include_str!("epub_template/nav.xhtml")
-            .replace("{title}", &self.title)
-            .replace("{toc_items}", &toc_items)
+        let titlepage_landmark = if self.title_page {
+            "            <li><a epub:type=\"titlepage\" href=\"xhtml/title.xhtml\">扉</a></li>\n"
+        } else {
+            ""
+        };
+        fill_template(
+            include_str!("epub_template/nav.xhtml"),
+            &[
+                ("titlepage_landmark", titlepage_landmark),
+                ("lang", &escape_html(&self.xhtml_options.lang)),
+                ("title", &escape_html(&self.title)),
+                ("writing_mode", self.xhtml_options.writing_mode.css_value()),
+                ("toc_items", &toc_items),
+                ("page_list", &page_list),
+            ],
+        )
+    }
+
+    /// NCX with the same entries and nesting as nav.xhtml, for readers without EPUB 3 nav
+    /// support.
+    fn generate_ncx(&self, tocs: &[Vec<TocEntry>]) -> String {
+        fn open(out: &mut String, depth: usize, play_order: &mut usize, label: &str, src: &str) {
+            let indent = "\t".repeat(depth + 1);
+            *play_order += 1;
+            writeln!(
+                out,
+                "{indent}<navPoint id=\"navpoint-{0}\" playOrder=\"{0}\">\n{indent}\t<navLabel><text>{1}</text></navLabel>\n{indent}\t<content src=\"{2}\"/>",
+                play_order,
+                escape_html(label),
+                escape_html(src),
+            )
+            .unwrap();
+        }
+        fn close(out: &mut String, depth: usize) {
+            writeln!(out, "{}</navPoint>", "\t".repeat(depth + 1)).unwrap();
+        }
+        fn render(nodes: &[TocNode], href: &str, depth: usize, play_order: &mut usize, out: &mut String) {
+            for node in nodes {
+                open(out, depth, play_order, &node.entry.text, &format!("{}#{}", href, node.entry.id));
+                render(&node.children, href, depth + 1, play_order, out);
+                close(out, depth);
+            }
+        }
+        fn tree_depth(nodes: &[TocNode]) -> usize {
+            nodes.iter().map(|n| 1 + tree_depth(&n.children)).max().unwrap_or(0)
+        }
+
+        let trees: Vec<TocTree> = (0..self.works.len())
+            .map(|i| TocTree::from_entries(tocs.get(i).map(Vec::as_slice).unwrap_or_default()))
+            .collect();
+        // Entries of the body: the headings, or in an anthology an entry per work with its
+        // headings nested under it
+        let contents_empty = !self.anthology && trees.iter().all(TocTree::is_empty);
+        let render_contents = |depth: usize, play_order: &mut usize, out: &mut String| -> usize {
+            if self.anthology {
+                for (i, (work, tree)) in self.works.iter().zip(&trees).enumerate() {
+                    open(out, depth, play_order, &work_label(work), &self.work_href(i));
+                    render(&tree.roots, &format!("xhtml/{}", body_file(i)), depth + 1, play_order, out);
+                    close(out, depth);
+                }
+                1 + trees.iter().map(|t| tree_depth(&t.roots)).max().unwrap_or(0)
+            } else {
+                let tree = &trees[0];
+                render(&tree.roots, "xhtml/0001.xhtml", depth, play_order, out);
+                tree_depth(&tree.roots)
+            }
+        };
+
+        let mut nav_points = String::new();
+        let depth = if self.title_page || contents_empty {
+            // Title page (or the body) first, with the headings nested under it as in nav.xhtml
+            let src = if self.title_page { "xhtml/title.xhtml" } else { "xhtml/0001.xhtml" };
+            open(&mut nav_points, 1, &mut 0, &self.title, src);
+            let depth = render_contents(2, &mut 1, &mut nav_points);
+            close(&mut nav_points, 1);
+            1 + depth
+        } else {
+            render_contents(1, &mut 0, &mut nav_points)
+        };
+
+        fill_template(
+            include_str!("epub_template/toc.ncx"),
+            &[
+                ("lang", &escape_html(&self.xhtml_options.lang)),
+                ("identifier", &escape_html(&self.identifier)),
+                ("depth", &depth.to_string()),
+                ("title", &escape_html(&self.title)),
+                ("creator", &escape_html(&self.creator)),
+                ("nav_points", &nav_points),
+            ],
+        )
     }
 
     fn get_css_contents(&self) -> Vec<(String, String)> {
@@ -129,7 +945,69 @@ impl EpubGenerator {
             ("text.css", include_str!("epub_template/css/text.css")),
         ];
         
-        css_files.iter().map(|(name, content)| (name.to_string(), content.to_string())).collect()
+        css_files
+            .iter()
+            .map(|(name, content)| match (*name, &self.stylesheet) {
+                ("book-style.css", Some(stylesheet)) => (name.to_string(), format!("{}\n{}\n", content, stylesheet)),
+                _ => (name.to_string(), content.to_string()),
+            })
+            .collect()
+    }
+}
+
+/// File name of the body of the `index`-th work (0001.xhtml, 0002.xhtml, ...).
+fn body_file(index: usize) -> String {
+    format!("{:04}.xhtml", index + 1)
+}
+
+/// File name of the endnotes of the `index`-th work (notes.xhtml, notes0002.xhtml, ...).
+fn notes_file(index: usize) -> String {
+    if index == 0 {
+        "notes.xhtml".to_string()
+    } else {
+        format!("notes{:04}.xhtml", index + 1)
+    }
+}
+
+/// Number of 改ページ, 改丁 and 改見開き in `blocks`.
+fn page_breaks(blocks: &AozoraBlock) -> usize {
+    blocks
+        .iter_items()
+        .filter(|item| {
+            matches!(
+                item,
+                ParsedItem::Command {
+                    cmd: Command::SingleCommand(SingleCommand::Kaipage | SingleCommand::Kaicho | SingleCommand::Kaimihiraki),
+                    ..
+                }
+            )
+        })
+        .count()
+}
+
+/// File name of the title page of the `index`-th work in an anthology.
+fn work_title_file(index: usize) -> String {
+    format!("title{:04}.xhtml", index + 1)
+}
+
+/// Label of a work in the TOC and on the title page of an anthology.
+fn work_label(work: &Work) -> String {
+    if work.creator.is_empty() {
+        work.title.clone()
+    } else {
+        format!("{}　{}", work.title, work.creator)
+    }
+}
+
+/// Media type of an image from its extension.
+fn image_media_type(src: &str) -> &'static str {
+    let extension = Path::new(src).extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+    match extension.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        _ => "image/png",
     }
 }
 
@@ -152,7 +1030,7 @@ mod tests {
         let text = cow.into_owned();
 
         let tokens = parse_aozora(text).expect("Tokenization failed");
-        let doc = parse(tokens).expect("Parsing failed");
+        let doc = parse(tokens);
         let root = parse_blocks(doc.items).expect("Block parsing failed");
 
         let generator = EpubGenerator::new(
@@ -169,6 +1047,399 @@ mod tests {
         let _ = fs::remove_file(output_path);
     }
 
+    #[test]
+    fn test_horizontal_writing_mode() {
+        let text = "タイトル\n著者\n\n本文".to_string();
+        let doc = parse(parse_aozora(text).unwrap());
+        let root = parse_blocks(doc.items).unwrap();
+
+        let generator = EpubGenerator::new(doc.metadata.title, doc.metadata.author, root);
+        assert!(generator.generate_opf(&[false]).contains("page-progression-direction=\"rtl\""));
+        assert!(generator.generate_title_page().contains("class=\"main vrtl"));
+
+        let generator = generator.with_writing_mode(WritingMode::HorizontalTb);
+        assert!(generator.generate_opf(&[false]).contains("page-progression-direction=\"ltr\""));
+        assert!(generator.generate_title_page().contains("class=\"main hltr"));
+        assert!(generator.generate_nav(&[Vec::new()]).contains("writing-mode: horizontal-tb;"));
+        assert!(generator.generate_opf(&[false]).contains("<dc:language id=\"pub-lang\">ja</dc:language>"));
+
+        let root = parse_blocks(Vec::new()).unwrap();
+        let generator = EpubGenerator::new("A & B".to_string(), "<著者>".to_string(), root);
+        assert!(generator.generate_opf(&[false]).contains("<dc:title id=\"title\">A &amp; B</dc:title>"));
+        assert!(generator.generate_title_page().contains("&lt;著者&gt;"));
+    }
+
+    #[test]
+    fn test_metadata() {
+        let root = parse_blocks(Vec::new()).unwrap();
+        let generator = EpubGenerator::new("T".to_string(), "A".to_string(), root);
+        assert!(!generator.generate_opf(&[false]).contains("dc:publisher"));
+
+        let metadata = EpubMetadata::new()
+            .with_description("説明 & 紹介")
+            .with_publisher("青空文庫")
+            .with_date("1948-05-01")
+            .with_subject("小説")
+            .with_subject("NDC 913")
+            .with_rights("Public Domain")
+            .with_dc("contributor", "入力者")
+            .with_dc("bad name", "ignored");
+        let opf = generator.with_metadata(metadata).generate_opf(&[false]);
+        assert!(opf.contains(
+            "<dc:creator id=\"creator01\">A</dc:creator>\n\t\t<dc:description>説明 &amp; 紹介</dc:description>\n\t\t<dc:publisher>青空文庫</dc:publisher>\n\t\t<dc:date>1948-05-01</dc:date>\n\t\t<dc:subject>小説</dc:subject>\n\t\t<dc:subject>NDC 913</dc:subject>\n\t\t<dc:rights>Public Domain</dc:rights>\n\t\t<dc:contributor>入力者</dc:contributor>\n"
+        ));
+        assert!(!opf.contains("ignored"));
+    }
+
+    #[test]
+    fn test_nested_nav() {
+        let text = "タイトル\n著者\n\n［＃大見出し］一［＃大見出し終わり］\n［＃中見出し］二［＃中見出し終わり］\n［＃小見出し］三［＃小見出し終わり］\n［＃大見出し］四［＃大見出し終わり］\n".to_string();
+        let doc = parse(parse_aozora(text).unwrap());
+        let root = parse_blocks(doc.items).unwrap();
+        let generator = EpubGenerator::new(doc.metadata.title, doc.metadata.author, root);
+        let tocs = vec![XhtmlGenerator::generate(&generator.works[0].blocks, &generator.title).1];
+
+        assert!(generator.generate_nav(&tocs).contains(
+            "<li><a href=\"xhtml/title.xhtml\">タイトル</a>\n\t\t\t<ol><li><a href=\"xhtml/0001.xhtml#midashi-一\">一</a><ol><li><a href=\"xhtml/0001.xhtml#midashi-二\">二</a><ol><li><a href=\"xhtml/0001.xhtml#midashi-三\">三</a></li></ol></li></ol></li><li><a href=\"xhtml/0001.xhtml#midashi-四\">四</a></li></ol>\n\t\t</li>"
+        ));
+    }
+
+    #[test]
+    fn test_title_page() {
+        let text = "タイトル\n著者\n\n［＃大見出し］一［＃大見出し終わり］\n".to_string();
+        let doc = parse(parse_aozora(text).unwrap());
+        let root = parse_blocks(doc.items).unwrap();
+        let generator = EpubGenerator::new(doc.metadata.title, doc.metadata.author, root);
+        assert!(!generator.generate_title_page().contains("book-title-after"));
+
+        let generator = generator.with_subtitle("副題 & 題");
+        assert!(generator.generate_title_page().contains(
+            "<div class=\"book-title-after\">\n                <p>副題 &amp; 題</p>\n            </div>"
+        ));
+        // The subtitle is not filled in again
+        let generator = generator.with_subtitle("sub {title}");
+        assert!(generator.generate_title_page().contains("<p>sub {title}</p>"));
+
+        let generator = generator.with_title_page(false);
+        let tocs = vec![XhtmlGenerator::generate(&generator.works[0].blocks, &generator.title).1];
+        let opf = generator.generate_opf(&[false]);
+        assert!(!opf.contains("title-page"));
+        assert!(opf.contains("<itemref idref=\"nav\" linear=\"yes\"/>"));
+        let nav = generator.generate_nav(&tocs);
+        assert!(!nav.contains("title.xhtml"));
+        assert!(nav.contains("<ol>\n            <li><a href=\"xhtml/0001.xhtml#midashi-一\">一</a></li>\n        </ol>"));
+        let ncx = generator.generate_ncx(&tocs);
+        assert!(!ncx.contains("title.xhtml"));
+        assert!(ncx.contains("<meta name=\"dtb:depth\" content=\"1\"/>"));
+        assert!(ncx.contains("\t\t<navPoint id=\"navpoint-1\" playOrder=\"1\">\n\t\t\t<navLabel><text>一</text></navLabel>"));
+    }
+
+    #[test]
+    fn test_braces_in_metadata() {
+        let text = "{creator}\n太宰治\n\n［＃大見出し］一［＃大見出し終わり］\n".to_string();
+        let doc = parse(parse_aozora(text).unwrap());
+        let root = parse_blocks(doc.items).unwrap();
+        let generator = EpubGenerator::new(doc.metadata.title, doc.metadata.author, root)
+            .with_metadata(EpubMetadata::new().with_description("see {identifier} and {title}"));
+        let tocs = vec![XhtmlGenerator::generate(&generator.works[0].blocks, &generator.title).1];
+
+        // Inserted values are not filled in again
+        let opf = generator.generate_opf(&[false]);
+        assert!(opf.contains("<dc:title id=\"title\">{creator}</dc:title>"));
+        assert!(opf.contains("<dc:description>see {identifier} and {title}</dc:description>"));
+        assert!(generator.generate_nav(&tocs).contains("<title>{creator}</title>"));
+        assert!(generator.generate_ncx(&tocs).contains("<docTitle><text>{creator}</text></docTitle>"));
+    }
+
+    #[test]
+    fn test_okuzuke() {
+        let text = "タイトル\n著者\n\n本文\n\n底本：「桜桃」角川文庫\n　1989年4月10日初版発行\n入力：入力者\n1999年4月8日公開\n".to_string();
+        let doc = parse(parse_aozora(text).unwrap());
+        let colophon = doc.colophon.clone().unwrap();
+        let root = parse_blocks(doc.items).unwrap();
+        let generator = EpubGenerator::new(doc.metadata.title, doc.metadata.author, root);
+        assert!(!generator.generate_opf(&[false]).contains("okuzuke"));
+
+        let generator = generator.with_okuzuke(true).with_colophon(colophon);
+        let opf = generator.generate_opf(&[true]);
+        assert!(opf.contains("<item id=\"okuzuke\" href=\"xhtml/okuzuke.xhtml\""));
+        assert!(opf.contains("idref=\"notes\"/>\n\t\t<itemref linear=\"yes\" idref=\"okuzuke\"/>"));
+
+        let okuzuke = generator.generate_okuzuke();
+        assert!(okuzuke.contains("<p>タイトル</p>"));
+        assert!(okuzuke.contains(
+            "<div class=\"original-books\">\n            <p>底本：「桜桃」角川文庫<br />1989年4月10日初版発行</p>\n            <p>入力：入力者</p>\n            <p>1999年4月8日公開</p>\n        </div>"
+        ));
+        assert!(okuzuke.contains(concat!("aozora_parser ", env!("CARGO_PKG_VERSION"))));
+
+        let generator = generator.with_okuzuke_template("<p>{title}／{creator}／{unknown}</p>");
+        assert_eq!(generator.generate_okuzuke(), "<p>タイトル／著者／{unknown}</p>");
+
+        // Substituted values cannot break out of their attribute
+        let options = XhtmlGeneratorOptions { lang: "ja\"><script>".to_string(), ..Default::default() };
+        let generator = generator.with_xhtml_options(options).with_okuzuke_template("<html lang=\"{lang}\">");
+        assert_eq!(generator.generate_okuzuke(), "<html lang=\"ja&quot;&gt;&lt;script&gt;\">");
+        assert!(generator.generate_nav(&[Vec::new()]).contains("lang=\"ja&quot;&gt;&lt;script&gt;\""));
+        assert!(generator.generate_ncx(&[Vec::new()]).contains("lang=\"ja&quot;&gt;&lt;script&gt;\""));
+    }
+
+    #[test]
+    fn test_reproducible_output() {
+        let build = |path: &Path| {
+            let text = "タイトル\n著者\n\n［＃大見出し］一［＃大見出し終わり］\n本文\n".to_string();
+            let doc = parse(parse_aozora(text).unwrap());
+            let root = parse_blocks(doc.items).unwrap();
+            let modified = DateTime::parse_from_rfc3339("2024-01-31T12:00:00Z").unwrap().with_timezone(&Utc);
+            EpubGenerator::new(doc.metadata.title, doc.metadata.author, root)
+                .with_identifier("urn:uuid:00000000-0000-4000-8000-000000000000")
+                .with_modified(modified)
+                .write_to_file(path)
+                .unwrap();
+            let bytes = fs::read(path).unwrap();
+            let _ = fs::remove_file(path);
+            bytes
+        };
+        let dir = std::env::temp_dir();
+        let first = build(&dir.join("aozora_parser_reproducible_1.epub"));
+        let second = build(&dir.join("aozora_parser_reproducible_2.epub"));
+        assert_eq!(first, second);
+
+        let root = parse_blocks(Vec::new()).unwrap();
+        let modified = DateTime::parse_from_rfc3339("2024-01-31T12:00:00Z").unwrap().with_timezone(&Utc);
+        let generator = EpubGenerator::new("T".to_string(), "A".to_string(), root)
+            .with_identifier("urn:isbn:9784000000000")
+            .with_modified(modified);
+        let opf = generator.generate_opf(&[false]);
+        assert!(opf.contains("<dc:identifier id=\"unique-id\">urn:isbn:9784000000000</dc:identifier>"));
+        assert!(opf.contains("<meta property=\"dcterms:modified\">2024-01-31T12:00:00Z</meta>"));
+    }
+
+    #[test]
+    fn test_anthology() {
+        let documents = vec![
+            parse(parse_aozora("桜桃\n太宰治\n\n［＃大見出し］一［＃大見出し終わり］\n本文\n".to_string()).unwrap()),
+            parse(parse_aozora("檸檬\n梶井基次郎\n\n本文\n".to_string()).unwrap()),
+        ];
+        let generator = EpubGenerator::anthology("作品集".to_string(), "編者".to_string(), documents).unwrap();
+        let tocs: Vec<_> = generator
+            .works
+            .iter()
+            .map(|work| XhtmlGenerator::generate(&work.blocks, &work.title).1)
+            .collect();
+
+        let opf = generator.generate_opf(&[false, true]);
+        assert!(opf.contains("<dc:creator id=\"creator01\">編者</dc:creator>\n\t\t<dc:creator id=\"creator02\">太宰治</dc:creator>\n\t\t<dc:creator id=\"creator03\">梶井基次郎</dc:creator>"));
+        assert!(opf.contains(
+            "<itemref linear=\"yes\" idref=\"title-page0001\"/>\n\t\t<itemref linear=\"yes\" idref=\"sec0001\"/>\n\t\t<itemref linear=\"yes\" idref=\"title-page0002\"/>\n\t\t<itemref linear=\"yes\" idref=\"sec0002\"/>\n\t\t<itemref linear=\"yes\" idref=\"notes0002\"/>"
+        ));
+        assert!(opf.contains("<item id=\"notes0002\" href=\"xhtml/notes0002.xhtml\""));
+
+        assert!(generator.generate_nav(&tocs).contains(
+            "<ol><li><a href=\"xhtml/title0001.xhtml\">桜桃　太宰治</a><ol><li><a href=\"xhtml/0001.xhtml#midashi-一\">一</a></li></ol></li><li><a href=\"xhtml/title0002.xhtml\">檸檬　梶井基次郎</a></li></ol>"
+        ));
+        let ncx = generator.generate_ncx(&tocs);
+        assert!(ncx.contains("<meta name=\"dtb:depth\" content=\"3\"/>"));
+        assert!(ncx.contains("<navLabel><text>檸檬　梶井基次郎</text></navLabel>\n\t\t\t\t<content src=\"xhtml/title0002.xhtml\"/>"));
+
+        assert!(generator.generate_title_page().contains(
+            "<div class=\"works\">\n            <p>桜桃　太宰治</p>\n            <p>檸檬　梶井基次郎</p>\n        </div>"
+        ));
+        assert!(generator.generate_work_title_page(&generator.works[1]).contains("<p>檸檬</p>"));
+        let generator = generator.with_endnotes();
+        assert_eq!(
+            generator.body_options(1).notes,
+            NotePlacement::Endnote { notes_href: "notes0002.xhtml".to_string(), body_href: "0002.xhtml".to_string() }
+        );
+    }
+
+    #[test]
+    fn test_accessibility_metadata() {
+        let doc = parse(parse_aozora("T\nA\n\n本文\n".to_string()).unwrap());
+        let generator = EpubGenerator::new(doc.metadata.title, doc.metadata.author, parse_blocks(doc.items).unwrap());
+        assert_eq!(
+            generator.accessibility_metadata(),
+            "\t\t<meta property=\"schema:accessMode\">textual</meta>\n\
+             \t\t<meta property=\"schema:accessModeSufficient\">textual</meta>\n\
+             \t\t<meta property=\"schema:accessibilityFeature\">tableOfContents</meta>\n\
+             \t\t<meta property=\"schema:accessibilityFeature\">readingOrder</meta>\n\
+             \t\t<meta property=\"schema:accessibilityHazard\">none</meta>\n"
+        );
+
+        let text = "T\nA\n\n［＃大見出し］一［＃大見出し終わり］\n青空《あおぞら》\n［＃挿絵（fig.png）入る］［＃改ページ］\n";
+        let doc = parse(parse_aozora(text.to_string()).unwrap());
+        let generator = EpubGenerator::new(doc.metadata.title, doc.metadata.author, parse_blocks(doc.items).unwrap());
+        let opf = generator.generate_opf(&[false]);
+        for line in [
+            "<meta property=\"schema:accessMode\">visual</meta>",
+            "<meta property=\"schema:accessModeSufficient\">textual,visual</meta>",
+            "<meta property=\"schema:accessibilityFeature\">structuralNavigation</meta>",
+            "<meta property=\"schema:accessibilityFeature\">rubyAnnotations</meta>",
+            "<meta property=\"schema:accessibilityFeature\">pageNavigation</meta>",
+        ] {
+            assert!(opf.contains(line), "{}", line);
+        }
+        assert!(!opf.contains("<meta property=\"schema:accessModeSufficient\">textual</meta>"));
+    }
+
+    #[test]
+    fn test_page_list() {
+        let documents = vec![
+            parse(parse_aozora("一\n甲\n\n上［＃改ページ］\n中［＃改段］\n下［＃改丁］\n".to_string()).unwrap()),
+            parse(parse_aozora("二\n乙\n\n本文［＃改見開き］\n".to_string()).unwrap()),
+        ];
+        let generator = EpubGenerator::anthology("集".to_string(), "編".to_string(), documents).unwrap();
+        assert_eq!(generator.first_page(1), 4);
+        assert_eq!(generator.body_options(1).first_page, Some(4));
+
+        let nav = generator.generate_nav(&[Vec::new(), Vec::new()]);
+        assert!(nav.contains(
+            "<nav epub:type=\"page-list\" id=\"page-list\" hidden=\"\">\n        <ol>\n\
+            \x20           <li><a href=\"xhtml/0001.xhtml\">1</a></li>\n\
+            \x20           <li><a href=\"xhtml/0001.xhtml#page-2\">2</a></li>\n\
+            \x20           <li><a href=\"xhtml/0001.xhtml#page-3\">3</a></li>\n\
+            \x20           <li><a href=\"xhtml/0002.xhtml\">4</a></li>\n\
+            \x20           <li><a href=\"xhtml/0002.xhtml#page-5\">5</a></li>\n\
+            \x20       </ol>\n    </nav>\n</body>"
+        ));
+        assert_eq!(generator.validate().unwrap(), Vec::new());
+
+        // Without page breaks there is no page-list
+        let doc = parse(parse_aozora("T\nA\n\n本文\n".to_string()).unwrap());
+        let generator = EpubGenerator::new(doc.metadata.title, doc.metadata.author, parse_blocks(doc.items).unwrap());
+        assert!(!generator.generate_nav(&[Vec::new()]).contains("page-list"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let text = "タイトル\n著者\n\n［＃大見出し］一［＃大見出し終わり］\n蒼穹［＃「蒼穹」に「空」の注記］\n［＃改ページ］\n".to_string();
+        let doc = parse(parse_aozora(text).unwrap());
+        let root = parse_blocks(doc.items).unwrap();
+        let generator = EpubGenerator::new(doc.metadata.title, doc.metadata.author, root);
+        assert_eq!(generator.validate().unwrap(), Vec::new());
+
+        let documents = vec![
+            parse(parse_aozora("一\n甲\n\n［＃中見出し］上［＃中見出し終わり］\n蒼穹［＃「蒼穹」に「空」の注記］\n".to_string()).unwrap()),
+            parse(parse_aozora("二\n乙\n\n本文\n".to_string()).unwrap()),
+        ];
+        let generator = EpubGenerator::anthology("集".to_string(), "編".to_string(), documents)
+            .unwrap()
+            .with_endnotes()
+            .with_okuzuke(true);
+        assert_eq!(generator.validate().unwrap(), Vec::new());
+
+        // Images referenced without an image directory are not in the package
+        let doc = parse(parse_aozora("T\nA\n\n［＃挿絵（fig.png）入る］\n".to_string()).unwrap());
+        let generator = EpubGenerator::new(doc.metadata.title, doc.metadata.author, parse_blocks(doc.items).unwrap());
+        assert_eq!(
+            generator.validate().unwrap(),
+            vec![EpubValidationError::BrokenHref { file: "item/xhtml/0001.xhtml".to_string(), href: "fig.png".to_string() }]
+        );
+    }
+
+    #[test]
+    fn test_compression() {
+        let text = "タイトル\n著者\n\n".to_string() + &"本文。".repeat(1000);
+        let doc = parse(parse_aozora(text).unwrap());
+        let root = parse_blocks(doc.items).unwrap();
+        let compression = EpubCompressionOptions {
+            documents: EpubCompression::Deflated(Some(9)),
+            styles: EpubCompression::Stored,
+            images: EpubCompression::Stored,
+        };
+        let generator = EpubGenerator::new(doc.metadata.title, doc.metadata.author, root).with_compression(compression);
+        let bytes = generator.write_to(std::io::Cursor::new(Vec::new())).unwrap().into_inner();
+
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+        let method = |archive: &mut zip::ZipArchive<_>, name: &str| archive.by_name(name).unwrap().compression();
+        assert_eq!(method(&mut archive, "mimetype"), zip::CompressionMethod::Stored);
+        assert_eq!(method(&mut archive, "item/xhtml/0001.xhtml"), zip::CompressionMethod::Deflated);
+        assert_eq!(method(&mut archive, "item/style/aozora.css"), zip::CompressionMethod::Stored);
+    }
+
+    #[test]
+    fn test_ncx() {
+        let text = "タイトル\n著者\n\n［＃大見出し］一［＃大見出し終わり］\n［＃中見出し］二［＃中見出し終わり］\n［＃大見出し］三［＃大見出し終わり］\n".to_string();
+        let doc = parse(parse_aozora(text).unwrap());
+        let root = parse_blocks(doc.items).unwrap();
+        let generator = EpubGenerator::new(doc.metadata.title, doc.metadata.author, root);
+        let tocs = vec![XhtmlGenerator::generate(&generator.works[0].blocks, &generator.title).1];
+
+        let ncx = generator.generate_ncx(&tocs);
+        assert!(ncx.contains(&format!("<meta name=\"dtb:uid\" content=\"{}\"/>", generator.identifier)));
+        assert!(ncx.contains("<meta name=\"dtb:depth\" content=\"3\"/>"));
+        assert!(ncx.contains(
+            "\t\t\t<navPoint id=\"navpoint-2\" playOrder=\"2\">\n\t\t\t\t<navLabel><text>一</text></navLabel>\n\t\t\t\t<content src=\"xhtml/0001.xhtml#midashi-一\"/>\n\t\t\t\t<navPoint id=\"navpoint-3\" playOrder=\"3\">"
+        ));
+        assert!(ncx.contains("<navPoint id=\"navpoint-4\" playOrder=\"4\">"));
+
+        let opf = generator.generate_opf(&[false]);
+        assert!(opf.contains("<item id=\"ncx\" href=\"toc.ncx\" media-type=\"application/x-dtbncx+xml\"/>"));
+        assert!(opf.contains("<spine toc=\"ncx\" "));
+    }
+
+    #[test]
+    fn test_images() {
+        let text = "タイトル\n著者\n\n［＃挿絵（fig1.png）入る］\n［＃挿絵（img/fig2.JPG）入る］\n［＃挿絵（fig1.png）入る］\n".to_string();
+        let doc = parse(parse_aozora(text).unwrap());
+        let root = parse_blocks(doc.items).unwrap();
+        let generator = EpubGenerator::new(doc.metadata.title, doc.metadata.author, root);
+        assert!(!generator.generate_opf(&[false]).contains("image0001"));
+
+        let generator = generator.with_image_dir("images");
+        assert_eq!(generator.images(), vec!["fig1.png", "img/fig2.JPG"]);
+        let opf = generator.generate_opf(&[false]);
+        assert!(opf.contains("<item id=\"image0001\" href=\"image/fig1.png\" media-type=\"image/png\"/>"));
+        assert!(opf.contains("<item id=\"image0002\" href=\"image/img/fig2.JPG\" media-type=\"image/jpeg\"/>"));
+        let (body, _) = XhtmlGenerator::generate_with(&generator.works[0].blocks, &generator.title, &generator.body_options(0));
+        assert!(body.contains("src=\"../image/img/fig2.JPG\""));
+    }
+
+    #[test]
+    fn test_cover_and_stylesheet() {
+        let cover = std::env::temp_dir().join(format!("aozora_parser_cover_{}.JPG", std::process::id()));
+        std::fs::write(&cover, b"jpeg").unwrap();
+        let doc = parse(parse_aozora("T\nA\n\n本文\n".to_string()).unwrap());
+        let generator = EpubGenerator::new(doc.metadata.title, doc.metadata.author, parse_blocks(doc.items).unwrap())
+            .with_cover_image(&cover)
+            .with_stylesheet("body { color: navy; }");
+
+        let opf = generator.generate_opf(&[false]);
+        assert!(opf.contains("<meta name=\"cover\" content=\"cover-image\"/>"));
+        assert!(opf.contains(
+            "<item id=\"cover-image\" href=\"cover.jpg\" media-type=\"image/jpeg\" properties=\"cover-image\"/>"
+        ));
+        let css = generator.get_css_contents();
+        let book_style = &css.iter().find(|(name, _)| name == "book-style.css").unwrap().1;
+        assert!(book_style.ends_with("\nbody { color: navy; }\n"));
+
+        let bytes = generator.write_to(Cursor::new(Vec::new())).unwrap().into_inner();
+        std::fs::remove_file(&cover).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes.clone())).unwrap();
+        assert_eq!(archive.by_name("item/cover.jpg").unwrap().size(), 4);
+        assert_eq!(validate_epub(&bytes).unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn test_endnotes() {
+        let text = "タイトル\n著者\n\n蒼穹［＃「蒼穹」に「あおぞら」の注記］".to_string();
+        let doc = parse(parse_aozora(text).unwrap());
+        let root = parse_blocks(doc.items).unwrap();
+        let generator = EpubGenerator::new(doc.metadata.title, doc.metadata.author, root).with_endnotes();
+
+        let (body, _, notes) = XhtmlGenerator::generate_with_notes(
+            &generator.works[0].blocks,
+            &generator.title,
+            &generator.body_options(0),
+        );
+        assert!(body.contains("href=\"notes.xhtml#note-1\""));
+        assert!(notes.unwrap().contains("href=\"0001.xhtml#noteref-1\""));
+
+        let opf = generator.generate_opf(&[true]);
+        assert!(opf.contains("<item id=\"notes\" href=\"xhtml/notes.xhtml\""));
+        assert!(opf.contains("<itemref linear=\"yes\" idref=\"notes\"/>"));
+        assert!(!generator.generate_opf(&[false]).contains("notes"));
+    }
+
     #[test]
     fn generate_outou_test_epub() {
         let mut source_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -182,7 +1453,7 @@ mod tests {
         let text = cow.into_owned();
 
         let tokens = parse_aozora(text).expect("Tokenization failed");
-        let doc = parse(tokens).expect("Parsing failed");
+        let doc = parse(tokens);
         let root = parse_blocks(doc.items).expect("Block parsing failed");
 
         let generator = EpubGenerator::new(
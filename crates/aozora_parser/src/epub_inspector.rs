@@ -0,0 +1,414 @@
+//! Reads back an EPUB package: its files, metadata, spine and table of contents, and the
+//! text of its content documents. Meant for finding out why a book misbehaves in a
+//! reader, so it reads any EPUB, not only the ones generated here.
+
+use crate::epub_validator::{parent_dir, parse_xml, resolve, ParsedXml, XmlNode, ATTR_PATTERN, BLOCK_ELEMENTS};
+use regex::Regex;
+use std::collections::HashMap;
+use std::io::{self, Cursor, Read};
+use zip::ZipArchive;
+
+/// What is inside an EPUB package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EpubInfo {
+    /// Path of the package document (the OPF file)
+    pub package: String,
+    /// Files in the order they are stored in the archive
+    pub files: Vec<EpubFile>,
+    /// `dc:*` elements and `meta` elements of the package metadata, in document order.
+    /// A `meta` is keyed by its `property` (or `name`, with the `content` as value).
+    pub metadata: Vec<(String, String)>,
+    /// Paths of the content documents in reading order
+    pub spine: Vec<String>,
+    /// Entries of the nav document, or of the NCX if there is none
+    pub toc: Vec<EpubTocEntry>,
+}
+
+/// A file in an EPUB archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EpubFile {
+    pub path: String,
+    /// Uncompressed size in bytes
+    pub size: u64,
+    /// Media type declared in the manifest; `None` for files outside of it
+    pub media_type: Option<String>,
+}
+
+/// An entry of the table of contents of an EPUB.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EpubTocEntry {
+    /// Nesting depth, starting at 1
+    pub depth: usize,
+    pub text: String,
+    /// Target resolved to a path in the archive, with the fragment if there is one
+    pub href: String,
+}
+
+/// An opened archive with its package document parsed.
+struct Package {
+    files: Vec<EpubFile>,
+    contents: HashMap<String, Vec<u8>>,
+    opf_path: String,
+    opf: ParsedXml,
+    re_attr: Regex,
+}
+
+impl Package {
+    fn open(bytes: &[u8]) -> io::Result<Self> {
+        let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+        let mut files = Vec::new();
+        let mut contents = HashMap::new();
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i)?;
+            if entry.is_dir() {
+                continue;
+            }
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content)?;
+            files.push(EpubFile { path: entry.name().to_string(), size: entry.size(), media_type: None });
+            contents.insert(entry.name().to_string(), content);
+        }
+
+        let re_attr = Regex::new(ATTR_PATTERN).unwrap();
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let container = contents
+            .get("META-INF/container.xml")
+            .ok_or_else(|| invalid("META-INF/container.xml is missing"))?;
+        let container = parse_xml(&String::from_utf8_lossy(container), &re_attr);
+        let opf_path = container
+            .tags
+            .iter()
+            .find(|t| t.name == "rootfile")
+            .and_then(|t| t.attr("full-path"))
+            .ok_or_else(|| invalid("META-INF/container.xml does not point at a package document"))?
+            .to_string();
+        let opf = contents.get(&opf_path).ok_or_else(|| invalid("the package document is missing"))?;
+        let opf = parse_xml(&String::from_utf8_lossy(opf), &re_attr);
+
+        let mut package = Package { files, contents, opf_path, opf, re_attr };
+        let media_types: HashMap<String, String> = package
+            .items()
+            .map(|(_, path, media_type, _)| (path, media_type.to_string()))
+            .collect();
+        for file in &mut package.files {
+            file.media_type = media_types.get(&file.path).cloned();
+        }
+        Ok(package)
+    }
+
+    /// Manifest items as (id, path in the archive, media type, properties).
+    fn items(&self) -> impl Iterator<Item = (&str, String, &str, &str)> {
+        let dir = parent_dir(&self.opf_path);
+        self.opf.tags.iter().filter(|t| t.name == "item").filter_map(move |t| {
+            Some((
+                t.attr("id")?,
+                resolve(dir, t.attr("href")?),
+                t.attr("media-type").unwrap_or(""),
+                t.attr("properties").unwrap_or(""),
+            ))
+        })
+    }
+
+    fn spine(&self) -> Vec<String> {
+        let paths: HashMap<&str, String> = self.items().map(|(id, path, _, _)| (id, path)).collect();
+        self.opf
+            .tags
+            .iter()
+            .filter(|t| t.name == "itemref")
+            .filter_map(|t| paths.get(t.attr("idref")?).cloned())
+            .collect()
+    }
+
+    fn metadata(&self) -> Vec<(String, String)> {
+        let mut metadata = Vec::new();
+        let mut in_metadata = false;
+        let mut open: Option<(String, String)> = None;
+        for node in &self.opf.nodes {
+            match node {
+                XmlNode::Start(i) => {
+                    let tag = &self.opf.tags[*i];
+                    match tag.name.as_str() {
+                        "metadata" => in_metadata = true,
+                        "meta" if in_metadata => match (tag.attr("property"), tag.attr("name")) {
+                            (Some(property), _) => open = Some((property.to_string(), String::new())),
+                            (None, Some(name)) => {
+                                metadata.push((name.to_string(), tag.attr("content").unwrap_or("").to_string()))
+                            }
+                            (None, None) => {}
+                        },
+                        name if in_metadata && name.starts_with("dc:") => open = Some((name.to_string(), String::new())),
+                        _ => {}
+                    }
+                }
+                XmlNode::Text(text) => {
+                    if let Some((_, value)) = &mut open {
+                        value.push_str(text);
+                    }
+                }
+                XmlNode::End(name) if name == "metadata" => in_metadata = false,
+                XmlNode::End(_) => {
+                    if let Some((key, value)) = open.take() {
+                        metadata.push((key, value.trim().to_string()));
+                    }
+                }
+            }
+        }
+        metadata
+    }
+
+    fn document(&self, path: &str) -> Option<ParsedXml> {
+        let bytes = self.contents.get(path)?;
+        Some(parse_xml(&String::from_utf8_lossy(bytes), &self.re_attr))
+    }
+
+    /// The `toc` nav of the nav document, falling back to the NCX of EPUB 2.
+    fn toc(&self) -> Vec<EpubTocEntry> {
+        let nav = self.items().find(|(_, _, _, properties)| properties.split_whitespace().any(|p| p == "nav"));
+        if let Some((_, path, _, _)) = nav
+            && let Some(xml) = self.document(&path)
+        {
+            return nav_entries(&xml, parent_dir(&path));
+        }
+        let ncx = self.items().find(|(_, _, media_type, _)| *media_type == "application/x-dtbncx+xml");
+        match ncx.and_then(|(_, path, _, _)| Some((self.document(&path)?, path))) {
+            Some((xml, path)) => ncx_entries(&xml, parent_dir(&path)),
+            None => Vec::new(),
+        }
+    }
+}
+
+fn nav_entries(xml: &ParsedXml, dir: &str) -> Vec<EpubTocEntry> {
+    let mut entries = Vec::new();
+    // Depth of <nav> elements and of <ol> inside the toc nav
+    let (mut navs, mut toc_nav, mut depth) = (0usize, None, 0);
+    let mut link: Option<EpubTocEntry> = None;
+    for node in &xml.nodes {
+        match node {
+            XmlNode::Start(i) => {
+                let tag = &xml.tags[*i];
+                match tag.name.as_str() {
+                    "nav" => {
+                        navs += 1;
+                        let is_toc = tag.attr("epub:type").is_some_and(|t| t.split_whitespace().any(|t| t == "toc"));
+                        if is_toc && toc_nav.is_none() {
+                            toc_nav = Some(navs);
+                        }
+                    }
+                    "ol" if toc_nav.is_some() => depth += 1,
+                    "a" if toc_nav.is_some() => {
+                        link = Some(EpubTocEntry {
+                            depth,
+                            text: String::new(),
+                            href: resolve_href(dir, tag.attr("href").unwrap_or("")),
+                        })
+                    }
+                    _ => {}
+                }
+            }
+            XmlNode::Text(text) => {
+                if let Some(link) = &mut link {
+                    link.text.push_str(text);
+                }
+            }
+            XmlNode::End(name) => match name.as_str() {
+                "nav" => {
+                    if toc_nav == Some(navs) {
+                        break;
+                    }
+                    navs = navs.saturating_sub(1);
+                }
+                // Stray closing tags are ignored rather than trusted
+                "ol" if toc_nav.is_some() => depth = depth.saturating_sub(1),
+                "a" => {
+                    if let Some(mut link) = link.take() {
+                        link.text = link.text.trim().to_string();
+                        entries.push(link);
+                    }
+                }
+                _ => {}
+            },
+        }
+    }
+    entries
+}
+
+fn ncx_entries(xml: &ParsedXml, dir: &str) -> Vec<EpubTocEntry> {
+    let mut entries: Vec<EpubTocEntry> = Vec::new();
+    let (mut depth, mut in_text) = (0, false);
+    for node in &xml.nodes {
+        match node {
+            XmlNode::Start(i) => {
+                let tag = &xml.tags[*i];
+                match tag.name.as_str() {
+                    "navPoint" => {
+                        depth += 1;
+                        entries.push(EpubTocEntry { depth, text: String::new(), href: String::new() });
+                    }
+                    "text" => in_text = depth > 0,
+                    "content" => {
+                        if let (Some(entry), Some(src)) = (entries.last_mut(), tag.attr("src")) {
+                            entry.href = resolve_href(dir, src);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            XmlNode::Text(text) if in_text => {
+                if let Some(entry) = entries.last_mut() {
+                    entry.text.push_str(text.trim());
+                }
+            }
+            XmlNode::Text(_) => {}
+            XmlNode::End(name) => match name.as_str() {
+                "navPoint" => depth = depth.saturating_sub(1),
+                "text" => in_text = false,
+                _ => {}
+            },
+        }
+    }
+    entries
+}
+
+/// Resolve a link relative to `dir`, keeping its fragment.
+fn resolve_href(dir: &str, href: &str) -> String {
+    match href.split_once('#') {
+        Some((file, fragment)) => format!("{}#{}", resolve(dir, file), fragment),
+        None => resolve(dir, href),
+    }
+}
+
+/// List the files, metadata, spine and table of contents of an EPUB archive.
+pub fn inspect_epub(bytes: &[u8]) -> io::Result<EpubInfo> {
+    let package = Package::open(bytes)?;
+    Ok(EpubInfo {
+        package: package.opf_path.clone(),
+        metadata: package.metadata(),
+        spine: package.spine(),
+        toc: package.toc(),
+        files: package.files,
+    })
+}
+
+/// Text of the content documents of an EPUB archive in reading order, one line per
+/// paragraph. Ruby is written in Aozora Bunko notation (`漢字《かんじ》`), so the result
+/// reads close to the text the book was made from.
+pub fn extract_epub_text(bytes: &[u8]) -> io::Result<String> {
+    let package = Package::open(bytes)?;
+    let mut out = String::new();
+    for path in package.spine() {
+        let Some(xml) = package.document(&path) else { continue };
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        document_text(&xml, &mut out);
+    }
+    Ok(out)
+}
+
+fn document_text(xml: &ParsedXml, out: &mut String) {
+    let new_line = |out: &mut String| {
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+    };
+    // For each open element, whether its text is not part of the body
+    let mut open: Vec<bool> = Vec::new();
+    let mut hidden = 0;
+    for node in &xml.nodes {
+        match node {
+            XmlNode::Start(i) => {
+                let tag = &xml.tags[*i];
+                let hides = matches!(tag.name.as_str(), "head" | "script" | "style" | "rp") || tag.attr("hidden").is_some();
+                open.push(hides);
+                hidden += usize::from(hides);
+                match tag.name.as_str() {
+                    "rt" if hidden == 0 => out.push('《'),
+                    "br" if hidden == 0 => out.push('\n'),
+                    name if BLOCK_ELEMENTS.contains(&name) => new_line(out),
+                    _ => {}
+                }
+            }
+            XmlNode::End(name) => {
+                hidden -= usize::from(open.pop().unwrap_or(false));
+                match name.as_str() {
+                    "rt" if hidden == 0 => out.push('》'),
+                    name if BLOCK_ELEMENTS.contains(&name) => new_line(out),
+                    _ => {}
+                }
+            }
+            // Indentation of the markup, not text (U+3000 indents paragraphs and is kept)
+            XmlNode::Text(text) if text.chars().all(|c| c.is_ascii_whitespace()) => {}
+            XmlNode::Text(text) if hidden == 0 => out.push_str(text),
+            XmlNode::Text(_) => {}
+        }
+    }
+    new_line(out);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_parser::parse_blocks;
+    use crate::epub_generator::EpubGenerator;
+    use crate::parser::parse;
+    use crate::tokenizer::parse_aozora;
+
+    fn book() -> Vec<u8> {
+        let text = "桜桃\n太宰治\n\n［＃大見出し］一［＃大見出し終わり］\n　青空《あおぞら》の下。\n\n［＃中見出し］二［＃中見出し終わり］\n　終わり。\n";
+        let blocks = parse_blocks(parse(parse_aozora(text.to_string()).unwrap()).items).unwrap();
+        EpubGenerator::new("桜桃".to_string(), "太宰治".to_string(), blocks)
+            .write_to(Cursor::new(Vec::new()))
+            .unwrap()
+            .into_inner()
+    }
+
+    #[test]
+    fn test_inspect_epub() {
+        let info = inspect_epub(&book()).unwrap();
+        assert_eq!(info.package, "item/standard.opf");
+        assert_eq!(info.files[0], EpubFile { path: "mimetype".to_string(), size: 20, media_type: None });
+        assert!(info.files.iter().any(|f| f.media_type.as_deref() == Some("application/xhtml+xml")));
+        assert!(info.metadata.contains(&("dc:title".to_string(), "桜桃".to_string())));
+        assert!(info.metadata.contains(&("dc:creator".to_string(), "太宰治".to_string())));
+        assert!(info.metadata.iter().any(|(key, _)| key == "dcterms:modified"));
+        assert!(info.spine.iter().all(|path| path.starts_with("item/")));
+
+        let toc: Vec<(usize, &str)> = info.toc.iter().map(|e| (e.depth, e.text.as_str())).collect();
+        assert_eq!(toc, [(1, "桜桃"), (2, "一"), (3, "二")]);
+        let first = info.toc.iter().find(|e| e.text == "一").unwrap();
+        assert!(first.href.starts_with("item/xhtml/") && first.href.contains('#'), "{}", first.href);
+    }
+
+    #[test]
+    fn test_extract_epub_text() {
+        let text = extract_epub_text(&book()).unwrap();
+        assert!(text.trim_start().starts_with("桜桃\n太宰治\n"), "{:?}", text);
+        // The landmarks of the nav document are hidden
+        assert!(!text.contains("Guide"), "{:?}", text);
+        // Blank lines come from the empty paragraphs the generator writes for line ends
+        assert!(text.contains("一\n\n　青空《あおぞら》の下。\n\n二\n\n　終わり。\n"), "{:?}", text);
+    }
+
+    #[test]
+    fn test_malformed_toc() {
+        let xml = |text: &str| parse_xml(text, &Regex::new(ATTR_PATTERN).unwrap());
+
+        let nav = xml("</nav><nav epub:type=\"toc\"><a href=\"x.xhtml\">x</a></ol></ol><ol><li><a href=\"y.xhtml\">y</a></li></ol></nav>");
+        let entries = nav_entries(&nav, "item");
+        let toc: Vec<(usize, &str)> = entries.iter().map(|e| (e.depth, e.text.as_str())).collect();
+        assert_eq!(toc, [(0, "x"), (1, "y")]);
+
+        let ncx = xml("<navMap></navPoint><navPoint><navLabel><text>x</text></navLabel></navPoint></navPoint><navPoint><navLabel><text>y</text></navLabel></navPoint></navMap>");
+        let entries = ncx_entries(&ncx, "item");
+        let toc: Vec<(usize, &str)> = entries.iter().map(|e| (e.depth, e.text.as_str())).collect();
+        assert_eq!(toc, [(1, "x"), (1, "y")]);
+    }
+
+    #[test]
+    fn test_not_an_epub() {
+        assert!(inspect_epub(b"not a zip").is_err());
+    }
+}
@@ -0,0 +1,427 @@
+//! Structural checks of an EPUB package, catching the most common epubcheck failures
+//! without running epubcheck: files missing from or undeclared in the manifest, broken
+//! internal links and fragment ids, a missing nav document and badly nested XHTML.
+
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+
+/// A problem found in an EPUB package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EpubValidationError {
+    /// META-INF/container.xml is missing or does not point at a package document
+    MissingPackage,
+    /// A file in the archive is not declared in the manifest
+    UndeclaredItem { path: String },
+    /// A manifest item points at a file that is not in the archive
+    MissingItem { href: String },
+    /// A spine itemref points at an id that is not in the manifest
+    UnknownSpineItem { idref: String },
+    /// No manifest item has the `nav` property
+    MissingNav,
+    /// A link or src in `file` points at a file that is not in the package
+    BrokenHref { file: String, href: String },
+    /// A link in `file` points at an id that does not exist in the target document
+    BrokenFragment { file: String, href: String },
+    /// `file` is not well-formed or nests elements where they are not allowed
+    InvalidNesting { file: String, message: String },
+}
+
+impl fmt::Display for EpubValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EpubValidationError::MissingPackage => write!(f, "META-INF/container.xml does not point at a package document"),
+            EpubValidationError::UndeclaredItem { path } => write!(f, "{}: not declared in the manifest", path),
+            EpubValidationError::MissingItem { href } => write!(f, "{}: declared in the manifest but missing", href),
+            EpubValidationError::UnknownSpineItem { idref } => write!(f, "spine item \"{}\" is not in the manifest", idref),
+            EpubValidationError::MissingNav => write!(f, "no manifest item has the nav property"),
+            EpubValidationError::BrokenHref { file, href } => write!(f, "{}: link to missing file {}", file, href),
+            EpubValidationError::BrokenFragment { file, href } => write!(f, "{}: link to missing id {}", file, href),
+            EpubValidationError::InvalidNesting { file, message } => write!(f, "{}: {}", file, message),
+        }
+    }
+}
+
+impl std::error::Error for EpubValidationError {}
+
+/// Elements that may only contain phrasing content
+const PHRASING_PARENTS: &[&str] = &[
+    "p", "h1", "h2", "h3", "h4", "h5", "h6", "span", "a", "ruby", "rt", "rp", "em", "strong", "b", "i",
+    "sup", "sub", "small", "navLabel",
+];
+
+/// An attribute of a tag, with its value in double or single quotes
+pub(crate) const ATTR_PATTERN: &str = r#"([\w:.-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#;
+
+/// Flow elements that cannot appear inside phrasing content
+pub(crate) const BLOCK_ELEMENTS: &[&str] = &[
+    "div", "p", "h1", "h2", "h3", "h4", "h5", "h6", "ol", "ul", "li", "section", "aside", "nav", "table",
+    "figure", "blockquote", "hr", "header", "footer",
+];
+
+/// A start tag, with its attributes unescaped.
+pub(crate) struct Tag {
+    pub(crate) name: String,
+    attrs: Vec<(String, String)>,
+}
+
+impl Tag {
+    pub(crate) fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+}
+
+/// Start tags of a parsed document, and the problems found while parsing it.
+pub(crate) struct ParsedXml {
+    pub(crate) tags: Vec<Tag>,
+    /// The document in order, for reading text: an empty element is a start followed by
+    /// an end
+    pub(crate) nodes: Vec<XmlNode>,
+    errors: Vec<String>,
+}
+
+pub(crate) enum XmlNode {
+    /// Index in [`ParsedXml::tags`]
+    Start(usize),
+    End(String),
+    /// Text with the entities unescaped
+    Text(String),
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Scan the tags of an XML document, checking that they are balanced and that no block
+/// element is opened inside an element that only takes phrasing content.
+pub(crate) fn parse_xml(text: &str, re_attr: &Regex) -> ParsedXml {
+    let mut parsed = ParsedXml { tags: Vec::new(), nodes: Vec::new(), errors: Vec::new() };
+    let mut stack: Vec<String> = Vec::new();
+    let mut rest = text;
+
+    while let Some(open) = rest.find('<') {
+        if open > 0 {
+            parsed.nodes.push(XmlNode::Text(unescape(&rest[..open])));
+        }
+        rest = &rest[open..];
+        let skip_to = |rest: &str, end: &str| rest.find(end).map(|i| i + end.len());
+        let consumed = if rest.starts_with("<!--") {
+            skip_to(rest, "-->")
+        } else if rest.starts_with("<?") {
+            skip_to(rest, "?>")
+        } else if rest.starts_with("<!") {
+            skip_to(rest, ">")
+        } else if let Some(close) = rest.strip_prefix("</") {
+            let end = close.find('>');
+            if let Some(end) = end {
+                let name = close[..end].trim();
+                parsed.nodes.push(XmlNode::End(name.to_string()));
+                match stack.pop() {
+                    Some(open) if open == name => {}
+                    Some(open) => parsed.errors.push(format!("</{}> closes <{}>", name, open)),
+                    None => parsed.errors.push(format!("</{}> without a start tag", name)),
+                }
+            }
+            end.map(|e| e + 3)
+        } else {
+            // Find the end of the tag, ignoring > inside quoted attribute values
+            let mut quote = None;
+            let end = rest.char_indices().skip(1).find(|&(_, c)| match quote {
+                Some(q) => {
+                    if c == q {
+                        quote = None;
+                    }
+                    false
+                }
+                None if c == '"' || c == '\'' => {
+                    quote = Some(c);
+                    false
+                }
+                None => c == '>',
+            });
+            end.map(|(end, _)| {
+                let inner = &rest[1..end];
+                let self_closing = inner.ends_with('/');
+                let inner = inner.trim_end_matches('/');
+                let name_end = inner.find(|c: char| c.is_whitespace()).unwrap_or(inner.len());
+                let name = inner[..name_end].to_string();
+                let attrs = re_attr
+                    .captures_iter(&inner[name_end..])
+                    .map(|caps| {
+                        let value = caps.get(2).or(caps.get(3)).map_or("", |m| m.as_str());
+                        (caps[1].to_string(), unescape(value))
+                    })
+                    .collect();
+
+                if BLOCK_ELEMENTS.contains(&name.as_str())
+                    && let Some(parent) = stack.iter().rev().find(|open| PHRASING_PARENTS.contains(&open.as_str()))
+                {
+                    parsed.errors.push(format!("<{}> inside <{}>", name, parent));
+                }
+                parsed.nodes.push(XmlNode::Start(parsed.tags.len()));
+                if self_closing {
+                    parsed.nodes.push(XmlNode::End(name.clone()));
+                } else {
+                    stack.push(name.clone());
+                }
+                parsed.tags.push(Tag { name, attrs });
+                end + 1
+            })
+        };
+        match consumed {
+            Some(n) => rest = &rest[n..],
+            None => {
+                parsed.errors.push("unterminated markup".to_string());
+                rest = "";
+                break;
+            }
+        }
+    }
+    if !rest.is_empty() {
+        parsed.nodes.push(XmlNode::Text(unescape(rest)));
+    }
+    for open in stack.iter().rev() {
+        parsed.errors.push(format!("<{}> is not closed", open));
+    }
+    parsed
+}
+
+/// Directory part of a path in the archive, with a trailing slash (empty for the root).
+pub(crate) fn parent_dir(path: &str) -> &str {
+    path.rfind('/').map_or("", |i| &path[..=i])
+}
+
+/// Resolve `href` relative to the directory `base`, normalizing `.` and `..`.
+pub(crate) fn resolve(base: &str, href: &str) -> String {
+    let mut parts: Vec<&str> = base.split('/').filter(|p| !p.is_empty()).collect();
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+/// Check the structure of an EPUB archive.
+/// Returns the problems found; an empty list means the package passed every check.
+pub fn validate_epub(bytes: &[u8]) -> std::io::Result<Vec<EpubValidationError>> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+    let mut files: HashMap<String, String> = HashMap::new();
+    let mut binary: HashSet<String> = HashSet::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let name = entry.name().to_string();
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        match String::from_utf8(content) {
+            Ok(text) => {
+                files.insert(name, text);
+            }
+            Err(_) => {
+                binary.insert(name);
+            }
+        }
+    }
+    let exists = |path: &str| files.contains_key(path) || binary.contains(path);
+
+    let re_attr = Regex::new(ATTR_PATTERN).unwrap();
+    let mut errors = Vec::new();
+
+    // Package document
+    let opf_path = files
+        .get("META-INF/container.xml")
+        .map(|container| parse_xml(container, &re_attr))
+        .and_then(|xml| {
+            xml.tags.iter().find(|t| t.name == "rootfile").and_then(|t| t.attr("full-path")).map(str::to_string)
+        });
+    let Some(opf) = opf_path.as_deref().and_then(|path| files.get(path).map(|text| (path, text))) else {
+        errors.push(EpubValidationError::MissingPackage);
+        return Ok(errors);
+    };
+    let (opf_path, opf_text) = opf;
+    let opf_dir = parent_dir(opf_path);
+    let package = parse_xml(opf_text, &re_attr);
+    for message in package.errors {
+        errors.push(EpubValidationError::InvalidNesting { file: opf_path.to_string(), message });
+    }
+
+    // Manifest and spine
+    let mut manifest: HashMap<String, String> = HashMap::new();
+    let mut declared: HashSet<String> = HashSet::new();
+    let mut documents = Vec::new();
+    let mut has_nav = false;
+    for tag in package.tags.iter().filter(|t| t.name == "item") {
+        let (Some(id), Some(href)) = (tag.attr("id"), tag.attr("href")) else { continue };
+        let path = resolve(opf_dir, href);
+        if !exists(&path) {
+            errors.push(EpubValidationError::MissingItem { href: href.to_string() });
+        }
+        let properties = tag.attr("properties").unwrap_or("");
+        has_nav |= properties.split_whitespace().any(|p| p == "nav");
+        let media_type = tag.attr("media-type").unwrap_or("");
+        if media_type == "application/xhtml+xml" || media_type == "application/x-dtbncx+xml" {
+            documents.push(path.clone());
+        }
+        manifest.insert(id.to_string(), path.clone());
+        declared.insert(path);
+    }
+    if !has_nav {
+        errors.push(EpubValidationError::MissingNav);
+    }
+    for tag in package.tags.iter().filter(|t| t.name == "itemref") {
+        if let Some(idref) = tag.attr("idref")
+            && !manifest.contains_key(idref)
+        {
+            errors.push(EpubValidationError::UnknownSpineItem { idref: idref.to_string() });
+        }
+    }
+
+    let mut paths: Vec<&String> = files.keys().chain(binary.iter()).collect();
+    paths.sort();
+    for path in paths {
+        let is_package_file = path == "mimetype" || path.starts_with("META-INF/") || path == opf_path;
+        if !is_package_file && !declared.contains(path) {
+            errors.push(EpubValidationError::UndeclaredItem { path: path.clone() });
+        }
+    }
+
+    // Content documents: nesting, then links once every document's ids are known
+    let mut parsed = Vec::new();
+    let mut ids: HashMap<&str, HashSet<String>> = HashMap::new();
+    documents.sort();
+    for path in &documents {
+        let Some(text) = files.get(path) else { continue };
+        let xml = parse_xml(text, &re_attr);
+        for message in &xml.errors {
+            errors.push(EpubValidationError::InvalidNesting { file: path.clone(), message: message.clone() });
+        }
+        ids.insert(path, xml.tags.iter().filter_map(|t| t.attr("id").map(str::to_string)).collect());
+        parsed.push((path, xml));
+    }
+    for (path, xml) in &parsed {
+        for tag in &xml.tags {
+            let href = match tag.name.as_str() {
+                "a" | "link" => tag.attr("href"),
+                "img" | "content" => tag.attr("src"),
+                _ => None,
+            };
+            let Some(href) = href else { continue };
+            if href.contains("://") || href.starts_with("mailto:") || href.is_empty() {
+                continue;
+            }
+            let (file_part, fragment) = match href.split_once('#') {
+                Some((file, fragment)) => (file, Some(fragment)),
+                None => (href, None),
+            };
+            let target = if file_part.is_empty() {
+                path.to_string()
+            } else {
+                resolve(parent_dir(path), file_part)
+            };
+            if !exists(&target) {
+                errors.push(EpubValidationError::BrokenHref { file: path.to_string(), href: href.to_string() });
+            } else if let (Some(fragment), Some(target_ids)) = (fragment, ids.get(target.as_str()))
+                && !target_ids.contains(fragment)
+            {
+                errors.push(EpubValidationError::BrokenFragment { file: path.to_string(), href: href.to_string() });
+            }
+        }
+    }
+    Ok(errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    fn archive(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    const CONTAINER: &str = r#"<container><rootfiles><rootfile full-path="item/standard.opf"/></rootfiles></container>"#;
+
+    #[test]
+    fn test_validate_epub() {
+        let opf = r#"<package><manifest>
+<item id="nav" href="nav.xhtml" properties="nav" media-type="application/xhtml+xml"/>
+<item id="sec" href="xhtml/0001.xhtml" media-type="application/xhtml+xml"/>
+<item id="gone" href="xhtml/gone.xhtml" media-type="application/xhtml+xml"/>
+</manifest><spine><itemref idref="sec"/><itemref idref="nope"/></spine></package>"#;
+        let nav = r#"<html><body><nav><ol><li><a href="xhtml/0001.xhtml#a">A</a></li><li><a href="xhtml/0001.xhtml#b">B</a></li></ol></nav></body></html>"#;
+        let body = r#"<html><body><p id="a">x<div class="page-break"></div></p><img src="../image/missing.png" alt=""/></body></html>"#;
+        let bytes = archive(&[
+            ("mimetype", "application/epub+zip"),
+            ("META-INF/container.xml", CONTAINER),
+            ("item/standard.opf", opf),
+            ("item/nav.xhtml", nav),
+            ("item/xhtml/0001.xhtml", body),
+            ("item/extra.css", ""),
+        ]);
+
+        assert_eq!(
+            validate_epub(&bytes).unwrap(),
+            vec![
+                EpubValidationError::MissingItem { href: "xhtml/gone.xhtml".to_string() },
+                EpubValidationError::UnknownSpineItem { idref: "nope".to_string() },
+                EpubValidationError::UndeclaredItem { path: "item/extra.css".to_string() },
+                EpubValidationError::InvalidNesting {
+                    file: "item/xhtml/0001.xhtml".to_string(),
+                    message: "<div> inside <p>".to_string(),
+                },
+                EpubValidationError::BrokenFragment {
+                    file: "item/nav.xhtml".to_string(),
+                    href: "xhtml/0001.xhtml#b".to_string(),
+                },
+                EpubValidationError::BrokenHref {
+                    file: "item/xhtml/0001.xhtml".to_string(),
+                    href: "../image/missing.png".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_package_and_nav() {
+        let bytes = archive(&[("mimetype", "application/epub+zip")]);
+        assert_eq!(validate_epub(&bytes).unwrap(), vec![EpubValidationError::MissingPackage]);
+
+        let opf = r#"<package><manifest><item id="sec" href="0001.xhtml" media-type="application/xhtml+xml"/></manifest></package>"#;
+        let bytes = archive(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("item/standard.opf", opf),
+            ("item/0001.xhtml", "<html><body><p>x</span></body></html>"),
+        ]);
+        assert_eq!(
+            validate_epub(&bytes).unwrap(),
+            vec![
+                EpubValidationError::MissingNav,
+                EpubValidationError::InvalidNesting {
+                    file: "item/0001.xhtml".to_string(),
+                    message: "</span> closes <p>".to_string(),
+                },
+            ]
+        );
+    }
+}
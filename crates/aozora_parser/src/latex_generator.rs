@@ -0,0 +1,238 @@
+//! Renders parsed blocks as a LaTeX document for print-quality PDF via an external TeX
+//! toolchain.
+//!
+//! The output uses the jlreq class (vertical with the `tate` option) and pxrubrica for
+//! `\ruby{}{}` and `\kenten{}`, which work with both LuaLaTeX and pLaTeX. 見出し become
+//! `\chapter*` / `\section*` / `\subsection*`, 字下げ blocks a `jisage` environment and
+//! 注記 footnotes.
+
+use crate::block_parser::{AozoraBlock, BlockElement};
+use crate::parser::{ParsedItem, SpecialCharacter};
+use crate::tokenizer::command::{Command, CommandBegin, Midashi, MidashiSize, SingleCommand};
+use crate::xhtml_generator::WritingMode;
+
+/// TeX engine the document is written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LatexEngine {
+    #[default]
+    LuaLatex,
+    /// pLaTeX (with dvipdfmx)
+    Platex,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LatexGeneratorOptions {
+    pub engine: LatexEngine,
+    /// Vertical (縦組み) by default
+    pub writing_mode: WritingMode,
+}
+
+/// Preamble definitions shared by both engines.
+const PREAMBLE: &str = r"\usepackage{pxrubrica}
+\newenvironment{jisage}[1]{\begin{list}{}{\setlength{\leftmargin}{#1\zw}\setlength{\topsep}{0pt}}\item\relax}{\end{list}}
+";
+
+pub struct LatexGenerator {
+    body: String,
+    /// Whether the current source line has produced any output
+    line_has_content: bool,
+}
+
+impl LatexGenerator {
+    pub fn generate(block: &AozoraBlock, title: &str, author: &str) -> String {
+        Self::generate_with(block, title, author, &LatexGeneratorOptions::default())
+    }
+
+    pub fn generate_with(
+        block: &AozoraBlock,
+        title: &str,
+        author: &str,
+        options: &LatexGeneratorOptions,
+    ) -> String {
+        let mut generator = LatexGenerator { body: String::new(), line_has_content: false };
+        generator.render_block(block);
+
+        let mut class_options = Vec::new();
+        if options.engine == LatexEngine::Platex {
+            class_options.push("platex");
+        }
+        if options.writing_mode == WritingMode::VerticalRl {
+            class_options.push("tate");
+        }
+        class_options.push("book");
+
+        format!(
+            "\\documentclass[{}]{{jlreq}}\n{}\\title{{{}}}\n\\author{{{}}}\n\\date{{}}\n\n\\begin{{document}}\n\\maketitle\n\n{}\n\\end{{document}}\n",
+            class_options.join(","),
+            PREAMBLE,
+            escape_latex(title),
+            escape_latex(author),
+            generator.body.trim_end()
+        )
+    }
+
+    fn push(&mut self, s: &str) {
+        self.body.push_str(s);
+        self.line_has_content = true;
+    }
+
+    /// Start a command that has to begin on its own line.
+    fn push_line(&mut self, s: &str) {
+        if self.line_has_content && !self.body.ends_with('\n') {
+            self.body.push_str("\n\n");
+        }
+        self.body.push_str(s);
+        self.body.push('\n');
+        self.line_has_content = true;
+    }
+
+    fn heading(&mut self, m: &Midashi, text: &str) {
+        let command = match m.size {
+            MidashiSize::Large => "chapter",
+            MidashiSize::Middle => "section",
+            MidashiSize::Small => "subsection",
+        };
+        self.push_line(&format!("\\{}*{{{}}}", command, text.trim()));
+    }
+
+    fn render_block(&mut self, block: &AozoraBlock) {
+        match &block.decoration {
+            Some(CommandBegin::Midashi(m)) => {
+                let (outer, has_content) = (std::mem::take(&mut self.body), self.line_has_content);
+                self.render_elements(block);
+                let text = std::mem::replace(&mut self.body, outer);
+                self.line_has_content = has_content;
+                self.heading(m, &text);
+            }
+            Some(CommandBegin::Alignment(a)) if a.is_upper => {
+                self.push_line(&format!("\\begin{{jisage}}{{{}}}", a.space));
+                self.render_elements(block);
+                self.push_line("\\end{jisage}");
+            }
+            Some(CommandBegin::Alignment(_)) => {
+                self.push_line("\\begin{flushright}");
+                self.render_elements(block);
+                self.push_line("\\end{flushright}");
+            }
+            Some(CommandBegin::Bold) => {
+                self.push("\\textbf{");
+                self.render_elements(block);
+                self.push("}");
+            }
+            Some(CommandBegin::Bouten(_)) => {
+                self.push("\\kenten{");
+                self.render_elements(block);
+                self.push("}");
+            }
+            _ => self.render_elements(block),
+        }
+    }
+
+    fn render_elements(&mut self, block: &AozoraBlock) {
+        for element in &block.elements {
+            match element {
+                BlockElement::Item(item) => self.render_item(item),
+                BlockElement::Block(child) => self.render_block(child),
+            }
+        }
+    }
+
+    fn render_item(&mut self, item: &ParsedItem) {
+        match item {
+            ParsedItem::Text(dt) => match &dt.ruby {
+                Some(ruby) => self.push(&format!("\\ruby{{{}}}{{{}}}", escape_latex(&dt.text), escape_latex(ruby))),
+                None => self.push(&escape_latex(&dt.text)),
+            },
+            // The blank line closing the header is not part of the text
+            ParsedItem::Newline(_) if self.body.is_empty() => {}
+            ParsedItem::Newline(_) => {
+                // A blank source line is kept as an empty paragraph
+                if !self.line_has_content {
+                    self.body.push_str("\\mbox{}");
+                }
+                if !self.body.ends_with("\n\n") {
+                    self.body.push_str(if self.body.ends_with('\n') { "\n" } else { "\n\n" });
+                }
+                self.line_has_content = false;
+            }
+            ParsedItem::Command { cmd: Command::SingleCommand(sc), .. } => match sc {
+                SingleCommand::Midashi((m, content)) => self.heading(m, &escape_latex(content)),
+                SingleCommand::Bold(s) => self.push(&format!("\\textbf{{{}}}", escape_latex(s))),
+                SingleCommand::Tatechuyoko(s) => self.push(&format!("\\tatechuyoko*{{{}}}", escape_latex(s))),
+                SingleCommand::Bouten((_, s)) => self.push(&format!("\\kenten{{{}}}", escape_latex(s))),
+                SingleCommand::Kaipage | SingleCommand::Kaimihiraki => self.push_line("\\clearpage"),
+                SingleCommand::Kaicho => self.push_line("\\cleardoublepage"),
+                SingleCommand::Note { note, .. } => self.push(&format!("\\footnote{{{}}}", escape_latex(note))),
+                _ => {}
+            },
+            ParsedItem::Command { .. } | ParsedItem::UnknownCommand { .. } => {}
+            ParsedItem::SpecialCharacter { kind, .. } => match kind {
+                SpecialCharacter::Odoriji => self.push("／＼"),
+                SpecialCharacter::DakutenOdoriji => self.push("／″＼"),
+                SpecialCharacter::Gaiji(gaiji) => match gaiji.unicode {
+                    Some(c) => self.push(&c.to_string()),
+                    None => self.push(&format!("※［＃{}］", escape_latex(&gaiji.annotation))),
+                },
+            },
+        }
+    }
+}
+
+/// Render blocks as a vertical LuaLaTeX document.
+/// See [`LatexGenerator::generate_with`].
+pub fn to_latex(block: &AozoraBlock, title: &str, author: &str) -> String {
+    LatexGenerator::generate(block, title, author)
+}
+
+fn escape_latex(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\textbackslash{}"),
+            '~' => out.push_str("\\textasciitilde{}"),
+            '^' => out.push_str("\\textasciicircum{}"),
+            '{' | '}' | '$' | '&' | '#' | '_' | '%' => {
+                out.push('\\');
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_parser::parse_blocks;
+    use crate::parser::parse;
+    use crate::tokenizer::parse_aozora;
+
+    fn blocks(text: &str) -> AozoraBlock {
+        parse_blocks(parse(parse_aozora(text.to_string()).unwrap()).items).unwrap()
+    }
+
+    #[test]
+    fn test_latex() {
+        let root = blocks("T\nA\n\n［＃大見出し］第一章［＃大見出し終わり］\n青空《あおぞら》の100%\n\n［＃ここから２字下げ］\n蒼穹［＃「蒼穹」に「空のこと」の注記］\n［＃ここで字下げ終わり］\n［＃改ページ］\n終\n");
+        let latex = to_latex(&root, "桜桃", "太宰治");
+
+        assert!(latex.starts_with("\\documentclass[tate,book]{jlreq}\n\\usepackage{pxrubrica}\n"));
+        assert!(latex.contains("\\title{桜桃}\n\\author{太宰治}"));
+        assert!(latex.contains(
+            "\\maketitle\n\n\\chapter*{第一章}\n\n\\ruby{青空}{あおぞら}の100\\%\n\n\\mbox{}\n\n\\begin{jisage}{2}\n\n蒼穹\\footnote{空のこと}\n\n\\end{jisage}\n\n\\clearpage\n\n終\n\\end{document}\n"
+        ));
+    }
+
+    #[test]
+    fn test_engine_and_writing_mode() {
+        let root = blocks("T\nA\n\n本文\n");
+        let options = LatexGeneratorOptions {
+            engine: LatexEngine::Platex,
+            writing_mode: WritingMode::HorizontalTb,
+        };
+        let latex = LatexGenerator::generate_with(&root, "T", "A", &options);
+        assert!(latex.starts_with("\\documentclass[platex,book]{jlreq}"));
+        assert!(latex.contains("\\maketitle\n\n本文\n\\end{document}"));
+    }
+}
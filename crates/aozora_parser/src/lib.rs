@@ -22,11 +22,19 @@
 //! use aozora_parser::{parse_aozora, parse, parse_blocks, EpubGenerator};
 //!
 //! let tokens = parse_aozora(text)?;
-//! let doc = parse(tokens)?;
+//! let doc = parse(tokens);
 //! let blocks = parse_blocks(doc.items)?;
 //! let generator = EpubGenerator::new(doc.metadata.title, doc.metadata.author, blocks);
 //! generator.write_to_file("output.epub")?;
 //! ```
+//!
+//! ## Serialization
+//!
+//! With the `serde` feature enabled, the token stream, `AozoraDocument`,
+//! `AozoraBlock` and `LintWarning` implement `Serialize`/`Deserialize`,
+//! so the parsed structure can be exchanged as JSON.
+//! `lint_report_json` renders lint warnings with their rule id, line and column
+//! for editors and CI.
 
 use std::path::Path;
 
@@ -36,26 +44,42 @@ pub mod tokenizer;
 pub mod parser;
 pub mod block_parser;
 pub mod linter;
+pub mod aozora_writer;
 mod xhtml_generator;
 mod epub_generator;
+mod epub_validator;
+mod epub_inspector;
+mod text_generator;
+mod markdown_generator;
+mod latex_generator;
 mod css;
+mod tatechuyoko;
 
 // Re-export main entry point functions
 pub use tokenizer::parse_aozora;
-pub use parser::parse;
-pub use block_parser::parse_blocks;
-pub use linter::lint;
-pub use css::default_css;
+pub use parser::{parse, parse_with};
+pub use block_parser::{parse_blocks, parse_blocks_with};
+pub use linter::{lint, lint_with, lint_with_diagnostics, lint_encoding, apply_fixes, apply_edits, lint_report};
+pub use tatechuyoko::tatechuyoko_fixes;
+#[cfg(feature = "serde")]
+pub use linter::lint_report_json;
+pub use css::{default_css, default_css_with_prefix, prefix_css_classes};
+pub use aozora_writer::{write_document, write_block};
 
 // Re-export primary types for working with documents
-pub use parser::{AozoraDocument, AozoraMetadata, ParsedItem, DecoratedText, SpecialCharacter, ParseError};
-pub use block_parser::{AozoraBlock, BlockElement, BlockParseError};
+pub use parser::{AozoraDocument, AozoraMetadata, Colophon, HeaderMode, HeaderNote, ParseOptions, ParsedItem, DecoratedText, SpecialCharacter, Gaiji, ParseDiagnostic, ParseDiagnosticKind};
+pub use block_parser::{AozoraBlock, BlockElement, BlockParseError, BlockParseOptions, BlockParseOutput, BlockDiagnostic};
 pub use tokenizer::{AozoraToken, Span, TokenizeError};
-pub use linter::{LintResult, LintWarning, LintWarningKind, Severity};
+pub use linter::{CharacterWidth, Fix, LintConfig, LintReportEntry, LintResult, LintRule, LintWarning, LintWarningKind, ParserDiagnostics, Severity};
 
 // Re-export generators
-pub use epub_generator::EpubGenerator;
-pub use xhtml_generator::{XhtmlGenerator, TocEntry};
+pub use epub_generator::{EpubCompression, EpubCompressionOptions, EpubGenerator, EpubMetadata};
+pub use epub_validator::{validate_epub, EpubValidationError};
+pub use epub_inspector::{extract_epub_text, inspect_epub, EpubFile, EpubInfo, EpubTocEntry};
+pub use latex_generator::{to_latex, LatexEngine, LatexGenerator, LatexGeneratorOptions};
+pub use markdown_generator::{to_markdown, MarkdownGenerator, MarkdownGeneratorOptions, MarkdownRuby};
+pub use text_generator::{to_plain_text, RubyStyle, TextGenerator, TextGeneratorOptions};
+pub use xhtml_generator::{escape_html, to_html_fragment, GaijiStyle, HeadingIdScheme, NotePlacement, XhtmlGenerator, XhtmlGeneratorOptions, SourceNoteStyle, TocEntry, TocNode, TocTree, WritingMode};
 
 // Re-export command types for advanced usage (matching decorations, etc.)
 pub mod command {
@@ -74,6 +98,13 @@ pub struct XhtmlOutput {
     pub metadata: AozoraMetadata,
 }
 
+impl XhtmlOutput {
+    /// Table of contents nested by heading level.
+    pub fn toc_tree(&self) -> TocTree {
+        TocTree::from_entries(&self.toc)
+    }
+}
+
 /// Result of XHTML conversion with lint warnings.
 #[derive(Debug, Clone)]
 pub struct XhtmlOutputWithLint {
@@ -87,13 +118,18 @@ pub struct XhtmlOutputWithLint {
     pub warnings: Vec<LintWarning>,
 }
 
+impl XhtmlOutputWithLint {
+    /// Table of contents nested by heading level.
+    pub fn toc_tree(&self) -> TocTree {
+        TocTree::from_entries(&self.toc)
+    }
+}
+
 /// Error type for high-level conversion functions.
 #[derive(Debug)]
 pub enum ConversionError {
     /// Error during tokenization
     Tokenize(TokenizeError),
-    /// Error during parsing
-    Parse(ParseError),
     /// Error during block parsing
     BlockParse(BlockParseError),
     /// Error during file I/O
@@ -103,9 +139,8 @@ pub enum ConversionError {
 impl std::fmt::Display for ConversionError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ConversionError::Tokenize(e) => write!(f, "Tokenization error: {:?}", e),
-            ConversionError::Parse(e) => write!(f, "Parse error: {:?}", e),
-            ConversionError::BlockParse(e) => write!(f, "Block parse error: {:?}", e),
+            ConversionError::Tokenize(e) => write!(f, "Tokenization error: {}", e),
+            ConversionError::BlockParse(e) => write!(f, "Block parse error: {}", e),
             ConversionError::Io(e) => write!(f, "I/O error: {}", e),
         }
     }
@@ -117,10 +152,6 @@ impl From<TokenizeError> for ConversionError {
     fn from(e: TokenizeError) -> Self { ConversionError::Tokenize(e) }
 }
 
-impl From<ParseError> for ConversionError {
-    fn from(e: ParseError) -> Self { ConversionError::Parse(e) }
-}
-
 impl From<BlockParseError> for ConversionError {
     fn from(e: BlockParseError) -> Self { ConversionError::BlockParse(e) }
 }
@@ -147,7 +178,7 @@ impl From<std::io::Error> for ConversionError {
 /// ```
 pub fn text_to_xhtml(text: String) -> Result<XhtmlOutput, ConversionError> {
     let tokens = parse_aozora(text)?;
-    let doc = parse(tokens)?;
+    let doc = parse(tokens);
     let blocks = parse_blocks(doc.items)?;
     let (xhtml, toc) = XhtmlGenerator::generate(&blocks, &doc.metadata.title);
     Ok(XhtmlOutput {
@@ -173,12 +204,50 @@ pub fn text_to_xhtml(text: String) -> Result<XhtmlOutput, ConversionError> {
 /// aozora_parser::text_to_epub(aozora_text, "output.epub")?;
 /// ```
 pub fn text_to_epub<P: AsRef<Path>>(text: String, path: P) -> Result<(), ConversionError> {
+    text_to_epub_generator(text)?.write_to_file(path)?;
+    Ok(())
+}
+
+/// Converts Aozora Bunko format text to an [`EpubGenerator`] to be configured further.
+///
+/// The title, author and subtitle are taken from the header, and the 底本 from the
+/// trailer (shown only if the colophon page is enabled with `with_okuzuke(true)`).
+///
+/// # Example
+///
+/// ```ignore
+/// aozora_parser::text_to_epub_generator(aozora_text)?
+///     .with_writing_mode(WritingMode::HorizontalTb)
+///     .write_to_file("output.epub")?;
+/// ```
+pub fn text_to_epub_generator(text: String) -> Result<EpubGenerator, ConversionError> {
     let tokens = parse_aozora(text)?;
-    let doc = parse(tokens)?;
+    let doc = parse(tokens);
     let blocks = parse_blocks(doc.items)?;
-    let generator = EpubGenerator::new(doc.metadata.title, doc.metadata.author, blocks);
-    generator.write_to_file(path)?;
-    Ok(())
+    let mut generator = EpubGenerator::new(doc.metadata.title, doc.metadata.author, blocks);
+    if let Some(subtitle) = doc.metadata.subtitle {
+        generator = generator.with_subtitle(subtitle);
+    }
+    if let Some(colophon) = doc.colophon {
+        generator = generator.with_colophon(colophon);
+    }
+    Ok(generator)
+}
+
+/// Normalizes Aozora Bunko format text by parsing it and writing it back.
+///
+/// Equivalent notations are rewritten into a single canonical form
+/// (see [`aozora_writer`]), so the result is stable under repeated formatting.
+///
+/// # Example
+///
+/// ```ignore
+/// let formatted = aozora_parser::format_text(aozora_text)?;
+/// ```
+pub fn format_text(text: String) -> Result<String, ConversionError> {
+    let tokens = parse_aozora(text)?;
+    let doc = parse(tokens);
+    Ok(write_document(&doc))
 }
 
 /// Converts Aozora Bunko format text to XHTML with lint warnings.
@@ -201,11 +270,12 @@ pub fn text_to_epub<P: AsRef<Path>>(text: String, path: P) -> Result<(), Convers
 pub fn text_to_xhtml_with_lint(text: String) -> Result<XhtmlOutputWithLint, ConversionError> {
     let original = text.clone();
     let tokens = parse_aozora(text)?;
-    let doc = parse(tokens)?;
-    let blocks = parse_blocks(doc.items)?;
+    let doc = parse(tokens);
+    let output = parse_blocks_with(doc.items, &BlockParseOptions::default())?;
     
     // Run linter
-    let lint_result = lint(blocks, &original);
+    let diagnostics = ParserDiagnostics { parse: &doc.diagnostics, blocks: &output.diagnostics };
+    let lint_result = lint_with_diagnostics(output.block, &original, diagnostics, &LintConfig::default());
     
     let (xhtml, toc) = XhtmlGenerator::generate(&lint_result.block, &doc.metadata.title);
     Ok(XhtmlOutputWithLint {
@@ -2,13 +2,30 @@
 //!
 //! This module provides lint warnings for common formatting issues
 //! without stopping the parsing process.
+//!
+//! Warnings can be suppressed in the text itself with a directive annotation:
+//!
+//! - `［＃kartana:allow(odd-ellipsis)］` suppresses the listed rules on its own line,
+//!   or on the next line when the directive is the only thing on its line.
+//! - `［＃kartana:allow-file(missing-paragraph-indent,odd-ellipsis)］` suppresses them in the whole text.
+//!
+//! Annotations cannot contain whitespace, so rule ids are separated by `,` or `、` alone.
 
-use crate::block_parser::{AozoraBlock, BlockElement};
-use crate::parser::{DecoratedText, ParsedItem};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+use encoding_rs::SHIFT_JIS;
+
+use crate::aozora_writer;
+use crate::block_parser::{AozoraBlock, BlockDiagnostic, BlockElement};
+use crate::parser::{script_of, DecoratedText, ParseDiagnostic, ParseDiagnosticKind, ParsedItem, Script};
 use crate::tokenizer::Span;
+use crate::tokenizer::command::{Command, SingleCommand};
 
 /// Severity level of a lint warning.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Severity {
     /// Error: Should be fixed
     Error,
@@ -20,6 +37,7 @@ pub enum Severity {
 
 /// Kind of lint warning.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LintWarningKind {
     // === 構文関連 ===
     /// ルビが対応するテキストなしで出現
@@ -28,6 +46,10 @@ pub enum LintWarningKind {
     UnknownCommand(String),
     /// 開始タグと終了タグの不一致
     MismatchedBlockTags,
+    /// 空のルビ、親文字に比べて長すぎるルビ、約物に付いたルビ
+    SuspiciousRuby,
+    /// ｜なしでひらがな・カタカナだけの親文字に付いたルビ
+    RubyOnKana,
 
     // === 表記関連 ===
     /// 段落先頭に字下げがない
@@ -38,10 +60,247 @@ pub enum LintWarningKind {
     OddEllipsisCount,
     /// ！？の後に不正な文字
     InvalidCharAfterExclamation,
+    /// 。。や、、のように句読点が重複
+    DuplicatedPunctuation,
+    /// ？！の順（！？が一般的）
+    ExclamationOrder,
+    /// 和文中の半角句読点・感嘆符（, . ! ?）
+    HalfWidthPunctuation,
+    /// 優先する幅（`LintConfig::preferred_width`）と異なる英数字
+    CharacterWidth,
+    /// 長すぎる段落（`LintConfig::max_paragraph_length` を超える）
+    LongParagraph,
+    /// 句読点のないまま長く続く文（`LintConfig::max_sentence_length` を超える）
+    LongSentence,
+
+    // === 文字コード関連 ===
+    /// Shift_JISで表せない文字（保存すると失われる）
+    UnencodableCharacter(char),
+
+    // === 注記関連 ===
+    /// ママ注記（底本通りの表記）
+    SicAnnotation(String),
+
+    // === ユーザー定義 ===
+    /// `LintConfig::add_rule` で登録したルールの警告（ルールIDを保持）
+    Custom(String),
+}
+
+impl LintWarningKind {
+    /// Identifiers of all lint rules, as returned by [`LintWarningKind::id`].
+    pub const RULE_IDS: &'static [&'static str] = &[
+        "ruby-without-text",
+        "unknown-command",
+        "mismatched-block-tags",
+        "suspicious-ruby",
+        "ruby-on-kana",
+        "missing-paragraph-indent",
+        "punctuation-before-quote",
+        "odd-ellipsis",
+        "invalid-char-after-exclamation",
+        "duplicated-punctuation",
+        "exclamation-order",
+        "half-width-punctuation",
+        "character-width",
+        "long-paragraph",
+        "long-sentence",
+        "unencodable-character",
+        "sic-annotation",
+    ];
+
+    /// Stable identifier of the rule that produces this kind of warning
+    /// (used in `LintConfig` and machine-readable output).
+    /// For [`LintWarningKind::Custom`] this is the id given by the rule.
+    pub fn id(&self) -> &str {
+        match self {
+            LintWarningKind::RubyWithoutText => "ruby-without-text",
+            LintWarningKind::UnknownCommand(_) => "unknown-command",
+            LintWarningKind::MismatchedBlockTags => "mismatched-block-tags",
+            LintWarningKind::SuspiciousRuby => "suspicious-ruby",
+            LintWarningKind::RubyOnKana => "ruby-on-kana",
+            LintWarningKind::MissingParagraphIndent => "missing-paragraph-indent",
+            LintWarningKind::PunctuationBeforeQuote => "punctuation-before-quote",
+            LintWarningKind::OddEllipsisCount => "odd-ellipsis",
+            LintWarningKind::InvalidCharAfterExclamation => "invalid-char-after-exclamation",
+            LintWarningKind::DuplicatedPunctuation => "duplicated-punctuation",
+            LintWarningKind::ExclamationOrder => "exclamation-order",
+            LintWarningKind::HalfWidthPunctuation => "half-width-punctuation",
+            LintWarningKind::CharacterWidth => "character-width",
+            LintWarningKind::LongParagraph => "long-paragraph",
+            LintWarningKind::LongSentence => "long-sentence",
+            LintWarningKind::UnencodableCharacter(_) => "unencodable-character",
+            LintWarningKind::SicAnnotation(_) => "sic-annotation",
+            LintWarningKind::Custom(id) => id,
+        }
+    }
+}
+
+/// A user-defined lint rule, registered with [`LintConfig::add_rule`].
+///
+/// Warnings should use [`LintWarningKind::Custom`] with an id of the rule's own,
+/// so they can be disabled and re-ranked like the built-in rules.
+///
+/// ```ignore
+/// struct NoHalfWidthDigits;
+///
+/// impl LintRule for NoHalfWidthDigits {
+///     fn check(&self, doc: &AozoraBlock, _text: &str, out: &mut Vec<LintWarning>) {
+///         for item in doc.iter_items() {
+///             if let ParsedItem::Text(dt) = item
+///                 && dt.text.chars().any(|c| c.is_ascii_digit())
+///             {
+///                 out.push(LintWarning::info(
+///                     LintWarningKind::Custom("half-width-digits".into()),
+///                     dt.span,
+///                     "半角数字が含まれています",
+///                 ));
+///             }
+///         }
+///     }
+/// }
+///
+/// let mut config = LintConfig::default();
+/// config.add_rule(NoHalfWidthDigits);
+/// ```
+pub trait LintRule: Send + Sync {
+    /// Append the warnings found in `doc` (parsed from `text`) to `out`.
+    fn check(&self, doc: &AozoraBlock, text: &str, out: &mut Vec<LintWarning>);
+}
+
+/// Preferred width of alphanumerics in the body, checked by the `character-width` rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CharacterWidth {
+    /// Full-width (Ａ１): half-width characters are rotated in vertical text
+    #[default]
+    FullWidth,
+    /// Half-width (A1)
+    HalfWidth,
+}
+
+/// Which lint rules to run and at what severity.
+///
+/// Rules are referred to by their id (see [`LintWarningKind::id`]).
+/// The default configuration enables every rule at its built-in severity, except
+/// the opt-in rules in [`LintConfig::OPT_IN_RULES`].
+///
+/// ```ignore
+/// let mut config = LintConfig::default();
+/// config.disable("missing-paragraph-indent");
+/// config.set_severity("odd-ellipsis", Severity::Error);
+/// let result = lint_with(block, &text, &config);
+/// ```
+#[derive(Clone)]
+pub struct LintConfig {
+    /// Rules whose warnings are dropped
+    pub disabled: HashSet<String>,
+    /// Severity overrides per rule
+    pub severities: HashMap<String, Severity>,
+    /// User-defined rules, run after the built-in ones
+    pub rules: Vec<Arc<dyn LintRule>>,
+    /// Preferred width of alphanumerics in the body
+    pub preferred_width: CharacterWidth,
+    /// Paragraphs longer than this many characters are reported
+    pub max_paragraph_length: usize,
+    /// Runs of this many characters without punctuation are reported
+    pub max_sentence_length: usize,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            disabled: Self::OPT_IN_RULES.iter().map(|id| id.to_string()).collect(),
+            severities: HashMap::new(),
+            rules: Vec::new(),
+            preferred_width: CharacterWidth::default(),
+            max_paragraph_length: DEFAULT_MAX_PARAGRAPH_LENGTH,
+            max_sentence_length: DEFAULT_MAX_SENTENCE_LENGTH,
+        }
+    }
+}
+
+impl fmt::Debug for LintConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LintConfig")
+            .field("disabled", &self.disabled)
+            .field("severities", &self.severities)
+            .field("rules", &self.rules.len())
+            .field("preferred_width", &self.preferred_width)
+            .field("max_paragraph_length", &self.max_paragraph_length)
+            .field("max_sentence_length", &self.max_sentence_length)
+            .finish()
+    }
+}
+
+impl LintConfig {
+    /// Rules that are disabled until [`LintConfig::enable`] is called: they report
+    /// notation that is correct, for reviewing it against the source.
+    pub const OPT_IN_RULES: &'static [&'static str] = &["sic-annotation"];
+
+    /// Disable a rule.
+    pub fn disable(&mut self, id: impl Into<String>) {
+        self.disabled.insert(id.into());
+    }
+
+    /// Re-enable a previously disabled rule.
+    pub fn enable(&mut self, id: &str) {
+        self.disabled.remove(id);
+    }
+
+    /// Override the severity of a rule.
+    pub fn set_severity(&mut self, id: impl Into<String>, severity: Severity) {
+        self.severities.insert(id.into(), severity);
+    }
+
+    /// Register a user-defined rule.
+    pub fn add_rule(&mut self, rule: impl LintRule + 'static) {
+        self.rules.push(Arc::new(rule));
+    }
+
+    /// Whether warnings of this kind are reported.
+    pub fn is_enabled(&self, kind: &LintWarningKind) -> bool {
+        !self.disabled.contains(kind.id())
+    }
+
+    /// Drop disabled warnings and apply severity overrides.
+    fn apply(&self, warnings: Vec<LintWarning>) -> Vec<LintWarning> {
+        warnings
+            .into_iter()
+            .filter(|w| self.is_enabled(&w.kind))
+            .map(|mut w| {
+                if let Some(severity) = self.severities.get(w.kind.id()) {
+                    w.severity = *severity;
+                }
+                w
+            })
+            .collect()
+    }
+}
+
+/// A suggested edit that resolves a warning: replace `span` of the original text with `replacement`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fix {
+    /// Range to replace (empty for an insertion)
+    pub span: Span,
+    /// Replacement text
+    pub replacement: String,
+}
+
+impl Fix {
+    /// Create a fix replacing `span` with `replacement`.
+    pub fn new(span: Span, replacement: impl Into<String>) -> Self {
+        Self { span, replacement: replacement.into() }
+    }
+
+    /// Create a fix inserting `text` at `pos`.
+    pub fn insert(pos: usize, text: impl Into<String>) -> Self {
+        Self::new(Span::new(pos, pos), text)
+    }
 }
 
 /// A lint warning with location and message.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LintWarning {
     /// Kind of warning
     pub kind: LintWarningKind,
@@ -51,6 +310,8 @@ pub struct LintWarning {
     pub severity: Severity,
     /// Human-readable message
     pub message: String,
+    /// Automatic fix, if the issue is trivially fixable
+    pub fix: Option<Fix>,
 }
 
 impl LintWarning {
@@ -61,9 +322,16 @@ impl LintWarning {
             span,
             severity,
             message: message.into(),
+            fix: None,
         }
     }
 
+    /// Attach an automatic fix.
+    pub fn with_fix(mut self, fix: Fix) -> Self {
+        self.fix = Some(fix);
+        self
+    }
+
     /// Create an error-level warning.
     pub fn error(kind: LintWarningKind, span: Span, message: impl Into<String>) -> Self {
         Self::new(kind, span, Severity::Error, message)
@@ -82,6 +350,7 @@ impl LintWarning {
 
 /// Result of linting.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LintResult {
     /// The block (unchanged)
     pub block: AozoraBlock,
@@ -89,6 +358,18 @@ pub struct LintResult {
     pub warnings: Vec<LintWarning>,
 }
 
+/// Problems the parsers recovered from while building the block.
+///
+/// They are reported as lint warnings; pass them to [`lint_with_diagnostics`] so that the
+/// text does not have to be parsed again.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParserDiagnostics<'a> {
+    /// Diagnostics of [`parse`](crate::parser::parse)
+    pub parse: &'a [ParseDiagnostic],
+    /// Diagnostics of [`parse_blocks_with`](crate::block_parser::parse_blocks_with)
+    pub blocks: &'a [BlockDiagnostic],
+}
+
 /// Lint an AozoraBlock and return warnings.
 ///
 /// This function validates the block against common formatting rules
@@ -108,13 +389,245 @@ pub struct LintResult {
 /// }
 /// ```
 pub fn lint(block: AozoraBlock, original_text: &str) -> LintResult {
+    lint_with(block, original_text, &LintConfig::default())
+}
+
+/// Lint an AozoraBlock with a rule configuration.
+///
+/// Disabled rules produce no warnings, and severity overrides replace
+/// the built-in severity of each rule. Rules registered with
+/// [`LintConfig::add_rule`] run after the built-in ones.
+///
+/// The text is parsed again for the problems the parsers recovered from; use
+/// [`lint_with_diagnostics`] when they are at hand.
+pub fn lint_with(block: AozoraBlock, original_text: &str, config: &LintConfig) -> LintResult {
+    let Ok(tokens) = crate::tokenizer::parse_aozora(original_text.to_string()) else {
+        return lint_with_diagnostics(block, original_text, ParserDiagnostics::default(), config);
+    };
+    let doc = crate::parser::parse(tokens);
+    let blocks = crate::block_parser::parse_blocks_with(doc.items, &Default::default())
+        .map(|output| output.diagnostics)
+        .unwrap_or_default();
+    let diagnostics = ParserDiagnostics { parse: &doc.diagnostics, blocks: &blocks };
+    lint_with_diagnostics(block, original_text, diagnostics, config)
+}
+
+/// Lint an AozoraBlock with a rule configuration, reporting `diagnostics`, what the
+/// parsers recovered from while building `block`.
+///
+/// ```ignore
+/// let doc = parse(parse_aozora(text.clone())?);
+/// let output = parse_blocks_with(doc.items, &BlockParseOptions::default())?;
+/// let diagnostics = ParserDiagnostics { parse: &doc.diagnostics, blocks: &output.diagnostics };
+/// let result = lint_with_diagnostics(output.block, &text, diagnostics, &LintConfig::default());
+/// ```
+pub fn lint_with_diagnostics(
+    block: AozoraBlock,
+    original_text: &str,
+    diagnostics: ParserDiagnostics,
+    config: &LintConfig,
+) -> LintResult {
     let mut warnings = Vec::new();
     
     // Run all lint checks
     check_paragraph_indent(&block, &mut warnings);
+    check_sic_annotations(&block, &mut warnings);
+    check_ruby(&block, &mut warnings);
+    check_ruby_on_kana(&block, &mut warnings);
+    check_character_width(&block, config.preferred_width, &mut warnings);
+    check_lengths(&block, config, &mut warnings);
     check_text_patterns(original_text, &mut warnings);
+    check_punctuation(original_text, &mut warnings);
+    check_parse_diagnostics(original_text, diagnostics, &mut warnings);
+    check_encodability(original_text, &mut warnings);
+    for rule in &config.rules {
+        rule.check(&block, original_text, &mut warnings);
+    }
+
+    let suppressions = Suppressions::from_text(original_text);
+    warnings.retain(|w| !suppressions.suppresses(w));
     
-    LintResult { block, warnings }
+    LintResult { block, warnings: config.apply(warnings) }
+}
+
+/// Name space of linter directives (`［＃kartana:...］`).
+const DIRECTIVE_PREFIX: &str = "kartana";
+
+/// A parsed `［＃kartana:...］` directive.
+#[derive(Debug, PartialEq)]
+enum Directive {
+    /// `allow(...)`: suppress rules on one line
+    Allow(Vec<String>),
+    /// `allow-file(...)`: suppress rules in the whole text
+    AllowFile(Vec<String>),
+}
+
+impl Directive {
+    /// Parse the content of an annotation (without ［＃ and ］).
+    fn parse(content: &str) -> Option<Self> {
+        let rest = content.trim().strip_prefix(DIRECTIVE_PREFIX)?;
+        let rest = rest.trim_start().strip_prefix([':', '：'])?.trim();
+        let (name, args) = rest.strip_suffix(')')?.split_once('(')?;
+        let ids = args
+            .split([',', '、'])
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty())
+            .collect();
+        match name.trim() {
+            "allow" => Some(Directive::Allow(ids)),
+            "allow-file" => Some(Directive::AllowFile(ids)),
+            _ => None,
+        }
+    }
+}
+
+/// Rules suppressed by directives in the text.
+#[derive(Debug, Default)]
+struct Suppressions {
+    /// Start offset of each line, in characters
+    line_starts: Vec<usize>,
+    /// Suppressed rule ids per line index
+    lines: HashMap<usize, HashSet<String>>,
+    file: HashSet<String>,
+}
+
+impl Suppressions {
+    fn from_text(text: &str) -> Self {
+        let mut suppressions = Suppressions { line_starts: line_starts(text), ..Default::default() };
+        for (index, line) in text.split('\n').enumerate() {
+            let mut rest = line;
+            let mut remainder = String::new();
+            let mut allowed = Vec::new();
+            while let Some(open) = rest.find("［＃") {
+                let Some(close) = rest[open..].find('］').map(|i| open + i) else {
+                    break;
+                };
+                let content = &rest[open + "［＃".len()..close];
+                remainder.push_str(&rest[..open]);
+                match Directive::parse(content) {
+                    Some(Directive::Allow(ids)) => allowed.extend(ids),
+                    Some(Directive::AllowFile(ids)) => suppressions.file.extend(ids),
+                    None => remainder.push_str(&rest[open..close + '］'.len_utf8()]),
+                }
+                rest = &rest[close + '］'.len_utf8()..];
+            }
+            remainder.push_str(rest);
+
+            if allowed.is_empty() {
+                continue;
+            }
+            // A directive on a line of its own applies to the next line
+            let target = if remainder.trim().is_empty() { index + 1 } else { index };
+            suppressions.lines.entry(target).or_default().extend(allowed);
+        }
+        suppressions
+    }
+
+    fn suppresses(&self, warning: &LintWarning) -> bool {
+        let id = warning.kind.id();
+        if self.file.contains(id) {
+            return true;
+        }
+        let line = line_index(&self.line_starts, warning.span.start);
+        self.lines.get(&line).is_some_and(|ids| ids.contains(id))
+    }
+}
+
+/// Apply the fixes attached to `warnings` to the original text.
+///
+/// Fixes are applied from the end of the text so earlier spans stay valid.
+/// A fix overlapping one that was already applied is skipped; running the
+/// linter again will report it if it still applies.
+///
+/// # Example
+///
+/// ```ignore
+/// let result = lint(block, &text);
+/// let fixed = apply_fixes(&text, &result.warnings);
+/// ```
+pub fn apply_fixes(text: &str, warnings: &[LintWarning]) -> String {
+    let fixes: Vec<Fix> = warnings.iter().filter_map(|w| w.fix.clone()).collect();
+    apply_edits(text, &fixes)
+}
+
+/// Apply `fixes` to `text`, in the same way as [`apply_fixes`].
+pub fn apply_edits(text: &str, fixes: &[Fix]) -> String {
+    let mut fixes: Vec<&Fix> = fixes.iter().collect();
+    fixes.sort_by_key(|f| (f.span.start, f.span.end));
+    fixes.dedup();
+
+    let mut chars: Vec<char> = text.chars().collect();
+    let mut limit = chars.len();
+    for fix in fixes.into_iter().rev() {
+        if fix.span.end > limit || fix.span.start > fix.span.end {
+            continue;
+        }
+        chars.splice(fix.span.start..fix.span.end, fix.replacement.chars());
+        limit = fix.span.start;
+    }
+    chars.into_iter().collect()
+}
+
+/// Start offset of each line of `text`, in characters.
+fn line_starts(text: &str) -> Vec<usize> {
+    let mut starts = vec![0];
+    starts.extend(text.chars().enumerate().filter(|(_, c)| *c == '\n').map(|(i, _)| i + 1));
+    starts
+}
+
+/// Index of the line containing character offset `pos`.
+fn line_index(line_starts: &[usize], pos: usize) -> usize {
+    line_starts.partition_point(|&s| s <= pos).saturating_sub(1)
+}
+
+/// A warning in machine-readable form, with its location resolved to a line and column.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LintReportEntry {
+    /// Rule id (see [`LintWarningKind::id`])
+    pub rule: String,
+    pub severity: Severity,
+    pub message: String,
+    /// Location in the original text, in characters
+    pub span: Span,
+    /// Line of `span.start`, starting at 1
+    pub line: usize,
+    /// Column of `span.start` in characters, starting at 1
+    pub column: usize,
+    /// Suggested fix
+    pub fix: Option<Fix>,
+}
+
+/// Convert warnings into report entries, resolving their positions in `text`.
+pub fn lint_report(text: &str, warnings: &[LintWarning]) -> Vec<LintReportEntry> {
+    let starts = line_starts(text);
+    warnings
+        .iter()
+        .map(|w| {
+            let line = line_index(&starts, w.span.start);
+            LintReportEntry {
+                rule: w.kind.id().to_string(),
+                severity: w.severity,
+                message: w.message.clone(),
+                span: w.span,
+                line: line + 1,
+                column: w.span.start - starts[line] + 1,
+                fix: w.fix.clone(),
+            }
+        })
+        .collect()
+}
+
+/// Serialize warnings as a JSON array of [`LintReportEntry`].
+///
+/// ```ignore
+/// let result = lint(block, &text);
+/// println!("{}", lint_report_json(&text, &result.warnings));
+/// ```
+#[cfg(feature = "serde")]
+pub fn lint_report_json(text: &str, warnings: &[LintWarning]) -> String {
+    serde_json::to_string_pretty(&lint_report(text, warnings))
+        .expect("lint report is always serializable")
 }
 
 /// Check for proper paragraph indentation.
@@ -135,7 +648,7 @@ fn check_paragraph_indent(block: &AozoraBlock, warnings: &mut Vec<LintWarning>)
                                 LintWarningKind::MissingParagraphIndent,
                                 dt.span,
                                 "段落の先頭には全角スペースまたは字下げが必要です",
-                            ));
+                            ).with_fix(Fix::insert(dt.span.start, "　")));
                         }
                         after_newline = false;
                     }
@@ -157,6 +670,297 @@ fn check_paragraph_indent(block: &AozoraBlock, warnings: &mut Vec<LintWarning>)
     }
 }
 
+/// Report ママ annotations so they can be reviewed against the source.
+fn check_sic_annotations(block: &AozoraBlock, warnings: &mut Vec<LintWarning>) {
+    for item in block.iter_items() {
+        if let ParsedItem::Command {
+            cmd: Command::SingleCommand(SingleCommand::Mama(content)),
+            span,
+        } = item
+        {
+            warnings.push(LintWarning::info(
+                LintWarningKind::SicAnnotation(content.clone()),
+                *span,
+                format!("「{}」は底本の表記のまま（ママ）です", content),
+            ));
+        }
+    }
+}
+
+/// Report notation the parser had to skip or repair: unknown annotations, ruby without
+/// a base and end annotations that do not match their block.
+fn check_parse_diagnostics(text: &str, diagnostics: ParserDiagnostics, warnings: &mut Vec<LintWarning>) {
+    for diagnostic in diagnostics.parse {
+        match &diagnostic.kind {
+            // Linter directives are meant for the linter only
+            ParseDiagnosticKind::UnknownCommand(content) if Directive::parse(content).is_some() => {}
+            ParseDiagnosticKind::UnknownCommand(content) => {
+                let message = format!("注記［＃{}］を解釈できないため無視されます", content);
+                warnings.push(LintWarning::warning(
+                    LintWarningKind::UnknownCommand(content.clone()),
+                    diagnostic.span,
+                    message,
+                ));
+            }
+            ParseDiagnosticKind::OrphanRuby(ruby) => {
+                warnings.push(LintWarning::warning(
+                    LintWarningKind::RubyWithoutText,
+                    diagnostic.span,
+                    format!("ルビ《{}》に対応する親文字がないため無視されます", ruby),
+                ));
+            }
+            ParseDiagnosticKind::DanglingRubySeparator => {}
+        }
+    }
+
+    let starts = line_starts(text);
+    // 1-based, as in the reports of karp
+    let position = |pos: usize| {
+        let line = line_index(&starts, pos);
+        format!("{}行{}文字目", line + 1, pos - starts[line] + 1)
+    };
+    for diagnostic in diagnostics.blocks {
+        match diagnostic {
+            BlockDiagnostic::MismatchedBlockTags { begin, begin_span, end, span } => {
+                let begin = aozora_writer::begin_notation(begin).unwrap_or_default();
+                let end = aozora_writer::end_notation(end).unwrap_or_default();
+                warnings.push(LintWarning::warning(
+                    LintWarningKind::MismatchedBlockTags,
+                    *span,
+                    format!(
+                        "{}の［＃{}］が{}の［＃{}］で閉じられています",
+                        position(begin_span.start), begin, position(span.start), end
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+/// Ruby longer than this many characters per base character is reported.
+const MAX_RUBY_LENGTH_RATIO: usize = 6;
+
+/// Check for ruby that is probably caused by a misplaced ｜ or 《》.
+fn check_ruby(block: &AozoraBlock, warnings: &mut Vec<LintWarning>) {
+    for item in block.iter_items() {
+        let ParsedItem::Text(DecoratedText { text, ruby: Some(ruby), span }) = item else {
+            continue;
+        };
+        let base_len = text.chars().count();
+        let ruby_len = ruby.chars().count();
+
+        let message = if ruby.is_empty() {
+            format!("「{}」のルビが空です", text)
+        } else if ruby_len > base_len * MAX_RUBY_LENGTH_RATIO {
+            format!("「{}」に対してルビ《{}》が長すぎます。｜や《》の位置を確認してください", text, ruby)
+        } else if text.chars().any(is_punctuation) {
+            format!("約物を含む「{}」にルビが付いています。｜の位置を確認してください", text)
+        } else {
+            continue;
+        };
+        warnings.push(LintWarning::warning(LintWarningKind::SuspiciousRuby, *span, message));
+    }
+}
+
+/// Check for alphanumerics in the body whose width differs from the preferred one.
+///
+/// Only the body is checked, so dates in the colophon and the like are left alone.
+fn check_character_width(block: &AozoraBlock, preferred: CharacterWidth, warnings: &mut Vec<LintWarning>) {
+    let (is_other_width, name) = match preferred {
+        CharacterWidth::FullWidth => (char::is_ascii_alphanumeric as fn(&char) -> bool, "全角"),
+        CharacterWidth::HalfWidth => (is_full_width_alphanumeric as fn(&char) -> bool, "半角"),
+    };
+
+    for item in block.iter_items() {
+        let ParsedItem::Text(DecoratedText { text, ruby: None, span }) = item else {
+            continue;
+        };
+        let chars: Vec<char> = text.chars().collect();
+        // Offsets inside the item are only known when it maps one-to-one onto the source
+        if span.end - span.start != chars.len() {
+            continue;
+        }
+
+        let mut pos = 0;
+        while pos < chars.len() {
+            if !is_other_width(&chars[pos]) {
+                pos += 1;
+                continue;
+            }
+            let start = pos;
+            while pos < chars.len() && is_other_width(&chars[pos]) {
+                pos += 1;
+            }
+            let run: String = chars[start..pos].iter().collect();
+            let converted: String = run.chars().map(|c| convert_width(c, preferred)).collect();
+            let run_span = Span::new(span.start + start, span.start + pos);
+            warnings.push(LintWarning::info(
+                LintWarningKind::CharacterWidth,
+                run_span,
+                format!("「{}」は{}の「{}」にすることが推奨されます", run, name, converted),
+            ).with_fix(Fix::new(run_span, converted)));
+        }
+    }
+}
+
+fn is_full_width_alphanumeric(c: &char) -> bool {
+    matches!(c, '０'..='９' | 'Ａ'..='Ｚ' | 'ａ'..='ｚ')
+}
+
+/// Convert an alphanumeric character to the given width.
+fn convert_width(c: char, width: CharacterWidth) -> char {
+    // Full-width ASCII variants are laid out in the same order, 0xFEE0 code points above
+    const OFFSET: u32 = 0xFEE0;
+    let converted = match width {
+        CharacterWidth::FullWidth if c.is_ascii_alphanumeric() => c as u32 + OFFSET,
+        CharacterWidth::HalfWidth if is_full_width_alphanumeric(&c) => c as u32 - OFFSET,
+        _ => return c,
+    };
+    char::from_u32(converted).unwrap_or(c)
+}
+
+/// Default of `LintConfig::max_paragraph_length`.
+const DEFAULT_MAX_PARAGRAPH_LENGTH: usize = 500;
+/// Default of `LintConfig::max_sentence_length`.
+const DEFAULT_MAX_SENTENCE_LENGTH: usize = 100;
+
+/// Check for overly long paragraphs and for long runs of text without punctuation.
+fn check_lengths(block: &AozoraBlock, config: &LintConfig, warnings: &mut Vec<LintWarning>) {
+    // Length and start offset of the current paragraph and sentence
+    let mut paragraph = (0, 0);
+    let mut sentence = (0, 0);
+    let mut last_end = 0;
+
+    let end_sentence = |sentence: &mut (usize, usize), end: usize, warnings: &mut Vec<LintWarning>| {
+        if sentence.0 > config.max_sentence_length {
+            warnings.push(LintWarning::info(
+                LintWarningKind::LongSentence,
+                Span::new(sentence.1, end),
+                format!("句読点のない文が{}文字続いています", sentence.0),
+            ));
+        }
+        *sentence = (0, end);
+    };
+    let end_paragraph = |paragraph: (usize, usize), end: usize, warnings: &mut Vec<LintWarning>| {
+        if paragraph.0 > config.max_paragraph_length {
+            warnings.push(LintWarning::info(
+                LintWarningKind::LongParagraph,
+                Span::new(paragraph.1, end),
+                format!("段落が{}文字あります（上限{}文字）", paragraph.0, config.max_paragraph_length),
+            ));
+        }
+    };
+
+    for item in block.iter_items() {
+        match item {
+            ParsedItem::Text(DecoratedText { text, span, .. }) => {
+                let chars: Vec<char> = text.chars().collect();
+                // Offsets inside the item are only known when it maps one-to-one onto the source
+                let one_to_one = span.end - span.start == chars.len();
+                if paragraph.0 == 0 {
+                    paragraph.1 = span.start;
+                }
+                if sentence.0 == 0 {
+                    sentence.1 = span.start;
+                }
+                for (i, c) in chars.iter().enumerate() {
+                    if *c == '　' && paragraph.0 == 0 {
+                        continue; // Paragraph indent
+                    }
+                    paragraph.0 += 1;
+                    if is_sentence_break(*c) {
+                        let end = if one_to_one { span.start + i + 1 } else { span.end };
+                        end_sentence(&mut sentence, end, warnings);
+                    } else {
+                        sentence.0 += 1;
+                    }
+                }
+                last_end = span.end;
+            }
+            ParsedItem::Newline(span) => {
+                end_sentence(&mut sentence, last_end, warnings);
+                end_paragraph(paragraph, last_end, warnings);
+                paragraph = (0, span.end);
+                sentence = (0, span.end);
+            }
+            _ => {}
+        }
+    }
+    end_sentence(&mut sentence, last_end, warnings);
+    end_paragraph(paragraph, last_end, warnings);
+}
+
+/// Characters that end a run counted by the `long-sentence` rule.
+fn is_sentence_break(c: char) -> bool {
+    matches!(c, '。' | '、' | '．' | '，' | '！' | '？' | '」' | '』' | '　')
+}
+
+/// Check for ruby attached to a kana-only base without ｜.
+///
+/// Without ｜ the ruby covers only the kana directly before it, which is usually
+/// a forgotten ｜ meant to extend the base over the preceding kanji.
+fn check_ruby_on_kana(block: &AozoraBlock, warnings: &mut Vec<LintWarning>) {
+    for item in block.iter_items() {
+        let ParsedItem::Text(DecoratedText { text, ruby: Some(ruby), span }) = item else {
+            continue;
+        };
+        // The span of ruby with ｜ covers one more character than 親文字《ルビ》
+        let explicit = span.end - span.start > text.chars().count() + ruby.chars().count() + 2;
+        if explicit || !matches!(text.chars().next().map(script_of), Some(Script::Hiragana | Script::Katakana)) {
+            continue;
+        }
+        warnings.push(LintWarning::warning(
+            LintWarningKind::RubyOnKana,
+            *span,
+            format!(
+                "ルビが仮名だけの「{}」に付いています。漢字から振る場合は親文字の前に｜を入れてください",
+                text
+            ),
+        ));
+    }
+}
+
+/// Run only the `unencodable-character` rule.
+///
+/// Unlike [`lint`] it needs no parsed block, so it also works on text that is still being
+/// written and cannot be parsed, e.g. to mark the characters in an editor as they are typed.
+/// Directives (`［＃kartana:allow(...)］`) are not applied.
+pub fn lint_encoding(text: &str) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    check_encodability(text, &mut warnings);
+    warnings
+}
+
+/// Check for characters that Shift_JIS cannot represent.
+///
+/// Aozora Bunko texts are saved in Shift_JIS, where such characters are lost, so the fix
+/// writes them as gaiji annotations with their code point. The description of the glyph
+/// is left as 〓 for the writer to fill in.
+fn check_encodability(text: &str, warnings: &mut Vec<LintWarning>) {
+    let mut buf = [0; 4];
+    for (pos, c) in text.chars().enumerate() {
+        if c.is_ascii() || !SHIFT_JIS.encode(c.encode_utf8(&mut buf)).2 {
+            continue;
+        }
+        let span = Span::new(pos, pos + 1);
+        let notation = format!("※［＃「〓」、U+{:04X}］", c as u32);
+        warnings.push(LintWarning::error(
+            LintWarningKind::UnencodableCharacter(c),
+            span,
+            format!("「{}」はShift_JISで表せないため保存時に失われます。外字注記{}にしてください", c, notation),
+        ).with_fix(Fix::new(span, notation)));
+    }
+}
+
+/// Punctuation that is never part of a ruby base.
+fn is_punctuation(c: char) -> bool {
+    matches!(c,
+        '、' | '。' | '，' | '．' | '・' |
+        '「' | '」' | '『' | '』' | '（' | '）' | '〈' | '〉' | '【' | '】' |
+        '！' | '？' | '…' | '―' | '　'
+    )
+}
+
 /// Check if a paragraph starts with valid indentation.
 fn is_valid_paragraph_start(text: &str) -> bool {
     if text.is_empty() {
@@ -190,7 +994,7 @@ fn check_text_patterns(text: &str, warnings: &mut Vec<LintWarning>) {
                 LintWarningKind::PunctuationBeforeQuote,
                 Span::new(pos, pos + 2),
                 "閉じ括弧は句点と同じ効果を持つため、句点との併用は冗長です",
-            ));
+            ).with_fix(Fix::new(Span::new(pos, pos + 2), "」")));
         }
         
         // Check odd ellipsis/dash count
@@ -208,7 +1012,7 @@ fn check_text_patterns(text: &str, warnings: &mut Vec<LintWarning>) {
                     LintWarningKind::OddEllipsisCount,
                     Span::new(start, pos),
                     format!("{}は偶数個（2個）で使用することが推奨されます", char_name),
-                ));
+                ).with_fix(Fix::insert(pos, target)));
             }
             continue; // Already advanced pos
         }
@@ -229,6 +1033,72 @@ fn check_text_patterns(text: &str, warnings: &mut Vec<LintWarning>) {
     }
 }
 
+/// Check for doubled punctuation, ？！ ordering and half-width punctuation in Japanese prose.
+fn check_punctuation(text: &str, warnings: &mut Vec<LintWarning>) {
+    let chars: Vec<char> = text.chars().collect();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        let c = chars[pos];
+
+        // Check 。。 or 、、
+        if matches!(c, '。' | '、' | '．' | '，') {
+            let start = pos;
+            while pos < chars.len() && chars[pos] == c {
+                pos += 1;
+            }
+            if pos - start > 1 {
+                warnings.push(LintWarning::warning(
+                    LintWarningKind::DuplicatedPunctuation,
+                    Span::new(start, pos),
+                    format!("「{}」が重複しています", c),
+                ).with_fix(Fix::new(Span::new(start, pos), c)));
+            }
+            continue; // Already advanced pos
+        }
+
+        // Check ？！ (！？ is the usual order)
+        if c == '？' && chars.get(pos + 1) == Some(&'！') {
+            warnings.push(LintWarning::info(
+                LintWarningKind::ExclamationOrder,
+                Span::new(pos, pos + 2),
+                "感嘆符と疑問符を重ねるときは「！？」の順が一般的です",
+            ).with_fix(Fix::new(Span::new(pos, pos + 2), "！？")));
+        }
+
+        // Check , . ! ? right after Japanese text (3.14 or URLs are left alone)
+        if let Some(full_width) = full_width_punctuation(c)
+            && pos > 0
+            && is_japanese(chars[pos - 1])
+            && !chars.get(pos + 1).is_some_and(|n| n.is_ascii_alphanumeric())
+        {
+            warnings.push(LintWarning::warning(
+                LintWarningKind::HalfWidthPunctuation,
+                Span::new(pos, pos + 1),
+                format!("和文中の半角「{}」は全角「{}」にすることが推奨されます", c, full_width),
+            ).with_fix(Fix::new(Span::new(pos, pos + 1), full_width)));
+        }
+
+        pos += 1;
+    }
+}
+
+/// Full-width counterpart of half-width punctuation.
+fn full_width_punctuation(c: char) -> Option<char> {
+    match c {
+        ',' => Some('、'),
+        '.' => Some('。'),
+        '!' => Some('！'),
+        '?' => Some('？'),
+        _ => None,
+    }
+}
+
+/// Whether a character is kanji or kana.
+fn is_japanese(c: char) -> bool {
+    matches!(script_of(c), Script::Kanji | Script::Hiragana | Script::Katakana)
+}
+
 /// Check if a character is valid after ！ or ？
 fn is_valid_after_exclamation(c: char) -> bool {
     matches!(c,
@@ -241,9 +1111,14 @@ fn is_valid_after_exclamation(c: char) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::block_parser::parse_blocks;
-    use crate::parser::parse;
-    use crate::tokenizer::parse_aozora;
+
+    /// Parse `text` and lint it together with the diagnostics of the parsers.
+    fn lint_text(text: &str, config: &LintConfig) -> LintResult {
+        let doc = crate::parser::parse(crate::tokenizer::parse_aozora(text.to_string()).unwrap());
+        let output = crate::block_parser::parse_blocks_with(doc.items, &Default::default()).unwrap();
+        let diagnostics = ParserDiagnostics { parse: &doc.diagnostics, blocks: &output.diagnostics };
+        lint_with_diagnostics(output.block, text, diagnostics, config)
+    }
 
     #[test]
     fn test_punctuation_before_quote() {
@@ -265,6 +1140,81 @@ mod tests {
         assert!(matches!(warnings[0].kind, LintWarningKind::OddEllipsisCount));
     }
 
+    #[test]
+    fn test_duplicated_punctuation() {
+        let text = "そうですね。。それで、、";
+        let mut warnings = Vec::new();
+        check_punctuation(text, &mut warnings);
+
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings.iter().all(|w| w.kind == LintWarningKind::DuplicatedPunctuation));
+        assert_eq!(warnings[0].span, Span::new(5, 7));
+        assert_eq!(apply_fixes(text, &warnings), "そうですね。それで、");
+    }
+
+    #[test]
+    fn test_exclamation_order() {
+        let text = "本当？！　嘘！？";
+        let mut warnings = Vec::new();
+        check_punctuation(text, &mut warnings);
+
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, LintWarningKind::ExclamationOrder);
+        assert_eq!(apply_fixes(text, &warnings), "本当！？　嘘！？");
+    }
+
+    #[test]
+    fn test_half_width_punctuation() {
+        let text = "そうです.本当に?円周率は3.14、http://www.aozora.gr.jp/ を見よ";
+        let mut warnings = Vec::new();
+        check_punctuation(text, &mut warnings);
+
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings.iter().all(|w| w.kind == LintWarningKind::HalfWidthPunctuation));
+        assert_eq!(apply_fixes(text, &warnings), "そうです。本当に？円周率は3.14、http://www.aozora.gr.jp/ を見よ");
+    }
+
+    #[test]
+    fn test_character_width() {
+        let text = "T\nA\n\n　第3章はＡ面\n\n底本：1999年4月8日\n";
+        let block = crate::block_parser::parse_blocks(
+            crate::parser::parse(crate::tokenizer::parse_aozora(text.to_string()).unwrap()).items,
+        ).unwrap();
+
+        // The colophon is not checked
+        let mut warnings = Vec::new();
+        check_character_width(&block, CharacterWidth::FullWidth, &mut warnings);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].span, Span::new(7, 8));
+        assert_eq!(apply_fixes(text, &warnings), "T\nA\n\n　第３章はＡ面\n\n底本：1999年4月8日\n");
+
+        let mut warnings = Vec::new();
+        check_character_width(&block, CharacterWidth::HalfWidth, &mut warnings);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(apply_fixes(text, &warnings), "T\nA\n\n　第3章はA面\n\n底本：1999年4月8日\n");
+    }
+
+    #[test]
+    fn test_long_paragraph_and_sentence() {
+        let text = "T\nA\n\n　あいうえお、かきくけこさしすせそ。\n　短い。\n";
+        let block = crate::block_parser::parse_blocks(
+            crate::parser::parse(crate::tokenizer::parse_aozora(text.to_string()).unwrap()).items,
+        ).unwrap();
+        let config = LintConfig { max_paragraph_length: 12, max_sentence_length: 8, ..Default::default() };
+
+        let mut warnings = Vec::new();
+        check_lengths(&block, &config, &mut warnings);
+        let kinds: Vec<_> = warnings.iter().map(|w| (w.kind.clone(), w.span)).collect();
+        assert_eq!(kinds, vec![
+            (LintWarningKind::LongSentence, Span::new(12, 23)),
+            (LintWarningKind::LongParagraph, Span::new(5, 23)),
+        ]);
+
+        let mut warnings = Vec::new();
+        check_lengths(&block, &LintConfig::default(), &mut warnings);
+        assert!(warnings.is_empty());
+    }
+
     #[test]
     fn test_even_ellipsis_ok() {
         let text = "これは……途中";
@@ -288,6 +1238,266 @@ mod tests {
         assert!(matches!(warnings[0].kind, LintWarningKind::InvalidCharAfterExclamation));
     }
 
+    #[test]
+    fn test_sic_annotation_reported() {
+        let text = "タイトル\n著者\n　獅子奮塵［＃「獅子奮塵」に「ママ」の注記］";
+        let result = lint_text(text, &LintConfig::default());
+        assert!(!result.warnings.iter().any(|w| matches!(w.kind, LintWarningKind::SicAnnotation(_))));
+
+        let mut config = LintConfig::default();
+        config.enable("sic-annotation");
+        let result = lint_text(text, &config);
+        let sic: Vec<_> = result.warnings.iter()
+            .filter(|w| matches!(w.kind, LintWarningKind::SicAnnotation(_)))
+            .collect();
+        assert_eq!(sic.len(), 1);
+        assert_eq!(sic[0].severity, Severity::Info);
+        assert_eq!(sic[0].kind, LintWarningKind::SicAnnotation("獅子奮塵".to_string()));
+    }
+
+    #[test]
+    fn test_apply_fixes() {
+        let text = "タイトル\n著者\n\nこれは…途中。」\n「台詞」\n";
+        let result = lint_text(text, &LintConfig::default());
+        assert!(result.warnings.iter().all(|w| w.fix.is_some()));
+
+        let fixed = apply_fixes(text, &result.warnings);
+        assert_eq!(fixed, "タイトル\n著者\n\n　これは……途中」\n「台詞」\n");
+    }
+
+    #[test]
+    fn test_apply_fixes_skips_overlaps() {
+        let warning = |start, end, replacement: &str| {
+            LintWarning::warning(LintWarningKind::PunctuationBeforeQuote, Span::new(start, end), "")
+                .with_fix(Fix::new(Span::new(start, end), replacement))
+        };
+        let warnings = vec![warning(0, 2, "X"), warning(1, 3, "Y")];
+        assert_eq!(apply_fixes("abcd", &warnings), "aYd");
+    }
+
+    #[test]
+    fn test_suspicious_ruby() {
+        let text = "タイトル\n著者\n\n　漢字《》と字《あいうえおかきくけこさしすせそたちつてと》と｜、《てん》と漢字《かんじ》\n";
+        let result = lint_text(text, &LintConfig::default());
+
+        let ruby: Vec<_> = result.warnings.iter()
+            .filter(|w| w.kind == LintWarningKind::SuspiciousRuby)
+            .collect();
+        assert_eq!(ruby.len(), 3);
+        assert!(ruby[0].message.contains("空"));
+        assert!(ruby[1].message.contains("長すぎ"));
+        assert!(ruby[2].message.contains("約物"));
+    }
+
+    #[test]
+    fn test_ruby_on_kana() {
+        let text = "T\nA\n\n　可愛らしい《かわいらしい》、｜ふりがな《ルビ》、漢字《かんじ》\n";
+        let block = crate::block_parser::parse_blocks(
+            crate::parser::parse(crate::tokenizer::parse_aozora(text.to_string()).unwrap()).items,
+        ).unwrap();
+        let mut warnings = Vec::new();
+        check_ruby_on_kana(&block, &mut warnings);
+
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, LintWarningKind::RubyOnKana);
+        // らしい《かわいらしい》
+        assert_eq!(warnings[0].span, Span::new(8, 19));
+    }
+
+    #[test]
+    fn test_unknown_command_reported() {
+        let text = "タイトル\n著者\n\n　本文［＃未知の注記］《るび》\n";
+        let result = lint_text(text, &LintConfig::default());
+
+        let unknown: Vec<_> = result.warnings.iter()
+            .filter(|w| matches!(w.kind, LintWarningKind::UnknownCommand(_)))
+            .collect();
+        assert_eq!(unknown.len(), 1);
+        assert_eq!(unknown[0].kind, LintWarningKind::UnknownCommand("未知の注記".to_string()));
+        assert_eq!(unknown[0].span, Span::new(12, 20));
+        assert!(result.warnings.iter().any(|w| w.kind == LintWarningKind::RubyWithoutText));
+    }
+
+    #[test]
+    fn test_mismatched_block_tags_reported() {
+        let text = "タイトル\n著者\n\n［＃ここから２字下げ］\n本文\n［＃大見出し終わり］\n";
+        let result = lint_text(text, &LintConfig::default());
+
+        let mismatched: Vec<_> = result.warnings.iter()
+            .filter(|w| w.kind == LintWarningKind::MismatchedBlockTags)
+            .collect();
+        assert_eq!(mismatched.len(), 1);
+        assert_eq!(mismatched[0].span, Span::new(24, 34));
+        assert_eq!(mismatched[0].message, "4行1文字目の［＃ここから２字下げ］が6行1文字目の［＃大見出し終わり］で閉じられています");
+    }
+
+    struct LongLine(usize);
+
+    impl LintRule for LongLine {
+        fn check(&self, _doc: &AozoraBlock, text: &str, out: &mut Vec<LintWarning>) {
+            let mut start = 0;
+            for line in text.split('\n') {
+                let len = line.chars().count();
+                if len > self.0 {
+                    out.push(LintWarning::info(
+                        LintWarningKind::Custom("long-line".to_string()),
+                        Span::new(start, start + len),
+                        "行が長すぎます",
+                    ));
+                }
+                start += len + 1;
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_rule() {
+        let text = "短い\n　とても長い行です\n";
+        let mut config = LintConfig::default();
+        config.add_rule(LongLine(5));
+        config.set_severity("long-line", Severity::Error);
+
+        let block = AozoraBlock { decoration: None, elements: vec![], span: Span::new(0, 0) };
+        let result = lint_with(block, text, &config);
+        let custom: Vec<_> = result.warnings.iter().filter(|w| w.kind.id() == "long-line").collect();
+        assert_eq!(custom.len(), 1);
+        assert_eq!(custom[0].span, Span::new(3, 12));
+        assert_eq!(custom[0].severity, Severity::Error);
+
+        config.disable("long-line");
+        let result = lint_with(result.block, text, &config);
+        assert!(result.warnings.iter().all(|w| w.kind.id() != "long-line"));
+    }
+
+    #[test]
+    fn test_parse_directive() {
+        assert_eq!(
+            Directive::parse("kartana:allow(odd-ellipsis,sic-annotation)"),
+            Some(Directive::Allow(vec!["odd-ellipsis".to_string(), "sic-annotation".to_string()]))
+        );
+        assert_eq!(
+            Directive::parse("kartana：allow-file(missing-paragraph-indent)"),
+            Some(Directive::AllowFile(vec!["missing-paragraph-indent".to_string()]))
+        );
+        assert_eq!(Directive::parse("kartana:deny(odd-ellipsis)"), None);
+        assert_eq!(Directive::parse("ここから２字下げ"), None);
+    }
+
+    #[test]
+    fn test_suppression_directives() {
+        let lint_text = |text: &str| lint_text(text, &LintConfig::default());
+        let count = |result: &LintResult, id: &str| result.warnings.iter().filter(|w| w.kind.id() == id).count();
+
+        // Same line
+        let result = lint_text("T\nA\n\n　あ…［＃kartana:allow(odd-ellipsis)］\n　い…\n");
+        assert_eq!(count(&result, "odd-ellipsis"), 1);
+        assert_eq!(count(&result, "unknown-command"), 0);
+
+        // Directive on its own line applies to the next one
+        let result = lint_text("T\nA\n\n［＃kartana:allow(odd-ellipsis)］\n　あ…\n　い…\n");
+        assert_eq!(count(&result, "odd-ellipsis"), 1);
+
+        // Whole file
+        let result = lint_text("T\nA\n\n［＃kartana:allow-file(odd-ellipsis)］\n　あ…\n　い…\n");
+        assert_eq!(count(&result, "odd-ellipsis"), 0);
+
+        // Malformed directives are still reported
+        let result = lint_text("T\nA\n\n　あ［＃kartana:deny(odd-ellipsis)］\n");
+        assert_eq!(count(&result, "unknown-command"), 1);
+    }
+
+    #[test]
+    fn test_lint_report() {
+        let text = "一行目\n二行目。」\n";
+        let warnings = vec![
+            LintWarning::warning(LintWarningKind::PunctuationBeforeQuote, Span::new(7, 9), "msg")
+                .with_fix(Fix::new(Span::new(7, 9), "」")),
+        ];
+        let report = lint_report(text, &warnings);
+        assert_eq!(report, vec![LintReportEntry {
+            rule: "punctuation-before-quote".to_string(),
+            severity: Severity::Warning,
+            message: "msg".to_string(),
+            span: Span::new(7, 9),
+            line: 2,
+            column: 4,
+            fix: Some(Fix::new(Span::new(7, 9), "」")),
+        }]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_lint_report_json() {
+        let warnings = vec![LintWarning::info(LintWarningKind::SicAnnotation("x".into()), Span::new(0, 1), "msg")];
+        let json: serde_json::Value = serde_json::from_str(&lint_report_json("x", &warnings)).unwrap();
+        assert_eq!(json[0]["rule"], "sic-annotation");
+        assert_eq!(json[0]["line"], 1);
+        assert_eq!(json[0]["column"], 1);
+        assert!(json[0]["fix"].is_null());
+    }
+
+    #[test]
+    fn test_lint_config() {
+        let text = "タイトル\n著者\nこれは…途中。」";
+
+        let mut config = LintConfig::default();
+        config.disable("punctuation-before-quote");
+        config.disable("missing-paragraph-indent");
+        config.set_severity("odd-ellipsis", Severity::Error);
+        let result = lint_text(text, &config);
+
+        assert_eq!(result.warnings.len(), 1);
+        assert_eq!(result.warnings[0].kind, LintWarningKind::OddEllipsisCount);
+        assert_eq!(result.warnings[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn test_rule_ids_are_listed() {
+        let kinds = [
+            LintWarningKind::RubyWithoutText,
+            LintWarningKind::UnknownCommand(String::new()),
+            LintWarningKind::MismatchedBlockTags,
+            LintWarningKind::SuspiciousRuby,
+            LintWarningKind::RubyOnKana,
+            LintWarningKind::MissingParagraphIndent,
+            LintWarningKind::PunctuationBeforeQuote,
+            LintWarningKind::OddEllipsisCount,
+            LintWarningKind::InvalidCharAfterExclamation,
+            LintWarningKind::DuplicatedPunctuation,
+            LintWarningKind::ExclamationOrder,
+            LintWarningKind::HalfWidthPunctuation,
+            LintWarningKind::CharacterWidth,
+            LintWarningKind::LongParagraph,
+            LintWarningKind::LongSentence,
+            LintWarningKind::UnencodableCharacter('\0'),
+            LintWarningKind::SicAnnotation(String::new()),
+        ];
+        let ids: Vec<_> = kinds.iter().map(|k| k.id()).collect();
+        assert_eq!(ids, LintWarningKind::RULE_IDS);
+    }
+
+    #[test]
+    fn test_unencodable_character() {
+        let text = "𠮷野家の\u{546D}と吉、ｱ";
+        let warnings = lint_encoding(text);
+
+        let kinds: Vec<_> = warnings.iter().map(|w| (w.kind.clone(), w.span, w.severity)).collect();
+        assert_eq!(kinds, vec![
+            (LintWarningKind::UnencodableCharacter('𠮷'), Span::new(0, 1), Severity::Error),
+            (LintWarningKind::UnencodableCharacter('\u{546D}'), Span::new(4, 5), Severity::Error),
+        ]);
+        let fixed = apply_fixes(text, &warnings);
+        assert_eq!(fixed, "※［＃「〓」、U+20BB7］野家の※［＃「〓」、U+546D］と吉、ｱ");
+
+        // The fix is read back as the same character
+        let doc = crate::parser::parse(crate::tokenizer::parse_aozora(format!("T\nA\n\n{}", fixed)).unwrap());
+        let gaiji: Vec<_> = doc.items.iter().filter_map(|item| match item {
+            ParsedItem::SpecialCharacter { kind: crate::parser::SpecialCharacter::Gaiji(g), .. } => g.unicode,
+            _ => None,
+        }).collect();
+        assert_eq!(gaiji, vec!['𠮷', '\u{546D}']);
+    }
+
     #[test]
     fn test_valid_after_exclamation() {
         let text = "びっくり！　続き";
@@ -0,0 +1,216 @@
+//! Renders parsed blocks as Markdown, so a manuscript can be published to platforms
+//! that take Markdown.
+//!
+//! Each source line becomes a paragraph. 見出し become `#` headings (大 `#`, 中 `##`,
+//! 小 `###`), bold and 傍点 become `**`, 改ページ becomes a thematic break and 注記
+//! become footnotes (`[^1]`). Indentation and other layout annotations are dropped.
+
+use crate::block_parser::{AozoraBlock, BlockElement};
+use crate::parser::{DecoratedText, ParsedItem, SpecialCharacter};
+use crate::tokenizer::command::{Command, CommandBegin, Midashi, MidashiSize, SingleCommand};
+
+/// How ruby is written in Markdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MarkdownRuby {
+    /// Inline HTML: `<ruby>漢字<rt>かんじ</rt></ruby>`
+    #[default]
+    Html,
+    /// Pandoc bracketed span: `[漢字]{ruby="かんじ"}`
+    Pandoc,
+    /// Drop the ruby
+    Strip,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MarkdownGeneratorOptions {
+    /// Notation of ruby
+    pub ruby: MarkdownRuby,
+}
+
+pub struct MarkdownGenerator {
+    options: MarkdownGeneratorOptions,
+    /// Finished paragraphs, headings and breaks
+    paragraphs: Vec<String>,
+    /// The line being built
+    line: String,
+    notes: Vec<String>,
+}
+
+impl MarkdownGenerator {
+    pub fn generate(block: &AozoraBlock) -> String {
+        Self::generate_with(block, &MarkdownGeneratorOptions::default())
+    }
+
+    pub fn generate_with(block: &AozoraBlock, options: &MarkdownGeneratorOptions) -> String {
+        let mut generator = MarkdownGenerator {
+            options: options.clone(),
+            paragraphs: Vec::new(),
+            line: String::new(),
+            notes: Vec::new(),
+        };
+        generator.render_block(block);
+        generator.end_line();
+
+        for (i, note) in generator.notes.iter().enumerate() {
+            generator.paragraphs.push(format!("[^{}]: {}", i + 1, note));
+        }
+        let mut out = generator.paragraphs.join("\n\n");
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out
+    }
+
+    fn end_line(&mut self) {
+        let line = std::mem::take(&mut self.line);
+        if !line.trim().is_empty() {
+            self.paragraphs.push(line);
+        }
+    }
+
+    fn push_heading(&mut self, m: &Midashi, text: &str) {
+        self.end_line();
+        let level = match m.size {
+            MidashiSize::Large => 1,
+            MidashiSize::Middle => 2,
+            MidashiSize::Small => 3,
+        };
+        self.paragraphs.push(format!("{} {}", "#".repeat(level), text.trim()));
+    }
+
+    fn render_block(&mut self, block: &AozoraBlock) {
+        match &block.decoration {
+            Some(CommandBegin::Midashi(m)) => {
+                // Render the heading text on its own, then emit it as a heading line
+                let outer = std::mem::take(&mut self.line);
+                self.render_elements(block);
+                let text = std::mem::replace(&mut self.line, outer);
+                self.push_heading(m, &text);
+            }
+            Some(CommandBegin::Bold) | Some(CommandBegin::Bouten(_)) => {
+                self.line.push_str("**");
+                self.render_elements(block);
+                self.line.push_str("**");
+            }
+            Some(CommandBegin::Italic) => {
+                self.line.push('*');
+                self.render_elements(block);
+                self.line.push('*');
+            }
+            _ => self.render_elements(block),
+        }
+    }
+
+    fn render_elements(&mut self, block: &AozoraBlock) {
+        for element in &block.elements {
+            match element {
+                BlockElement::Item(item) => self.render_item(item),
+                BlockElement::Block(child) => self.render_block(child),
+            }
+        }
+    }
+
+    fn render_item(&mut self, item: &ParsedItem) {
+        match item {
+            ParsedItem::Text(dt) => self.render_text(dt),
+            ParsedItem::Newline(_) => self.end_line(),
+            ParsedItem::Command { cmd: Command::SingleCommand(sc), .. } => match sc {
+                SingleCommand::Midashi((m, content)) => self.push_heading(m, &escape_markdown(content)),
+                SingleCommand::Bold(s) | SingleCommand::Bouten((_, s)) => {
+                    self.line.push_str(&format!("**{}**", escape_markdown(s)))
+                }
+                SingleCommand::Italic(s) => self.line.push_str(&format!("*{}*", escape_markdown(s))),
+                SingleCommand::Tatechuyoko(s) => self.line.push_str(&escape_markdown(s)),
+                SingleCommand::Kaipage | SingleCommand::Kaicho | SingleCommand::Kaimihiraki => {
+                    self.end_line();
+                    self.paragraphs.push("---".to_string());
+                }
+                SingleCommand::Note { note, .. } => {
+                    self.notes.push(escape_markdown(note));
+                    self.line.push_str(&format!("[^{}]", self.notes.len()));
+                }
+                _ => {}
+            },
+            ParsedItem::Command { .. } | ParsedItem::UnknownCommand { .. } => {}
+            ParsedItem::SpecialCharacter { kind, .. } => match kind {
+                SpecialCharacter::Odoriji => self.line.push_str("／＼"),
+                SpecialCharacter::DakutenOdoriji => self.line.push_str("／″＼"),
+                SpecialCharacter::Gaiji(gaiji) => match gaiji.unicode {
+                    Some(c) => self.line.push(c),
+                    None => self.line.push_str(&format!("※［＃{}］", escape_markdown(&gaiji.annotation))),
+                },
+            },
+        }
+    }
+
+    fn render_text(&mut self, dt: &DecoratedText) {
+        let text = escape_markdown(&dt.text);
+        // A # at the start of a line would make a heading
+        let text = if self.line.is_empty() && text.starts_with('#') {
+            format!("\\{}", text)
+        } else {
+            text
+        };
+        match (&dt.ruby, self.options.ruby) {
+            (Some(ruby), MarkdownRuby::Html) => {
+                self.line.push_str(&format!("<ruby>{}<rt>{}</rt></ruby>", text, escape_markdown(ruby)))
+            }
+            (Some(ruby), MarkdownRuby::Pandoc) => {
+                self.line.push_str(&format!("[{}]{{ruby=\"{}\"}}", text, ruby.replace('"', "\\\"")))
+            }
+            _ => self.line.push_str(&text),
+        }
+    }
+}
+
+/// Render blocks as Markdown with the default options.
+/// See [`MarkdownGenerator::generate_with`].
+pub fn to_markdown(block: &AozoraBlock) -> String {
+    MarkdownGenerator::generate(block)
+}
+
+/// Escape characters that Markdown would treat as markup.
+fn escape_markdown(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '>') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_parser::parse_blocks;
+    use crate::parser::parse;
+    use crate::tokenizer::parse_aozora;
+
+    fn blocks(text: &str) -> AozoraBlock {
+        parse_blocks(parse(parse_aozora(text.to_string()).unwrap()).items).unwrap()
+    }
+
+    #[test]
+    fn test_markdown() {
+        let root = blocks("T\nA\n\n［＃大見出し］第一章［＃大見出し終わり］\n青空《あおぞら》の*下*\n\n［＃中見出し］一［＃中見出し終わり］\n蒼穹［＃「蒼穹」に「空のこと」の注記］\n［＃改ページ］\n#終わり\n");
+
+        assert_eq!(
+            to_markdown(&root),
+            "# 第一章\n\n<ruby>青空<rt>あおぞら</rt></ruby>の\\*下\\*\n\n## 一\n\n蒼穹[^1]\n\n---\n\n\\#終わり\n\n[^1]: 空のこと\n"
+        );
+    }
+
+    #[test]
+    fn test_ruby_styles() {
+        let root = blocks("T\nA\n\n青空《あおぞら》文庫\n");
+        let options = MarkdownGeneratorOptions { ruby: MarkdownRuby::Pandoc };
+        assert_eq!(
+            MarkdownGenerator::generate_with(&root, &options),
+            "[青空]{ruby=\"あおぞら\"}文庫\n"
+        );
+        let options = MarkdownGeneratorOptions { ruby: MarkdownRuby::Strip };
+        assert_eq!(MarkdownGenerator::generate_with(&root, &options), "青空文庫\n");
+    }
+}
@@ -1,8 +1,9 @@
 use itertools::Itertools;
 
-use crate::tokenizer::{self, AozoraToken, CommandToken, Span, TextToken};
+use crate::tokenizer::{self, AozoraToken, Span, TextToken};
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DecoratedText {
     pub text: String,
     pub ruby: Option<String>,
@@ -10,38 +11,540 @@ pub struct DecoratedText {
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SpecialCharacter {
     Odoriji,
     DakutenOdoriji,
+    /// 外字（※［＃「木＋吶のつくり」、第3水準1-85-54］）
+    Gaiji(Gaiji),
 }
 
+/// A character outside the text encoding, written as ※［＃「description」、code…］.
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Gaiji {
+    /// Description of the glyph (木＋吶のつくり)
+    pub description: String,
+    /// The character, when the annotation gives its code point (U+546D)
+    pub unicode: Option<char>,
+    /// JIS X 0213 面区点 code, when given (第3水準1-85-54 -> 1-85-54)
+    pub jis: Option<String>,
+    /// The whole annotation without ［＃ and ］, kept for writing it back
+    pub annotation: String,
+}
+
+impl Gaiji {
+    /// Parse the content of a gaiji annotation (the part inside ※［＃ and ］).
+    pub fn parse(annotation: &str) -> Option<Self> {
+        let rest = annotation.strip_prefix('「')?;
+        let (description, codes) = rest.split_once('」')?;
+        let codes = codes.strip_prefix('、').unwrap_or(codes);
+
+        let mut unicode = None;
+        let mut jis = None;
+        for code in codes.split('、') {
+            if let Some(hex) = code.strip_prefix("U+").or_else(|| code.strip_prefix("u+")) {
+                unicode = u32::from_str_radix(hex, 16).ok().and_then(char::from_u32);
+            } else if let Some(level) = code.strip_prefix("第3水準").or_else(|| code.strip_prefix("第4水準")) {
+                jis = Some(level.to_string());
+            }
+        }
+        Some(Gaiji {
+            description: description.to_string(),
+            unicode,
+            jis,
+            annotation: annotation.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ParsedItem {
     Text(DecoratedText),
     Command { cmd: crate::tokenizer::command::Command, span: Span },
     Newline(Span),
     SpecialCharacter { kind: SpecialCharacter, span: Span },
+    /// An annotation that could not be interpreted, kept as written (without ［＃ and ］)
+    /// so that it is not lost when the text is written back. Generators leave it out.
+    UnknownCommand { content: String, span: Span },
 }
 
-#[derive(Debug, Clone)]
-pub enum ParseError {
-    UnexpectedToken { token: AozoraToken, span: Span },
+/// Characters of the notation itself, written in the text as ※［＃name、code］.
+pub(crate) const NOTATION_SYMBOLS: &[(char, &str)] = &[
+    ('《', "始め二重山括弧、1-1-52"),
+    ('》', "終わり二重山括弧、1-1-53"),
+    ('｜', "縦線、1-1-35"),
+    ('［', "始め角括弧、1-1-46"),
+    ('］', "終わり角括弧、1-1-47"),
+    ('＃', "井げた、1-1-84"),
+];
+
+impl ParsedItem {
+    /// Shift the span of this item by `offset` characters.
+    pub(crate) fn shift_spans(&mut self, offset: usize) {
+        match self {
+            ParsedItem::Text(dt) => dt.span = dt.span.shift(offset),
+            ParsedItem::Command { span, .. }
+            | ParsedItem::Newline(span)
+            | ParsedItem::SpecialCharacter { span, .. }
+            | ParsedItem::UnknownCommand { span, .. } => *span = span.shift(offset),
+        }
+    }
+
+    pub(crate) fn span(&self) -> Span {
+        match self {
+            ParsedItem::Text(dt) => dt.span,
+            ParsedItem::Command { span, .. }
+            | ParsedItem::Newline(span)
+            | ParsedItem::SpecialCharacter { span, .. }
+            | ParsedItem::UnknownCommand { span, .. } => *span,
+        }
+    }
+}
+
+/// Kind of a recoverable problem found while parsing.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ParseDiagnosticKind {
+    /// ルビの親文字がない（行頭や注記の直後の《》）
+    OrphanRuby(String),
+    /// ｜の後にルビが続かない
+    DanglingRubySeparator,
+    /// 解釈できない注記
+    UnknownCommand(String),
 }
 
+/// A recoverable problem found while parsing.
+///
+/// The parser never stops on these; the offending notation is skipped or kept
+/// as plain text and the problem is reported here.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParseDiagnostic {
+    pub kind: ParseDiagnosticKind,
+    pub span: Span,
+    pub message: String,
+}
+
+impl ParseDiagnostic {
+    fn new(kind: ParseDiagnosticKind, span: Span) -> Self {
+        let message = match &kind {
+            ParseDiagnosticKind::OrphanRuby(ruby) => format!("ルビ《{}》に対応する親文字がありません", ruby),
+            ParseDiagnosticKind::DanglingRubySeparator => "｜の後にルビがありません".to_string(),
+            ParseDiagnosticKind::UnknownCommand(content) => format!("注記［＃{}］を解釈できません", content),
+        };
+        Self { kind, span, message }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AozoraMetadata {
     pub title: String,
     pub author: String,
+    /// 副題
+    pub subtitle: Option<String>,
+    /// 原題（翻訳作品の場合）
+    pub original_title: Option<String>,
+    /// 翻訳者
+    pub translator: Option<String>,
+    /// 注記説明ブロック（区切り線で囲まれた部分）の内容
+    pub notes: Vec<HeaderNote>,
 }
 
+/// One entry of the 注記説明 block between the dashed separators.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HeaderNote {
+    /// 見出し（例: 【テキスト中に現れる記号について】）
+    Heading(String),
+    /// 記号の説明（例: 《》：ルビ）と、それに続く用例
+    Annotation {
+        symbol: String,
+        description: String,
+        examples: Vec<String>,
+    },
+    /// その他の行（底本情報、入力者・校正者のクレジットなど）
+    Text(String),
+}
+
+impl HeaderNote {
+    /// Classify a line of the 注記説明 block and append it to `notes`.
+    fn push_line(notes: &mut Vec<HeaderNote>, line: &str) {
+        let line = line.trim_end();
+        if line.trim().is_empty() {
+            return;
+        }
+        if let Some(example) = line.strip_prefix("（例）") {
+            if let Some(HeaderNote::Annotation { examples, .. }) = notes.last_mut() {
+                examples.push(example.to_string());
+                return;
+            }
+        } else if line.starts_with('【') && line.ends_with('】') {
+            let heading = line.trim_start_matches('【').trim_end_matches('】');
+            notes.push(HeaderNote::Heading(heading.to_string()));
+            return;
+        } else if let Some((symbol, description)) = line.split_once('：')
+            && !symbol.is_empty()
+            && !symbol.starts_with('\u{3000}')
+        {
+            notes.push(HeaderNote::Annotation {
+                symbol: symbol.to_string(),
+                description: description.to_string(),
+                examples: Vec::new(),
+            });
+            return;
+        }
+        notes.push(HeaderNote::Text(line.to_string()));
+    }
+}
+
+/// 底本 trailer at the end of the text (底本, 入力者, 校正者, 公開日 and so on).
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Colophon {
+    /// 底本（続く字下げ行は改行で連結）
+    pub source: String,
+    /// 底本の親本
+    pub parent_source: Option<String>,
+    /// 入力者
+    pub inputter: Option<String>,
+    /// 校正者
+    pub proofreader: Option<String>,
+    /// 公開日
+    pub published: Option<String>,
+    /// 修正日
+    pub revised: Vec<String>,
+    /// その他の行（初出、青空文庫作成ファイルについての説明など）
+    pub notes: Vec<String>,
+}
+
+impl Colophon {
+    /// Build the trailer from its lines, starting with the 底本： line.
+    fn from_lines(lines: &[String]) -> Self {
+        let mut colophon = Colophon::default();
+        // Field that indented continuation lines are appended to
+        let mut continued: Option<&mut String> = None;
+
+        for line in lines {
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('　') {
+                let text = line.trim_start_matches('　');
+                match continued.as_deref_mut() {
+                    Some(field) => {
+                        field.push('\n');
+                        field.push_str(text);
+                    }
+                    None => colophon.notes.push(text.to_string()),
+                }
+                continue;
+            }
+
+            continued = None;
+            if let Some(rest) = line.strip_prefix("底本の親本：") {
+                continued = Some(colophon.parent_source.insert(rest.to_string()));
+            } else if let Some(rest) = line.strip_prefix("底本：") {
+                colophon.source = rest.to_string();
+                continued = Some(&mut colophon.source);
+            } else if let Some(rest) = line.strip_prefix("入力：") {
+                colophon.inputter = Some(rest.to_string());
+            } else if let Some(rest) = line.strip_prefix("校正：") {
+                colophon.proofreader = Some(rest.to_string());
+            } else if let Some(date) = line.strip_suffix("公開") {
+                colophon.published = Some(date.to_string());
+            } else if let Some(date) = line.strip_suffix("修正") {
+                colophon.revised.push(date.to_string());
+            } else {
+                colophon.notes.push(line.to_string());
+            }
+        }
+        colophon
+    }
+}
+
+/// Index of the first token of the 底本 trailer, if the text has one.
+///
+/// The trailer starts at the last line beginning with 底本： and runs to the end of the text.
+fn trailer_start(tokens: &[AozoraToken]) -> Option<usize> {
+    let line_starts = std::iter::once(0).chain(
+        tokens
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| matches!(t, AozoraToken::Newline(_)))
+            .map(|(i, _)| i + 1),
+    );
+    line_starts
+        .rev()
+        .filter(|&start| start > 0 && start < tokens.len())
+        .find(|&start| {
+            let end = tokens[start..]
+                .iter()
+                .position(|t| matches!(t, AozoraToken::Newline(_)))
+                .map_or(tokens.len(), |p| start + p);
+            line_text(&tokens[start..end]).starts_with("底本：")
+        })
+}
+
+/// Separator line surrounding the 注記説明 block.
+pub(crate) const COMMENT_SEPARATOR: &str = "-------------------------------------------------------";
+
+/// Maximum number of header lines (作品名, 副題, 原題, 原副題, 著者名, 翻訳者名).
+const MAX_HEADER_LINES: usize = 6;
+
+/// Helper to flatten a line of tokens into its text, ignoring ruby and commands
+fn line_text(line: &[AozoraToken]) -> String {
+    line.iter()
+        .filter_map(|t| match t {
+            AozoraToken::Text(t) => Some(t.content.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Whether a line reads as body text rather than a header line: indented, holding an
+/// annotation, or a sentence of dialogue. A bracket alone is not enough, as in the
+/// title 「いき」の構造.
+fn looks_like_body(line: &[AozoraToken]) -> bool {
+    let text = line_text(line);
+    text.starts_with('　')
+        || line.iter().any(|t| matches!(t, AozoraToken::Command(_)))
+        || (text.contains(['「', '『']) && text.trim_end().ends_with(['。', '」', '』', '！', '？']))
+}
+
+/// Number of lines at the start of the document that make up the header.
+///
+/// The header runs until the first blank line or comment separator, and stops early at
+/// a line that looks like body text, which is left to the body. If no terminator is
+/// found within `MAX_HEADER_LINES` lines, the header is title + author only.
+fn header_line_count(tokens: &[AozoraToken]) -> usize {
+    let lines: Vec<&[AozoraToken]> = tokens
+        .split(|t| matches!(t, AozoraToken::Newline(_)))
+        .take(MAX_HEADER_LINES + 2)
+        .collect();
+    let reached_eof = lines.len() < MAX_HEADER_LINES + 2;
+
+    for (i, line) in lines.iter().enumerate() {
+        let text = line_text(line);
+        let is_last = reached_eof && i == lines.len() - 1;
+        if (text.trim().is_empty() && !is_last) || text.contains(COMMENT_SEPARATOR) {
+            return i.max(2);
+        }
+        if looks_like_body(line) {
+            return i.min(2);
+        }
+    }
+    2
+}
+
+/// Helper to restore the source notation of a token (used for the 注記説明 block)
+fn token_source(token: &AozoraToken) -> String {
+    match token {
+        AozoraToken::Text(t) => t.content.clone(),
+        AozoraToken::Ruby { content, .. } => format!("《{}》", content),
+        AozoraToken::RubySeparator(_) => "｜".to_string(),
+        AozoraToken::Command(c) => format!("［＃{}］", c.content),
+        AozoraToken::Newline(_) => "\n".to_string(),
+        AozoraToken::Odoriji(_) => "／＼".to_string(),
+        AozoraToken::DakutenOdoriji(_) => "／″＼".to_string(),
+    }
+}
+
+/// Script class used to find the base text of a ruby written without ｜
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum Script {
+    Kanji,
+    Hiragana,
+    Katakana,
+    Alphanumeric,
+    Other,
+}
+
+pub(crate) fn script_of(c: char) -> Script {
+    if tokenizer::is_kanji(c) {
+        Script::Kanji
+    } else if tokenizer::is_hiragana(c) {
+        Script::Hiragana
+    } else if tokenizer::is_katakana(c) {
+        Script::Katakana
+    } else if c.is_alphanumeric() {
+        Script::Alphanumeric
+    } else {
+        Script::Other
+    }
+}
+
+/// Split buffered text into the plain prefix and the ruby base.
+///
+/// Following the 青空文庫 rule, a ruby without ｜ covers only the run of
+/// same-script characters directly before it (e.g. the kanji in 「その漢字《かんじ》」).
+/// Returns None if the buffer is empty.
+fn split_ruby_base(buffer: &[TextToken]) -> Option<(Option<DecoratedText>, DecoratedText)> {
+    // Flatten the buffer into characters with their positions in the source text
+    let chars: Vec<(char, usize)> = buffer
+        .iter()
+        .flat_map(|t| t.content.chars().enumerate().map(move |(i, c)| (c, t.span.start + i)))
+        .collect();
+    let &(last, last_pos) = chars.last()?;
+    let script = script_of(last);
+    let split = chars
+        .iter()
+        .rposition(|&(c, _)| script_of(c) != script)
+        .map_or(0, |i| i + 1);
+
+    let prefix = (split > 0).then(|| DecoratedText {
+        text: chars[..split].iter().map(|&(c, _)| c).collect(),
+        ruby: None,
+        span: Span::new(chars[0].1, chars[split - 1].1 + 1),
+    });
+    let base = DecoratedText {
+        text: chars[split..].iter().map(|&(c, _)| c).collect(),
+        ruby: None,
+        span: Span::new(chars[split].1, last_pos + 1),
+    };
+    Some((prefix, base))
+}
+
+/// Whether a header line is written in Latin script (used to detect 原題)
+fn is_latin_line(line: &str) -> bool {
+    line.chars().any(|c| c.is_alphabetic())
+        && line.chars().all(|c| (c as u32) < 0x0250 || c.is_whitespace())
+}
+
+/// Build metadata from the header lines, following the 青空文庫 header convention:
+/// 作品名, 副題, 原題, 著者名, 翻訳者名 (optional lines may be omitted).
+fn metadata_from_header(mut lines: Vec<String>) -> AozoraMetadata {
+    let title = if lines.is_empty() { String::new() } else { lines.remove(0) };
+
+    let mut translator = None;
+    if lines.len() >= 2 && lines.last().is_some_and(|l| l.trim_end().ends_with('訳')) {
+        let line = lines.pop().unwrap();
+        translator = Some(line.trim_end().trim_end_matches('訳').trim_end().to_string());
+    }
+    let author = lines.pop().unwrap_or_default();
+
+    let mut subtitle: Option<String> = None;
+    let mut original_title: Option<String> = None;
+    for line in lines {
+        let slot = if is_latin_line(&line) { &mut original_title } else { &mut subtitle };
+        match slot {
+            Some(existing) => {
+                existing.push('\u{3000}');
+                existing.push_str(&line);
+            }
+            None => *slot = Some(line),
+        }
+    }
+
+    AozoraMetadata {
+        title,
+        author,
+        subtitle,
+        original_title,
+        translator,
+        notes: Vec::new(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AozoraDocument {
     pub metadata: AozoraMetadata,
     pub items: Vec<ParsedItem>,
+    /// Recoverable problems found while parsing
+    pub diagnostics: Vec<ParseDiagnostic>,
+    /// 底本 trailer at the end of the text
+    pub colophon: Option<Colophon>,
+    /// Length of the source text, header and trailer included
+    pub source_len: usize,
+}
+
+impl AozoraDocument {
+    /// End of the source text, i.e. the offset the next appended document starts at.
+    pub fn end(&self) -> usize {
+        self.source_len
+    }
+
+    /// Append the body of another document, e.g. the next chapter of a work.
+    ///
+    /// The spans of `other` are shifted to follow this document, and the two bodies
+    /// are separated by a line break. The metadata of `self` is kept; the 底本
+    /// trailer of `other` is used only if `self` has none.
+    pub fn append(&mut self, mut other: AozoraDocument) {
+        let offset = self.end();
+        if self.items.last().is_some_and(|i| !matches!(i, ParsedItem::Newline(_))) {
+            self.items.push(ParsedItem::Newline(Span::new(offset, offset)));
+        }
+
+        for item in &mut other.items {
+            item.shift_spans(offset);
+        }
+        for diagnostic in &mut other.diagnostics {
+            diagnostic.span = diagnostic.span.shift(offset);
+        }
+        self.items.append(&mut other.items);
+        self.diagnostics.append(&mut other.diagnostics);
+        if self.colophon.is_none() {
+            self.colophon = other.colophon;
+        }
+        self.source_len = offset + other.source_len;
+    }
+}
+
+/// Helper to get span from AozoraToken
+fn token_span(token: &AozoraToken) -> Span {
+    match token {
+        AozoraToken::Text(t) => t.span,
+        AozoraToken::Command(c) => c.span,
+        AozoraToken::Ruby { span, .. } => *span,
+        AozoraToken::RubySeparator(span)
+        | AozoraToken::Newline(span)
+        | AozoraToken::Odoriji(span)
+        | AozoraToken::DakutenOdoriji(span) => *span,
+    }
+}
+
+/// How the leading header (作品名, 著者名, ...) of the text is handled.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum HeaderMode {
+    /// Read the header from the first lines of the text
+    #[default]
+    Detect,
+    /// The text has no header; every line is body text
+    BodyOnly,
+    /// The text has no header; use the given metadata instead
+    External(AozoraMetadata),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ParseOptions {
+    pub header: HeaderMode,
+}
+
+pub fn parse(tokens: Vec<AozoraToken>) -> AozoraDocument {
+    parse_with(tokens, &ParseOptions::default())
 }
 
-pub fn parse(tokens: Vec<AozoraToken>) -> Result<AozoraDocument, ParseError> {
-    let mut tokens_iter = tokens.iter().multipeek();
+/// Parse with options, e.g. to handle fragments of a text that have no header.
+///
+/// Parsing always succeeds; problems in the notation are collected in
+/// `AozoraDocument::diagnostics`.
+pub fn parse_with(tokens: Vec<AozoraToken>, options: &ParseOptions) -> AozoraDocument {
+    // The 底本 trailer is split off before the body is parsed
+    let trailer_start = trailer_start(&tokens);
+    let body_end = trailer_start.unwrap_or(tokens.len());
+    let colophon = trailer_start.map(|start| {
+        let lines: Vec<String> = tokens[start..]
+            .split(|t| matches!(t, AozoraToken::Newline(_)))
+            .map(|line| line.iter().map(token_source).collect())
+            .collect();
+        Colophon::from_lines(&lines)
+    });
+
+    let mut tokens_iter = tokens[..body_end].iter().multipeek();
     
     // Helper to consume a line as String
     let mut consume_line_as_string = || -> String {
@@ -70,10 +573,19 @@ pub fn parse(tokens: Vec<AozoraToken>) -> Result<AozoraDocument, ParseError> {
         line
     };
 
-    let title = consume_line_as_string();
-    let author = consume_line_as_string();
+    let mut metadata = match &options.header {
+        HeaderMode::Detect => {
+            let header_lines = (0..header_line_count(&tokens))
+                .map(|_| consume_line_as_string())
+                .collect();
+            metadata_from_header(header_lines)
+        }
+        HeaderMode::BodyOnly => AozoraMetadata::default(),
+        HeaderMode::External(metadata) => metadata.clone(),
+    };
 
     let mut parsed_items: Vec<ParsedItem> = Vec::new();
+    let mut diagnostics: Vec<ParseDiagnostic> = Vec::new();
     let mut ruby_buffer: Vec<TextToken> = Vec::new();
 
     // Helper to calculate span from ruby_buffer
@@ -89,20 +601,26 @@ pub fn parse(tokens: Vec<AozoraToken>) -> Result<AozoraDocument, ParseError> {
 
     // Loop through remaining tokens
     let mut in_comment_block = false;
+    let mut comment_line = String::new();
+    let mut in_page_center = false;
 
     while let Some(token) = tokens_iter.next() {
         if in_comment_block {
              // Check if this line is a separator to end the block
              match token {
-                 AozoraToken::Text(t) => {
-                     if t.content.contains("-------------------------------------------------------") {
-                         in_comment_block = false;
-                         if let Some(AozoraToken::Newline(_)) = tokens_iter.peek() {
-                             tokens_iter.next();
-                         }
+                 AozoraToken::Text(t) if t.content.contains(COMMENT_SEPARATOR) => {
+                     in_comment_block = false;
+                     HeaderNote::push_line(&mut metadata.notes, &comment_line);
+                     comment_line.clear();
+                     if let Some(AozoraToken::Newline(_)) = tokens_iter.peek() {
+                         tokens_iter.next();
                      }
                  }
-                 _ => {}
+                 AozoraToken::Newline(_) => {
+                     HeaderNote::push_line(&mut metadata.notes, &comment_line);
+                     comment_line.clear();
+                 }
+                 _ => comment_line.push_str(&token_source(token)),
              }
              continue;
         }
@@ -110,7 +628,7 @@ pub fn parse(tokens: Vec<AozoraToken>) -> Result<AozoraDocument, ParseError> {
         match token {
             AozoraToken::Text(t) => {
                  // Check if this starts a comment block
-                 if t.content.contains("-------------------------------------------------------") {
+                 if t.content.contains(COMMENT_SEPARATOR) {
                      in_comment_block = true;
                      // Flush buffer
                      if !ruby_buffer.is_empty() {
@@ -179,6 +697,7 @@ pub fn parse(tokens: Vec<AozoraToken>) -> Result<AozoraDocument, ParseError> {
                 }
                 
                 if !valid_ruby {
+                    diagnostics.push(ParseDiagnostic::new(ParseDiagnosticKind::DanglingRubySeparator, *sep_span));
                     // Treat | as literal text
                     parsed_items.push(ParsedItem::Text(DecoratedText {
                         text: "｜".to_string(),
@@ -197,34 +716,73 @@ pub fn parse(tokens: Vec<AozoraToken>) -> Result<AozoraDocument, ParseError> {
                 }
             }
             AozoraToken::Ruby { content, span: ruby_span } => {
-                // Ruby without separator applies to the last text token in buffer
-                if let Some(last_text) = ruby_buffer.pop() {
-                     // Flush any previous tokens in buffer
-                     if !ruby_buffer.is_empty() {
-                         let span = buffer_span(&ruby_buffer);
-                         parsed_items.push(ParsedItem::Text(DecoratedText {
-                             text: ruby_buffer.iter().map(|t| t.content.clone()).join(""),
-                             ruby: None,
-                             span,
-                         }));
-                         ruby_buffer.clear();
+                // Ruby without separator applies to the trailing same-script run in buffer
+                if let Some((prefix, base)) = split_ruby_base(&ruby_buffer) {
+                     ruby_buffer.clear();
+                     // Flush the text before the ruby base
+                     if let Some(prefix) = prefix {
+                         parsed_items.push(ParsedItem::Text(prefix));
                      }
-                     
-                     // Push the last token with ruby
-                     let full_span = last_text.span.merge(ruby_span);
+
+                     // Push the base text with ruby
                      parsed_items.push(ParsedItem::Text(DecoratedText {
-                        text: last_text.content.clone(),
+                        text: base.text,
                         ruby: Some(content.clone()),
-                        span: full_span,
+                        span: base.span.merge(ruby_span),
                     }));
                 } else {
-                    // Ruby without text - will be detected by Linter
-                    if content.is_empty() {
-                        continue;
-                    }
+                    // Ruby without text is dropped
+                    diagnostics.push(ParseDiagnostic::new(
+                        ParseDiagnosticKind::OrphanRuby(content.clone()),
+                        *ruby_span,
+                    ));
                 }
             }
             AozoraToken::Command(c) => {
+                // ※ followed by ［＃「…」、…］ is a gaiji
+                if ruby_buffer.last().is_some_and(|t| t.content.ends_with('※'))
+                    && let Some(gaiji) = Gaiji::parse(&c.content)
+                {
+                    let last = ruby_buffer.last_mut().unwrap();
+                    last.content.pop();
+                    last.span.end -= 1;
+                    let start = last.span.end;
+                    if last.content.is_empty() {
+                        ruby_buffer.pop();
+                    }
+                    if !ruby_buffer.is_empty() {
+                        let span = buffer_span(&ruby_buffer);
+                        parsed_items.push(ParsedItem::Text(DecoratedText {
+                            text: ruby_buffer.iter().map(|t| t.content.clone()).join(""),
+                            ruby: None,
+                            span,
+                        }));
+                        ruby_buffer.clear();
+                    }
+                    parsed_items.push(ParsedItem::SpecialCharacter {
+                        kind: SpecialCharacter::Gaiji(gaiji),
+                        span: Span::new(start, c.span.end),
+                    });
+                    continue;
+                }
+                // ※［＃始め二重山括弧、1-1-52］ and the like stand for a character of the notation
+                if ruby_buffer.last().is_some_and(|t| t.content.ends_with('※'))
+                    && let Some((symbol, _)) = NOTATION_SYMBOLS.iter().find(|(_, name)| *name == c.content)
+                {
+                    let last = ruby_buffer.last_mut().unwrap();
+                    last.content.pop();
+                    last.span.end -= 1;
+                    let start = last.span.end;
+                    if last.content.is_empty() {
+                        ruby_buffer.pop();
+                    }
+                    ruby_buffer.push(TextToken {
+                        content: symbol.to_string(),
+                        kind: tokenizer::TextKind::Other,
+                        span: Span::new(start, c.span.end),
+                    });
+                    continue;
+                }
                 // Flush buffer
                 if !ruby_buffer.is_empty() {
                     let span = buffer_span(&ruby_buffer);
@@ -240,38 +798,102 @@ pub fn parse(tokens: Vec<AozoraToken>) -> Result<AozoraDocument, ParseError> {
                     let mut merged = false;
                     if let crate::tokenizer::command::Command::SingleCommand(
                         crate::tokenizer::command::SingleCommand::Midashi((m, content))
-                    ) = &cmd {
-                        if let Some(ParsedItem::Text(dt)) = parsed_items.last() {
-                            if dt.text == *content {
-                                // Match found! Convert to block.
-                                let text_item = parsed_items.pop().unwrap();
-                                let text_span = if let ParsedItem::Text(dt) = &text_item {
-                                    dt.span
-                                } else {
-                                    Span::default()
-                                };
-                                
-                                parsed_items.push(ParsedItem::Command {
-                                    cmd: crate::tokenizer::command::Command::CommandBegin(
-                                        crate::tokenizer::command::CommandBegin::Midashi(m.clone())
-                                    ),
-                                    span: text_span,
-                                });
-                                parsed_items.push(text_item);
-                                parsed_items.push(ParsedItem::Command {
-                                    cmd: crate::tokenizer::command::Command::CommandEnd(
-                                        crate::tokenizer::command::CommandEnd::Midashi(m.clone())
-                                    ),
-                                    span: c.span,
-                                });
-                                merged = true;
+                    ) = &cmd
+                        && let Some(ParsedItem::Text(dt)) = parsed_items.last()
+                        && dt.text == *content
+                    {
+                        // Match found! Convert to block.
+                        let text_item = parsed_items.pop().unwrap();
+                        let text_span = if let ParsedItem::Text(dt) = &text_item {
+                            dt.span
+                        } else {
+                            Span::default()
+                        };
+
+                        parsed_items.push(ParsedItem::Command {
+                            cmd: crate::tokenizer::command::Command::CommandBegin(
+                                crate::tokenizer::command::CommandBegin::Midashi(m.clone())
+                            ),
+                            span: text_span,
+                        });
+                        parsed_items.push(text_item);
+                        parsed_items.push(ParsedItem::Command {
+                            cmd: crate::tokenizer::command::Command::CommandEnd(
+                                crate::tokenizer::command::CommandEnd::Midashi(m.clone())
+                            ),
+                            span: c.span,
+                        });
+                        merged = true;
+                    }
+
+                    // 縦中横 takes the text it refers to out of the text before it. When that text
+                    // is not there the annotation is kept as written, leaving the text as it is
+                    if let crate::tokenizer::command::Command::SingleCommand(
+                        crate::tokenizer::command::SingleCommand::Tatechuyoko(content)
+                    ) = &cmd
+                    {
+                        if let Some(ParsedItem::Text(dt)) = parsed_items.last_mut()
+                            && dt.ruby.is_none()
+                            && let Some(rest) = dt.text.strip_suffix(content.as_str())
+                        {
+                            let start = dt.span.end - content.chars().count();
+                            dt.text = rest.to_string();
+                            dt.span.end = start;
+                            if dt.text.is_empty() {
+                                parsed_items.pop();
                             }
+                            parsed_items.push(ParsedItem::Command {
+                                cmd: cmd.clone(),
+                                span: Span::new(start, c.span.end),
+                            });
+                        } else {
+                            parsed_items.push(ParsedItem::UnknownCommand {
+                                content: c.content.clone(),
+                                span: c.span,
+                            });
                         }
+                        merged = true;
+                    }
+
+                    // ページの左右中央 has no closing annotation; it lasts until the next page break
+                    if in_page_center
+                        && let crate::tokenizer::command::Command::SingleCommand(
+                            crate::tokenizer::command::SingleCommand::Kaipage
+                            | crate::tokenizer::command::SingleCommand::Kaicho
+                            | crate::tokenizer::command::SingleCommand::Kaimihiraki
+                        ) = &cmd
+                    {
+                        parsed_items.push(ParsedItem::Command {
+                            cmd: crate::tokenizer::command::Command::CommandEnd(
+                                crate::tokenizer::command::CommandEnd::PageCenter
+                            ),
+                            span: Span::new(c.span.start, c.span.start),
+                        });
+                        in_page_center = false;
+                    }
+                    if let crate::tokenizer::command::Command::CommandBegin(
+                        crate::tokenizer::command::CommandBegin::PageCenter
+                    ) = &cmd
+                    {
+                        if in_page_center {
+                            // Already centered; a repeated annotation is redundant
+                            merged = true;
+                        }
+                        in_page_center = true;
                     }
 
                     if !merged {
                         parsed_items.push(ParsedItem::Command { cmd, span: c.span });
                     }
+                } else {
+                    diagnostics.push(ParseDiagnostic::new(
+                        ParseDiagnosticKind::UnknownCommand(c.content.clone()),
+                        c.span,
+                    ));
+                    parsed_items.push(ParsedItem::UnknownCommand {
+                        content: c.content.clone(),
+                        span: c.span,
+                    });
                 }
             }
              AozoraToken::Newline(span) => {
@@ -326,13 +948,23 @@ pub fn parse(tokens: Vec<AozoraToken>) -> Result<AozoraDocument, ParseError> {
         }));
     }
 
-    Ok(AozoraDocument {
-        metadata: AozoraMetadata {
-            title,
-            author,
-        },
+    if in_page_center {
+        let end = tokens.last().map_or(0, |t| token_span(t).end);
+        parsed_items.push(ParsedItem::Command {
+            cmd: crate::tokenizer::command::Command::CommandEnd(
+                crate::tokenizer::command::CommandEnd::PageCenter
+            ),
+            span: Span::new(end, end),
+        });
+    }
+
+    AozoraDocument {
+        metadata,
         items: parsed_items,
-    })
+        diagnostics,
+        colophon,
+        source_len: tokens.last().map_or(0, |t| token_span(t).end),
+    }
 }
 
 #[cfg(test)]
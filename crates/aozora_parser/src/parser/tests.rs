@@ -25,19 +25,19 @@ fn debug_hashigaki() {
     let tokens = parse_aozora(text).expect("Tokenization failed");
     
     for (i, token) in tokens.iter().enumerate() {
-        if let AozoraToken::Text(t) = token {
-            if t.content.contains("はしがき") {
-                println!("Found 'はしがき' at index {}", i);
-                let start = if i > 5 { i - 5 } else { 0 };
-                let end = if i + 5 < tokens.len() { i + 5 } else { tokens.len() };
-                println!("Context: {:?}", &tokens[start..end]);
-            }
+        if let AozoraToken::Text(t) = token
+            && t.content.contains("はしがき")
+        {
+            println!("Found 'はしがき' at index {}", i);
+            let start = i.saturating_sub(5);
+            let end = if i + 5 < tokens.len() { i + 5 } else { tokens.len() };
+            println!("Context: {:?}", &tokens[start..end]);
         }
-        if let AozoraToken::Command(c) = token {
-                if c.content.contains("はしがき") {
-                println!("Found command 'はしがき' at index {}", i);
-                println!("Context: {:?}", token);
-                }
+        if let AozoraToken::Command(c) = token
+            && c.content.contains("はしがき")
+        {
+            println!("Found command 'はしがき' at index {}", i);
+            println!("Context: {:?}", token);
         }
     }
 }
@@ -47,7 +47,7 @@ fn test_simple_text() {
     let tokens = vec![
         AozoraToken::Text(TextToken { content: "こんにちは".to_string(), kind: TextKind::Hiragana, span: Span::new(13, 18) }),
     ];
-    let doc = parse(with_metadata(tokens)).unwrap();
+    let doc = parse(with_metadata(tokens));
     assert_eq!(doc.metadata.title, "Title");
     assert_eq!(doc.metadata.author, "Author");
     assert_eq!(doc.items.len(), 1);
@@ -66,7 +66,7 @@ fn test_ruby_no_separator() {
         AozoraToken::Text(TextToken { content: "漢字".to_string(), kind: TextKind::Kanji, span: Span::new(13, 15) }),
         AozoraToken::Ruby { content: "かんじ".to_string(), span: Span::new(15, 20) },
     ];
-    let doc = parse(with_metadata(tokens)).unwrap();
+    let doc = parse(with_metadata(tokens));
     assert_eq!(doc.items.len(), 1);
     if let ParsedItem::Text(t) = &doc.items[0] {
         assert_eq!(t.text, "漢字");
@@ -76,6 +76,39 @@ fn test_ruby_no_separator() {
     }
 }
 
+#[test]
+fn test_ruby_attaches_to_trailing_script_run() {
+    // 、ＡＢＣ《エービーシー》: the comma must not be part of the ruby base
+    let tokens = vec![
+        AozoraToken::Text(TextToken { content: "これは".to_string(), kind: TextKind::Hiragana, span: Span::new(13, 16) }),
+        AozoraToken::Text(TextToken { content: "、ＡＢＣ".to_string(), kind: TextKind::Other, span: Span::new(16, 20) }),
+        AozoraToken::Ruby { content: "エービーシー".to_string(), span: Span::new(20, 28) },
+    ];
+    let doc = parse(with_metadata(tokens));
+    assert_eq!(doc.items, vec![
+        ParsedItem::Text(DecoratedText { text: "これは、".to_string(), ruby: None, span: Span::new(13, 17) }),
+        ParsedItem::Text(DecoratedText { text: "ＡＢＣ".to_string(), ruby: Some("エービーシー".to_string()), span: Span::new(17, 28) }),
+    ]);
+}
+
+#[test]
+fn test_ruby_on_mixed_script_token() {
+    // An Other-kind token ending in kanji only lends the kanji to the ruby
+    let tokens = vec![
+        AozoraToken::Text(TextToken { content: "ABC漢字".to_string(), kind: TextKind::Other, span: Span::new(13, 18) }),
+        AozoraToken::Ruby { content: "かんじ".to_string(), span: Span::new(18, 23) },
+    ];
+    let doc = parse(with_metadata(tokens));
+    assert_eq!(doc.items.len(), 2);
+    if let ParsedItem::Text(t) = &doc.items[1] {
+        assert_eq!(t.text, "漢字");
+        assert_eq!(t.ruby, Some("かんじ".to_string()));
+        assert_eq!(t.span, Span::new(16, 23));
+    } else {
+        panic!("Expected Text");
+    }
+}
+
 #[test]
 fn test_ruby_with_separator() {
     // ｜ロンドン警視庁《スコットランドヤード》
@@ -85,7 +118,7 @@ fn test_ruby_with_separator() {
         AozoraToken::Text(TextToken { content: "警視庁".to_string(), kind: TextKind::Kanji, span: Span::new(18, 21) }),
         AozoraToken::Ruby { content: "スコットランドヤード".to_string(), span: Span::new(21, 33) },
     ];
-    let doc = parse(with_metadata(tokens)).unwrap();
+    let doc = parse(with_metadata(tokens));
     assert_eq!(doc.items.len(), 1);
     if let ParsedItem::Text(t) = &doc.items[0] {
         assert_eq!(t.text, "ロンドン警視庁");
@@ -104,7 +137,7 @@ fn test_ruby_with_separator_multiple_text() {
         AozoraToken::Text(TextToken { content: "文庫".to_string(), kind: TextKind::Kanji, span: Span::new(16, 18) }),
         AozoraToken::Ruby { content: "あおぞらぶんこ".to_string(), span: Span::new(18, 27) },
     ];
-    let doc = parse(with_metadata(tokens)).unwrap();
+    let doc = parse(with_metadata(tokens));
     assert_eq!(doc.items.len(), 1);
     if let ParsedItem::Text(t) = &doc.items[0] {
         assert_eq!(t.text, "青空文庫");
@@ -121,7 +154,7 @@ fn test_mixed_text_flushing() {
         AozoraToken::Text(TextToken { content: "こんにちは".to_string(), kind: TextKind::Hiragana, span: Span::new(13, 18) }),
         AozoraToken::Text(TextToken { content: "世界".to_string(), kind: TextKind::Kanji, span: Span::new(18, 20) }),
     ];
-    let doc = parse(with_metadata(tokens)).unwrap();
+    let doc = parse(with_metadata(tokens));
     assert_eq!(doc.items.len(), 1); 
     // Should merge into one text item if no ruby/command intervenes
     if let ParsedItem::Text(t) = &doc.items[0] {
@@ -153,7 +186,7 @@ fn test_comment_block_skipping() {
     ];
     
     // Pass tokens directly as they include metadata lines
-    let doc = parse(tokens).unwrap();
+    let doc = parse(tokens);
     
     assert_eq!(doc.metadata.title, "Title");
     assert_eq!(doc.metadata.author, "Author");
@@ -177,7 +210,7 @@ fn debug_outou_block_parse() {
     let (cow, _, _) = SHIFT_JIS.decode(&bytes);
     let text = cow.into_owned();
     let tokens = parse_aozora(text).expect("Tokenization failed");
-    let doc = parse(tokens).expect("Parsing failed");
+    let doc = parse(tokens);
     
     // Print out CommandBegin/End items to debug
     for (i, item) in doc.items.iter().enumerate() {
@@ -203,3 +236,293 @@ fn debug_outou_block_parse() {
         }
     }
 }
+
+#[test]
+fn test_page_center_closed_at_page_break() {
+    use crate::tokenizer::command::{Command, CommandBegin, CommandEnd, SingleCommand};
+
+    let text = "Title\nAuthor\n［＃ページの左右中央］第一部［＃改ページ］本文［＃ページの左右中央］".to_string();
+    let doc = parse(parse_aozora(text).unwrap());
+    let commands: Vec<&Command> = doc.items.iter().filter_map(|item| match item {
+        ParsedItem::Command { cmd, .. } => Some(cmd),
+        _ => None,
+    }).collect();
+
+    assert_eq!(commands, vec![
+        &Command::CommandBegin(CommandBegin::PageCenter),
+        &Command::CommandEnd(CommandEnd::PageCenter),
+        &Command::SingleCommand(SingleCommand::Kaipage),
+        &Command::CommandBegin(CommandBegin::PageCenter),
+        &Command::CommandEnd(CommandEnd::PageCenter),
+    ]);
+}
+
+#[test]
+fn test_extended_header() {
+    let text = "黄金虫\nThe Gold-Bug\nエドガー・アラン・ポー\n佐々木直次郎訳\n\n　本文".to_string();
+    let doc = parse(parse_aozora(text).unwrap());
+
+    assert_eq!(doc.metadata.title, "黄金虫");
+    assert_eq!(doc.metadata.original_title.as_deref(), Some("The Gold-Bug"));
+    assert_eq!(doc.metadata.subtitle, None);
+    assert_eq!(doc.metadata.author, "エドガー・アラン・ポー");
+    assert_eq!(doc.metadata.translator.as_deref(), Some("佐々木直次郎"));
+
+    // The blank line and body are left to the body items
+    assert!(matches!(doc.items[0], ParsedItem::Newline(_)));
+    assert!(matches!(&doc.items[1], ParsedItem::Text(t) if t.text == "　本文"));
+}
+
+#[test]
+fn test_header_with_subtitle_before_separator() {
+    let text = "作品名\n副題\n著者名\n-------------------------------------------------------\n注記\n-------------------------------------------------------\n本文".to_string();
+    let doc = parse(parse_aozora(text).unwrap());
+
+    assert_eq!(doc.metadata.title, "作品名");
+    assert_eq!(doc.metadata.subtitle.as_deref(), Some("副題"));
+    assert_eq!(doc.metadata.author, "著者名");
+    assert_eq!(doc.metadata.translator, None);
+}
+
+#[test]
+fn test_header_without_terminator_falls_back_to_two_lines() {
+    let text = "Title\nAuthor\n本文\n".to_string();
+    let doc = parse(parse_aozora(text).unwrap());
+
+    assert_eq!(doc.metadata.title, "Title");
+    assert_eq!(doc.metadata.author, "Author");
+    assert!(matches!(&doc.items[0], ParsedItem::Text(t) if t.text == "本文"));
+}
+
+#[test]
+fn test_header_stops_at_dialogue() {
+    let text = "作品名\n著者名\n「おはよう」と彼は言った。\n\n　二段落目\n".to_string();
+    let doc = parse(parse_aozora(text).unwrap());
+
+    assert_eq!(doc.metadata.title, "作品名");
+    assert_eq!(doc.metadata.author, "著者名");
+    assert_eq!(doc.metadata.subtitle, None);
+    assert!(matches!(&doc.items[0], ParsedItem::Text(t) if t.text == "「おはよう」と彼は言った。"));
+}
+
+#[test]
+fn test_text_without_header() {
+    let text = "「おはよう」\nと彼は言った。\n\n　二段落目\n".to_string();
+    let doc = parse(parse_aozora(text).unwrap());
+
+    assert_eq!(doc.metadata.title, "");
+    assert_eq!(doc.metadata.author, "");
+    assert!(matches!(&doc.items[0], ParsedItem::Text(t) if t.text == "「おはよう」"));
+
+    // A title with brackets is still a title
+    let text = "「いき」の構造\n九鬼周造\n\n　本文\n".to_string();
+    let doc = parse(parse_aozora(text).unwrap());
+    assert_eq!(doc.metadata.title, "「いき」の構造");
+    assert_eq!(doc.metadata.author, "九鬼周造");
+}
+
+#[test]
+fn test_comment_block_notes() {
+    let text = "桜桃\n太宰治\n\n-------------------------------------------------------\n【テキスト中に現れる記号について】\n\n《》：ルビ\n（例）目を挙《あ》ぐ。\n\n［＃］：入力者注　主に外字の説明や、傍点の位置の指定\n（例）［＃ここから３字下げ］\n-------------------------------------------------------\n本文".to_string();
+    let doc = parse(parse_aozora(text).unwrap());
+
+    assert_eq!(doc.metadata.notes, vec![
+        HeaderNote::Heading("テキスト中に現れる記号について".to_string()),
+        HeaderNote::Annotation {
+            symbol: "《》".to_string(),
+            description: "ルビ".to_string(),
+            examples: vec!["目を挙《あ》ぐ。".to_string()],
+        },
+        HeaderNote::Annotation {
+            symbol: "［＃］".to_string(),
+            description: "入力者注　主に外字の説明や、傍点の位置の指定".to_string(),
+            examples: vec!["［＃ここから３字下げ］".to_string()],
+        },
+    ]);
+    // The block itself is still removed from the body
+    assert!(doc.items.iter().all(|i| !matches!(i, ParsedItem::Command { .. })));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_round_trip() {
+    let text = "Title\nAuthor\n\n［＃大見出し］第一章［＃大見出し終わり］\n｜青空《あおぞら》文庫［＃「文庫」に傍点］\n《》\n".to_string();
+    let tokens = parse_aozora(text.clone()).unwrap();
+    let doc = parse(tokens.clone());
+    let blocks = crate::block_parser::parse_blocks(doc.items.clone()).unwrap();
+
+    let json = serde_json::to_string(&tokens).unwrap();
+    assert_eq!(serde_json::from_str::<Vec<AozoraToken>>(&json).unwrap(), tokens);
+
+    let json = serde_json::to_string(&doc).unwrap();
+    assert_eq!(serde_json::from_str::<AozoraDocument>(&json).unwrap(), doc);
+
+    let json = serde_json::to_string(&blocks).unwrap();
+    assert_eq!(serde_json::from_str::<crate::block_parser::AozoraBlock>(&json).unwrap(), blocks);
+
+    let lint = crate::linter::lint_with_diagnostics(
+        blocks,
+        &text,
+        crate::linter::ParserDiagnostics { parse: &doc.diagnostics, blocks: &[] },
+        &Default::default(),
+    );
+    assert!(!lint.warnings.is_empty());
+    let json = serde_json::to_string(&lint.warnings).unwrap();
+    let warnings: Vec<crate::linter::LintWarning> = serde_json::from_str(&json).unwrap();
+    assert_eq!(warnings.len(), lint.warnings.len());
+}
+
+#[test]
+fn test_parse_body_only() {
+    let text = "一行目\n二行目\n".to_string();
+    let options = ParseOptions { header: HeaderMode::BodyOnly };
+    let doc = parse_with(parse_aozora(text).unwrap(), &options);
+
+    assert_eq!(doc.metadata, AozoraMetadata::default());
+    assert!(matches!(&doc.items[0], ParsedItem::Text(t) if t.text == "一行目" && t.span == Span::new(0, 3)));
+    assert_eq!(doc.items.len(), 4);
+}
+
+#[test]
+fn test_parse_with_external_metadata() {
+    let metadata = AozoraMetadata {
+        title: "桜桃".to_string(),
+        author: "太宰治".to_string(),
+        ..Default::default()
+    };
+    let options = ParseOptions { header: HeaderMode::External(metadata.clone()) };
+    let doc = parse_with(parse_aozora("子供より親が大事、と思いたい。".to_string()).unwrap(), &options);
+
+    assert_eq!(doc.metadata, metadata);
+    assert_eq!(doc.items.len(), 1);
+}
+
+#[test]
+fn test_parse_diagnostics() {
+    let text = "T\nA\n\n《るび》本文｜\n［＃未知の注記］\n".to_string();
+    let doc = parse(parse_aozora(text).unwrap());
+
+    let kinds: Vec<_> = doc.diagnostics.iter().map(|d| d.kind.clone()).collect();
+    assert_eq!(kinds, vec![
+        ParseDiagnosticKind::OrphanRuby("るび".to_string()),
+        ParseDiagnosticKind::DanglingRubySeparator,
+        ParseDiagnosticKind::UnknownCommand("未知の注記".to_string()),
+    ]);
+    assert_eq!(doc.diagnostics[0].span, Span::new(5, 9));
+    assert_eq!(doc.diagnostics[1].span, Span::new(11, 12));
+    assert_eq!(doc.diagnostics[2].span, Span::new(13, 21));
+
+    // Parsing recovers: the body text and the literal ｜ are kept
+    let texts: Vec<_> = doc.items.iter().filter_map(|i| match i {
+        ParsedItem::Text(t) => Some(t.text.as_str()),
+        _ => None,
+    }).collect();
+    assert_eq!(texts, vec!["本文", "｜"]);
+}
+
+#[test]
+fn test_colophon() {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("src/parser_test_data/桜桃.txt");
+    let bytes = fs::read(&path).expect("Could not find test file");
+    let (cow, _, _) = SHIFT_JIS.decode(&bytes);
+    let doc = parse(parse_aozora(cow.into_owned()).unwrap());
+
+    let colophon = doc.colophon.expect("底本 trailer not found");
+    assert_eq!(colophon.source, "角川文庫「人間失格・桜桃」角川書店\n1989（平成元）年4月10日初版発行");
+    assert_eq!(colophon.parent_source, None);
+    assert_eq!(colophon.inputter.as_deref(), Some("高橋美奈子"));
+    assert_eq!(colophon.proofreader.as_deref(), Some("瀬戸さえ子"));
+    assert_eq!(colophon.published.as_deref(), Some("1999年4月8日"));
+    assert_eq!(colophon.revised, vec!["2004年2月23日".to_string()]);
+    assert_eq!(colophon.notes.len(), 2);
+
+    // The trailer is no longer part of the body
+    assert!(doc.items.iter().all(|i| !matches!(i, ParsedItem::Text(t) if t.text.contains("底本"))));
+}
+
+#[test]
+fn test_colophon_absent() {
+    let doc = parse(parse_aozora("T\nA\n\n本文の底本：は行頭にない\n".to_string()).unwrap());
+    assert_eq!(doc.colophon, None);
+}
+
+#[test]
+fn test_append_documents() {
+    let mut first = parse(parse_aozora("作品\n著者\n\n第一章\n".to_string()).unwrap());
+    let second = parse(parse_aozora("作品\n著者\n\n《るび》第二章".to_string()).unwrap());
+    let first_end = first.end();
+    assert_eq!(first_end, 11);
+
+    first.append(second);
+
+    assert_eq!(first.metadata.title, "作品");
+    // The second chapter follows the first one without overlapping spans
+    let last = first.items.last().unwrap();
+    assert!(matches!(last, ParsedItem::Text(t) if t.text == "第二章" && t.span == Span::new(first_end + 11, first_end + 14)));
+    assert_eq!(first.diagnostics[0].span, Span::new(first_end + 7, first_end + 11));
+    let starts: Vec<usize> = first.items.iter().map(|i| i.span().start).collect();
+    assert!(starts.windows(2).all(|w| w[0] <= w[1]));
+}
+
+#[test]
+fn test_append_after_colophon() {
+    // The trailer is not part of the body, but still takes up source text
+    let text = "作品\n著者\n\n第一章\n\n底本：「作品」文庫\n";
+    let mut first = parse(parse_aozora(text.to_string()).unwrap());
+    assert!(first.colophon.is_some());
+    assert_eq!(first.end(), text.chars().count());
+    let second = parse(parse_aozora("作品\n著者\n\n第二章".to_string()).unwrap());
+
+    // Appending the blocks gives the same spans as appending the documents
+    let mut blocks = crate::block_parser::parse_blocks(first.items.clone()).unwrap();
+    blocks.append(crate::block_parser::parse_blocks(second.items.clone()).unwrap(), first.end());
+
+    first.append(second);
+    assert_eq!(blocks, crate::block_parser::parse_blocks(first.items.clone()).unwrap());
+
+    let last = first.items.last().unwrap();
+    assert!(matches!(last, ParsedItem::Text(t) if t.text == "第二章" && t.span == Span::new(text.chars().count() + 7, text.chars().count() + 10)));
+    assert_eq!(first.end(), text.chars().count() + 10);
+}
+
+#[test]
+fn test_tatechuyoko() {
+    let text = "T\nA\n\n第12［＃「12」は縦中横］話と!?［＃「!?」は縦中横］と3［＃「4」は縦中横］\n".to_string();
+    let doc = parse(parse_aozora(text).unwrap());
+
+    let tcy = ParsedItem::Command {
+        cmd: crate::tokenizer::command::Command::SingleCommand(
+            crate::tokenizer::command::SingleCommand::Tatechuyoko("12".to_string()),
+        ),
+        span: Span::new(6, 19),
+    };
+    assert_eq!(doc.items[1], ParsedItem::Text(DecoratedText { text: "第".to_string(), ruby: None, span: Span::new(5, 6) }));
+    assert_eq!(doc.items[2], tcy);
+    assert_eq!(doc.items[3], ParsedItem::Text(DecoratedText { text: "話と".to_string(), ruby: None, span: Span::new(19, 21) }));
+    assert!(matches!(&doc.items[4], ParsedItem::Command { span, .. } if *span == Span::new(21, 34)));
+    // An annotation on text that is not there is kept as written
+    assert_eq!(doc.items[5], ParsedItem::Text(DecoratedText { text: "と3".to_string(), ruby: None, span: Span::new(34, 36) }));
+    assert!(matches!(&doc.items[6], ParsedItem::UnknownCommand { content, .. } if content == "「4」は縦中横"));
+    assert!(matches!(doc.items[7], ParsedItem::Newline(_)));
+}
+
+#[test]
+fn test_gaiji() {
+    let text = "T\nA\n\n※［＃「木＋吶のつくり」、第3水準1-85-54］と※［＃「口＋世」、U+546D、190-下-5］\n".to_string();
+    let doc = parse(parse_aozora(text).unwrap());
+
+    let gaiji: Vec<_> = doc.items.iter().filter_map(|item| match item {
+        ParsedItem::SpecialCharacter { kind: SpecialCharacter::Gaiji(g), span } => Some((g, *span)),
+        _ => None,
+    }).collect();
+    assert_eq!(gaiji.len(), 2);
+    assert_eq!(gaiji[0].0.description, "木＋吶のつくり");
+    assert_eq!(gaiji[0].0.jis.as_deref(), Some("1-85-54"));
+    assert_eq!(gaiji[0].0.unicode, None);
+    assert_eq!(gaiji[0].1, Span::new(5, 30));
+    assert_eq!(gaiji[1].0.unicode, Some('\u{546D}'));
+    assert!(doc.diagnostics.is_empty());
+    // ※ is not left in the text
+    assert!(doc.items.iter().all(|item| !matches!(item, ParsedItem::Text(dt) if dt.text.contains('※'))));
+}
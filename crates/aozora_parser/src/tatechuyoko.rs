@@ -0,0 +1,123 @@
+//! Setting short half-width runs for vertical text: numbers of two or three digits and pairs
+//! of `!` and `?` are set horizontally with 縦中横, single characters are made full-width.
+
+use crate::linter::Fix;
+use crate::tokenizer::Span;
+
+/// Longest number set in 縦中横; longer ones are left as they are
+const MAX_DIGITS: usize = 3;
+/// Longest run of `!` and `?` set in 縦中横; longer ones are made full-width
+const MAX_MARKS: usize = 2;
+
+fn full_width(run: &str) -> String {
+    // Full-width ASCII variants are 0xFEE0 code points above
+    run.chars().map(|c| char::from_u32(c as u32 + 0xFEE0).unwrap_or(c)).collect()
+}
+
+/// Edits that set the half-width numbers and exclamation marks of `text` for vertical text,
+/// in characters of `text`; apply them with [`apply_edits`](crate::apply_edits).
+///
+/// Readings and annotations are left alone, as are runs that already have 縦中横 and numbers
+/// that are part of a longer ASCII run (`A4`, `3.14`, `10:30`).
+pub fn tatechuyoko_fixes(text: &str) -> Vec<Fix> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut fixes = Vec::new();
+    // Closing bracket of the reading or annotation the scan is in
+    let mut closing = None;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if let Some(close) = closing {
+            if c == close {
+                closing = None;
+            }
+            i += 1;
+            continue;
+        }
+        let is_digit = c.is_ascii_digit();
+        if !is_digit && !matches!(c, '!' | '?') {
+            closing = match c {
+                '《' => Some('》'),
+                '［' => Some('］'),
+                _ => None,
+            };
+            i += 1;
+            continue;
+        }
+
+        let same = |c: &char| if is_digit { c.is_ascii_digit() } else { matches!(c, '!' | '?') };
+        let start = i;
+        let end = start + chars[start..].iter().take_while(|c| same(c)).count();
+        i = end;
+        let run: String = chars[start..end].iter().collect();
+
+        // Part of a word, a decimal or a time
+        let joined = |c: Option<&char>| {
+            c.is_some_and(|c| c.is_ascii_alphanumeric() || (is_digit && matches!(c, '.' | ',' | ':' | '/' | '-')))
+        };
+        if joined(start.checked_sub(1).and_then(|j| chars.get(j))) || joined(chars.get(end)) {
+            continue;
+        }
+        let annotation = format!("［＃「{}」は縦中横］", run);
+        let annotation: Vec<char> = annotation.chars().collect();
+        if chars[end..].starts_with(&annotation) {
+            continue;
+        }
+
+        let span = Span::new(start, end);
+        let limit = if is_digit { MAX_DIGITS } else { MAX_MARKS };
+        let replacement = match run.chars().count() {
+            1 => full_width(&run),
+            n if n <= limit => format!("{}{}", run, annotation.iter().collect::<String>()),
+            _ if !is_digit => full_width(&run),
+            _ => continue,
+        };
+        fixes.push(Fix::new(span, replacement));
+    }
+    fixes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apply_edits;
+
+    fn convert(text: &str) -> String {
+        apply_edits(text, &tatechuyoko_fixes(text))
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(convert("第1話"), "第１話");
+        assert_eq!(convert("第12話"), "第12［＃「12」は縦中横］話");
+        assert_eq!(convert("100人"), "100［＃「100」は縦中横］人");
+        assert_eq!(convert("2024年"), "2024年");
+    }
+
+    #[test]
+    fn test_marks() {
+        assert_eq!(convert("本当!?　嘘!"), "本当!?［＃「!?」は縦中横］　嘘！");
+        assert_eq!(convert("え!!!"), "え！！！");
+    }
+
+    #[test]
+    fn test_left_alone() {
+        for text in [
+            "A4の紙",
+            "円周率は3.14",
+            "10:30に",
+            "Hello!",
+            "漢字《かん1》",
+            "［＃1字下げ］",
+            "第12［＃「12」は縦中横］話",
+        ] {
+            assert_eq!(convert(text), text);
+        }
+    }
+
+    #[test]
+    fn test_spans_count_characters() {
+        let fixes = tatechuyoko_fixes("青空12");
+        assert_eq!(fixes, vec![Fix::new(Span::new(2, 4), "12［＃「12」は縦中横］")]);
+    }
+}
@@ -0,0 +1,123 @@
+//! Flattens parsed blocks into plain text, for word counts, diffing and text-to-speech.
+//!
+//! Annotations (［＃…］) are dropped; line breaks are kept as they are in the source.
+
+use crate::aozora_writer::needs_ruby_separator;
+use crate::block_parser::AozoraBlock;
+use crate::parser::{ParsedItem, SpecialCharacter};
+use crate::tokenizer::command::{Command, SingleCommand};
+
+/// How ruby is written in plain text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RubyStyle {
+    /// Drop the ruby: 漢字
+    #[default]
+    Strip,
+    /// Put the ruby in parentheses after its base: 漢字（かんじ）
+    Parenthesize,
+    /// Keep Aozora Bunko notation: 漢字《かんじ》, with ｜ where the base needs it
+    Aozora,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TextGeneratorOptions {
+    /// Treatment of ruby
+    pub ruby: RubyStyle,
+}
+
+pub struct TextGenerator;
+
+impl TextGenerator {
+    pub fn generate(block: &AozoraBlock) -> String {
+        Self::generate_with(block, &TextGeneratorOptions::default())
+    }
+
+    pub fn generate_with(block: &AozoraBlock, options: &TextGeneratorOptions) -> String {
+        let mut out = String::new();
+        for item in block.iter_items() {
+            match item {
+                ParsedItem::Text(dt) => match (&dt.ruby, options.ruby) {
+                    (Some(ruby), RubyStyle::Parenthesize) => {
+                        out.push_str(&dt.text);
+                        out.push_str(&format!("（{}）", ruby));
+                    }
+                    (Some(ruby), RubyStyle::Aozora) => {
+                        if needs_ruby_separator(&out, &dt.text) {
+                            out.push('｜');
+                        }
+                        out.push_str(&dt.text);
+                        out.push_str(&format!("《{}》", ruby));
+                    }
+                    _ => out.push_str(&dt.text),
+                },
+                ParsedItem::Newline(_) => out.push('\n'),
+                ParsedItem::SpecialCharacter { kind, .. } => match kind {
+                    SpecialCharacter::Odoriji => out.push_str("／＼"),
+                    SpecialCharacter::DakutenOdoriji => out.push_str("／″＼"),
+                    SpecialCharacter::Gaiji(gaiji) => match (gaiji.unicode, options.ruby) {
+                        (Some(c), _) => out.push(c),
+                        (None, RubyStyle::Aozora) => {
+                            out.push_str(&format!("※［＃{}］", gaiji.annotation))
+                        }
+                        // 〓 (geta) is the usual placeholder for a missing character
+                        (None, _) => out.push('〓'),
+                    },
+                },
+                // The text of 縦中横 is kept in the annotation
+                ParsedItem::Command { cmd: Command::SingleCommand(SingleCommand::Tatechuyoko(s)), .. } => out.push_str(s),
+                ParsedItem::Command { .. } | ParsedItem::UnknownCommand { .. } => {}
+            }
+        }
+        out
+    }
+}
+
+/// Render blocks as plain text with the given ruby treatment.
+/// See [`TextGenerator::generate_with`].
+pub fn to_plain_text(block: &AozoraBlock, ruby: RubyStyle) -> String {
+    TextGenerator::generate_with(block, &TextGeneratorOptions { ruby })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_parser::parse_blocks;
+    use crate::parser::parse;
+    use crate::tokenizer::parse_aozora;
+
+    fn blocks(text: &str) -> AozoraBlock {
+        parse_blocks(parse(parse_aozora(text.to_string()).unwrap()).items).unwrap()
+    }
+
+    #[test]
+    fn test_ruby_styles() {
+        let root = blocks("T\nA\n\n［＃大見出し］第一章［＃大見出し終わり］\n漢｜字《じ》と青空《あおぞら》／＼\n");
+
+        assert_eq!(to_plain_text(&root, RubyStyle::Strip), "\n第一章\n漢字と青空／＼\n");
+        assert_eq!(
+            to_plain_text(&root, RubyStyle::Parenthesize),
+            "\n第一章\n漢字（じ）と青空（あおぞら）／＼\n"
+        );
+        assert_eq!(
+            to_plain_text(&root, RubyStyle::Aozora),
+            "\n第一章\n漢｜字《じ》と青空《あおぞら》／＼\n"
+        );
+    }
+
+    #[test]
+    fn test_gaiji_and_notes() {
+        let root = blocks("T\nA\n\n※［＃「木＋吶のつくり」、第3水準1-85-54］と※［＃「口＋世」、U+546D、190-下-5］［＃「世」に「ママ」の注記］\n");
+
+        assert_eq!(TextGenerator::generate(&root), "\n〓と\u{546D}\n");
+        assert_eq!(
+            to_plain_text(&root, RubyStyle::Aozora),
+            "\n※［＃「木＋吶のつくり」、第3水準1-85-54］と\u{546D}\n"
+        );
+    }
+
+    #[test]
+    fn test_tatechuyoko_text_is_kept() {
+        let root = blocks("T\nA\n\n第12［＃「12」は縦中横］話\n");
+        assert_eq!(TextGenerator::generate(&root), "\n第12話\n");
+    }
+}
@@ -1,14 +1,14 @@
 pub mod command;
 
-fn is_hiragana(c: char) -> bool {
+pub(crate) fn is_hiragana(c: char) -> bool {
     (0x3040 <= (c as u32)) && ((c as u32) <= 0x309F)
 }
 
-fn is_katakana(c: char) -> bool {
+pub(crate) fn is_katakana(c: char) -> bool {
     (0x30A0 <= (c as u32)) && ((c as u32) <= 0x30FF)
 }
 
-fn is_kanji(c: char) -> bool {
+pub(crate) fn is_kanji(c: char) -> bool {
     match c {
         '々' | '〆' | '〇' | 'ヶ' | '仝' => true,
         c if ('\u{4E00}'..='\u{9FFF}').contains(&c) => true,
@@ -33,6 +33,7 @@ fn is_other(c: char) -> bool {
 
 /// 元テキスト内での位置情報（文字単位）
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Span {
     /// 開始位置（0-indexed、文字単位）
     pub start: usize,
@@ -53,9 +54,18 @@ impl Span {
             end: self.end.max(other.end),
         }
     }
+
+    /// Spanを指定した文字数だけ後ろにずらす
+    pub fn shift(&self, offset: usize) -> Span {
+        Span {
+            start: self.start + offset,
+            end: self.end + offset,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TextKind {
     Hiragana,
     Katakana,
@@ -64,6 +74,7 @@ pub enum TextKind {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TextToken {
     pub content: String,
     pub kind: TextKind,
@@ -71,12 +82,14 @@ pub struct TextToken {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CommandToken {
     pub content: String,
     pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AozoraToken {
     Text(TextToken),
 
@@ -96,6 +109,18 @@ pub enum TokenizeError {
     UnclosedCommand(Span),
 }
 
+impl std::fmt::Display for TokenizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenizeError::UnclosedCommand(span) => {
+                write!(f, "bracket at character {} is never closed", span.start)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TokenizeError {}
+
 pub fn parse_aozora(text: String) -> Result<Vec<AozoraToken>, TokenizeError> {
     let mut tokens = Vec::new();
     let chars: Vec<char> = text.chars().collect();
@@ -171,6 +196,8 @@ pub fn parse_aozora(text: String) -> Result<Vec<AozoraToken>, TokenizeError> {
                     let c = chars.get(pos);
                     match c {
                         Some(&'］') => {
+                            // '］'を消費
+                            pos += 1;
                             tokens.push(AozoraToken::Command(CommandToken {
                                 content: buffer,
                                 span: Span::new(start, pos),
@@ -2,9 +2,8 @@ use regex::Regex;
 
 use crate::tokenizer::CommandToken;
 
-const ZERO_TO_NINE: [char; 10] = ['０', '１', '２', '３', '４', '５', '６', '７', '８', '９'];
-
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MidashiSize {
     Large,
     Middle,
@@ -12,6 +11,7 @@ pub enum MidashiSize {
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MidashiType {
     Normal,
     Dogyo,
@@ -22,6 +22,7 @@ pub enum MidashiType {
 ///
 /// https://www.aozora.gr.jp/annotation/emphasis.html#boten_chuki
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Bouten {
     Sirogoma,
     BlackCircle,
@@ -37,6 +38,7 @@ pub enum Bouten {
 ///
 /// https://www.aozora.gr.jp/annotation/emphasis.html#bosen_chuki
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Bousen {
     Bousen,
     Double,
@@ -51,6 +53,7 @@ pub enum Bousen {
 ///
 /// https://www.aozora.gr.jp/annotation/heading.html#dogyo_midashi
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Midashi {
     pub size: MidashiSize,
     pub kind: MidashiType,
@@ -76,12 +79,14 @@ pub struct Midashi {
 ///
 /// https://www.aozora.gr.jp/annotation/layout_2.html#chitsuki
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Alignment {
     pub is_upper: bool,
     pub space: usize,
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CommandBegin {
     // Other
     Midashi(Midashi),
@@ -106,9 +111,20 @@ pub enum CommandBegin {
     ///
     /// https://www.aozora.gr.jp/annotation/etc.html#jizume
     Jitsume(usize),
+    /// ページの左右中央を表します．対応する終了注記はなく，
+    /// 次の改ページ（改丁，改見開きを含む）まで有効です．詳細は以下のURLを参照してください．
+    ///
+    /// https://www.aozora.gr.jp/annotation/layout_1.html#chuo
+    PageCenter,
+    /// 段組みを表します．値は段数です．ブロック内の改段は段の区切りとなります．
+    /// 詳細は以下のURLを参照してください．
+    ///
+    /// https://www.aozora.gr.jp/annotation/etc.html#dangumi
+    Dangumi(usize),
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CommandEnd {
     // Other
     Midashi(Midashi),
@@ -124,9 +140,33 @@ pub enum CommandEnd {
     Kakomikei,
     Yokogumi,
     Jitsume,
+    PageCenter,
+    Dangumi,
+}
+
+impl CommandBegin {
+    /// 終了注記がこの開始注記に対応するかを返します．
+    /// 見出しは大きさと種類まで一致する必要があります．
+    pub fn is_closed_by(&self, end: &CommandEnd) -> bool {
+        match (self, end) {
+            (CommandBegin::Midashi(begin), CommandEnd::Midashi(end)) => begin == end,
+            (CommandBegin::Alignment(_), CommandEnd::Alignment)
+            | (CommandBegin::Bouten(_), CommandEnd::Bouten)
+            | (CommandBegin::Bousen(_), CommandEnd::Bousen)
+            | (CommandBegin::Bold, CommandEnd::Bold)
+            | (CommandBegin::Italic, CommandEnd::Italic)
+            | (CommandBegin::Kakomikei, CommandEnd::Kakomikei)
+            | (CommandBegin::Yokogumi, CommandEnd::Yokogumi)
+            | (CommandBegin::Jitsume(_), CommandEnd::Jitsume)
+            | (CommandBegin::PageCenter, CommandEnd::PageCenter)
+            | (CommandBegin::Dangumi(_), CommandEnd::Dangumi) => true,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SingleCommand {
     // Other
     Midashi((Midashi, String)),
@@ -143,9 +183,46 @@ pub enum SingleCommand {
     Bousen((Bousen, String)),
     Bold(String),
     Italic(String),
+
+    // Layout
+    /// 縦中横を表します．縦書きの中で横に並べて一文字分に収める文字列で，
+    /// 対象の文字列は直前の本文から取り除かれています．詳細は以下のURLを参照してください．
+    ///
+    /// https://www.aozora.gr.jp/annotation/etc.html#tatechu_yoko
+    Tatechuyoko(String),
+
+    // Note
+    /// ママ注記を表します．底本の表記が誤りのように見えても
+    /// そのまま入力したことを示します．詳細は以下のURLを参照してください．
+    ///
+    /// https://www.aozora.gr.jp/annotation/etc.html
+    Mama(String),
+    /// 底本の表記を修正した箇所の注記を表します．
+    /// visibleが本文中の表記，originalが底本での表記です．
+    ///
+    /// https://www.aozora.gr.jp/annotation/etc.html
+    SourceNote { visible: String, original: String },
+    /// 注記を表します．targetが注記の対象となる本文，noteが注記の内容です．
+    /// XHTMLでは脚注（または後注）として出力されます．
+    ///
+    /// https://www.aozora.gr.jp/annotation/etc.html
+    Note { target: String, note: String },
+
+    // Image
+    /// 挿絵などの画像を表します．srcは画像ファイルのパス（テキストファイルからの相対パス），
+    /// descriptionは「挿絵」などの説明，captionはキャプション，sizeは横×縦のピクセル数です．
+    ///
+    /// https://www.aozora.gr.jp/annotation/graphics.html
+    Image {
+        description: String,
+        src: String,
+        caption: Option<String>,
+        size: Option<(u32, u32)>,
+    },
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Command {
     CommandBegin(CommandBegin),
     SingleCommand(SingleCommand),
@@ -179,6 +256,21 @@ pub fn parse_command(commands: CommandToken) -> Option<Command> {
     let re_jisage = Regex::new(r"^(?P<num>[１２３４５６７８９０]+)字下げ$").unwrap();
     // Regex for block jisage begin (e.g. ここから１０字下げ)
     let re_jisage_begin = Regex::new(r"^ここから(?P<num>[１２３４５６７８９０]+)字下げ$").unwrap();
+    // Regex for multi-column begin (e.g. ここから２段組み)
+    let re_dangumi_begin = Regex::new(r"^ここから(?P<num>[１２３４５６７８９０]+)段組み(?:、段間に罫)?$").unwrap();
+    // Regex for sic notes (e.g. 「...」に「ママ」の注記, 「...」はママ)
+    let re_mama = Regex::new(r"^「(?P<content>.+?)」(?:に「ママ」の注記|はママ)$").unwrap();
+    // Regex for source notes (e.g. 「...」は底本では「...」)
+    let re_source_note = Regex::new(r"^「(?P<visible>.+?)」は底本では「(?P<original>.+?)」$").unwrap();
+    // Regex for notes (e.g. 「...」に「...」の注記); ママ is matched first
+    let re_note = Regex::new(r"^「(?P<target>.+?)」に「(?P<note>.+?)」の注記$").unwrap();
+    // Regex for tate-chu-yoko (e.g. 「12」は縦中横)
+    let re_tatechuyoko = Regex::new(r"^「(?P<content>.+?)」は縦中横$").unwrap();
+    // Regex for images (e.g. 挿絵（fig1_01.png、横320×縦240）入る, 「…」のキャプション付きの図（…）入る)
+    let re_image = Regex::new(
+        r"^(?:「(?P<caption>.+?)」のキャプション付きの)?(?P<description>[^（]*)（(?P<src>[^、）]+)(?:、横(?P<width>[0-9０-９]+)×縦(?P<height>[0-9０-９]+))?）入る$",
+    )
+    .unwrap();
 
     if let Some(caps) = re_ref.captures(s) {
         let content = caps.name("content").unwrap().as_str().to_string();
@@ -247,6 +339,36 @@ pub fn parse_command(commands: CommandToken) -> Option<Command> {
                 space: n as usize,
             })));
         }
+    } else if let Some(caps) = re_dangumi_begin.captures(s) {
+        let num_str = caps.name("num").unwrap().as_str();
+        if let Some(n) = full_width_digit_to_u32(num_str) {
+            return Some(Command::CommandBegin(CommandBegin::Dangumi(n as usize)));
+        }
+    } else if let Some(caps) = re_mama.captures(s) {
+        let content = caps.name("content").unwrap().as_str().to_string();
+        return Some(Command::SingleCommand(SingleCommand::Mama(content)));
+    } else if let Some(caps) = re_source_note.captures(s) {
+        let visible = caps.name("visible").unwrap().as_str().to_string();
+        let original = caps.name("original").unwrap().as_str().to_string();
+        return Some(Command::SingleCommand(SingleCommand::SourceNote { visible, original }));
+    } else if let Some(caps) = re_note.captures(s) {
+        let target = caps.name("target").unwrap().as_str().to_string();
+        let note = caps.name("note").unwrap().as_str().to_string();
+        return Some(Command::SingleCommand(SingleCommand::Note { target, note }));
+    } else if let Some(caps) = re_tatechuyoko.captures(s) {
+        let content = caps.name("content").unwrap().as_str().to_string();
+        return Some(Command::SingleCommand(SingleCommand::Tatechuyoko(content)));
+    } else if let Some(caps) = re_image.captures(s) {
+        let size = match (caps.name("width"), caps.name("height")) {
+            (Some(w), Some(h)) => full_width_digit_to_u32(w.as_str()).zip(full_width_digit_to_u32(h.as_str())),
+            _ => None,
+        };
+        return Some(Command::SingleCommand(SingleCommand::Image {
+            description: caps.name("description").unwrap().as_str().to_string(),
+            src: caps.name("src").unwrap().as_str().to_string(),
+            caption: caps.name("caption").map(|c| c.as_str().to_string()),
+            size,
+        }));
     }
 
     match s {
@@ -254,6 +376,8 @@ pub fn parse_command(commands: CommandToken) -> Option<Command> {
         "改ページ" => Some(Command::SingleCommand(SingleCommand::Kaipage)),
         "改見開き" => Some(Command::SingleCommand(SingleCommand::Kaimihiraki)),
         "改段" => Some(Command::SingleCommand(SingleCommand::Kaidan)),
+        "ページの左右中央" => Some(Command::CommandBegin(CommandBegin::PageCenter)),
+        "ここで段組み終わり" => Some(Command::CommandEnd(CommandEnd::Dangumi)),
         "ここで字下げ終わり" => Some(Command::CommandEnd(CommandEnd::Alignment)),
         _ => None,
     }
@@ -380,6 +504,125 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_mama() {
+        let token = CommandToken {
+            content: "「獅子奮塵」に「ママ」の注記".to_string(),
+            span: Span::default(),
+        };
+        let cmd = parse_command(token).unwrap();
+        assert_eq!(
+            cmd,
+            Command::SingleCommand(SingleCommand::Mama("獅子奮塵".to_string()))
+        );
+
+        let token = CommandToken {
+            content: "「御待兼」はママ".to_string(),
+            span: Span::default(),
+        };
+        let cmd = parse_command(token).unwrap();
+        assert_eq!(
+            cmd,
+            Command::SingleCommand(SingleCommand::Mama("御待兼".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_source_note() {
+        let token = CommandToken {
+            content: "「戦かい」は底本では「戦い」".to_string(),
+            span: Span::default(),
+        };
+        let cmd = parse_command(token).unwrap();
+        assert_eq!(
+            cmd,
+            Command::SingleCommand(SingleCommand::SourceNote {
+                visible: "戦かい".to_string(),
+                original: "戦い".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_note() {
+        let token = CommandToken {
+            content: "「蒼穹」に「あおぞら。空のこと」の注記".to_string(),
+            span: Span::default(),
+        };
+        let cmd = parse_command(token).unwrap();
+        assert_eq!(
+            cmd,
+            Command::SingleCommand(SingleCommand::Note {
+                target: "蒼穹".to_string(),
+                note: "あおぞら。空のこと".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_tatechuyoko() {
+        let token = CommandToken {
+            content: "「12」は縦中横".to_string(),
+            span: Span::default(),
+        };
+        assert_eq!(
+            parse_command(token).unwrap(),
+            Command::SingleCommand(SingleCommand::Tatechuyoko("12".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_image() {
+        let token = CommandToken {
+            content: "挿絵（fig1_01.png、横320×縦240）入る".to_string(),
+            span: Span::default(),
+        };
+        assert_eq!(
+            parse_command(token).unwrap(),
+            Command::SingleCommand(SingleCommand::Image {
+                description: "挿絵".to_string(),
+                src: "fig1_01.png".to_string(),
+                caption: None,
+                size: Some((320, 240)),
+            })
+        );
+
+        let token = CommandToken {
+            content: "「第一図」のキャプション付きの図（img/fig2.jpg）入る".to_string(),
+            span: Span::default(),
+        };
+        assert_eq!(
+            parse_command(token).unwrap(),
+            Command::SingleCommand(SingleCommand::Image {
+                description: "図".to_string(),
+                src: "img/fig2.jpg".to_string(),
+                caption: Some("第一図".to_string()),
+                size: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_dangumi() {
+        let token = CommandToken {
+            content: "ここから２段組み".to_string(),
+            span: Span::default(),
+        };
+        assert_eq!(
+            parse_command(token).unwrap(),
+            Command::CommandBegin(CommandBegin::Dangumi(2))
+        );
+
+        let token = CommandToken {
+            content: "ここで段組み終わり".to_string(),
+            span: Span::default(),
+        };
+        assert_eq!(
+            parse_command(token).unwrap(),
+            Command::CommandEnd(CommandEnd::Dangumi)
+        );
+    }
+
     #[test]
     fn test_jisage() {
         let token = CommandToken {
@@ -1,103 +1,501 @@
 use crate::block_parser::{AozoraBlock, BlockElement};
-use crate::parser::{DecoratedText, ParsedItem, SpecialCharacter};
+use crate::parser::{DecoratedText, Gaiji, ParsedItem, SpecialCharacter};
+use crate::tokenizer::Span;
 use crate::tokenizer::command::{
-    Command, CommandBegin, Midashi, MidashiSize, MidashiType, SingleCommand,
+    Command, CommandBegin, MidashiSize, MidashiType, SingleCommand,
 };
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TocEntry {
     pub level: u32,
     pub text: String,
     pub id: String,
+    /// True for 窓見出し, which are rendered inset into the text rather than as a standalone heading
+    pub is_window: bool,
+    /// Location of the heading in the original text, in characters
+    pub span: Span,
+}
+
+/// A heading together with the headings nested under it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TocNode {
+    pub entry: TocEntry,
+    pub children: Vec<TocNode>,
+}
+
+/// Table of contents nested by heading level.
+///
+/// Each entry contains the following entries of a deeper level, so 中見出し sit under
+/// the preceding 大見出し. A skipped level (大見出し followed directly by 小見出し)
+/// nests one step.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TocTree {
+    pub roots: Vec<TocNode>,
+}
+
+impl TocTree {
+    /// Nest a flat list of entries, as returned by `XhtmlGenerator::generate`.
+    pub fn from_entries(entries: &[TocEntry]) -> Self {
+        fn build(entries: &[TocEntry], pos: &mut usize, parent_level: u32) -> Vec<TocNode> {
+            let mut nodes = Vec::new();
+            while let Some(entry) = entries.get(*pos).filter(|e| e.level > parent_level) {
+                *pos += 1;
+                let children = build(entries, pos, entry.level);
+                nodes.push(TocNode { entry: entry.clone(), children });
+            }
+            nodes
+        }
+        TocTree { roots: build(entries, &mut 0, 0) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    /// Render as nested `<ol>` lists linking to `{href}#{id}`.
+    /// Returns an empty string for an empty tree.
+    pub fn to_ol(&self, href: &str) -> String {
+        fn render(nodes: &[TocNode], href: &str, out: &mut String) {
+            if nodes.is_empty() {
+                return;
+            }
+            out.push_str("<ol>");
+            for node in nodes {
+                write!(
+                    out,
+                    "<li><a href=\"{}#{}\">{}</a>",
+                    escape_html(href),
+                    escape_html(&node.entry.id),
+                    escape_html(&node.entry.text)
+                )
+                .unwrap();
+                render(&node.children, href, out);
+                out.push_str("</li>");
+            }
+            out.push_str("</ol>");
+        }
+        let mut out = String::new();
+        render(&self.roots, href, &mut out);
+        out
+    }
+}
+
+/// TOC level of a heading (大見出し -> 2, 中見出し -> 3, 小見出し -> 4)
+fn heading_level(size: MidashiSize) -> u32 {
+    match size {
+        MidashiSize::Large => 2,
+        MidashiSize::Middle => 3,
+        MidashiSize::Small => 4,
+    }
+}
+
+/// How 底本 correction notes (［＃「x」は底本では「y」］) are rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SourceNoteStyle {
+    /// Drop the note from the output
+    #[default]
+    Hidden,
+    /// Emit a numbered reference and collect the notes at the end of the body
+    Footnote,
+    /// Emit the note in place, right after the corrected text
+    Inline,
+}
+
+/// Where notes (［＃「x」に「y」の注記］, and 底本 notes with [`SourceNoteStyle::Footnote`])
+/// are collected. Either way the text gets a numbered link to the note, and each note
+/// links back to it.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum NotePlacement {
+    /// At the end of the body, in the same document
+    #[default]
+    Footnote,
+    /// In a separate document returned by [`XhtmlGenerator::generate_with_notes`].
+    /// `notes_href` is the notes document as seen from the body, `body_href` the body
+    /// as seen from the notes document.
+    Endnote { notes_href: String, body_href: String },
+}
+
+/// How gaiji (※［＃「…」、…］) are rendered. Reader support for rare characters varies,
+/// so a book can fall back to glyph images or to the original annotation.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum GaijiStyle {
+    /// Emit the character itself, or the annotation when it has no code point
+    #[default]
+    Unicode,
+    /// Emit `<img src="{href_prefix}{code}.png">`, where code is the JIS code (`1-85-54`)
+    /// or the code point (`u546d`)
+    Image { href_prefix: String },
+    /// Emit the ※［＃…］ annotation as written in the source
+    Annotation,
+}
+
+/// Text direction of the generated document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WritingMode {
+    /// 縦書き (vertical-rl)
+    #[default]
+    VerticalRl,
+    /// 横書き (horizontal-tb)
+    HorizontalTb,
+}
+
+impl WritingMode {
+    /// Class put on the root element, as used by the bundled stylesheets
+    pub fn class_name(&self) -> &'static str {
+        match self {
+            WritingMode::VerticalRl => "vrtl",
+            WritingMode::HorizontalTb => "hltr",
+        }
+    }
+
+    /// Value of the CSS `writing-mode` property
+    pub fn css_value(&self) -> &'static str {
+        match self {
+            WritingMode::VerticalRl => "vertical-rl",
+            WritingMode::HorizontalTb => "horizontal-tb",
+        }
+    }
+
+    /// `page-progression-direction` of the EPUB spine
+    pub fn page_progression_direction(&self) -> &'static str {
+        match self {
+            WritingMode::VerticalRl => "rtl",
+            WritingMode::HorizontalTb => "ltr",
+        }
+    }
+}
+
+/// How heading ids are generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeadingIdScheme {
+    /// Derived from the heading text (`midashi-第一章`), so ids survive edits elsewhere.
+    /// Duplicates get a numeric suffix (`midashi-第一章-2`).
+    #[default]
+    Text,
+    /// Numbered in render order (`midashi-1`, `midashi-2`, ...)
+    Numbered,
 }
 
-pub struct XhtmlGenerator {
-    css: String,
+/// Options controlling XHTML generation.
+#[derive(Debug, Clone)]
+pub struct XhtmlGeneratorOptions {
+    /// Rendering of 底本 correction notes
+    pub source_notes: SourceNoteStyle,
+    /// Text direction
+    pub writing_mode: WritingMode,
+    /// href of the linked stylesheet
+    pub stylesheet: String,
+    /// Emit `<p><br/></p>` for blank lines
+    pub empty_paragraphs: bool,
+    /// Prefix added to every generated class name (e.g. `aozora-` gives `aozora-jisage-2`)
+    pub class_prefix: String,
+    /// Prefix of heading ids
+    pub heading_id_prefix: String,
+    /// How heading ids are generated
+    pub heading_ids: HeadingIdScheme,
+    /// Class names replacing the generated ones, so the output can use an existing stylesheet.
+    /// A key ending in `*` matches every class starting with the rest of the key, and `*`
+    /// in the value is replaced with the matched remainder (`"jisage-*"` → `"indent-*"`
+    /// turns `jisage-2` into `indent-2`). Mapped names are not prefixed with `class_prefix`.
+    pub class_map: HashMap<String, String>,
+    /// Rendering of gaiji
+    pub gaiji: GaijiStyle,
+    /// Where notes are collected
+    pub notes: NotePlacement,
+    /// Marker of a note in the text and in the notes; `{n}` is replaced with the note number
+    pub note_marker: String,
+    /// Language of the text, emitted as `lang` / `xml:lang` (and `dc:language` in EPUB)
+    pub lang: String,
+    /// XHTML shell of the generated documents, replacing the built-in one. The slots
+    /// `{title}`, `{lang}`, `{stylesheet}`, `{root_class}`, `{main_class}` and `{body}` are filled in;
+    /// other text (custom head, meta tags, scripts) is kept as written.
+    pub template: Option<String>,
+    /// Prefix added to the src of images (挿絵), e.g. `../image/` when the images are
+    /// copied next to the documents
+    pub image_href_prefix: String,
+    /// Number of the page the document starts on. When set, page breaks (改ページ, 改丁,
+    /// 改見開き) get the id `page-{n}` and a label for the page they start, so that a
+    /// page-list can link to them.
+    pub first_page: Option<usize>,
+    /// Mark paragraphs and headings with `data-span="{start}-{end}"`, their location in the
+    /// source text in characters, so that an editor can find the element showing a position
+    /// of the text and the other way round.
+    pub source_spans: bool,
+}
+
+impl Default for XhtmlGeneratorOptions {
+    fn default() -> Self {
+        Self {
+            source_notes: SourceNoteStyle::default(),
+            writing_mode: WritingMode::default(),
+            stylesheet: "../style/book-style.css".to_string(),
+            empty_paragraphs: true,
+            class_prefix: String::new(),
+            heading_id_prefix: "midashi-".to_string(),
+            heading_ids: HeadingIdScheme::default(),
+            class_map: HashMap::new(),
+            gaiji: GaijiStyle::default(),
+            notes: NotePlacement::default(),
+            note_marker: "※{n}".to_string(),
+            lang: "ja".to_string(),
+            template: None,
+            image_href_prefix: String::new(),
+            first_page: None,
+            source_spans: false,
+        }
+    }
+}
+
+/// Once the buffered output grows past this size, a streaming generator hands it to its writer.
+const FLUSH_THRESHOLD: usize = 16 * 1024;
+
+pub struct XhtmlGenerator<'w> {
     body: String,
     toc_entries: Vec<TocEntry>,
     next_id: usize,
+    used_ids: HashSet<String>,
+    options: XhtmlGeneratorOptions,
+    footnotes: Vec<String>,
+    /// Number of the current page, when page breaks are numbered
+    page: usize,
+    /// Emit HTML5 (`<br>`, no `epub:type`) instead of XHTML
+    html: bool,
+    /// Receives the output as it is generated when streaming
+    sink: Option<&'w mut dyn FnMut(&str) -> std::fmt::Result>,
+    /// Set when the sink returned an error; later output is discarded
+    sink_failed: bool,
+}
+
+impl Default for XhtmlGenerator<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl XhtmlGenerator {
+impl<'w> XhtmlGenerator<'w> {
     pub fn new() -> Self {
+        Self::with_options(XhtmlGeneratorOptions::default())
+    }
+
+    pub fn with_options(options: XhtmlGeneratorOptions) -> Self {
         XhtmlGenerator {
-            css: String::new(),
             body: String::new(),
             toc_entries: Vec::new(),
             next_id: 1,
+            used_ids: HashSet::new(),
+            page: options.first_page.unwrap_or(1),
+            options,
+            footnotes: Vec::new(),
+            html: false,
+            sink: None,
+            sink_failed: false,
         }
     }
 
     pub fn generate(block: &AozoraBlock, title: &str) -> (String, Vec<TocEntry>) {
-        let mut generator = XhtmlGenerator::new();
+        Self::generate_with(block, title, &XhtmlGeneratorOptions::default())
+    }
+
+    pub fn generate_with(
+        block: &AozoraBlock,
+        title: &str,
+        options: &XhtmlGeneratorOptions,
+    ) -> (String, Vec<TocEntry>) {
+        let (xhtml, toc, _) = Self::generate_with_notes(block, title, options);
+        (xhtml, toc)
+    }
+
+    /// Like [`generate_with`](Self::generate_with), also returning the notes document
+    /// when the notes are placed in one ([`NotePlacement::Endnote`]) and there are any.
+    pub fn generate_with_notes(
+        block: &AozoraBlock,
+        title: &str,
+        options: &XhtmlGeneratorOptions,
+    ) -> (String, Vec<TocEntry>, Option<String>) {
+        let mut generator = XhtmlGenerator::with_options(options.clone());
         generator.render_block(block);
+        let notes = generator.finish_notes(title);
+        let body = std::mem::take(&mut generator.body);
+        (generator.document(title, &body), generator.toc_entries, notes)
+    }
+
+    /// Like [`generate_with_notes`](Self::generate_with_notes), but writes the document
+    /// into `out` while it is generated instead of building it in memory, so large books
+    /// are generated with bounded memory.
+    pub fn write_with<W: std::fmt::Write>(
+        block: &AozoraBlock,
+        title: &str,
+        options: &XhtmlGeneratorOptions,
+        out: &mut W,
+    ) -> Result<(Vec<TocEntry>, Option<String>), std::fmt::Error> {
+        let mut sink = |s: &str| out.write_str(s);
+        let mut generator = XhtmlGenerator::with_options(options.clone());
+        let (head, tail) = generator.document_parts(title);
+        generator.body = head;
+        generator.sink = Some(&mut sink);
+
+        generator.render_block(block);
+        let notes = generator.finish_notes(title);
+        generator.body.push_str(&tail);
+        generator.flush_output();
+
+        if generator.sink_failed {
+            return Err(std::fmt::Error);
+        }
+        Ok((std::mem::take(&mut generator.toc_entries), notes))
+    }
+
+    /// [`write_with`](Self::write_with) for an [`std::io::Write`], e.g. a file or an
+    /// entry of a ZIP archive.
+    pub fn write_io_with<W: std::io::Write>(
+        block: &AozoraBlock,
+        title: &str,
+        options: &XhtmlGeneratorOptions,
+        out: &mut W,
+    ) -> std::io::Result<(Vec<TocEntry>, Option<String>)> {
+        struct Adapter<'a, W> {
+            inner: &'a mut W,
+            error: Option<std::io::Error>,
+        }
+        impl<W: std::io::Write> std::fmt::Write for Adapter<'_, W> {
+            fn write_str(&mut self, s: &str) -> std::fmt::Result {
+                self.inner.write_all(s.as_bytes()).map_err(|e| {
+                    self.error = Some(e);
+                    std::fmt::Error
+                })
+            }
+        }
+
+        let mut adapter = Adapter { inner: out, error: None };
+        match Self::write_with(block, title, options, &mut adapter) {
+            Ok(result) => Ok(result),
+            Err(_) => Err(adapter.error.unwrap_or_else(|| std::io::Error::other("formatter error"))),
+        }
+    }
+
+    /// Place the collected notes: append them to the body, or return the notes document.
+    fn finish_notes(&mut self, title: &str) -> Option<String> {
+        match &self.options.notes {
+            NotePlacement::Footnote => {
+                self.render_footnotes();
+                None
+            }
+            NotePlacement::Endnote { body_href, .. } if !self.footnotes.is_empty() => {
+                let class = self.class("endnotes");
+                let notes = self.render_notes(body_href, "endnote");
+                Some(self.document(
+                    title,
+                    &format!("<section class=\"{}\" epub:type=\"endnotes\">{}</section>", class, notes),
+                ))
+            }
+            NotePlacement::Endnote { .. } => None,
+        }
+    }
+
+    /// Hand the buffered output to the sink, if streaming.
+    fn flush_output(&mut self) {
+        if let Some(sink) = self.sink.as_mut() {
+            if !self.sink_failed && sink(&self.body).is_err() {
+                self.sink_failed = true;
+            }
+            self.body.clear();
+        }
+    }
 
-        (
-            format!(
-                r#"<?xml version="1.0" encoding="UTF-8"?>
-<!DOCTYPE html>
-<html
- xmlns="http://www.w3.org/1999/xhtml"
- xmlns:epub="http://www.idpf.org/2007/ops"
- xml:lang="ja"
- class="vrtl"
->
-<head>
-<meta charset="UTF-8"/>
-<title>{}</title>
-<link rel="stylesheet" type="text/css" href="../style/book-style.css"/>
-
-</head>
-<body>
-<div class="main">
-{}
-</div>
-</body>
-</html>"#,
-                title, generator.body
-            ),
-            generator.toc_entries,
+    /// Render the body as an HTML5 fragment, without doctype or head, for embedding
+    /// into web pages. Notes are always placed at the end of the fragment.
+    pub fn generate_fragment(block: &AozoraBlock, options: &XhtmlGeneratorOptions) -> String {
+        let mut generator = XhtmlGenerator::with_options(options.clone());
+        generator.html = true;
+        generator.render_block(block);
+        generator.render_footnotes();
+        let classes = format!(
+            "{} {}",
+            generator.class("main"),
+            generator.class(options.writing_mode.class_name())
+        );
+        format!(
+            "<div class=\"{}\" lang=\"{}\">{}</div>",
+            classes,
+            escape_html(&options.lang),
+            generator.body
         )
     }
 
+    /// Closing of a void element (`/>` in XHTML, `>` in HTML).
+    fn void_end(&self) -> &'static str {
+        if self.html { ">" } else { "/>" }
+    }
+
+    /// ` epub:type="..."` attribute; omitted in HTML.
+    fn epub_type(&self, epub_type: &str) -> String {
+        if self.html {
+            String::new()
+        } else {
+            format!(" epub:type=\"{}\"", epub_type)
+        }
+    }
+
+    /// Wrap body content into a complete XHTML document.
+    fn document(&self, title: &str, body: &str) -> String {
+        let (head, tail) = self.document_parts(title);
+        format!("{}{}{}", head, body, tail)
+    }
+
+    /// The filled-in template before and after the `{body}` slot.
+    fn document_parts(&self, title: &str) -> (String, String) {
+        let template = self.options.template.as_deref().unwrap_or(DEFAULT_TEMPLATE);
+        let (head, tail) = template.split_once("{body}").unwrap_or((template, ""));
+        let title = escape_html(title);
+        let stylesheet = escape_html(&self.options.stylesheet);
+        let root_class = self.class(self.options.writing_mode.class_name());
+        let main_class = self.class("main");
+        let lang = escape_html(&self.options.lang);
+        let slots = [
+            ("title", &*title),
+            ("lang", &*lang),
+            ("stylesheet", &*stylesheet),
+            ("root_class", root_class.as_str()),
+            ("main_class", main_class.as_str()),
+        ];
+        (fill_template(head, &slots), fill_template(tail, &slots))
+    }
+
     fn render_block(&mut self, block: &AozoraBlock) {
         let (tag, classes, close_tag, is_heading) = self.resolve_decoration(&block.decoration);
 
         // Generate ID if heading
         let id_attr = if is_heading {
-            let id = format!("midashi-{}", self.next_id);
-            self.next_id += 1;
-
             // Extract text for TOC
             let toc_text = self.extract_text_from_block(block);
+            let id = self.next_heading_id(&toc_text);
 
-            // Deducing level from tag (h2 -> 2, etc)
-            let level = if tag == "h2" {
-                2
-            } else if tag == "h3" {
-                3
-            } else if tag == "h4" {
-                4
-            } else {
-                2
+            let (level, is_window) = match &block.decoration {
+                Some(CommandBegin::Midashi(m)) => {
+                    (heading_level(m.size), m.kind == MidashiType::Mado)
+                }
+                _ => (2, false),
             };
 
             self.toc_entries.push(TocEntry {
                 level,
                 text: toc_text,
                 id: id.clone(),
+                is_window,
+                span: block.span,
             });
-            format!(" id=\"{}\"", id)
+            format!(" id=\"{}\"{}", id, self.span_attr(block.span))
         } else {
             String::new()
         };
 
         if !tag.is_empty() {
             write!(self.body, "<{}{}", tag, id_attr).unwrap();
-            if !classes.is_empty() {
-                write!(self.body, " class=\"{}\"", classes.join(" ")).unwrap();
-            }
+            self.write_class_attr(&classes);
             write!(self.body, ">").unwrap();
         }
 
@@ -110,8 +508,9 @@ impl XhtmlGenerator {
                         ParsedItem::Newline(_) => {
                             if inline_buffer.is_empty() {
                                 // Only output empty p if NOT in heading
-                                if !is_heading {
-                                    write!(self.body, "<p><br/></p>").unwrap();
+                                if !is_heading && self.options.empty_paragraphs {
+                                    let span_attr = self.span_attr(item.span());
+                                    write!(self.body, "<p{}><br{}</p>", span_attr, self.void_end()).unwrap();
                                 }
                             } else {
                                 self.flush_paragraph(&inline_buffer, is_heading);
@@ -120,12 +519,19 @@ impl XhtmlGenerator {
                         }
                         ParsedItem::Command { cmd: Command::CommandBegin(_), .. }
                         | ParsedItem::Command { cmd: Command::CommandEnd(_), .. }
-                        | ParsedItem::Command { cmd: Command::SingleCommand(SingleCommand::Midashi(_)), .. } => {
+                        | ParsedItem::Command { cmd: Command::SingleCommand(
+                            SingleCommand::Midashi(_)
+                            | SingleCommand::Kaicho
+                            | SingleCommand::Kaimihiraki
+                            | SingleCommand::Kaipage
+                            | SingleCommand::Kaidan
+                        ), .. } => {
                             // Flush existing buffer
                             self.flush_paragraph(&inline_buffer, is_heading);
                             inline_buffer.clear();
 
                             // If it is SingleCommand, we must render it now (as block)
+                            // Breaks are <div>s too, so they must not end up inside a <p>
                             if let ParsedItem::Command { cmd: Command::SingleCommand(_sc), .. } = item {
                                 // Temporarily treat as item
                                 // But render_item doesn't wrap in p unless caller does.
@@ -144,6 +550,9 @@ impl XhtmlGenerator {
                     self.render_block(sub_block);
                 }
             }
+            if self.body.len() >= FLUSH_THRESHOLD {
+                self.flush_output();
+            }
         }
         self.flush_paragraph(&inline_buffer, is_heading);
 
@@ -152,6 +561,69 @@ impl XhtmlGenerator {
         }
     }
 
+    fn next_heading_id(&mut self, text: &str) -> String {
+        let number = self.next_id;
+        self.next_id += 1;
+
+        let base = match self.options.heading_ids {
+            HeadingIdScheme::Text => {
+                let slug = slugify(text);
+                // Headings without usable characters fall back to their number
+                if slug.is_empty() { number.to_string() } else { slug }
+            }
+            HeadingIdScheme::Numbered => number.to_string(),
+        };
+        let base = format!("{}{}", self.options.heading_id_prefix, base);
+
+        let mut id = base.clone();
+        let mut n = 2;
+        while !self.used_ids.insert(id.clone()) {
+            id = format!("{}-{}", base, n);
+            n += 1;
+        }
+        id
+    }
+
+    /// Class name after `class_map`, or with the configured prefix if it is not mapped
+    fn class(&self, name: &str) -> String {
+        if let Some(mapped) = self.options.class_map.get(name) {
+            return mapped.clone();
+        }
+        // The longest matching wildcard wins
+        let wildcard = self
+            .options
+            .class_map
+            .iter()
+            .filter_map(|(key, value)| {
+                let rest = name.strip_prefix(key.strip_suffix('*')?)?;
+                Some((key.len(), value.replace('*', rest)))
+            })
+            .max_by_key(|(len, _)| *len);
+        match wildcard {
+            Some((_, mapped)) => mapped,
+            None => format!("{}{}", self.options.class_prefix, name),
+        }
+    }
+
+    fn write_span(&mut self, class: &str, content: &str) {
+        let class = self.class(class);
+        write!(self.body, "<span class=\"{}\">{}</span>", class, escape_html(content)).unwrap();
+    }
+
+    fn write_break(&mut self, class: &str, role: Option<&str>) {
+        let class = self.class(class);
+        write!(self.body, "<div class=\"{}\"", class).unwrap();
+        if let Some(role) = role {
+            write!(self.body, " role=\"{}\"", role).unwrap();
+            if self.options.first_page.is_some() {
+                self.page += 1;
+                let epub_type = self.epub_type("pagebreak");
+                write!(self.body, " id=\"page-{0}\" aria-label=\"{0}\"{1}", self.page, epub_type).unwrap();
+            }
+        }
+        write!(self.body, "></div>").unwrap();
+    }
+
     fn flush_paragraph(&mut self, buffer: &[&ParsedItem], is_heading: bool) {
         if buffer.is_empty() {
             return;
@@ -159,7 +631,9 @@ impl XhtmlGenerator {
 
         // If inside a heading block, DO NOT print <p> tag.
         if !is_heading {
-            write!(self.body, "<p>").unwrap();
+            let span = buffer.iter().map(|item| item.span()).reduce(|a, b| a.merge(&b)).unwrap_or_default();
+            let span_attr = self.span_attr(span);
+            write!(self.body, "<p{}>", span_attr).unwrap();
         }
         for item in buffer {
             self.render_item(item);
@@ -169,6 +643,22 @@ impl XhtmlGenerator {
         }
     }
 
+    /// ` data-span="..."` attribute, when source spans are enabled.
+    fn span_attr(&self, span: Span) -> String {
+        if self.options.source_spans {
+            format!(" data-span=\"{}-{}\"", span.start, span.end)
+        } else {
+            String::new()
+        }
+    }
+
+    fn write_class_attr(&mut self, classes: &[String]) {
+        if !classes.is_empty() {
+            let classes: Vec<String> = classes.iter().map(|c| self.class(c)).collect();
+            write!(self.body, " class=\"{}\"", classes.join(" ")).unwrap();
+        }
+    }
+
     fn resolve_decoration(
         &self,
         decoration: &Option<CommandBegin>,
@@ -184,15 +674,28 @@ impl XhtmlGenerator {
                         MidashiSize::Small => "h4",
                     };
 
-                    if let MidashiType::Dogyo = m.kind {
-                        (
+                    match m.kind {
+                        MidashiType::Dogyo => (
                             "span".to_string(),
                             vec!["midashi-dogyo".to_string()],
                             "</span>".to_string(),
                             false,
-                        )
-                    } else {
-                        (tag.to_string(), vec![], format!("</{}>", tag), true)
+                        ),
+                        MidashiType::Mado => {
+                            // 窓見出し floats into the first lines of the following paragraph
+                            let size_class = match m.size {
+                                MidashiSize::Large => "L",
+                                MidashiSize::Middle => "M",
+                                MidashiSize::Small => "S",
+                            };
+                            (
+                                "span".to_string(),
+                                vec!["mado".to_string(), size_class.to_string()],
+                                "</span>".to_string(),
+                                true,
+                            )
+                        }
+                        MidashiType::Normal => (tag.to_string(), vec![], format!("</{}>", tag), true),
                     }
                 }
                 CommandBegin::Alignment(a) => {
@@ -216,6 +719,18 @@ impl XhtmlGenerator {
                     "</div>".to_string(),
                     false,
                 ),
+                CommandBegin::Dangumi(n) => (
+                    "div".to_string(),
+                    vec!["dangumi".to_string(), format!("dangumi-{}", n)],
+                    "</div>".to_string(),
+                    false,
+                ),
+                CommandBegin::PageCenter => (
+                    "div".to_string(),
+                    vec!["page-center".to_string()],
+                    "</div>".to_string(),
+                    false,
+                ),
                 _ => ("div".to_string(), vec![], "</div>".to_string(), false),
             },
         }
@@ -246,6 +761,10 @@ impl XhtmlGenerator {
             ParsedItem::Command { cmd: Command::SingleCommand(SingleCommand::Midashi((_, content))), .. } => {
                 acc.push_str(content);
             }
+            // The digits of 縦中横 are moved out of the text into the command
+            ParsedItem::Command { cmd: Command::SingleCommand(SingleCommand::Tatechuyoko(content)), .. } => {
+                acc.push_str(content);
+            }
             // Ignore other commands
             _ => {}
         }
@@ -257,71 +776,47 @@ impl XhtmlGenerator {
                 self.render_text(dt);
             }
             ParsedItem::Newline(_) => {}
-            ParsedItem::Command { cmd: Command::SingleCommand(sc), .. } => {
+            ParsedItem::Command { cmd: Command::SingleCommand(sc), span } => {
                 match sc {
-                    SingleCommand::Bold(s) => {
-                        write!(self.body, "<span class=\"bold\">{}</span>", escape_html(s))
-                            .unwrap();
-                    }
-                    SingleCommand::Italic(s) => {
-                        write!(
-                            self.body,
-                            "<span class=\"italic\">{}</span>",
-                            escape_html(s)
-                        )
-                        .unwrap();
-                    }
-                    SingleCommand::Bouten((_, s)) => {
-                        write!(self.body, "<span class=\"em\">{}</span>", escape_html(s)).unwrap();
-                    }
-                    SingleCommand::Bousen((_, s)) => {
-                        write!(
-                            self.body,
-                            "<span class=\"bousen\">{}</span>",
-                            escape_html(s)
-                        )
-                        .unwrap();
-                    }
-                    SingleCommand::Kaipage => {
-                        write!(self.body, "<div class=\"page-break\"></div>").unwrap();
+                    SingleCommand::Bold(s) => self.write_span("bold", s),
+                    SingleCommand::Italic(s) => self.write_span("italic", s),
+                    SingleCommand::Tatechuyoko(s) => self.write_span("tcy", s),
+                    SingleCommand::Bouten((_, s)) => self.write_span("em", s),
+                    SingleCommand::Bousen((_, s)) => self.write_span("bousen", s),
+                    SingleCommand::Kaipage | SingleCommand::Kaicho => {
+                        self.write_break("page-break", Some("doc-pagebreak"))
                     }
-                    SingleCommand::Kaicho => {
-                        write!(self.body, "<div class=\"page-break\"></div>").unwrap();
+                    SingleCommand::Kaimihiraki => self.write_break("kaimihiraki", Some("doc-pagebreak")),
+                    SingleCommand::Kaidan => self.write_break("column-break", None),
+                    SingleCommand::Mama(_) => {
+                        let class = self.class("mama");
+                        write!(self.body, "<sup class=\"{}\">〔ママ〕</sup>", class).unwrap();
                     }
-                    SingleCommand::Kaimihiraki => {
-                        write!(self.body, "<div class=\"kaimihiraki\"></div>").unwrap();
+                    SingleCommand::SourceNote { visible, original } => {
+                        self.render_source_note(visible, original);
                     }
-                    SingleCommand::Kaidan => {
-                        write!(self.body, "<div class=\"column-break\"></div>").unwrap();
+                    SingleCommand::Note { note, .. } => self.write_noteref(escape_html(note).into_owned()),
+                    SingleCommand::Image { description, src, caption, size } => {
+                        self.render_image(description, src, caption.as_deref(), *size)
                     }
                     SingleCommand::Midashi((m, content)) => {
                         let (tag, classes, close, _) =
                             self.resolve_decoration(&Some(CommandBegin::Midashi(m.clone())));
 
                         // Generate ID for inline midashi too
-                        let id = format!("midashi-{}", self.next_id);
-                        self.next_id += 1;
+                        let id = self.next_heading_id(content);
 
                         // Add to TOC
-                        let level = if tag == "h2" {
-                            2
-                        } else if tag == "h3" {
-                            3
-                        } else if tag == "h4" {
-                            4
-                        } else {
-                            2
-                        };
                         self.toc_entries.push(TocEntry {
-                            level,
+                            level: heading_level(m.size),
                             text: content.clone(),
                             id: id.clone(),
+                            is_window: m.kind == MidashiType::Mado,
+                            span: *span,
                         });
 
-                        write!(self.body, "<{} id=\"{}\"", tag, id).unwrap();
-                        if !classes.is_empty() {
-                            write!(self.body, " class=\"{}\"", classes.join(" ")).unwrap();
-                        }
+                        write!(self.body, "<{} id=\"{}\"{}", tag, id, self.span_attr(*span)).unwrap();
+                        self.write_class_attr(&classes);
                         write!(self.body, ">").unwrap();
                         write!(self.body, "{}", escape_html(content)).unwrap();
                         write!(self.body, "{}", close).unwrap();
@@ -332,11 +827,121 @@ impl XhtmlGenerator {
             ParsedItem::SpecialCharacter { kind, .. } => match kind {
                 SpecialCharacter::Odoriji => write!(self.body, "／＼").unwrap(),
                 SpecialCharacter::DakutenOdoriji => write!(self.body, "／″＼").unwrap(),
+                SpecialCharacter::Gaiji(gaiji) => self.render_gaiji(gaiji),
             },
             _ => {}
         }
     }
 
+    fn render_image(&mut self, description: &str, src: &str, caption: Option<&str>, size: Option<(u32, u32)>) {
+        let class = self.class("img");
+        let src = format!("{}{}", self.options.image_href_prefix, src);
+        let alt = caption.unwrap_or(description);
+        write!(self.body, "<span class=\"{}\"><img src=\"{}\" alt=\"{}\"", class, escape_html(&src), escape_html(alt))
+            .unwrap();
+        if let Some((width, height)) = size {
+            write!(self.body, " width=\"{}\" height=\"{}\"", width, height).unwrap();
+        }
+        write!(self.body, "{}", self.void_end()).unwrap();
+        if let Some(caption) = caption {
+            self.write_span("caption", caption);
+        }
+        write!(self.body, "</span>").unwrap();
+    }
+
+    fn render_gaiji(&mut self, gaiji: &Gaiji) {
+        let annotation = format!("※［＃{}］", gaiji.annotation);
+        match &self.options.gaiji {
+            GaijiStyle::Unicode => match gaiji.unicode {
+                Some(c) => self.body.push(c),
+                None => self.write_span("gaiji", &annotation),
+            },
+            GaijiStyle::Image { href_prefix } => {
+                let code = match (&gaiji.jis, gaiji.unicode) {
+                    (Some(jis), _) => jis.clone(),
+                    (None, Some(c)) => format!("u{:04x}", c as u32),
+                    (None, None) => return self.write_span("gaiji", &annotation),
+                };
+                let src = format!("{}{}.png", href_prefix, code);
+                let class = self.class("gaiji");
+                // The alt text is what screen readers announce; fall back to the annotation
+                let alt = if gaiji.description.is_empty() { &annotation } else { &gaiji.description };
+                write!(
+                    self.body,
+                    "<img class=\"{}\" src=\"{}\" alt=\"{}\"{}",
+                    class,
+                    escape_html(&src),
+                    escape_html(alt),
+                    self.void_end()
+                )
+                .unwrap();
+            }
+            GaijiStyle::Annotation => self.write_span("gaiji", &annotation),
+        }
+    }
+
+    fn render_source_note(&mut self, visible: &str, original: &str) {
+        match self.options.source_notes {
+            SourceNoteStyle::Hidden => {}
+            SourceNoteStyle::Inline => {
+                self.write_span("source-note", &format!("〔底本では「{}」〕", original));
+            }
+            SourceNoteStyle::Footnote => self.write_noteref(format!(
+                "「{}」は底本では「{}」",
+                escape_html(visible),
+                escape_html(original)
+            )),
+        }
+    }
+
+    fn note_marker(&self, n: usize) -> String {
+        escape_html(&self.options.note_marker.replace("{n}", &n.to_string())).into_owned()
+    }
+
+    /// Write a link to a new note with the given (escaped) content.
+    fn write_noteref(&mut self, note: String) {
+        self.footnotes.push(note);
+        let n = self.footnotes.len();
+        let notes_href = match &self.options.notes {
+            NotePlacement::Footnote => Cow::Borrowed(""),
+            NotePlacement::Endnote { notes_href, .. } => escape_html(notes_href),
+        };
+        let (class, marker) = (self.class("noteref"), self.note_marker(n));
+        let epub_type = self.epub_type("noteref");
+        write!(
+            self.body,
+            "<a id=\"noteref-{n}\" class=\"{class}\" href=\"{notes_href}#note-{n}\"{epub_type}>{marker}</a>"
+        )
+        .unwrap();
+    }
+
+    /// Notes with links back to `body_href`.
+    fn render_notes(&self, body_href: &str, epub_type: &str) -> String {
+        let note_class = self.class("note");
+        let body_href = escape_html(body_href);
+        let epub_type = self.epub_type(epub_type);
+        let mut out = String::new();
+        for (i, note) in self.footnotes.iter().enumerate() {
+            let n = i + 1;
+            write!(
+                out,
+                "<aside id=\"note-{n}\" class=\"{note_class}\"{epub_type}><p><a href=\"{body_href}#noteref-{n}\">{}</a>　{note}</p></aside>",
+                self.note_marker(n)
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    fn render_footnotes(&mut self) {
+        if self.footnotes.is_empty() {
+            return;
+        }
+        let footnotes_class = self.class("footnotes");
+        let notes = self.render_notes("", "footnote");
+        write!(self.body, "<div class=\"{}\">{}</div>", footnotes_class, notes).unwrap();
+    }
+
     fn render_text(&mut self, dt: &DecoratedText) {
         let content = escape_html(&dt.text);
         if let Some(ruby) = &dt.ruby {
@@ -353,19 +958,118 @@ impl XhtmlGenerator {
     }
 }
 
-fn escape_html(s: &str) -> String {
-    s.replace('&', "&amp;")
-        .replace('<', "&lt;")
-        .replace('>', "&gt;")
-        .replace('"', "&quot;")
-        .replace('\'', "&apos;")
+/// Turn heading text into an id fragment: letters and digits (including kanji and kana)
+/// are kept, ASCII is lowercased and everything else becomes a single `-`.
+fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Built-in XHTML shell; see [`XhtmlGeneratorOptions::template`].
+const DEFAULT_TEMPLATE: &str = include_str!("epub_template/page.xhtml");
+
+/// Replace `{name}` slots in one pass, so filled-in text is never substituted again.
+/// Unknown slots are left as they are.
+pub(crate) fn fill_template(template: &str, slots: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let slot = after.find('}').and_then(|close| {
+            let name = &after[..close];
+            slots.iter().find(|(n, _)| *n == name).map(|(_, value)| (close, *value))
+        });
+        match slot {
+            Some((close, value)) => {
+                out.push_str(value);
+                rest = &after[close + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Render blocks as an HTML5 fragment (`<div class="main vrtl">…</div>`) with the
+/// default options. See [`XhtmlGenerator::generate_fragment`].
+pub fn to_html_fragment(block: &AozoraBlock) -> String {
+    XhtmlGenerator::generate_fragment(block, &XhtmlGeneratorOptions::default())
+}
+
+/// Escape text for use in XHTML content and attribute values, in a single pass.
+/// Borrows the input when nothing needs escaping.
+pub fn escape_html(s: &str) -> Cow<'_, str> {
+    let Some(first) = s.find(['&', '<', '>', '"', '\'']) else {
+        return Cow::Borrowed(s);
+    };
+    let mut out = String::with_capacity(s.len() + 16);
+    out.push_str(&s[..first]);
+    for c in s[first..].chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    Cow::Owned(out)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::block_parser::parse_blocks;
-    use crate::tokenizer::{self, AozoraToken, Span, TextKind, TextToken};
+    use crate::tokenizer::Span;
+
+    fn entry(level: u32, text: &str) -> TocEntry {
+        TocEntry { level, text: text.to_string(), id: text.to_string(), is_window: false, span: Span::default() }
+    }
+
+    #[test]
+    fn test_toc_tree() {
+        let entries = vec![
+            entry(2, "第一部"),
+            entry(3, "一"),
+            entry(4, "節"),
+            entry(3, "二"),
+            entry(2, "第二部"),
+            entry(4, "飛び"),
+        ];
+        let tree = TocTree::from_entries(&entries);
+
+        assert_eq!(tree.roots.len(), 2);
+        assert_eq!(tree.roots[0].children.len(), 2);
+        assert_eq!(tree.roots[0].children[0].children[0].entry.text, "節");
+        assert_eq!(tree.roots[1].children[0].entry.text, "飛び");
+        assert_eq!(
+            TocTree::from_entries(&entries[4..]).to_ol("body.xhtml"),
+            "<ol><li><a href=\"body.xhtml#第二部\">第二部</a><ol><li><a href=\"body.xhtml#飛び\">飛び</a></li></ol></li></ol>"
+        );
+        assert_eq!(TocTree::default().to_ol("body.xhtml"), "");
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert!(matches!(escape_html("青空文庫"), Cow::Borrowed("青空文庫")));
+        assert_eq!(
+            escape_html("<a href=\"x\">'&'</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&apos;&amp;&apos;&lt;/a&gt;"
+        );
+        assert_eq!(escape_html("本文&"), "本文&amp;");
+    }
 
     #[test]
     fn test_simple_html_generation() {
@@ -405,7 +1109,7 @@ mod integration_tests {
 
         // Parse
         let tokens = parse_aozora(text).expect("Tokenization failed");
-        let doc = parse(tokens).expect("Parsing failed");
+        let doc = parse(tokens);
         let root = parse_blocks(doc.items).expect("Block parsing failed");
 
         // Generate
@@ -425,25 +1129,25 @@ mod integration_tests {
         let tokens = parse_aozora(text).expect("Tokenization failed");
 
         for (i, token) in tokens.iter().enumerate() {
-            if let AozoraToken::RubySeparator(_) = token {
-                if i + 1 < tokens.len() {
-                    match &tokens[i + 1] {
-                        AozoraToken::Newline(_) => {
-                            println!("Found RubySeparator followed by Newline at index {}", i);
-                            let start = if i > 10 { i - 10 } else { 0 };
-                            let end = if i + 5 < tokens.len() {
-                                i + 5
-                            } else {
-                                tokens.len()
-                            };
-                            println!("Context: {:?}", &tokens[start..end]);
-                        }
-                        AozoraToken::Command(_) => {
-                            println!("Found RubySeparator followed by Command at index {}", i);
-                            // This might also cause unexpected token if parser expects Text/Ruby
-                        }
-                        _ => {}
+            if let AozoraToken::RubySeparator(_) = token
+                && i + 1 < tokens.len()
+            {
+                match &tokens[i + 1] {
+                    AozoraToken::Newline(_) => {
+                        println!("Found RubySeparator followed by Newline at index {}", i);
+                        let start = i.saturating_sub(10);
+                        let end = if i + 5 < tokens.len() {
+                            i + 5
+                        } else {
+                            tokens.len()
+                        };
+                        println!("Context: {:?}", &tokens[start..end]);
+                    }
+                    AozoraToken::Command(_) => {
+                        println!("Found RubySeparator followed by Command at index {}", i);
+                        // This might also cause unexpected token if parser expects Text/Ruby
                     }
+                    _ => {}
                 }
             }
         }
@@ -472,13 +1176,360 @@ mod integration_tests {
         println!("Generated HTML: {}", html);
 
         // Validation: H2 should NOT contain p
-        assert!(html.contains("<h2 id=\"midashi-1\">見出し</h2>"));
+        assert!(html.contains("<h2 id=\"midashi-見出し\">見出し</h2>"));
         assert!(!html.contains("<p>見出し</p>"));
 
         // Validation: TOC
         assert_eq!(toc.len(), 1);
         assert_eq!(toc[0].text, "見出し");
         assert_eq!(toc[0].level, 2);
-        assert_eq!(toc[0].id, "midashi-1");
+        assert_eq!(toc[0].id, "midashi-見出し");
+        assert!(!toc[0].is_window);
+        assert_eq!(toc[0].span, crate::tokenizer::Span::new(0, 22));
+    }
+
+    #[test]
+    fn test_mado_midashi_rendering() {
+        let text = "タイトル\n著者\n［＃窓中見出し］青空文庫［＃窓中見出し終わり］\n本文".to_string();
+        let tokens = parse_aozora(text).unwrap();
+        let doc = parse(tokens);
+        let root = parse_blocks(doc.items).unwrap();
+        let (html, toc) = XhtmlGenerator::generate(&root, "Test");
+
+        assert!(html.contains("<span id=\"midashi-青空文庫\" class=\"mado M\">青空文庫</span>"));
+        assert!(!html.contains("<h3"));
+        assert_eq!(toc.len(), 1);
+        assert_eq!(toc[0].level, 3);
+        assert!(toc[0].is_window);
+    }
+
+    fn source_note_block() -> AozoraBlock {
+        let text = "タイトル\n著者\n戦かい［＃「戦かい」は底本では「戦い」］だ".to_string();
+        let tokens = parse_aozora(text).unwrap();
+        let doc = parse(tokens);
+        parse_blocks(doc.items).unwrap()
+    }
+
+    #[test]
+    fn test_source_note_styles() {
+        let root = source_note_block();
+
+        let (html, _) = XhtmlGenerator::generate(&root, "Test");
+        assert!(html.contains("<p>戦かいだ</p>"));
+
+        let options = XhtmlGeneratorOptions {
+            source_notes: SourceNoteStyle::Inline,
+            ..Default::default()
+        };
+        let (html, _) = XhtmlGenerator::generate_with(&root, "Test", &options);
+        assert!(html.contains("戦かい<span class=\"source-note\">〔底本では「戦い」〕</span>だ"));
+
+        let options = XhtmlGeneratorOptions {
+            source_notes: SourceNoteStyle::Footnote,
+            ..Default::default()
+        };
+        let (html, _) = XhtmlGenerator::generate_with(&root, "Test", &options);
+        assert!(html.contains("href=\"#note-1\""));
+        assert!(html.contains("<aside id=\"note-1\""));
+        assert!(html.contains("「戦かい」は底本では「戦い」"));
+    }
+
+    #[test]
+    fn test_notes() {
+        let text = "タイトル\n著者\n蒼穹［＃「蒼穹」に「あおぞら」の注記］と戦かい［＃「戦かい」は底本では「戦い」］".to_string();
+        let root = parse_blocks(parse(parse_aozora(text).unwrap()).items).unwrap();
+
+        let (html, _) = XhtmlGenerator::generate(&root, "Test");
+        assert!(html.contains("<p>蒼穹<a id=\"noteref-1\" class=\"noteref\" href=\"#note-1\" epub:type=\"noteref\">※1</a>と戦かい</p>"));
+        assert!(html.contains("<aside id=\"note-1\" class=\"note\" epub:type=\"footnote\"><p><a href=\"#noteref-1\">※1</a>　あおぞら</p></aside>"));
+        assert!(!html.contains("note-2"));
+
+        let options = XhtmlGeneratorOptions {
+            source_notes: SourceNoteStyle::Footnote,
+            notes: NotePlacement::Endnote {
+                notes_href: "notes.xhtml".to_string(),
+                body_href: "0001.xhtml".to_string(),
+            },
+            note_marker: "〔注{n}〕".to_string(),
+            ..Default::default()
+        };
+        let (html, _, notes) = XhtmlGenerator::generate_with_notes(&root, "Test", &options);
+        let notes = notes.unwrap();
+        assert!(html.contains("href=\"notes.xhtml#note-2\" epub:type=\"noteref\">〔注2〕</a>"));
+        assert!(!html.contains("<aside"));
+        assert!(notes.contains("<section class=\"endnotes\" epub:type=\"endnotes\">"));
+        assert!(notes.contains("<a href=\"0001.xhtml#noteref-1\">〔注1〕</a>　あおぞら"));
+        assert!(notes.contains("<a href=\"0001.xhtml#noteref-2\">〔注2〕</a>　「戦かい」は底本では「戦い」"));
+    }
+
+    #[test]
+    fn test_html_fragment() {
+        let text = "タイトル\n著者\n\n蒼穹［＃「蒼穹」に「あおぞら」の注記］\n\n<本文>".to_string();
+        let root = parse_blocks(parse(parse_aozora(text).unwrap()).items).unwrap();
+        let html = to_html_fragment(&root);
+
+        assert!(html.starts_with("<div class=\"main vrtl\" lang=\"ja\">"));
+        assert!(html.ends_with("</div>"));
+        assert!(!html.contains("<?xml") && !html.contains("<head>"));
+        assert!(html.contains("<p><br></p>"));
+        assert!(html.contains("<p>&lt;本文&gt;</p>"));
+        assert!(html.contains("<a id=\"noteref-1\" class=\"noteref\" href=\"#note-1\">※1</a>"));
+        assert!(!html.contains("epub:type"));
+    }
+
+    #[test]
+    fn test_template() {
+        let text = "タイトル\n著者\n本文{title}".to_string();
+        let root = parse_blocks(parse(parse_aozora(text).unwrap()).items).unwrap();
+
+        let (html, _) = XhtmlGenerator::generate(&root, "A&B");
+        assert!(html.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>"));
+        assert!(html.contains("<title>A&amp;B</title>"));
+
+        let options = XhtmlGeneratorOptions {
+            template: Some("<html class=\"{root_class}\"><head><title>{title}</title><script src=\"a.js\"></script>{unknown}</head><body class=\"{main_class}\">{body}</body></html>".to_string()),
+            ..Default::default()
+        };
+        let (html, _) = XhtmlGenerator::generate_with(&root, "Test", &options);
+        assert_eq!(
+            html,
+            "<html class=\"vrtl\"><head><title>Test</title><script src=\"a.js\"></script>{unknown}</head><body class=\"main\"><div><p>本文{title}</p></div></body></html>"
+        );
+    }
+
+    #[test]
+    fn test_streaming_writer() {
+        let mut text = "タイトル\n著者\n".to_string();
+        for i in 0..20 {
+            text.push_str(&format!("［＃中見出し］{}［＃中見出し終わり］\n青空《あおぞら》の{}\n", i, "本文".repeat(500)));
+        }
+        let root = parse_blocks(parse(parse_aozora(text).unwrap()).items).unwrap();
+        let options = XhtmlGeneratorOptions::default();
+        let (expected, expected_toc) = XhtmlGenerator::generate_with(&root, "Test", &options);
+        assert!(expected.len() > FLUSH_THRESHOLD * 2);
+
+        let mut streamed = String::new();
+        let (toc, notes) = XhtmlGenerator::write_with(&root, "Test", &options, &mut streamed).unwrap();
+        assert_eq!(streamed, expected);
+        assert_eq!(toc, expected_toc);
+        assert!(notes.is_none());
+
+        let mut bytes = Vec::new();
+        XhtmlGenerator::write_io_with(&root, "Test", &options, &mut bytes).unwrap();
+        assert_eq!(String::from_utf8(bytes).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tatechuyoko() {
+        let text = "タイトル\n著者\n第12［＃「12」は縦中横］話\n".to_string();
+        let root = parse_blocks(parse(parse_aozora(text).unwrap()).items).unwrap();
+        let (html, _) = XhtmlGenerator::generate(&root, "Test");
+        assert!(html.contains("<p>第<span class=\"tcy\">12</span>話</p>"));
+    }
+
+    #[test]
+    fn test_accessibility_attributes() {
+        let text = "タイトル\n著者\n本文［＃改ページ］\n※［＃「木＋吶のつくり」、第3水準1-85-54］".to_string();
+        let root = parse_blocks(parse(parse_aozora(text).unwrap()).items).unwrap();
+        let options = XhtmlGeneratorOptions {
+            lang: "ja-JP".to_string(),
+            gaiji: GaijiStyle::Image { href_prefix: String::new() },
+            ..Default::default()
+        };
+        let (html, _) = XhtmlGenerator::generate_with(&root, "Test", &options);
+
+        assert!(html.contains(" lang=\"ja-JP\"\n xml:lang=\"ja-JP\"\n"));
+        assert!(html.contains("<div class=\"page-break\" role=\"doc-pagebreak\"></div>"));
+        assert!(html.contains("alt=\"木＋吶のつくり\""));
+    }
+
+    #[test]
+    fn test_page_numbers() {
+        let text = "タイトル\n著者\n一［＃改ページ］\n二［＃改段］\n三［＃改丁］\n".to_string();
+        let root = parse_blocks(parse(parse_aozora(text).unwrap()).items).unwrap();
+        let (html, _) = XhtmlGenerator::generate(&root, "Test");
+        assert!(!html.contains("page-2"));
+
+        let options = XhtmlGeneratorOptions { first_page: Some(5), ..Default::default() };
+        let (html, _) = XhtmlGenerator::generate_with(&root, "Test", &options);
+        assert!(html.contains(
+            "<div class=\"page-break\" role=\"doc-pagebreak\" id=\"page-6\" aria-label=\"6\" epub:type=\"pagebreak\"></div>"
+        ));
+        assert!(html.contains("<div class=\"column-break\"></div>"));
+        assert!(html.contains("id=\"page-7\""));
+    }
+
+    #[test]
+    fn test_source_spans() {
+        let text = "タイトル\n著者\n\n［＃大見出し］一［＃大見出し終わり］\n本文\n\n二行目".to_string();
+        let root = parse_blocks(parse(parse_aozora(text.clone()).unwrap()).items).unwrap();
+        let (html, _) = XhtmlGenerator::generate(&root, "Test");
+        assert!(!html.contains("data-span"));
+
+        let options = XhtmlGeneratorOptions { source_spans: true, ..Default::default() };
+        let (html, _) = XhtmlGenerator::generate_with(&root, "Test", &options);
+        let chars: Vec<char> = text.chars().collect();
+        let spanned: Vec<String> = html
+            .split("data-span=\"")
+            .skip(1)
+            .map(|rest| {
+                let (start, end) = rest[..rest.find('"').unwrap()].split_once('-').unwrap();
+                chars[start.parse().unwrap()..end.parse().unwrap()].iter().collect()
+            })
+            .collect();
+        assert!(spanned.contains(&"本文".to_string()), "{:?}", spanned);
+        assert!(spanned.contains(&"二行目".to_string()), "{:?}", spanned);
+        assert!(spanned.iter().any(|s| s.contains('一')), "{:?}", spanned);
+    }
+
+    #[test]
+    fn test_generator_options() {
+        let text = "タイトル\n著者\n［＃大見出し］一［＃大見出し終わり］\n\n［＃ここから２字下げ］\n本文［＃改ページ］\n［＃ここで字下げ終わり］".to_string();
+        let root = parse_blocks(parse(parse_aozora(text).unwrap()).items).unwrap();
+        let options = XhtmlGeneratorOptions {
+            writing_mode: WritingMode::HorizontalTb,
+            stylesheet: "/css/site.css".to_string(),
+            empty_paragraphs: false,
+            class_prefix: "ao-".to_string(),
+            heading_id_prefix: "h".to_string(),
+            heading_ids: HeadingIdScheme::Numbered,
+            ..Default::default()
+        };
+        let (html, toc) = XhtmlGenerator::generate_with(&root, "Test", &options);
+
+        assert!(html.contains(" class=\"ao-hltr\""));
+        assert!(html.contains("href=\"/css/site.css\""));
+        assert!(html.contains("<div class=\"ao-main\">"));
+        assert!(!html.contains("<p><br/></p>"));
+        assert!(html.contains("<div class=\"ao-jisage-2\"><p>本文</p><div class=\"ao-page-break\" role=\"doc-pagebreak\"></div>"));
+        assert!(html.contains("<h2 id=\"h1\">一</h2>"));
+        assert_eq!(toc[0].id, "h1");
+
+        let (html, _) = XhtmlGenerator::generate(&root, "Test");
+        assert!(html.contains(" class=\"vrtl\""));
+        assert!(html.contains("<p><br/></p>"));
+    }
+
+    #[test]
+    fn test_heading_ids() {
+        let text = "タイトル\n著者\n［＃大見出し］第一章　Ａ Tale［＃大見出し終わり］\n［＃中見出し］一［＃中見出し終わり］\n本文\n［＃中見出し］一［＃中見出し終わり］\n［＃小見出し］……［＃小見出し終わり］\n".to_string();
+        let root = parse_blocks(parse(parse_aozora(text).unwrap()).items).unwrap();
+
+        let (_, toc) = XhtmlGenerator::generate(&root, "Test");
+        let ids: Vec<_> = toc.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, ["midashi-第一章-Ａ-tale", "midashi-一", "midashi-一-2", "midashi-4"]);
+
+        let options = XhtmlGeneratorOptions { heading_ids: HeadingIdScheme::Numbered, ..Default::default() };
+        let (_, toc) = XhtmlGenerator::generate_with(&root, "Test", &options);
+        let ids: Vec<_> = toc.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, ["midashi-1", "midashi-2", "midashi-3", "midashi-4"]);
+    }
+
+    #[test]
+    fn test_heading_with_tatechuyoko() {
+        let text = "タイトル\n著者\n［＃大見出し］第12［＃「12」は縦中横］話［＃大見出し終わり］\n".to_string();
+        let root = parse_blocks(parse(parse_aozora(text).unwrap()).items).unwrap();
+
+        let (html, toc) = XhtmlGenerator::generate(&root, "Test");
+        assert_eq!(toc[0].text, "第12話");
+        assert_eq!(toc[0].id, "midashi-第12話");
+        assert!(html.contains("第<span class=\"tcy\">12</span>話"));
+        let headings: Vec<_> = root.headings().map(|(_, block)| block.text()).collect();
+        assert_eq!(headings, ["第12話"]);
+    }
+
+    #[test]
+    fn test_class_map() {
+        let text = "タイトル\n著者\n［＃ここから２字下げ］\n本文［＃改ページ］\n［＃ここで字下げ終わり］\n［＃ここから２段組み］上段［＃改段］下段［＃ここで段組み終わり］".to_string();
+        let root = parse_blocks(parse(parse_aozora(text).unwrap()).items).unwrap();
+        let options = XhtmlGeneratorOptions {
+            class_prefix: "ao-".to_string(),
+            class_map: HashMap::from([
+                ("dangumi*".to_string(), "columns*".to_string()),
+                ("jisage-*".to_string(), "indent indent-*".to_string()),
+                ("page-break".to_string(), "pagebreak".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let (html, _) = XhtmlGenerator::generate_with(&root, "Test", &options);
+
+        assert!(html.contains("<div class=\"indent indent-2\">"));
+        assert!(html.contains("<p>本文</p><div class=\"pagebreak\" role=\"doc-pagebreak\"></div>"));
+        assert!(html.contains("<div class=\"columns columns-2\"><p>上段</p><div class=\"ao-column-break\"></div>"));
+    }
+
+    #[test]
+    fn test_image() {
+        let text = "T\nA\n\n［＃挿絵（fig1_01.png、横320×縦240）入る］\n［＃「第一図」のキャプション付きの図（fig2.png）入る］\n".to_string();
+        let root = parse_blocks(parse(parse_aozora(text).unwrap()).items).unwrap();
+        let (xhtml, _) = XhtmlGenerator::generate(&root, "T");
+        assert!(xhtml.contains(
+            "<p><span class=\"img\"><img src=\"fig1_01.png\" alt=\"挿絵\" width=\"320\" height=\"240\"/></span></p>"
+        ));
+        assert!(xhtml.contains(
+            "<p><span class=\"img\"><img src=\"fig2.png\" alt=\"第一図\"/><span class=\"caption\">第一図</span></span></p>"
+        ));
+
+        let options = XhtmlGeneratorOptions { image_href_prefix: "../image/".to_string(), ..Default::default() };
+        let (xhtml, _) = XhtmlGenerator::generate_with(&root, "T", &options);
+        assert!(xhtml.contains("src=\"../image/fig1_01.png\""));
+    }
+
+    #[test]
+    fn test_gaiji_style() {
+        let text = "タイトル\n著者\n※［＃「木＋吶のつくり」、第3水準1-85-54］と※［＃「口＋世」、U+546D、190-下-5］".to_string();
+        let root = parse_blocks(parse(parse_aozora(text).unwrap()).items).unwrap();
+
+        let (html, _) = XhtmlGenerator::generate(&root, "Test");
+        assert!(html.contains("<p><span class=\"gaiji\">※［＃「木＋吶のつくり」、第3水準1-85-54］</span>と\u{546D}</p>"));
+
+        let options = XhtmlGeneratorOptions {
+            gaiji: GaijiStyle::Image { href_prefix: "../gaiji/".to_string() },
+            ..Default::default()
+        };
+        let (html, _) = XhtmlGenerator::generate_with(&root, "Test", &options);
+        assert!(html.contains("<img class=\"gaiji\" src=\"../gaiji/1-85-54.png\" alt=\"木＋吶のつくり\"/>と<img class=\"gaiji\" src=\"../gaiji/u546d.png\" alt=\"口＋世\"/>"));
+
+        let options = XhtmlGeneratorOptions { gaiji: GaijiStyle::Annotation, ..Default::default() };
+        let (html, _) = XhtmlGenerator::generate_with(&root, "Test", &options);
+        assert!(html.contains("<span class=\"gaiji\">※［＃「口＋世」、U+546D、190-下-5］</span>"));
+    }
+
+    #[test]
+    fn test_page_center_block() {
+        let text = "タイトル\n著者\n［＃ページの左右中央］\n第一部\n［＃改ページ］\n本文".to_string();
+        let tokens = parse_aozora(text).unwrap();
+        let doc = parse(tokens);
+        let root = parse_blocks(doc.items).unwrap();
+        let (html, _) = XhtmlGenerator::generate(&root, "Test");
+
+        assert!(html.contains("<div class=\"page-center\">"));
+        assert!(html.contains("<p>第一部</p></div>"));
+        assert!(html.contains("<p>本文</p>"));
+    }
+
+    #[test]
+    fn test_dangumi_block() {
+        let text = "タイトル\n著者\n［＃ここから２段組み］上段［＃改段］下段［＃ここで段組み終わり］".to_string();
+        let tokens = parse_aozora(text).unwrap();
+        let doc = parse(tokens);
+        let root = parse_blocks(doc.items).unwrap();
+        let (html, _) = XhtmlGenerator::generate(&root, "Test");
+
+        assert!(html.contains(
+            "<div class=\"dangumi dangumi-2\"><p>上段</p><div class=\"column-break\"></div><p>下段</p></div>"
+        ));
+    }
+
+    #[test]
+    fn test_mama_marker() {
+        let text = "タイトル\n著者\n獅子奮塵［＃「獅子奮塵」に「ママ」の注記］の勢い".to_string();
+        let tokens = parse_aozora(text).unwrap();
+        let doc = parse(tokens);
+        let root = parse_blocks(doc.items).unwrap();
+        let (html, _) = XhtmlGenerator::generate(&root, "Test");
+
+        assert!(html.contains("<p>獅子奮塵<sup class=\"mama\">〔ママ〕</sup>の勢い</p>"));
+        assert!(!html.contains("注記"));
     }
 }
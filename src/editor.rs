@@ -1,21 +1,225 @@
+mod debounce;
+mod encoding;
+mod furigana;
+mod glossary;
+mod history;
+pub mod notes;
+mod outline;
+mod preview;
+mod ruby;
+mod search;
+pub mod stats;
+mod tatechuyoko;
+pub mod versions;
+
 use dioxus::prelude::*;
+use crate::export::export_chapter;
+use crate::session::{ChapterPosition, Session};
+use crate::settings::{update_settings, use_settings, KeybindingProfile, Settings};
+use crate::toast::use_toasts;
+use debounce::use_debounce;
+use encoding::{EncodingBackdrop, EncodingNotice};
+use glossary::{CompletionBar, Glossary, GlossaryPanel};
+use history::{History, Snapshot};
+use notes::NotesPanel;
+use outline::OutlinePanel;
+use preview::PreviewPane;
+use ruby::{RubyDialog, RubyTarget};
+use tatechuyoko::TatechuyokoDialog;
+use search::{Highlights, SearchBackdrop, SearchBar};
+use stats::StatusBar;
+use versions::HistoryDialog;
 use crate::top_page::works::{ActionIcon, Series};
 use encoding_rs::SHIFT_JIS;
 use std::fs;
-use std::path::PathBuf;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 const BACK_ICON: Asset = asset!("/assets/icons/back.svg");
 const PREVIEW_ICON: Asset = asset!("/assets/icons/read.svg");
+const UNDO_ICON: Asset = asset!("/assets/icons/undo.svg");
+const REDO_ICON: Asset = asset!("/assets/icons/redo.svg");
+const SPLIT_ICON: Asset = asset!("/assets/icons/split.svg");
+const OUTLINE_ICON: Asset = asset!("/assets/icons/outline.svg");
+const EXPORT_ICON: Asset = asset!("/assets/icons/export.svg");
+const HISTORY_ICON: Asset = asset!("/assets/icons/history.svg");
+const RUBY_ICON: Asset = asset!("/assets/icons/Ruby.svg");
+const FOCUS_ICON: Asset = asset!("/assets/icons/focus.svg");
+const GLOSSARY_ICON: Asset = asset!("/assets/icons/glossary.svg");
+const NOTES_ICON: Asset = asset!("/assets/icons/notes.svg");
+const TATECHUYOKO_ICON: Asset = asset!("/assets/icons/tatechuyoko.svg");
+
+/// Puts the caret and the scroll back where they were once the text is in the textarea,
+/// then reports them as they change.
+const SESSION_SCRIPT: &str = r#"
+const [cursor, scrollTop] = await dioxus.recv();
+let textarea = null;
+for (let frame = 0; frame < 120; frame++) {
+    textarea = document.querySelector(".simple_editor_textarea");
+    if (textarea && textarea.value.length >= cursor) break;
+    await new Promise(requestAnimationFrame);
+}
+if (textarea) {
+    textarea.focus();
+    textarea.setSelectionRange(cursor, cursor);
+    textarea.scrollTop = scrollTop;
+    const report = () => dioxus.send([textarea.selectionStart, textarea.scrollTop]);
+    for (const event of ["scroll", "keyup", "mouseup"]) {
+        textarea.addEventListener(event, report);
+    }
+}
+await new Promise(() => {});
+"#;
+
+/// Choices of the autosave interval in seconds (0 is off)
+const AUTOSAVE_INTERVALS: [(u64, &str); 4] = [(0, "自動保存オフ"), (5, "5秒"), (30, "30秒"), (60, "60秒")];
+
+/// Move what is kept beside the text of a chapter, its versions and its notes, along with
+/// the text moved from `old_file` to `new_file`.
+pub fn move_companions(old_file: &Path, new_file: &Path) -> std::io::Result<()> {
+    versions::rename(old_file, new_file)?;
+    if let Err(e) = notes::rename(old_file, new_file) {
+        let _ = versions::rename(new_file, old_file);
+        return Err(e);
+    }
+    Ok(())
+}
 
 // --- Hook: use_editor_file ---
 #[derive(Clone, Copy, PartialEq)]
 pub struct UseEditorFile {
     pub content: Signal<String>,
     pub status: Signal<String>,
+    /// Shared settings, for the autosave interval and the versions kept
+    pub settings: Signal<Settings>,
+    /// Content as it is on disk
+    saved: Signal<String>,
+    history: Signal<History>,
     file_path: Signal<PathBuf>,
 }
 
 impl UseEditorFile {
+    /// Whether there are edits that have not been saved yet
+    pub fn is_dirty(&self) -> bool {
+        *self.content.read() != *self.saved.read()
+    }
+
+    /// Replace the content with `text` typed by the user, remembering the previous
+    /// content for undo.
+    pub fn edit(&mut self, text: String) {
+        let before = self.content.peek().clone();
+        self.history.write().record(before, &text);
+        self.content.set(text);
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history.read().can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.history.read().can_redo()
+    }
+
+    pub fn undo(&mut self) {
+        let current = self.content.peek().clone();
+        let snapshot = self.history.write().undo(current);
+        if let Some(snapshot) = snapshot {
+            self.restore(snapshot);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        let current = self.content.peek().clone();
+        let snapshot = self.history.write().redo(current);
+        if let Some(snapshot) = snapshot {
+            self.restore(snapshot);
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        // The caret is not part of the rendered state, so the textarea is updated directly;
+        // setting the value there first keeps the selection from being reset by the re-render
+        let eval = document::eval(
+            r#"
+            const [text, cursor] = await dioxus.recv();
+            const textarea = document.querySelector(".simple_editor_textarea");
+            if (textarea) {
+                textarea.value = text;
+                textarea.focus();
+                textarea.setSelectionRange(cursor, cursor);
+            }
+            "#,
+        );
+        let _ = eval.send((snapshot.text.clone(), snapshot.cursor));
+        self.content.set(snapshot.text);
+    }
+
+    /// Select `range`, in bytes of the content, in the textarea and scroll it into view.
+    /// The focus stays where it is, such as in the search bar.
+    pub fn select(&self, range: Range<usize>) {
+        self.select_in_textarea(range, true);
+    }
+
+    /// Select `range`, in bytes of the content, and move the focus to the textarea.
+    pub fn focus_at(&self, range: Range<usize>) {
+        self.select_in_textarea(range, false);
+    }
+
+    fn select_in_textarea(&self, range: Range<usize>, keep_focus: bool) {
+        let text = self.content.peek().clone();
+        let start = text[..range.start].encode_utf16().count();
+        let end = start + text[range].encode_utf16().count();
+        let eval = document::eval(
+            r#"
+            const [text, start, end, keepFocus] = await dioxus.recv();
+            const textarea = document.querySelector(".simple_editor_textarea");
+            if (textarea) {
+                const focused = document.activeElement;
+                if (textarea.value !== text) textarea.value = text;
+                textarea.focus();
+                textarea.setSelectionRange(start, end);
+                if (keepFocus && focused && focused !== textarea) focused.focus();
+            }
+            "#,
+        );
+        let _ = eval.send((text, start, end, keep_focus));
+    }
+
+    /// The selection of the textarea, in bytes of the content.
+    pub async fn selection(&self) -> Option<Range<usize>> {
+        let (start, end) = document::eval(
+            r#"
+            const textarea = document.querySelector(".simple_editor_textarea");
+            return textarea ? [textarea.selectionStart, textarea.selectionEnd] : [0, 0];
+            "#,
+        )
+        .join::<(usize, usize)>()
+        .await
+        .ok()?;
+        let text = self.content.peek();
+        // The textarea counts in UTF-16 code units
+        let byte = |utf16: usize| {
+            let mut units = 0;
+            text.char_indices()
+                .find(|(_, c)| {
+                    units += c.len_utf16();
+                    units > utf16
+                })
+                .map_or(text.len(), |(i, _)| i)
+        };
+        Some(byte(start)..byte(end))
+    }
+
+    /// Whether the content can be saved in Shift_JIS without losing characters
+    pub fn is_encodable(&self) -> bool {
+        !SHIFT_JIS.encode(&self.content.peek()).2
+    }
+
+    pub fn set_autosave_interval(&mut self, seconds: u64) {
+        update_settings(self.settings, |settings| settings.autosave_interval = seconds);
+    }
+
     pub fn save(&mut self) {
         let text = (self.content)();
         println!("[use_file] Saving content len: {}", text.len());
@@ -37,12 +241,16 @@ impl UseEditorFile {
             let _ = fs::create_dir_all(parent);
         }
 
-        if let Err(e) = fs::write(&path, cow) {
+        if let Err(e) = fs::write(&path, &cow) {
             println!("Error saving file: {}", e);
             self.status.set(format!("Error: {}", e));
         } else {
             println!("File saved successfully");
             self.status.set("Saved".to_string());
+            self.saved.set(text);
+            if let Err(e) = versions::record(&path, &cow, self.settings.peek().snapshot_retention) {
+                println!("Error keeping a version: {}", e);
+            }
         }
     }
 }
@@ -50,6 +258,9 @@ impl UseEditorFile {
 pub fn use_editor_file(series_title: String, chapter_title: String) -> UseEditorFile {
     let mut content = use_signal(|| String::new());
     let mut status = use_signal(|| String::new());
+    let settings = use_settings();
+    let mut saved = use_signal(|| String::new());
+    let history = use_signal(History::default);
     let mut file_path = use_signal(|| PathBuf::new());
 
     // Initialize path
@@ -66,6 +277,7 @@ pub fn use_editor_file(series_title: String, chapter_title: String) -> UseEditor
                 let (cow, _, _) = SHIFT_JIS.decode(&bytes);
                 // Normalize newlines to \n for internal processing
                 let text = cow.replace("\r\n", "\n").replace("\r", "\n");
+                saved.set(text.clone());
                 content.set(text);
                 status.set("Loaded".to_string());
             } else {
@@ -74,11 +286,40 @@ pub fn use_editor_file(series_title: String, chapter_title: String) -> UseEditor
         }
     });
 
-    UseEditorFile {
+    let mut file = UseEditorFile {
         content,
         status,
+        settings,
+        saved,
+        history,
         file_path,
-    }
+    };
+
+    // Autosave: every edit restarts the countdown, so saving waits for a pause in typing
+    let mut autosave = use_debounce();
+    use_effect(move || {
+        let text = content();
+        let interval = settings.read().autosave_interval;
+        autosave.cancel();
+        if interval == 0 || text == *saved.peek() {
+            return;
+        }
+        autosave.call(Duration::from_secs(interval), move || {
+            // Unencodable characters are marked in the editor; saving waits until they are replaced
+            if file.is_encodable() {
+                file.save();
+            }
+        });
+    });
+
+    // Leaving the editor does not lose edits either when autosave is on
+    use_drop(move || {
+        if settings.peek().autosave_interval > 0 && *content.peek() != *saved.peek() && file.is_encodable() {
+            file.save();
+        }
+    });
+
+    file
 }
 
 // --- Component: Editor ---
@@ -89,14 +330,98 @@ pub fn Editor(series_title: String, chapter_title: String) -> Element {
     
     // Custom Hooks
     let mut file = use_editor_file(series_title.clone(), chapter_title.clone());
+    let mut search_open = use_signal(|| false);
+    let search_highlights = use_signal(Highlights::default);
+    let settings = use_settings();
+    let show_preview = use_memo(move || settings.read().show_preview);
+    let show_outline = use_memo(move || settings.read().show_outline);
+    let show_glossary = use_memo(move || settings.read().show_glossary);
+    let show_notes = use_memo(move || settings.read().show_notes);
+    let mut ruby_target = use_signal(|| None::<RubyTarget>);
+    let mut history_open = use_signal(|| false);
+    let mut tatechuyoko_open = use_signal(|| false);
+    let mut focus_mode = use_signal(|| false);
+    let st = series_title.clone();
+    let glossary = use_signal(|| Glossary::load(&st));
+    let mut completion = use_signal(|| None::<glossary::Completion>);
+    // Characters that saving in Shift_JIS would destroy
+    let unencodable = use_memo(move || aozora_parser::lint_encoding(&file.content.read()));
+
+    // Session: reopen the chapter where it was left, and keep track of where it is now
+    let mut position = use_signal(|| None::<ChapterPosition>);
+    let (st, ct) = (series_title.clone(), chapter_title.clone());
+    use_future(move || {
+        let (st, ct) = (st.clone(), ct.clone());
+        async move {
+            let restored = Session::load().position(&st, &ct);
+            Session::record(&st, &ct, None);
+            let mut eval = document::eval(SESSION_SCRIPT);
+            let _ = eval.send((restored.cursor, restored.scroll_top));
+            let mut written = Instant::now();
+            while let Ok((cursor, scroll_top)) = eval.recv::<(usize, f64)>().await {
+                let current = ChapterPosition { cursor, scroll_top };
+                position.set(Some(current));
+                // Scrolling reports often; the file only needs to catch up now and then
+                if written.elapsed() > Duration::from_secs(2) {
+                    Session::record(&st, &ct, Some(current));
+                    written = Instant::now();
+                }
+            }
+        }
+    });
+    // In UTF-16 code units, as the textarea counts
+    let caret = use_memo(move || position().map(|current| current.cursor));
+    let (st, ct) = (series_title.clone(), chapter_title.clone());
+    use_drop(move || {
+        if let Some(current) = *position.peek() {
+            Session::record(&st, &ct, Some(current));
+        }
+    });
 
     // Event Handlers
     let mut handle_save = move |_| {
+        // Not on autosave, which would change the text under the caret while typing
+        if settings.peek().tatechuyoko_on_save {
+            tatechuyoko::apply(file, &aozora_parser::tatechuyoko_fixes(&file.content.peek()));
+        }
         file.save();
     };
 
     let mut handle_change = move |new_text: String| {
-        file.content.set(new_text);
+        file.edit(new_text);
+        // Look up the glossary for what has just been typed
+        spawn(async move {
+            let caret = file.selection().await.map(|selection| selection.end);
+            completion.set(caret.and_then(|caret| glossary.peek().complete(&file.content.peek(), caret)));
+        });
+    };
+
+    let open_ruby = move || {
+        spawn(async move {
+            if let Some(selection) = file.selection().await {
+                let target = RubyTarget::at(&file.content.peek(), selection);
+                ruby_target.set(Some(target));
+            }
+        });
+    };
+
+    // Distraction-free mode also takes the window to full screen where the platform allows
+    let mut toggle_focus = move || {
+        let entering = !focus_mode();
+        focus_mode.set(entering);
+        document::eval(if entering {
+            "document.documentElement.requestFullscreen?.().catch(() => {})"
+        } else {
+            "if (document.fullscreenElement) document.exitFullscreen()"
+        });
+    };
+
+    let toasts = use_toasts();
+    let (st, ct) = (series_title.clone(), chapter_title.clone());
+    let handle_export = move |_| {
+        // The export reads the file, so it has to have the latest edits
+        file.save();
+        export_chapter(toasts, st.clone(), ct.clone());
     };
 
     let st = series_title.clone();
@@ -111,47 +436,262 @@ pub fn Editor(series_title: String, chapter_title: String) -> Element {
         let key = evt.key();
         let modifiers = evt.modifiers();
         let key_str = key.to_string();
+        let keybindings = settings.peek().keybindings;
+        let command = keybindings.command(modifiers);
+        // Redo is Ctrl+Y on Windows and ⌘+Shift+Z on Mac; Ctrl+Shift+Z works with both
+        let redo = (key_str == "Z" && modifiers.shift())
+            || ((key_str == "y" || key_str == "Y") && keybindings == KeybindingProfile::Standard);
 
-        if (key_str == "s" || key_str == "S") && modifiers.ctrl() {
+        if let (Key::Tab, Some(current)) = (&key, completion()) {
+            evt.prevent_default();
+            completion.set(None);
+            glossary::accept(file, &current, 0);
+        } else if key == Key::Escape && completion().is_some() {
+            completion.set(None);
+        } else if key == Key::F11 || (key == Key::Escape && focus_mode()) {
+            evt.prevent_default();
+            toggle_focus();
+        } else if (key_str == "f" || key_str == "F") && command {
+            evt.prevent_default();
+            if search_open() {
+                // Already open: move back to the search field
+                document::eval(r#"document.querySelector(".search_input")?.focus()"#);
+            }
+            search_open.set(true);
+        } else if (key_str == "r" || key_str == "R") && command {
+            evt.prevent_default();
+            open_ruby();
+        } else if (key_str == "s" || key_str == "S") && command {
             println!("Ctrl+S pressed, saving...");
             evt.prevent_default();
             evt.stop_propagation();
             handle_save(());
+        } else if command && redo {
+            evt.prevent_default();
+            file.redo();
+        } else if command && key_str == "z" {
+            evt.prevent_default();
+            file.undo();
         }
     };
 
+    let textarea_style = {
+        let settings = settings.read();
+        let font = if settings.font_family.is_empty() {
+            String::new()
+        } else {
+            format!("font-family: {}, var(--font-novel);", settings.font_family)
+        };
+        let vertical = if focus_mode() && settings.focus_vertical { "writing-mode: vertical-rl;" } else { "" };
+        format!(
+            "{} font-size: {}px; line-height: {}; {}",
+            font,
+            settings.font_size,
+            settings.line_height as f64 / 100.0,
+            vertical
+        )
+    };
+
     rsx! {
         div {
-            class: "editor_layout",
-            header {
-                class: "editor_header",
-                ActionIcon {
-                    icon: BACK_ICON,
-                    onclick: move |_| navigator.go_back(),
-                }
+            class: if focus_mode() { "editor_layout focus_mode" } else { "editor_layout" },
+            if focus_mode() {
                 div {
-                    style: "margin-left: auto; color: var(--text-information); font-size: 0.9rem;",
-                    "{series_title} - {chapter_title}"
+                    class: "focus_controls",
+                    button {
+                        class: if settings.read().focus_vertical { "active" } else { "" },
+                        onclick: move |_| update_settings(settings, |settings| settings.focus_vertical = !settings.focus_vertical),
+                        "縦書き"
+                    }
+                    button {
+                        title: "集中モードを終了 (F11 / Esc)",
+                        onclick: move |_| toggle_focus(),
+                        "終了"
+                    }
                 }
-                ActionIcon {
-                    icon: PREVIEW_ICON,
-                    onclick: handle_preview,
+            } else {
+                header {
+                    class: "editor_header",
+                    ActionIcon {
+                        icon: BACK_ICON,
+                        onclick: move |_| navigator.go_back(),
+                    }
+                    div {
+                        style: "margin-left: auto; color: var(--text-information); font-size: 0.9rem;",
+                        "{series_title} - {chapter_title}"
+                        if file.is_dirty() {
+                            span {
+                                class: "dirty_indicator",
+                                title: "未保存の変更があります",
+                                "●"
+                            }
+                        }
+                    }
+                    ActionIcon {
+                        icon: UNDO_ICON,
+                        onclick: move |_| file.undo(),
+                        class: if file.can_undo() { "" } else { "disabled" },
+                    }
+                    ActionIcon {
+                        icon: REDO_ICON,
+                        onclick: move |_| file.redo(),
+                        class: if file.can_redo() { "" } else { "disabled" },
+                    }
+                    select {
+                        class: "autosave_select",
+                        onchange: move |evt| {
+                            if let Ok(seconds) = evt.value().parse() {
+                                file.set_autosave_interval(seconds);
+                            }
+                        },
+                        for (seconds, label) in AUTOSAVE_INTERVALS {
+                            option {
+                                value: "{seconds}",
+                                selected: file.settings.read().autosave_interval == seconds,
+                                "{label}"
+                            }
+                        }
+                    }
+                    ActionIcon {
+                        icon: RUBY_ICON,
+                        onclick: move |_| open_ruby(),
+                    }
+                    ActionIcon {
+                        icon: TATECHUYOKO_ICON,
+                        onclick: move |_| tatechuyoko_open.set(true),
+                    }
+                    ActionIcon {
+                        icon: OUTLINE_ICON,
+                        onclick: move |_| update_settings(settings, |settings| settings.show_outline = !settings.show_outline),
+                        class: if show_outline() { "active" } else { "" },
+                    }
+                    ActionIcon {
+                        icon: GLOSSARY_ICON,
+                        onclick: move |_| update_settings(settings, |settings| settings.show_glossary = !settings.show_glossary),
+                        class: if show_glossary() { "active" } else { "" },
+                    }
+                    ActionIcon {
+                        icon: NOTES_ICON,
+                        onclick: move |_| update_settings(settings, |settings| settings.show_notes = !settings.show_notes),
+                        class: if show_notes() { "active" } else { "" },
+                    }
+                    ActionIcon {
+                        icon: SPLIT_ICON,
+                        onclick: move |_| update_settings(settings, |settings| settings.show_preview = !settings.show_preview),
+                        class: if show_preview() { "active" } else { "" },
+                    }
+                    ActionIcon {
+                        icon: HISTORY_ICON,
+                        onclick: move |_| history_open.set(true),
+                    }
+                    ActionIcon {
+                        icon: EXPORT_ICON,
+                        onclick: handle_export,
+                    }
+                    ActionIcon {
+                        icon: FOCUS_ICON,
+                        onclick: move |_| toggle_focus(),
+                    }
+                    ActionIcon {
+                        icon: PREVIEW_ICON,
+                        onclick: handle_preview,
+                    }
+                }
+            }
+
+            if search_open() {
+                SearchBar {
+                    file,
+                    highlights: search_highlights,
+                    on_close: move |_| {
+                        search_open.set(false);
+                        document::eval(r#"document.querySelector(".simple_editor_textarea")?.focus()"#);
+                    },
                 }
             }
 
             main {
-                class: "editor_content",
+                class: if show_preview() && !focus_mode() { "editor_content split" } else { "editor_content" },
+                if show_outline() && !focus_mode() {
+                    OutlinePanel { file }
+                }
                 div {
                     class: "text_area_container",
                     div {
                         class: "simple_editor_container",
+                        if search_open() && !search_highlights.read().matches.is_empty() {
+                            SearchBackdrop {
+                                content: file.content,
+                                highlights: search_highlights,
+                                style: textarea_style.clone(),
+                            }
+                        }
+                        if !unencodable.read().is_empty() {
+                            EncodingBackdrop {
+                                content: file.content,
+                                warnings: unencodable,
+                                style: textarea_style.clone(),
+                            }
+                        }
                         textarea {
                             class: "simple_editor_textarea",
+                            style: "{textarea_style}",
                             value: "{file.content}",
                             oninput: move |evt| handle_change(evt.value()),
-                            onkeydown: handle_keydown
+                            onkeydown: handle_keydown,
+                            onclick: move |_| completion.set(None),
                         }
                     }
+                    CompletionBar { file, completion }
+                    if !unencodable.read().is_empty() {
+                        EncodingNotice { file, warnings: unencodable, caret }
+                    }
+                }
+                if show_glossary() && !focus_mode() {
+                    GlossaryPanel {
+                        file,
+                        glossary,
+                        on_change: move |_| {
+                            if let Err(e) = glossary.peek().save(&series_title) {
+                                println!("Error saving glossary: {}", e);
+                            }
+                        },
+                    }
+                }
+                if show_notes() && !focus_mode() {
+                    NotesPanel { file }
+                }
+                if show_preview() && !focus_mode() {
+                    PreviewPane { content: file.content }
+                }
+            }
+
+            if !focus_mode() {
+                StatusBar { content: file.content }
+            }
+
+            if history_open() {
+                HistoryDialog {
+                    file,
+                    on_close: move |_| history_open.set(false),
+                }
+            }
+
+            if tatechuyoko_open() {
+                TatechuyokoDialog {
+                    file,
+                    on_close: move |_| tatechuyoko_open.set(false),
+                }
+            }
+
+            if let Some(target) = ruby_target() {
+                RubyDialog {
+                    file,
+                    target,
+                    on_close: move |_| {
+                        ruby_target.set(None);
+                        document::eval(r#"document.querySelector(".simple_editor_textarea")?.focus()"#);
+                    },
                 }
             }
         }
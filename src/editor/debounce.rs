@@ -0,0 +1,37 @@
+use dioxus::prelude::*;
+use std::time::Duration;
+
+/// Runs a closure once calls pause, each call cancelling the one still waiting.
+#[derive(Clone, Copy)]
+pub struct Debounce {
+    pending: Signal<Option<Task>>,
+}
+
+impl Debounce {
+    /// Run `f` after `delay` unless another call comes first.
+    pub fn call(&mut self, delay: Duration, f: impl FnOnce() + 'static) {
+        self.cancel();
+        let mut pending = self.pending;
+        let task = spawn(async move {
+            tokio::time::sleep(delay).await;
+            pending.set(None);
+            f();
+        });
+        self.pending.set(Some(task));
+    }
+
+    /// Drop the waiting call, returning whether there was one.
+    pub fn cancel(&mut self) -> bool {
+        match self.pending.write().take() {
+            Some(task) => {
+                task.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+pub fn use_debounce() -> Debounce {
+    Debounce { pending: use_signal(|| None) }
+}
@@ -0,0 +1,141 @@
+use super::UseEditorFile;
+use aozora_parser::{LintWarning, LintWarningKind};
+use dioxus::prelude::*;
+
+/// Keeps the backdrops scrolled with the textarea in front of them.
+pub(super) const SYNC_SCROLL_SCRIPT: &str = r#"
+window.kartanaBackdrop?.abort();
+const controller = new AbortController();
+window.kartanaBackdrop = controller;
+const sync = () => {
+    const textarea = document.querySelector(".simple_editor_textarea");
+    if (!textarea) return;
+    for (const backdrop of document.querySelectorAll(".encoding_backdrop, .search_backdrop")) {
+        backdrop.scrollTop = textarea.scrollTop;
+        backdrop.scrollLeft = textarea.scrollLeft;
+    }
+};
+document.addEventListener("scroll", sync, { capture: true, signal: controller.signal });
+document.addEventListener("input", sync, { capture: true, signal: controller.signal });
+sync();
+"#;
+
+/// Text split at the characters Shift_JIS cannot represent; `true` marks those.
+fn segments(text: &str, warnings: &[LintWarning]) -> Vec<(String, bool)> {
+    let mut segments = Vec::new();
+    let mut chars = text.chars();
+    let mut pos = 0;
+    for warning in warnings {
+        let plain: String = chars.by_ref().take(warning.span.start - pos).collect();
+        let marked: String = chars.by_ref().take(warning.span.end - warning.span.start).collect();
+        segments.push((plain, false));
+        segments.push((marked, true));
+        pos = warning.span.end;
+    }
+    segments.push((chars.collect(), false));
+    segments
+}
+
+/// Index in chars of a caret the textarea reports in UTF-16 code units.
+fn char_index(text: &str, utf16: usize) -> usize {
+    let mut units = 0;
+    text.chars()
+        .take_while(|c| {
+            units += c.len_utf16();
+            units <= utf16
+        })
+        .count()
+}
+
+// --- Component: EncodingBackdrop ---
+
+/// Copy of the text laid out behind the textarea, with the characters that would be lost on
+/// save underlined. It is only there while such characters are.
+#[component]
+pub fn EncodingBackdrop(content: Signal<String>, warnings: Memo<Vec<LintWarning>>, style: String) -> Element {
+    use_effect(move || {
+        warnings.read();
+        document::eval(SYNC_SCROLL_SCRIPT);
+    });
+
+    let segments = segments(&content.read(), &warnings.read());
+
+    rsx! {
+        div {
+            class: "encoding_backdrop",
+            style: "{style}",
+            for (text, marked) in segments {
+                if marked {
+                    mark { "{text}" }
+                } else {
+                    "{text}"
+                }
+            }
+            // A trailing line break would otherwise take no height
+            " "
+        }
+    }
+}
+
+// --- Component: EncodingNotice ---
+
+/// Lists the characters that would be lost on save. The underlines cannot be hovered behind
+/// the textarea, so the one at the caret, on either side of it, gets its suggestion here.
+#[component]
+pub fn EncodingNotice(file: UseEditorFile, warnings: Memo<Vec<LintWarning>>, caret: Memo<Option<usize>>) -> Element {
+    let suggestions: Vec<String> = warnings
+        .read()
+        .iter()
+        .filter_map(|warning| warning.fix.as_ref())
+        .map(|fix| fix.replacement.clone())
+        .collect();
+    let mut characters = String::new();
+    for warning in warnings.read().iter() {
+        if let LintWarningKind::UnencodableCharacter(c) = warning.kind
+            && !characters.contains(c)
+        {
+            characters.push(c);
+        }
+    }
+    let details = warnings
+        .read()
+        .iter()
+        .map(|warning| warning.message.clone())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let pointed = caret().and_then(|caret| {
+        let at = char_index(&file.content.read(), caret);
+        warnings
+            .read()
+            .iter()
+            .find(|warning| warning.span.start <= at && at <= warning.span.end)
+            .cloned()
+    });
+
+    rsx! {
+        div {
+            class: "encoding_notice",
+            title: "{details}",
+            if let Some(warning) = pointed {
+                span { "{warning.message}" }
+                button {
+                    onclick: move |_| {
+                        let text = file.content.peek().clone();
+                        file.edit(aozora_parser::apply_fixes(&text, std::slice::from_ref(&warning)));
+                    },
+                    "この文字を置き換える"
+                }
+            } else {
+                span { "Shift_JISで保存できない文字があります: {characters}" }
+            }
+            button {
+                title: "{suggestions.join(\" \")}",
+                onclick: move |_| {
+                    let text = file.content.peek().clone();
+                    file.edit(aozora_parser::apply_fixes(&text, &warnings.peek()));
+                },
+                "外字注記に置き換える"
+            }
+        }
+    }
+}
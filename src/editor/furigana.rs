@@ -0,0 +1,86 @@
+//! Readings suggested for new ruby. The dictionary is bundled with the `furigana` feature;
+//! without it there are no suggestions.
+
+#[cfg(feature = "furigana")]
+use std::{collections::HashMap, sync::LazyLock};
+
+#[cfg(feature = "furigana")]
+struct Dictionary {
+    /// On and kun reading of each kanji, either possibly empty
+    kanji: HashMap<char, (&'static str, &'static str)>,
+    /// Words read otherwise than kanji by kanji
+    words: HashMap<&'static str, &'static str>,
+    /// Characters of the longest word
+    longest: usize,
+}
+
+#[cfg(feature = "furigana")]
+static DICTIONARY: LazyLock<Dictionary> = LazyLock::new(|| {
+    let mut dictionary = Dictionary { kanji: HashMap::new(), words: HashMap::new(), longest: 0 };
+    for line in include_str!("furigana.tsv").lines().filter(|line| !line.starts_with('#')) {
+        let mut columns = line.split('\t');
+        let (Some(key), Some(reading)) = (columns.next(), columns.next()) else {
+            continue;
+        };
+        let mut chars = key.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => {
+                dictionary.kanji.insert(c, (reading, columns.next().unwrap_or_default()));
+            }
+            _ => {
+                dictionary.longest = dictionary.longest.max(key.chars().count());
+                dictionary.words.insert(key, reading);
+            }
+        }
+    }
+    dictionary
+});
+
+#[cfg(feature = "furigana")]
+fn is_kanji(c: char) -> bool {
+    matches!(c, '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' | '々')
+}
+
+/// The most likely reading of `base`, or `None` when part of it is not in the dictionary.
+/// Words not listed are read kanji by kanji: on readings within a compound, kun readings
+/// on their own, so rendaku and irregular readings are only right when listed.
+#[cfg(feature = "furigana")]
+pub fn suggest(base: &str) -> Option<String> {
+    let dictionary = &*DICTIONARY;
+    let chars: Vec<char> = base.chars().collect();
+    let mut reading = String::new();
+    // Reading of the kanji before, repeated by 々
+    let mut last = "";
+    let mut i = 0;
+    while i < chars.len() {
+        let word = (2..=dictionary.longest.min(chars.len() - i)).rev().find_map(|len| {
+            let word: String = chars[i..i + len].iter().collect();
+            dictionary.words.get(word.as_str()).map(|reading| (len, *reading))
+        });
+        if let Some((len, word)) = word {
+            reading.push_str(word);
+            last = "";
+            i += len;
+            continue;
+        }
+        let c = chars[i];
+        if matches!(c, 'ぁ'..='ゖ' | 'ァ'..='ヺ' | 'ー') {
+            reading.push(c);
+        } else if c == '々' && !last.is_empty() {
+            reading.push_str(last);
+        } else {
+            let &(on, kun) = dictionary.kanji.get(&c)?;
+            let in_compound = (i > 0 && is_kanji(chars[i - 1])) || chars.get(i + 1).is_some_and(|&c| is_kanji(c));
+            let preferred = if in_compound { [on, kun] } else { [kun, on] };
+            last = preferred.into_iter().find(|r| !r.is_empty())?;
+            reading.push_str(last);
+        }
+        i += 1;
+    }
+    (reading != base).then_some(reading)
+}
+
+#[cfg(not(feature = "furigana"))]
+pub fn suggest(_base: &str) -> Option<String> {
+    None
+}
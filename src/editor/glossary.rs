@@ -0,0 +1,258 @@
+use super::ruby::notation;
+use super::UseEditorFile;
+use crate::toml_file;
+use crate::top_page::works::Series;
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{ops::Range, path::PathBuf};
+
+const GLOSSARY_FILE: &str = "glossary.toml";
+/// Characters typed before the caret that are looked at for a completion
+const COMPLETION_WINDOW: usize = 16;
+/// Fewer characters than this would bring up completions on every particle
+const COMPLETION_MIN: usize = 2;
+
+/// A character name or term of a series.
+#[derive(PartialEq, Eq, Clone, Default, Deserialize, Serialize)]
+pub struct Term {
+    pub name: String,
+    /// Reading given as ruby; empty for terms read as written
+    #[serde(default)]
+    pub reading: String,
+    #[serde(default)]
+    pub note: String,
+}
+
+/// Names and terms of a series, kept in `glossary.toml` next to its chapters.
+#[derive(PartialEq, Eq, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Glossary {
+    pub terms: Vec<Term>,
+}
+
+/// Terms that the text just typed could be the start of.
+#[derive(Clone, PartialEq)]
+pub struct Completion {
+    /// Bytes of the typed text, replaced by the term
+    pub range: Range<usize>,
+    pub typed: String,
+    pub terms: Vec<Term>,
+}
+
+impl Glossary {
+    fn path(series_title: &str) -> PathBuf {
+        Series::series_dir(series_title).join(GLOSSARY_FILE)
+    }
+    pub fn load(series_title: &str) -> Self {
+        toml_file::load(Self::path(series_title)).unwrap_or_default()
+    }
+    pub fn save(&self, series_title: &str) -> Result<(), Box<dyn std::error::Error>> {
+        toml_file::save(Self::path(series_title), self)
+    }
+
+    /// Terms whose name or reading starts with the text before `caret`, the longest typed
+    /// text that matches any being taken.
+    pub fn complete(&self, text: &str, caret: usize) -> Option<Completion> {
+        let line = &text[text[..caret].rfind('\n').map_or(0, |i| i + 1)..caret];
+        // Inside a reading or an annotation, the text is not the story's
+        if line.rfind(['《', '［']) > line.rfind(['》', '］']) {
+            return None;
+        }
+        let starts: Vec<usize> = line.char_indices().map(|(i, _)| i).collect();
+        let window = &starts[starts.len().saturating_sub(COMPLETION_WINDOW)..];
+        window
+            .iter()
+            .take(window.len().saturating_sub(COMPLETION_MIN - 1))
+            .find_map(|&start| {
+                let typed = &line[start..];
+                let terms: Vec<Term> = self
+                    .terms
+                    .iter()
+                    .filter(|term| term.name.starts_with(typed) || (!term.reading.is_empty() && term.reading.starts_with(typed)))
+                    .cloned()
+                    .collect();
+                (!terms.is_empty()).then(|| Completion {
+                    range: caret - typed.len()..caret,
+                    typed: typed.to_string(),
+                    terms,
+                })
+            })
+    }
+}
+
+/// Replace `range` of the text with `term`. The reading is given at the first appearance of
+/// the term in the chapter, as ruby usually is.
+pub fn insert(mut file: UseEditorFile, range: Range<usize>, term: &Term) {
+    let text = file.content.peek().clone();
+    let before = &text[..range.start];
+    let inserted = if term.reading.is_empty() || before.contains(&term.name) {
+        term.name.clone()
+    } else {
+        notation(before, &term.name, &term.reading)
+    };
+    let end = range.start + inserted.len();
+    file.edit(format!("{}{}{}", before, inserted, &text[range.end..]));
+    file.focus_at(end..end);
+}
+
+/// Insert the term at `index` of `completion`, unless the text has changed under it.
+pub fn accept(file: UseEditorFile, completion: &Completion, index: usize) {
+    let text = file.content.peek().clone();
+    if text.get(completion.range.clone()) == Some(completion.typed.as_str()) {
+        insert(file, completion.range.clone(), &completion.terms[index]);
+    }
+}
+
+// --- Component: CompletionBar ---
+
+#[component]
+pub fn CompletionBar(file: UseEditorFile, completion: Signal<Option<Completion>>) -> Element {
+    let Some(current) = completion() else {
+        return rsx! {};
+    };
+
+    rsx! {
+        div {
+            class: "completion_bar",
+            span { class: "completion_hint", "Tab" }
+            for (i, term) in current.terms.iter().enumerate() {
+                span {
+                    class: "completion_term",
+                    onclick: {
+                        let current = current.clone();
+                        move |_| {
+                            completion.set(None);
+                            accept(file, &current, i);
+                        }
+                    },
+                    "{term.name}"
+                    if !term.reading.is_empty() {
+                        small { "（{term.reading}）" }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// --- Component: GlossaryPanel ---
+
+#[component]
+pub fn GlossaryPanel(file: UseEditorFile, glossary: Signal<Glossary>, on_change: EventHandler<()>) -> Element {
+    let mut name = use_signal(String::new);
+    let mut reading = use_signal(String::new);
+    let mut note = use_signal(String::new);
+    // Index of the term in the form, or `None` while adding one
+    let mut editing = use_signal(|| None::<usize>);
+
+    let mut clear = move || {
+        name.set(String::new());
+        reading.set(String::new());
+        note.set(String::new());
+        editing.set(None);
+    };
+    let mut submit = move || {
+        let term = Term {
+            name: name().trim().to_string(),
+            reading: reading().trim().to_string(),
+            note: note().trim().to_string(),
+        };
+        if term.name.is_empty() {
+            return;
+        }
+        match editing() {
+            Some(index) => glossary.write().terms[index] = term,
+            None => glossary.write().terms.push(term),
+        }
+        on_change.call(());
+        clear();
+    };
+
+    rsx! {
+        aside {
+            class: "glossary_panel",
+            if glossary.read().terms.is_empty() {
+                p { class: "outline_empty", "登場人物や用語を登録すると、入力中に候補として表示されます" }
+            }
+            for (i, term) in glossary().terms.into_iter().enumerate() {
+                div {
+                    class: "glossary_term",
+                    title: "{term.note}",
+                    span {
+                        class: "glossary_name",
+                        onclick: {
+                            let term = term.clone();
+                            move |_| {
+                                let term = term.clone();
+                                spawn(async move {
+                                    if let Some(selection) = file.selection().await {
+                                        insert(file, selection, &term);
+                                    }
+                                });
+                            }
+                        },
+                        "{term.name}"
+                        if !term.reading.is_empty() {
+                            small { "（{term.reading}）" }
+                        }
+                    }
+                    button {
+                        title: "編集",
+                        onclick: {
+                            let term = term.clone();
+                            move |_| {
+                                name.set(term.name.clone());
+                                reading.set(term.reading.clone());
+                                note.set(term.note.clone());
+                                editing.set(Some(i));
+                            }
+                        },
+                        "✎"
+                    }
+                    button {
+                        title: "削除",
+                        onclick: move |_| {
+                            glossary.write().terms.remove(i);
+                            on_change.call(());
+                            clear();
+                        },
+                        "×"
+                    }
+                }
+            }
+            div {
+                class: "glossary_form",
+                input {
+                    value: "{name}",
+                    placeholder: "名前",
+                    oninput: move |evt| name.set(evt.value()),
+                }
+                input {
+                    value: "{reading}",
+                    placeholder: "読み",
+                    oninput: move |evt| reading.set(evt.value()),
+                }
+                input {
+                    value: "{note}",
+                    placeholder: "メモ",
+                    oninput: move |evt| note.set(evt.value()),
+                    onkeydown: move |evt: KeyboardEvent| {
+                        if evt.key() == Key::Enter {
+                            submit();
+                        }
+                    },
+                }
+                div {
+                    class: "form_actions",
+                    button {
+                        onclick: move |_| submit(),
+                        if editing().is_some() { "更新" } else { "追加" }
+                    }
+                    if editing().is_some() {
+                        button { onclick: move |_| clear(), "キャンセル" }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::time::{Duration, Instant};
+
+/// Edits within this time of the first edit of a step are undone with it, so that undo
+/// does not go back a single character at a time while typing
+const COALESCE_WINDOW: Duration = Duration::from_secs(1);
+/// Number of steps kept; the oldest are forgotten first
+const MAX_STEPS: usize = 200;
+
+/// The text at one point of the history, with where the caret goes when it is restored.
+#[derive(Clone, PartialEq)]
+pub struct Snapshot {
+    pub text: String,
+    /// Caret position in UTF-16 code units, as the textarea counts them
+    pub cursor: usize,
+}
+
+impl Snapshot {
+    /// `text` with the caret placed right after the part that differs from `other`.
+    fn new(text: String, other: &str) -> Self {
+        let prefix = text
+            .chars()
+            .zip(other.chars())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let rest: Vec<char> = text.chars().skip(prefix).collect();
+        let other_rest: Vec<char> = other.chars().skip(prefix).collect();
+        let suffix = rest
+            .iter()
+            .rev()
+            .zip(other_rest.iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let cursor = text.chars().take(prefix).map(char::len_utf16).sum::<usize>()
+            + rest[..rest.len() - suffix].iter().map(|c| c.len_utf16()).sum::<usize>();
+        Self { text, cursor }
+    }
+}
+
+/// Undo and redo stacks of the editor.
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    /// When the first edit of the last step was made
+    step_start: Option<Instant>,
+}
+
+impl History {
+    /// Remember `before` as the state to return to when the edit to `after` is undone.
+    pub fn record(&mut self, before: String, after: &str) {
+        self.record_at(before, after, Instant::now());
+    }
+
+    fn record_at(&mut self, before: String, after: &str, now: Instant) {
+        if before == after {
+            return;
+        }
+        let coalesce = self
+            .step_start
+            .is_some_and(|start| now.duration_since(start) < COALESCE_WINDOW)
+            && !self.undo.is_empty();
+        self.redo.clear();
+        if coalesce {
+            return;
+        }
+        self.step_start = Some(now);
+        self.undo.push(Snapshot::new(before, after));
+        if self.undo.len() > MAX_STEPS {
+            self.undo.remove(0);
+        }
+    }
+
+    /// The state before the last edit; `current` becomes redoable.
+    pub fn undo(&mut self, current: String) -> Option<Snapshot> {
+        let snapshot = self.undo.pop()?;
+        self.redo.push(Snapshot::new(current, &snapshot.text));
+        // The next edit starts a new step instead of merging into the undone one
+        self.step_start = None;
+        Some(snapshot)
+    }
+
+    /// The state undone last; `current` becomes undoable again.
+    pub fn redo(&mut self, current: String) -> Option<Snapshot> {
+        let snapshot = self.redo.pop()?;
+        self.undo.push(Snapshot::new(current, &snapshot.text));
+        self.step_start = None;
+        Some(snapshot)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_cursor() {
+        // After what was inserted, or where something was removed
+        assert_eq!(Snapshot::new("abXc".to_string(), "abc").cursor, 3);
+        assert_eq!(Snapshot::new("ab".to_string(), "abc").cursor, 2);
+        assert_eq!(Snapshot::new("aYc".to_string(), "abc").cursor, 2);
+        // Counted in UTF-16 code units
+        assert_eq!(Snapshot::new("a𠮷b".to_string(), "ab").cursor, 3);
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut history = History::default();
+        history.record_at("".to_string(), "a", at(0));
+        history.record_at("a".to_string(), "ab", at(600));
+        // Within a second of the previous edit, but not of the first edit of the step
+        history.record_at("ab".to_string(), "abc", at(1200));
+        history.record_at("abc".to_string(), "abcd", at(1800));
+
+        assert_eq!(history.undo("abcd".to_string()).unwrap().text, "ab");
+        assert_eq!(history.undo("ab".to_string()).unwrap().text, "");
+        assert!(!history.can_undo());
+        assert_eq!(history.redo("".to_string()).unwrap().text, "ab");
+    }
+
+    #[test]
+    fn test_max_steps() {
+        let start = Instant::now();
+        let mut history = History::default();
+        for i in 0..MAX_STEPS + 50 {
+            let at = start + COALESCE_WINDOW * i as u32;
+            history.record_at(i.to_string(), &(i + 1).to_string(), at);
+        }
+        assert_eq!(history.undo.len(), MAX_STEPS);
+        // The oldest steps are forgotten
+        assert_eq!(history.undo[0].text, "50");
+    }
+}
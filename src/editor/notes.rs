@@ -0,0 +1,82 @@
+use super::debounce::use_debounce;
+use super::UseEditorFile;
+use dioxus::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Pause in typing before the notes are written
+const NOTES_DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// Notes of the chapter at `file` are kept in `.notes/` next to it, as UTF-8. They are
+/// never part of an export, which only reads the chapter files.
+fn notes_path(file: &Path) -> PathBuf {
+    file.with_file_name(".notes").join(file.file_name().unwrap_or_default())
+}
+
+pub fn load(file: &Path) -> String {
+    fs::read_to_string(notes_path(file)).unwrap_or_default()
+}
+
+/// Write the notes of the chapter at `file`; empty notes leave no file behind.
+pub fn save(file: &Path, text: &str) -> std::io::Result<()> {
+    let path = notes_path(file);
+    if text.is_empty() {
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, text)
+}
+
+/// Move the notes along with a renamed chapter.
+pub fn rename(old_file: &Path, new_file: &Path) -> std::io::Result<()> {
+    let old_path = notes_path(old_file);
+    if old_path.exists() {
+        let new_path = notes_path(new_file);
+        if let Some(dir) = new_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::rename(old_path, new_path)?;
+    }
+    Ok(())
+}
+
+// --- Component: NotesPanel ---
+
+#[component]
+pub fn NotesPanel(file: UseEditorFile) -> Element {
+    let mut text = use_signal(|| load(&file.file_path.peek()));
+
+    // Written once typing pauses, and when the panel goes away
+    let mut debounce = use_debounce();
+    let write = move || {
+        if let Err(e) = save(&file.file_path.peek(), &text.peek()) {
+            println!("Error saving notes: {}", e);
+        }
+    };
+    use_drop(move || {
+        if debounce.cancel() {
+            write();
+        }
+    });
+
+    rsx! {
+        aside {
+            class: "notes_panel",
+            textarea {
+                class: "notes_textarea",
+                placeholder: "この章のメモ（書き出しには含まれません）",
+                value: "{text}",
+                oninput: move |evt| {
+                    text.set(evt.value());
+                    debounce.call(NOTES_DEBOUNCE, write);
+                },
+            }
+        }
+    }
+}
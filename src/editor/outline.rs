@@ -0,0 +1,88 @@
+use super::debounce::use_debounce;
+use super::UseEditorFile;
+use aozora_parser::tokenizer::command::{Command, Midashi, MidashiSize, SingleCommand};
+use aozora_parser::ParsedItem;
+use dioxus::prelude::*;
+use std::time::Duration;
+
+/// Pause in typing before the outline is built again
+const OUTLINE_DEBOUNCE: Duration = Duration::from_millis(400);
+
+/// A heading, with the character its annotation starts at.
+#[derive(Clone, PartialEq)]
+struct Heading {
+    indent: usize,
+    text: String,
+    start: usize,
+}
+
+/// 大見出し is flush, smaller headings are indented under it
+fn indent(midashi: &Midashi) -> usize {
+    match midashi.size {
+        MidashiSize::Large => 0,
+        MidashiSize::Middle => 1,
+        MidashiSize::Small => 2,
+    }
+}
+
+/// Headings of the text, or nothing when it cannot be parsed.
+fn headings(text: &str) -> Vec<Heading> {
+    let Some(root) = aozora_parser::parse_aozora(text.to_string())
+        .ok()
+        .and_then(|tokens| aozora_parser::parse_blocks(aozora_parser::parse(tokens).items).ok())
+    else {
+        return Vec::new();
+    };
+    let mut headings: Vec<Heading> = root
+        .headings()
+        .map(|(midashi, block)| Heading { indent: indent(midashi), text: block.text(), start: block.span.start })
+        .collect();
+    // ［＃「…」は大見出し］ is a single command rather than a block
+    headings.extend(root.iter_items().filter_map(|item| match item {
+        ParsedItem::Command { cmd: Command::SingleCommand(SingleCommand::Midashi((midashi, content))), span } => {
+            Some(Heading { indent: indent(midashi), text: content.clone(), start: span.start })
+        }
+        _ => None,
+    }));
+    headings.sort_by_key(|heading| heading.start);
+    headings
+}
+
+// --- Component: OutlinePanel ---
+
+#[component]
+pub fn OutlinePanel(file: UseEditorFile) -> Element {
+    let mut entries = use_signal(|| headings(&file.content.peek()));
+
+    // Parsed once typing pauses instead of on every keystroke
+    let mut debounce = use_debounce();
+    use_effect(move || {
+        let text = file.content.read().clone();
+        debounce.call(OUTLINE_DEBOUNCE, move || entries.set(headings(&text)));
+    });
+
+    rsx! {
+        nav {
+            class: "outline_panel",
+            if entries.read().is_empty() {
+                p { class: "outline_empty", "見出しはありません" }
+            }
+            for entry in entries() {
+                div {
+                    class: "outline_entry",
+                    style: "padding-left: {entry.indent}em;",
+                    onclick: move |_| {
+                        // The span counts characters
+                        let text = file.content.peek().clone();
+                        let start = text
+                            .char_indices()
+                            .nth(entry.start)
+                            .map_or(text.len(), |(i, _)| i);
+                        file.focus_at(start..start);
+                    },
+                    "{entry.text}"
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use super::debounce::use_debounce;
+use crate::reader_page::reader_styles;
+use crate::settings::{use_settings, PreviewDirection};
+use aozora_parser::{WritingMode, XhtmlGenerator, XhtmlGeneratorOptions};
+use dioxus::prelude::*;
+use std::time::Duration;
+
+/// Pause in typing before the preview is rendered again
+const PREVIEW_DEBOUNCE: Duration = Duration::from_millis(400);
+
+/// Keeps the scroll position across re-renders: the iframe is reloaded with every update,
+/// but its window name survives the reload.
+const KEEP_SCROLL_SCRIPT: &str = "<script>
+addEventListener('load', () => {
+  const root = document.scrollingElement;
+  const [left, top] = window.name.split(',').map(Number);
+  root.scrollLeft = left || 0;
+  root.scrollTop = top || 0;
+  addEventListener('scroll', () => { window.name = root.scrollLeft + ',' + root.scrollTop; });
+});
+</script>";
+
+/// Inside the preview: scrolls to the paragraph at a position of the text sent by the editor,
+/// and reports the paragraph at the top of the view when the reader scrolls. Positions are in
+/// characters, as in the `data-span` of the generated elements.
+const FOLLOW_SOURCE_SCRIPT: &str = "<script>
+let byReader = false;
+for (const type of ['wheel', 'keydown', 'pointerdown', 'touchstart']) {
+  addEventListener(type, () => { byReader = true; }, { passive: true });
+}
+const spans = () => [...document.querySelectorAll('[data-span]')]
+  .map((el) => [el, Number(el.dataset.span.split('-')[0])]);
+addEventListener('message', (e) => {
+  const caret = e.data?.kartanaCaret;
+  if (caret === undefined) return;
+  const found = spans().filter(([, start]) => start <= caret).pop();
+  if (!found) return;
+  byReader = false;
+  found[0].scrollIntoView({ block: 'nearest', inline: 'nearest' });
+});
+let pending = false;
+addEventListener('scroll', () => {
+  if (!byReader || pending) return;
+  pending = true;
+  requestAnimationFrame(() => {
+    pending = false;
+    const visible = spans().find(([el]) => {
+      const rect = el.getBoundingClientRect();
+      return rect.bottom > 0 && rect.top < innerHeight && rect.right > 0 && rect.left < innerWidth;
+    });
+    if (visible) parent.postMessage({ kartanaPreview: visible[1] }, '*');
+  });
+});
+</script>";
+
+/// In the editor: sends the caret to the preview when it moves, and scrolls the text to the
+/// paragraph the preview reports, measuring its line on a copy of the textarea.
+const FOLLOW_PREVIEW_SCRIPT: &str = r#"
+window.kartanaPreviewSync?.abort();
+const controller = new AbortController();
+window.kartanaPreviewSync = controller;
+const signal = controller.signal;
+const textarea = () => document.querySelector(".simple_editor_textarea");
+const frame = () => document.querySelector(".preview_pane .reader_iframe");
+const sendCaret = (e) => {
+    const t = textarea();
+    const f = frame();
+    if (!t || !f || (e.target !== t && e.target !== f)) return;
+    const caret = Array.from(t.value.slice(0, t.selectionStart)).length;
+    f.contentWindow?.postMessage({ kartanaCaret: caret }, "*");
+};
+for (const type of ["keyup", "click", "load"]) {
+    document.addEventListener(type, sendCaret, { capture: true, signal });
+}
+const lineTop = (t, offset) => {
+    const style = getComputedStyle(t);
+    const mirror = document.createElement("div");
+    for (const p of ["fontFamily", "fontSize", "fontWeight", "lineHeight", "letterSpacing", "paddingTop", "paddingLeft", "paddingRight", "tabSize"]) {
+        mirror.style[p] = style[p];
+    }
+    Object.assign(mirror.style, {
+        position: "absolute",
+        visibility: "hidden",
+        boxSizing: "border-box",
+        width: t.clientWidth + "px",
+        whiteSpace: "pre-wrap",
+        overflowWrap: "break-word",
+    });
+    mirror.textContent = t.value.slice(0, offset);
+    const marker = document.createElement("span");
+    marker.textContent = "\u200b";
+    mirror.append(marker);
+    document.body.append(mirror);
+    const top = marker.offsetTop - parseFloat(style.paddingTop);
+    mirror.remove();
+    return top;
+};
+addEventListener("message", (e) => {
+    const start = e.data?.kartanaPreview;
+    const t = textarea();
+    if (start === undefined || !t) return;
+    t.scrollTop = lineTop(t, Array.from(t.value).slice(0, start).join("").length);
+}, { signal });
+"#;
+
+fn to_xhtml(text: String, direction: PreviewDirection) -> Result<String, aozora_parser::ConversionError> {
+    let writing_mode = match direction {
+        PreviewDirection::Vertical => WritingMode::VerticalRl,
+        PreviewDirection::Horizontal => WritingMode::HorizontalTb,
+    };
+    let doc = aozora_parser::parse(aozora_parser::parse_aozora(text)?);
+    let blocks = aozora_parser::parse_blocks(doc.items)?;
+    let options = XhtmlGeneratorOptions { writing_mode, source_spans: true, ..Default::default() };
+    Ok(XhtmlGenerator::generate_with(&blocks, &doc.metadata.title, &options).0)
+}
+
+/// The text as the reader would show it, or why it could not be converted.
+fn render(text: String, direction: PreviewDirection) -> String {
+    match to_xhtml(text, direction) {
+        Ok(xhtml) => xhtml.replacen("</head>", &format!("{}{}{}</head>", reader_styles(), KEEP_SCROLL_SCRIPT, FOLLOW_SOURCE_SCRIPT), 1),
+        Err(e) => format!(
+            "<!DOCTYPE html><html lang=\"ja\"><body><p>{}</p></body></html>",
+            e.to_string().replace('&', "&amp;").replace('<', "&lt;")
+        ),
+    }
+}
+
+// --- Component: PreviewPane ---
+
+#[component]
+pub fn PreviewPane(content: Signal<String>) -> Element {
+    let settings = use_settings();
+    let mut document = use_signal(|| render(content.peek().clone(), settings.peek().preview_direction));
+
+    // Render once typing pauses instead of on every keystroke
+    let mut debounce = use_debounce();
+    use_effect(move || {
+        let text = content();
+        let direction = settings.read().preview_direction;
+        debounce.call(PREVIEW_DEBOUNCE, move || document.set(render(text, direction)));
+    });
+
+    // The preview and the text follow each other while the pane is open
+    use_hook(|| {
+        document::eval(FOLLOW_PREVIEW_SCRIPT);
+    });
+    use_drop(|| {
+        document::eval("window.kartanaPreviewSync?.abort();");
+    });
+
+    rsx! {
+        div {
+            class: "preview_pane",
+            iframe {
+                class: "reader_iframe",
+                srcdoc: "{document}",
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use super::{furigana, UseEditorFile};
+use aozora_parser::tokenizer::{AozoraToken, TextKind};
+use dioxus::prelude::*;
+use std::ops::Range;
+
+/// The ruby the dialog edits: either one already in the text, or a new one on the selection.
+#[derive(Clone, PartialEq)]
+pub struct RubyTarget {
+    /// Bytes of the content replaced by the new notation
+    pub range: Range<usize>,
+    pub base: String,
+    pub reading: String,
+    /// Whether the reading was guessed from the dictionary rather than taken from the text
+    pub suggested: bool,
+}
+
+impl RubyTarget {
+    /// The ruby around `selection`, or a new ruby on the selected text with a suggested reading.
+    pub fn at(text: &str, selection: Range<usize>) -> Self {
+        let line_start = text[..selection.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = text[selection.end..]
+            .find('\n')
+            .map_or(text.len(), |i| selection.end + i);
+        rubies(&text[line_start..line_end])
+            .into_iter()
+            .map(|ruby| RubyTarget {
+                range: line_start + ruby.range.start..line_start + ruby.range.end,
+                ..ruby
+            })
+            .find(|ruby| ruby.range.start <= selection.start && selection.end <= ruby.range.end)
+            .unwrap_or_else(|| {
+                let base = text[selection.clone()].to_string();
+                let suggestion = furigana::suggest(&base);
+                RubyTarget {
+                    suggested: suggestion.is_some(),
+                    reading: suggestion.unwrap_or_default(),
+                    base,
+                    range: selection,
+                }
+            })
+    }
+}
+
+/// Rubies written in `line`, with their notation as ranges.
+fn rubies(line: &str) -> Vec<RubyTarget> {
+    // An unclosed annotation makes the line untokenizable; there is nothing to edit then
+    let Ok(tokens) = aozora_parser::parse_aozora(line.to_string()) else {
+        return Vec::new();
+    };
+    // Token spans count characters
+    let byte = |chars: usize| line.char_indices().nth(chars).map_or(line.len(), |(i, _)| i);
+    let mut rubies = Vec::new();
+    let mut separator = None;
+    let mut previous: Option<&AozoraToken> = None;
+    for token in &tokens {
+        if let AozoraToken::RubySeparator(span) = token {
+            separator = Some(*span);
+        } else if let AozoraToken::Ruby { content, span } = token {
+            // ｜ marks where the base starts; without it the base is the kanji just before
+            let (start, base_start) = match (separator.take(), previous) {
+                (Some(sep), _) => (sep.start, sep.end),
+                (None, Some(AozoraToken::Text(text))) if text.kind == TextKind::Kanji => {
+                    (text.span.start, text.span.start)
+                }
+                _ => (span.start, span.start),
+            };
+            rubies.push(RubyTarget {
+                range: byte(start)..byte(span.end),
+                base: line[byte(base_start)..byte(span.start)].to_string(),
+                reading: content.clone(),
+                suggested: false,
+            });
+        }
+        previous = Some(token);
+    }
+    rubies
+}
+
+/// Notation of `base` with `reading`, following `before` on its line. ｜ is only added
+/// when the base would not be recognized without it.
+pub fn notation(before: &str, base: &str, reading: &str) -> String {
+    if reading.is_empty() {
+        return base.to_string();
+    }
+    let line = &before[before.rfind('\n').map_or(0, |i| i + 1)..];
+    let implicit = format!("{}{}《{}》", line, base, reading);
+    let recognized = rubies(&implicit)
+        .last()
+        .is_some_and(|ruby| ruby.base == base && ruby.range.start == line.len());
+    if recognized {
+        format!("{}《{}》", base, reading)
+    } else {
+        format!("｜{}《{}》", base, reading)
+    }
+}
+
+// --- Component: RubyDialog ---
+
+#[component]
+pub fn RubyDialog(file: UseEditorFile, target: RubyTarget, on_close: EventHandler<()>) -> Element {
+    let mut base = use_signal(|| target.base.clone());
+    let mut reading = use_signal(|| target.reading.clone());
+
+    let range = target.range.clone();
+    let mut apply = move || {
+        let text = file.content.peek().clone();
+        let inserted = notation(&text[..range.start], &base.peek(), &reading.peek());
+        let end = range.start + inserted.len();
+        file.edit(format!("{}{}{}", &text[..range.start], inserted, &text[range.end..]));
+        on_close.call(());
+        file.focus_at(end..end);
+    };
+    let handle_keydown = move |evt: KeyboardEvent| match evt.key() {
+        Key::Enter => {
+            evt.prevent_default();
+            apply();
+        }
+        Key::Escape => on_close.call(()),
+        _ => {}
+    };
+
+    rsx! {
+        div {
+            class: "modal_overlay",
+            div {
+                class: "modal_content create_form ruby_dialog",
+                h2 { "ルビ" }
+                div {
+                    class: "ruby_preview",
+                    ruby { "{base}" rt { "{reading}" } }
+                }
+                input {
+                    value: "{base}",
+                    placeholder: "親文字",
+                    oninput: move |evt| base.set(evt.value()),
+                    onkeydown: handle_keydown,
+                }
+                input {
+                    value: "{reading}",
+                    placeholder: "読み",
+                    onmounted: move |evt| async move {
+                        let _ = evt.set_focus(true).await;
+                        // A suggested reading is replaced by typing over it
+                        document::eval("document.activeElement?.select();");
+                    },
+                    oninput: move |evt| reading.set(evt.value()),
+                    onkeydown: handle_keydown,
+                }
+                if target.suggested && reading() == target.reading {
+                    p { class: "ruby_hint", "辞書から推測した読みです" }
+                }
+                div {
+                    class: "form_actions",
+                    button { onclick: move |_| apply(), "挿入" }
+                    button { onclick: move |_| on_close.call(()), "キャンセル" }
+                }
+            }
+        }
+    }
+}
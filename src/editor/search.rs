@@ -0,0 +1,386 @@
+use super::encoding::SYNC_SCROLL_SCRIPT;
+use super::UseEditorFile;
+use dioxus::prelude::*;
+use regex::{Captures, Regex};
+use std::ops::Range;
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct SearchOptions {
+    /// Treat the query as a regular expression, and `$1` etc. in the replacement as groups
+    pub regex: bool,
+    /// Skip matches inside readings (《》) and annotations (［＃］), so that only the text
+    /// of the story is searched
+    pub aozora_aware: bool,
+}
+
+fn pattern(query: &str, options: SearchOptions) -> Result<Regex, String> {
+    let query = if options.regex { query.to_string() } else { regex::escape(query) };
+    Regex::new(&query).map_err(|e| e.to_string())
+}
+
+/// Byte ranges of the readings and annotations in `text`, brackets included.
+fn annotations(text: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let (open, close) = match c {
+            '《' => ('《', '》'),
+            '［' if chars.peek().is_some_and(|&(_, next)| next == '＃') => ('［', '］'),
+            _ => continue,
+        };
+        // Annotations may nest, and quote text containing brackets, e.g. ［＃「［」に傍点］
+        let mut depth = 1;
+        let mut quoted = 0;
+        let mut end = text.len();
+        for (i, c) in chars.by_ref() {
+            match c {
+                '「' if open == '［' => quoted += 1,
+                '」' if quoted > 0 => quoted -= 1,
+                _ if quoted > 0 => {}
+                _ if c == open => depth += 1,
+                _ if c == close => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = i + c.len_utf8();
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        ranges.push(start..end);
+    }
+    ranges
+}
+
+/// Matches of `pattern` in `text` that the options allow.
+fn captures<'t>(text: &'t str, pattern: &Regex, options: SearchOptions) -> Vec<Captures<'t>> {
+    let skipped = if options.aozora_aware { annotations(text) } else { Vec::new() };
+    pattern
+        .captures_iter(text)
+        .filter(|caps| {
+            let found = caps.get(0).unwrap();
+            // Empty matches would make next/previous stand still
+            !found.is_empty()
+                && !skipped
+                    .iter()
+                    .any(|range| found.start() < range.end && range.start < found.end())
+        })
+        .collect()
+}
+
+fn expand(caps: &Captures, replacement: &str, options: SearchOptions) -> String {
+    if options.regex {
+        let mut expanded = String::new();
+        caps.expand(replacement, &mut expanded);
+        expanded
+    } else {
+        replacement.to_string()
+    }
+}
+
+/// Byte ranges of the matches of `query` in `text`.
+pub fn find(text: &str, query: &str, options: SearchOptions) -> Result<Vec<Range<usize>>, String> {
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    let pattern = pattern(query, options)?;
+    Ok(captures(text, &pattern, options)
+        .iter()
+        .map(|caps| caps.get(0).unwrap().range())
+        .collect())
+}
+
+/// `text` with the match at `index` replaced, or `None` when nothing matches.
+pub fn replace_one(
+    text: &str,
+    query: &str,
+    replacement: &str,
+    options: SearchOptions,
+    index: usize,
+) -> Result<Option<String>, String> {
+    let pattern = pattern(query, options)?;
+    let all = captures(text, &pattern, options);
+    if all.is_empty() {
+        return Ok(None);
+    }
+    // The index may be left over from before an edit removed matches
+    let caps = &all[index % all.len()];
+    let range = caps.get(0).unwrap().range();
+    let mut replaced = text.to_string();
+    replaced.replace_range(range, &expand(caps, replacement, options));
+    Ok(Some(replaced))
+}
+
+/// `text` with every match replaced, and the number of replacements.
+pub fn replace_all(
+    text: &str,
+    query: &str,
+    replacement: &str,
+    options: SearchOptions,
+) -> Result<(String, usize), String> {
+    let pattern = pattern(query, options)?;
+    let all = captures(text, &pattern, options);
+    let mut replaced = String::with_capacity(text.len());
+    let mut last = 0;
+    for caps in &all {
+        let found = caps.get(0).unwrap();
+        replaced.push_str(&text[last..found.start()]);
+        replaced.push_str(&expand(caps, replacement, options));
+        last = found.end();
+    }
+    replaced.push_str(&text[last..]);
+    Ok((replaced, all.len()))
+}
+
+/// Matches of the search bar, shown behind the textarea.
+#[derive(Clone, Default, PartialEq)]
+pub struct Highlights {
+    pub matches: Vec<Range<usize>>,
+    pub current: usize,
+}
+
+/// Text split at the matches; `Some(true)` marks the current one.
+fn segments(text: &str, highlights: &Highlights) -> Vec<(String, Option<bool>)> {
+    let current = highlights.current % highlights.matches.len().max(1);
+    let mut segments = Vec::new();
+    let mut last = 0;
+    for (i, range) in highlights.matches.iter().enumerate() {
+        // The matches may be a render behind the text
+        let (Some(plain), Some(found)) = (text.get(last..range.start), text.get(range.clone())) else {
+            break;
+        };
+        segments.push((plain.to_string(), None));
+        segments.push((found.to_string(), Some(i == current)));
+        last = range.end;
+    }
+    segments.push((text[last..].to_string(), None));
+    segments
+}
+
+// --- Component: SearchBackdrop ---
+
+/// Copy of the text laid out behind the textarea, like the EncodingBackdrop, with the matches
+/// of the search bar highlighted.
+#[component]
+pub fn SearchBackdrop(content: Signal<String>, highlights: Signal<Highlights>, style: String) -> Element {
+    use_effect(move || {
+        highlights.read();
+        document::eval(SYNC_SCROLL_SCRIPT);
+    });
+
+    let segments = segments(&content.read(), &highlights.read());
+
+    rsx! {
+        div {
+            class: "search_backdrop",
+            style: "{style}",
+            for (text, found) in segments {
+                match found {
+                    Some(true) => rsx! { mark { class: "current", "{text}" } },
+                    Some(false) => rsx! { mark { "{text}" } },
+                    None => rsx! { "{text}" },
+                }
+            }
+            // A trailing line break would otherwise take no height
+            " "
+        }
+    }
+}
+
+// --- Component: SearchBar ---
+
+#[component]
+pub fn SearchBar(file: UseEditorFile, highlights: Signal<Highlights>, on_close: EventHandler<()>) -> Element {
+    let mut query = use_signal(String::new);
+    let mut replacement = use_signal(String::new);
+    let mut options = use_signal(SearchOptions::default);
+    let mut current = use_signal(|| 0usize);
+    let mut message = use_signal(String::new);
+
+    let matches = use_memo(move || find(&file.content.read(), &query.read(), options()));
+
+    // Highlight the matches while typing
+    use_effect(move || {
+        let matches = matches.read().clone().unwrap_or_default();
+        highlights.set(Highlights { matches, current: current() });
+    });
+
+    // Select the current match, keeping it within the matches after edits
+    let mut select = move |index: usize| {
+        let ranges = matches.peek().clone().unwrap_or_default();
+        if !ranges.is_empty() {
+            let index = index % ranges.len();
+            current.set(index);
+            file.select(ranges[index].clone());
+        }
+    };
+    let mut step = move |forward: bool| {
+        let count = matches.peek().as_ref().map_or(0, |ranges| ranges.len());
+        if count > 0 {
+            let index = *current.peek();
+            select(if forward { index + 1 } else { index + count - 1 });
+        }
+    };
+    let replace = move |_| {
+        let text = file.content.peek().clone();
+        match replace_one(&text, &query.peek(), &replacement.peek(), *options.peek(), *current.peek()) {
+            Ok(Some(replaced)) => {
+                file.edit(replaced);
+                // The following match has moved into the place of the replaced one
+                select(*current.peek());
+            }
+            Ok(None) => {}
+            Err(e) => message.set(e),
+        }
+    };
+    let replace_every = move |_| {
+        let text = file.content.peek().clone();
+        match replace_all(&text, &query.peek(), &replacement.peek(), *options.peek()) {
+            Ok((replaced, count)) => {
+                if count > 0 {
+                    file.edit(replaced);
+                }
+                message.set(format!("{}件置換しました", count));
+            }
+            Err(e) => message.set(e),
+        }
+    };
+
+    let handle_keydown = move |evt: KeyboardEvent| match evt.key() {
+        Key::Enter => {
+            evt.prevent_default();
+            step(!evt.modifiers().shift());
+        }
+        Key::Escape => {
+            evt.prevent_default();
+            on_close.call(());
+        }
+        _ => {}
+    };
+
+    let count = match &*matches.read() {
+        Ok(ranges) if ranges.is_empty() => "0件".to_string(),
+        Ok(ranges) => format!("{}/{}", (current() % ranges.len()) + 1, ranges.len()),
+        Err(_) => "不正な正規表現".to_string(),
+    };
+
+    rsx! {
+        div {
+            class: "search_bar",
+            input {
+                class: "search_input",
+                placeholder: "検索",
+                value: "{query}",
+                onmounted: move |evt| async move {
+                    let _ = evt.set_focus(true).await;
+                },
+                oninput: move |evt| {
+                    query.set(evt.value());
+                    message.set(String::new());
+                    // Jump to the first match while typing
+                    select(0);
+                },
+                onkeydown: handle_keydown,
+            }
+            span { class: "search_count", "{count}" }
+            button { onclick: move |_| step(false), title: "前を検索 (Shift+Enter)", "↑" }
+            button { onclick: move |_| step(true), title: "次を検索 (Enter)", "↓" }
+            label {
+                title: "正規表現で検索します",
+                input {
+                    r#type: "checkbox",
+                    checked: options().regex,
+                    onchange: move |evt| options.write().regex = evt.checked(),
+                }
+                ".*"
+            }
+            label {
+                title: "ルビ《》と注記［＃］の中を検索しません",
+                input {
+                    r#type: "checkbox",
+                    checked: options().aozora_aware,
+                    onchange: move |evt| options.write().aozora_aware = evt.checked(),
+                }
+                "本文のみ"
+            }
+            input {
+                class: "search_input",
+                placeholder: "置換",
+                value: "{replacement}",
+                oninput: move |evt| replacement.set(evt.value()),
+                onkeydown: move |evt: KeyboardEvent| {
+                    if evt.key() == Key::Escape {
+                        on_close.call(());
+                    }
+                },
+            }
+            button { onclick: replace, "置換" }
+            button { onclick: replace_every, "すべて置換" }
+            if !message().is_empty() {
+                span { class: "search_count", "{message}" }
+            }
+            button { class: "search_close", onclick: move |_| on_close.call(()), title: "閉じる (Esc)", "×" }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAIN: SearchOptions = SearchOptions { regex: false, aozora_aware: false };
+    const REGEX: SearchOptions = SearchOptions { regex: true, aozora_aware: false };
+    const AWARE: SearchOptions = SearchOptions { regex: false, aozora_aware: true };
+
+    #[test]
+    fn test_find() {
+        let text = "吾輩は猫である。猫";
+        assert_eq!(find(text, "猫", PLAIN).unwrap(), vec![9..12, 24..27]);
+        assert!(find(text, "", PLAIN).unwrap().is_empty());
+        // The query is literal unless regex is on
+        assert!(find(text, "猫.", PLAIN).unwrap().is_empty());
+        assert_eq!(find(text, "猫.", REGEX).unwrap(), vec![9..15]);
+        assert!(find(text, "(", REGEX).is_err());
+    }
+
+    #[test]
+    fn test_find_skips_annotations() {
+        let text = "猫《ねこ》と猫［＃「猫」に傍点］";
+        assert_eq!(find(text, "猫", PLAIN).unwrap().len(), 3);
+        assert_eq!(find(text, "猫", AWARE).unwrap(), vec![0..3, 18..21]);
+    }
+
+    #[test]
+    fn test_find_after_quoted_bracket() {
+        // The quoted bracket does not keep the annotation open
+        let text = "［＃「［」に傍点］猫";
+        assert_eq!(find(text, "猫", AWARE).unwrap().len(), 1);
+        // Nor does a nested annotation close it early
+        let text = "［＃「猫［＃傍点］」の左に注記］猫";
+        assert_eq!(find(text, "猫", AWARE).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_replace_all() {
+        assert_eq!(
+            replace_all("猫と犬", "(猫|犬)", "[$1]", REGEX).unwrap(),
+            ("[猫]と[犬]".to_string(), 2)
+        );
+        // `$1` is literal unless regex is on
+        assert_eq!(replace_all("猫", "猫", "$1", PLAIN).unwrap(), ("$1".to_string(), 1));
+        assert_eq!(
+            replace_all("猫［＃「猫」に傍点］", "猫", "犬", AWARE).unwrap(),
+            ("犬［＃「猫」に傍点］".to_string(), 1)
+        );
+    }
+
+    #[test]
+    fn test_replace_one() {
+        let options = PLAIN;
+        assert_eq!(replace_one("猫猫", "猫", "犬", options, 1).unwrap(), Some("猫犬".to_string()));
+        // An index left over from before an edit wraps around
+        assert_eq!(replace_one("猫猫", "猫", "犬", options, 2).unwrap(), Some("犬猫".to_string()));
+        assert_eq!(replace_one("犬", "猫", "犬", options, 0).unwrap(), None);
+    }
+}
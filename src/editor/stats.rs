@@ -0,0 +1,95 @@
+use dioxus::prelude::*;
+
+/// Squares on a row and rows on a sheet of 原稿用紙
+const MANUSCRIPT_COLUMNS: usize = 20;
+const MANUSCRIPT_ROWS: usize = 20;
+
+/// Counts of the text shown in the status bar.
+#[derive(Clone, Copy, PartialEq)]
+pub struct TextStats {
+    /// Characters as typed, line breaks excluded
+    pub total: usize,
+    /// Characters of the story itself, without ruby and annotations; `None` when the
+    /// text could not be parsed
+    pub body: Option<usize>,
+    /// Sheets of 400-character 原稿用紙 the story fills, each paragraph starting on a new row
+    pub manuscript_sheets: Option<f64>,
+}
+
+impl TextStats {
+    pub fn count(text: &str) -> Self {
+        let plain = aozora_parser::parse_aozora(text.to_string())
+            .ok()
+            .map(aozora_parser::parse)
+            .and_then(|doc| aozora_parser::parse_blocks(doc.items).ok())
+            .map(|blocks| aozora_parser::to_plain_text(&blocks, aozora_parser::RubyStyle::Strip));
+        Self {
+            total: count_chars(text),
+            body: plain.as_deref().map(count_chars),
+            manuscript_sheets: plain.as_deref().map(|plain| page_count(plain, MANUSCRIPT_COLUMNS, MANUSCRIPT_ROWS)),
+        }
+    }
+}
+
+fn count_chars(text: &str) -> usize {
+    text.chars().filter(|&c| c != '\n' && c != '\r').count()
+}
+
+/// Pages of `columns` characters by `rows` lines that `plain` fills, each paragraph starting
+/// on a new line; the last page counts only as far as it is filled.
+pub fn page_count(plain: &str, columns: usize, rows: usize) -> f64 {
+    let lines: usize = plain
+        .trim_end_matches('\n')
+        .lines()
+        .map(|line| line.chars().count().div_ceil(columns).max(1))
+        .sum();
+    lines as f64 / rows as f64
+}
+
+// --- Component: StatusBar ---
+
+#[component]
+pub fn StatusBar(content: Signal<String>) -> Element {
+    let stats = use_memo(move || TextStats::count(&content.read()));
+    let mut selected = use_signal(|| 0usize);
+
+    // The selection is not part of the textarea's events, so it is reported from the page
+    use_future(move || async move {
+        let mut eval = document::eval(
+            r#"
+            window.kartanaSelection?.abort();
+            const controller = new AbortController();
+            window.kartanaSelection = controller;
+            const report = () => {
+                const textarea = document.querySelector(".simple_editor_textarea");
+                if (!textarea) return;
+                const text = textarea.value.substring(textarea.selectionStart, textarea.selectionEnd);
+                dioxus.send([...text].filter((c) => c !== "\n").length);
+            };
+            for (const event of ["selectionchange", "select", "keyup", "mouseup"]) {
+                document.addEventListener(event, report, { signal: controller.signal });
+            }
+            await new Promise(() => {});
+            "#,
+        );
+        while let Ok(count) = eval.recv::<usize>().await {
+            selected.set(count);
+        }
+    });
+
+    let TextStats { total, body, manuscript_sheets } = stats();
+    let body = body.map_or("—".to_string(), |count| count.to_string());
+    let sheets = manuscript_sheets.map_or("—".to_string(), |sheets| format!("{:.1}", sheets));
+
+    rsx! {
+        footer {
+            class: "editor_footer",
+            span { title: "改行を除いた文字数", "{total}文字" }
+            span { title: "ルビと注記を除いた本文の文字数", "本文 {body}文字" }
+            span { title: "400字詰め原稿用紙に換算した枚数", "原稿用紙 {sheets}枚" }
+            if selected() > 0 {
+                span { "選択 {selected}文字" }
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use super::UseEditorFile;
+use crate::settings::update_settings;
+use aozora_parser::{apply_edits, tatechuyoko_fixes, Fix};
+use dioxus::prelude::*;
+
+/// Characters shown on each side of a change
+const CONTEXT: usize = 8;
+
+/// A change as listed in the dialog, with the text around it on its line.
+#[derive(Clone, PartialEq)]
+struct Change {
+    fix: Fix,
+    line: usize,
+    before: String,
+    original: String,
+    after: String,
+}
+
+fn changes(text: &str) -> Vec<Change> {
+    let chars: Vec<char> = text.chars().collect();
+    tatechuyoko_fixes(text)
+        .into_iter()
+        .map(|fix| {
+            let (start, end) = (fix.span.start, fix.span.end);
+            let line_start = chars[..start].iter().rposition(|&c| c == '\n').map_or(0, |i| i + 1);
+            let line_end = chars[end..].iter().position(|&c| c == '\n').map_or(chars.len(), |i| end + i);
+            Change {
+                line: chars[..start].iter().filter(|&&c| c == '\n').count() + 1,
+                before: chars[start.saturating_sub(CONTEXT).max(line_start)..start].iter().collect(),
+                original: chars[start..end].iter().collect(),
+                after: chars[end..(end + CONTEXT).min(line_end)].iter().collect(),
+                fix,
+            }
+        })
+        .collect()
+}
+
+/// Apply `fixes` to the text being edited, as one edit that can be undone.
+pub fn apply(mut file: UseEditorFile, fixes: &[Fix]) {
+    if fixes.is_empty() {
+        return;
+    }
+    let text = file.content.peek().clone();
+    file.edit(apply_edits(&text, fixes));
+}
+
+// --- Component: TatechuyokoDialog ---
+
+#[component]
+pub fn TatechuyokoDialog(file: UseEditorFile, on_close: EventHandler<()>) -> Element {
+    let changes = use_signal(|| changes(&file.content.peek()));
+    // Whether each change is to be applied
+    let mut chosen = use_signal(|| vec![true; changes.peek().len()]);
+    let count = chosen.read().iter().filter(|chosen| **chosen).count();
+
+    rsx! {
+        div {
+            class: "modal_overlay",
+            div {
+                class: "modal_content tatechuyoko_dialog",
+                h2 { "縦中横・全角に整える" }
+                if changes.read().is_empty() {
+                    p { "変換する半角の数字や感嘆符はありません" }
+                } else {
+                    ul {
+                        class: "tatechuyoko_list",
+                        for (i, change) in changes().into_iter().enumerate() {
+                            li {
+                                label {
+                                    input {
+                                        r#type: "checkbox",
+                                        checked: chosen.read()[i],
+                                        onchange: move |evt| chosen.write()[i] = evt.checked(),
+                                    }
+                                    span { class: "tatechuyoko_line", "{change.line}行" }
+                                    span {
+                                        class: "tatechuyoko_context",
+                                        "{change.before}"
+                                        del { "{change.original}" }
+                                        ins { "{change.fix.replacement}" }
+                                        "{change.after}"
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                label {
+                    class: "tatechuyoko_on_save",
+                    input {
+                        r#type: "checkbox",
+                        checked: file.settings.read().tatechuyoko_on_save,
+                        onchange: move |evt| update_settings(file.settings, |settings| settings.tatechuyoko_on_save = evt.checked()),
+                    }
+                    "Ctrl+Sで保存するときにも変換する"
+                }
+                div {
+                    class: "form_actions",
+                    button {
+                        disabled: count == 0,
+                        onclick: move |_| {
+                            let fixes: Vec<Fix> = changes
+                                .peek()
+                                .iter()
+                                .zip(chosen.peek().iter())
+                                .filter(|(_, chosen)| **chosen)
+                                .map(|(change, _)| change.fix.clone())
+                                .collect();
+                            apply(file, &fixes);
+                            on_close.call(());
+                        },
+                        "変換する（{count}件）"
+                    }
+                    button { onclick: move |_| on_close.call(()), "キャンセル" }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use super::UseEditorFile;
+use crate::settings::update_settings;
+use dioxus::prelude::*;
+use encoding_rs::SHIFT_JIS;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Choices of how many versions to keep per chapter (0 is off)
+const RETENTION_CHOICES: [(usize, &str); 5] =
+    [(0, "保存しない"), (10, "10件"), (20, "20件"), (50, "50件"), (100, "100件")];
+/// Beyond this many line pairs, changes are shown as a whole instead of line by line
+const DIFF_LIMIT: usize = 4_000_000;
+
+/// A saved version of a chapter.
+#[derive(Clone, PartialEq)]
+pub struct Version {
+    path: PathBuf,
+    /// When it was saved, as shown in the list
+    pub label: String,
+}
+
+impl Version {
+    pub fn read(&self) -> Option<String> {
+        let bytes = fs::read(&self.path).ok()?;
+        let (cow, _, _) = SHIFT_JIS.decode(&bytes);
+        Some(cow.replace("\r\n", "\n"))
+    }
+}
+
+/// Versions of the chapter at `file` are kept in `.history/<chapter>/` next to it.
+fn history_dir(file: &Path) -> PathBuf {
+    let chapter = file.file_stem().unwrap_or_default();
+    file.with_file_name(".history").join(chapter)
+}
+
+/// Keep `bytes`, the content just saved to `file`, as a version. Nothing is kept when it
+/// is the same as the latest version, and the oldest are removed beyond `retention`.
+pub fn record(file: &Path, bytes: &[u8], retention: usize) -> std::io::Result<()> {
+    if retention == 0 {
+        return Ok(());
+    }
+    let dir = history_dir(file);
+    let mut existing = files(&dir);
+    if existing.last().and_then(|last| fs::read(last).ok()).as_deref() == Some(bytes) {
+        return Ok(());
+    }
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.txt", chrono::Local::now().format("%Y%m%d-%H%M%S-%3f")));
+    fs::write(&path, bytes)?;
+    existing.push(path);
+    for old in &existing[..existing.len().saturating_sub(retention)] {
+        fs::remove_file(old)?;
+    }
+    Ok(())
+}
+
+/// Files of the versions, oldest first; their names sort by time.
+fn files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+/// Versions of the chapter at `file`, newest first.
+pub fn list(file: &Path) -> Vec<Version> {
+    files(&history_dir(file))
+        .into_iter()
+        .rev()
+        .map(|path| {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+            let label = chrono::NaiveDateTime::parse_from_str(&stem, "%Y%m%d-%H%M%S-%3f")
+                .map_or(stem.clone(), |time| time.format("%Y-%m-%d %H:%M:%S").to_string());
+            Version { path, label }
+        })
+        .collect()
+}
+
+/// Move the versions along with a renamed chapter. Versions already kept under the new
+/// name, left from an earlier chapter of that name, are merged with them.
+pub fn rename(old_file: &Path, new_file: &Path) -> std::io::Result<()> {
+    let (old_dir, new_dir) = (history_dir(old_file), history_dir(new_file));
+    if !old_dir.exists() {
+        return Ok(());
+    }
+    if !new_dir.exists() {
+        return fs::rename(old_dir, new_dir);
+    }
+    for path in files(&old_dir) {
+        fs::rename(&path, new_dir.join(path.file_name().unwrap_or_default()))?;
+    }
+    fs::remove_dir(old_dir)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LineChange {
+    Same,
+    Removed,
+    Added,
+}
+
+/// Line diff from `old` to `new`.
+fn diff<'a>(old: &'a str, new: &'a str) -> Vec<(LineChange, &'a str)> {
+    let (old, new): (Vec<&str>, Vec<&str>) = (old.lines().collect(), new.lines().collect());
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+
+    let mut lines: Vec<(LineChange, &str)> = old[..prefix].iter().map(|l| (LineChange::Same, *l)).collect();
+    if a.len() * b.len() > DIFF_LIMIT {
+        lines.extend(a.iter().map(|l| (LineChange::Removed, *l)));
+        lines.extend(b.iter().map(|l| (LineChange::Added, *l)));
+    } else {
+        // Longest common subsequence of the changed middle
+        let mut lcs = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i][j] = if a[i] == b[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                lines.push((LineChange::Same, a[i]));
+                i += 1;
+                j += 1;
+            } else if j < b.len() && (i == a.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+                lines.push((LineChange::Added, b[j]));
+                j += 1;
+            } else {
+                lines.push((LineChange::Removed, a[i]));
+                i += 1;
+            }
+        }
+    }
+    lines.extend(old[old.len() - suffix..].iter().map(|l| (LineChange::Same, *l)));
+    lines
+}
+
+// --- Component: HistoryDialog ---
+
+#[component]
+pub fn HistoryDialog(file: UseEditorFile, on_close: EventHandler<()>) -> Element {
+    let versions = use_signal(|| list(&file.file_path.peek()));
+    let mut selected = use_signal(|| 0usize);
+
+    let version = versions.read().get(selected()).cloned();
+    let old = version.as_ref().and_then(Version::read);
+
+    rsx! {
+        div {
+            class: "modal_overlay",
+            div {
+                class: "modal_content history_dialog",
+                div {
+                    class: "history_header",
+                    h2 { "履歴" }
+                    select {
+                        class: "autosave_select",
+                        title: "章ごとに残す版の数",
+                        onchange: move |evt| {
+                            if let Ok(count) = evt.value().parse() {
+                                update_settings(file.settings, |settings| settings.snapshot_retention = count);
+                            }
+                        },
+                        for (count, label) in RETENTION_CHOICES {
+                            option { value: "{count}", selected: file.settings.read().snapshot_retention == count, "{label}" }
+                        }
+                    }
+                }
+                if versions.read().is_empty() {
+                    p { "保存された版はありません" }
+                } else {
+                    div {
+                        class: "history_body",
+                        ul {
+                            class: "history_list",
+                            for (i, version) in versions().into_iter().enumerate() {
+                                li {
+                                    class: if i == selected() { "selected" } else { "" },
+                                    onclick: move |_| selected.set(i),
+                                    "{version.label}"
+                                }
+                            }
+                        }
+                        div {
+                            class: "history_diff",
+                            if let Some(old) = &old {
+                                // Changes from the selected version to the text being edited
+                                for (change, line) in diff(old, &file.content.read()) {
+                                    div {
+                                        class: match change {
+                                            LineChange::Same => "diff_line",
+                                            LineChange::Removed => "diff_line removed",
+                                            LineChange::Added => "diff_line added",
+                                        },
+                                        "{line}"
+                                    }
+                                }
+                            } else {
+                                p { "この版を読み込めません" }
+                            }
+                        }
+                    }
+                }
+                div {
+                    class: "form_actions",
+                    if let Some(old) = old.clone() {
+                        button {
+                            onclick: move |_| {
+                                // Restoring is an edit, so it can be undone
+                                file.edit(old.clone());
+                                on_close.call(());
+                            },
+                            "この版に戻す"
+                        }
+                    }
+                    button { onclick: move |_| on_close.call(()), "閉じる" }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    fn chapter(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kartana_versions_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("chapter.txt")
+    }
+
+    fn contents(file: &Path) -> Vec<String> {
+        list(file).iter().filter_map(Version::read).collect()
+    }
+
+    #[test]
+    fn test_diff() {
+        use LineChange::*;
+        assert_eq!(
+            diff("a\nb\nc\nd", "a\nx\nc\nd\ne"),
+            vec![(Same, "a"), (Added, "x"), (Removed, "b"), (Same, "c"), (Same, "d"), (Added, "e")]
+        );
+        assert_eq!(diff("a\nb", "a\nb"), vec![(Same, "a"), (Same, "b")]);
+        assert_eq!(diff("", "a"), vec![(Added, "a")]);
+    }
+
+    #[test]
+    fn test_record() {
+        let file = chapter("record");
+        record(&file, b"one", 0).unwrap();
+        assert!(list(&file).is_empty());
+
+        record(&file, b"one", 2).unwrap();
+        // The same as the latest version
+        record(&file, b"one", 2).unwrap();
+        assert_eq!(contents(&file), ["one"]);
+
+        for text in ["two", "three"] {
+            // Versions are named by the millisecond they were saved at
+            sleep(Duration::from_millis(2));
+            record(&file, text.as_bytes(), 2).unwrap();
+        }
+        // Newest first, and the oldest beyond the retention are removed
+        assert_eq!(contents(&file), ["three", "two"]);
+        fs::remove_dir_all(file.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_rename_onto_stale_history() {
+        let old = chapter("rename");
+        let new = old.with_file_name("renamed.txt");
+        record(&new, b"stale", 10).unwrap();
+        sleep(Duration::from_millis(2));
+        record(&old, b"current", 10).unwrap();
+
+        rename(&old, &new).unwrap();
+        assert!(list(&old).is_empty());
+        assert_eq!(contents(&new), ["current", "stale"]);
+        fs::remove_dir_all(old.parent().unwrap()).unwrap();
+    }
+}
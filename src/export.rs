@@ -0,0 +1,113 @@
+use crate::top_page::works::Series;
+use crate::toast::{ToastKind, Toasts};
+use aozora_parser::{EpubGenerator, EpubMetadata};
+use dioxus::prelude::*;
+use encoding_rs::SHIFT_JIS;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn read_chapter(series_title: &str, chapter_title: &str) -> Result<String, String> {
+    let path = Series::series_dir(series_title).join(format!("{}.txt", chapter_title));
+    let bytes = fs::read(&path).map_err(|e| format!("「{}」を読み込めません: {}", chapter_title, e))?;
+    let (cow, _, _) = SHIFT_JIS.decode(&bytes);
+    Ok(cow.replace("\r\n", "\n"))
+}
+
+fn parse_chapter(series_title: &str, chapter_title: &str) -> Result<aozora_parser::AozoraDocument, String> {
+    let text = read_chapter(series_title, chapter_title)?;
+    let tokens = aozora_parser::parse_aozora(text)
+        .map_err(|e| format!("「{}」を解析できません: {}", chapter_title, e))?;
+    let mut document = aozora_parser::parse(tokens);
+    // Chapters without a title line are listed under their name in the app
+    if document.metadata.title.is_empty() {
+        document.metadata.title = chapter_title.to_string();
+    }
+    Ok(document)
+}
+
+/// Ask where to save an EPUB, suggesting `name`.
+async fn choose_path(name: &str) -> Option<PathBuf> {
+    rfd::AsyncFileDialog::new()
+        .add_filter("EPUB", &["epub"])
+        .set_file_name(format!("{}.epub", name))
+        .save_file()
+        .await
+        .map(|file| file.path().to_path_buf())
+}
+
+/// Run blocking work off the UI thread.
+async fn blocking<T: Send + 'static>(work: impl FnOnce() -> Result<T, String> + Send + 'static) -> Result<T, String> {
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn finish(mut toasts: Toasts, toast: usize, path: &Path, result: Result<(), String>) {
+    match result {
+        Ok(()) => toasts.update(toast, ToastKind::Success, format!("{} に書き出しました", path.display())),
+        Err(e) => toasts.update(toast, ToastKind::Error, format!("書き出しに失敗しました: {}", e)),
+    }
+}
+
+/// Export one chapter as an EPUB, asking where to save it.
+pub fn export_chapter(mut toasts: Toasts, series_title: String, chapter_title: String) {
+    // Exporting carries on when the page that started it is left
+    spawn_forever(async move {
+        let Some(path) = choose_path(&chapter_title).await else {
+            return;
+        };
+        let toast = toasts.show(ToastKind::Progress, format!("「{}」を書き出しています…", chapter_title));
+        let output = path.clone();
+        let result = blocking(move || {
+            let text = read_chapter(&series_title, &chapter_title)?;
+            aozora_parser::text_to_epub(text, &output).map_err(|e| e.to_string())
+        })
+        .await;
+        finish(toasts, toast, &path, result);
+    });
+}
+
+/// Export every chapter of a series as one EPUB, each chapter a section of its own with a
+/// table of contents entry, asking where to save it.
+pub fn export_series(mut toasts: Toasts, series: Series) {
+    spawn_forever(async move {
+        let Some(path) = choose_path(&series.title).await else {
+            return;
+        };
+        let toast = toasts.show(ToastKind::Progress, format!("「{}」を書き出しています…", series.title));
+        let total = series.chapters.len();
+        let mut documents = Vec::with_capacity(total);
+        for (i, chapter) in series.chapters.iter().enumerate() {
+            toasts.update(toast, ToastKind::Progress, format!("「{}」を読み込んでいます… ({}/{})", series.title, i + 1, total));
+            let (series_title, chapter_title) = (series.title.clone(), chapter.title.clone());
+            match blocking(move || parse_chapter(&series_title, &chapter_title)).await {
+                Ok(document) => documents.push(document),
+                Err(e) => return finish(toasts, toast, &path, Err(e)),
+            }
+        }
+
+        toasts.update(toast, ToastKind::Progress, format!("「{}」を書き出しています…", series.title));
+        let info = &series.metadata;
+        // Without an author in the series settings, take it from the chapters
+        let author = Some(info.author.clone())
+            .filter(|author| !author.is_empty())
+            .or_else(|| documents.iter().map(|doc| doc.metadata.author.clone()).find(|author| !author.is_empty()))
+            .unwrap_or_default();
+        let mut metadata = EpubMetadata::new();
+        if !info.publisher.is_empty() {
+            metadata = metadata.with_publisher(&info.publisher);
+        }
+        if !info.description.is_empty() {
+            metadata = metadata.with_description(&info.description);
+        }
+        let (title, output) = (series.title.clone(), path.clone());
+        let result = blocking(move || {
+            let generator = EpubGenerator::anthology(title, author, documents)
+                .map_err(|e| e.to_string())?
+                .with_metadata(metadata);
+            generator.write_to_file(&output).map_err(|e| e.to_string())
+        })
+        .await;
+        finish(toasts, toast, &path, result);
+    });
+}
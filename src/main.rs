@@ -1,6 +1,12 @@
 
 
 mod editor;
+mod export;
+mod session;
+mod settings;
+mod settings_page;
+mod toast;
+mod toml_file;
 mod top_page;
 mod reader_page;
 
@@ -8,6 +14,9 @@ use dioxus::prelude::*;
 use editor::Editor;
 use top_page::Top;
 use reader_page::Reader;
+use settings::use_settings_provider;
+use settings_page::SettingsPage;
+use toast::{use_toasts_provider, ToastList};
 
 #[derive(Debug, Clone, Routable, PartialEq)]
 #[rustfmt::skip]
@@ -18,6 +27,8 @@ enum Route {
     Editor { series_title: String, chapter_title: String },
     #[route("/reader/:series_title/:chapter_title")]
     Reader { series_title: String, chapter_title: String },
+    #[route("/settings")]
+    SettingsPage {},
 }
 
 const FAVICON: Asset = asset!("/assets/favicon.ico");
@@ -32,6 +43,8 @@ const ICONS_CSS: Asset = asset!("/assets/css/icons.css");
 const MODAL_CSS: Asset = asset!("/assets/css/modal.css");
 const EDITOR_CSS: Asset = asset!("/assets/css/editor.css");
 const READER_CSS: Asset = asset!("/assets/css/reader.css");
+const TOAST_CSS: Asset = asset!("/assets/css/toast.css");
+const SETTINGS_CSS: Asset = asset!("/assets/css/settings.css");
 
 fn main() {
     dioxus::launch(App);
@@ -39,6 +52,9 @@ fn main() {
 
 #[component]
 fn App() -> Element {
+    use_settings_provider();
+    use_toasts_provider();
+
     rsx! {
         document::Link { rel: "icon", href: FAVICON }
         // Inject font-face dynamically to handle asset bundling correctly
@@ -60,6 +76,9 @@ fn App() -> Element {
         document::Link { rel: "stylesheet", href: MODAL_CSS }
         document::Link { rel: "stylesheet", href: EDITOR_CSS }
         document::Link { rel: "stylesheet", href: READER_CSS }
+        document::Link { rel: "stylesheet", href: TOAST_CSS }
+        document::Link { rel: "stylesheet", href: SETTINGS_CSS }
         Router::<Route> {}
+        ToastList {}
     }
 }
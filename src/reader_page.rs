@@ -1,15 +1,129 @@
 use dioxus::prelude::*;
 use std::fs;
 use encoding_rs::SHIFT_JIS;
+use crate::editor::stats::page_count;
+use crate::settings::{update_settings, use_settings};
 use crate::top_page::works::{ActionIcon, Series};
 
 const BACK_ICON: Asset = asset!("/assets/icons/back.svg");
+const PAGES_ICON: Asset = asset!("/assets/icons/pages.svg");
+/// Characters read in a minute, a common figure for Japanese fiction
+const READING_SPEED: usize = 500;
+/// Characters on a line and lines on a page of a 文庫本
+const PAGE_COLUMNS: usize = 38;
+const PAGE_ROWS: usize = 16;
+
+/// How long a chapter takes to read, and the pages it would fill in a 文庫本.
+#[derive(Clone, Copy, PartialEq)]
+struct ReadingEstimate {
+    minutes: usize,
+    pages: usize,
+}
+
+impl ReadingEstimate {
+    /// From the plain text of the story, without ruby and annotations; each paragraph
+    /// starts on a new line of the page.
+    fn of(plain: &str) -> Self {
+        let chars = plain.chars().filter(|&c| c != '\n' && c != '\r').count();
+        Self {
+            minutes: chars.div_ceil(READING_SPEED).max(1),
+            pages: (page_count(plain, PAGE_COLUMNS, PAGE_ROWS).ceil() as usize).max(1),
+        }
+    }
+}
+
+/// Inside the book in paged mode: turns the pages of one screen each that the columns of
+/// `html.paged` lay the text out in, and reports the page to the Reader. Pages follow the
+/// vertical text: the next one is to the left.
+const PAGE_SCRIPT: &str = "<script>
+let page = 0;
+const pageCount = () => Math.max(1, Math.ceil(document.scrollingElement.scrollHeight / innerHeight - 0.01));
+const show = (n) => {
+  page = Math.min(Math.max(n, 0), pageCount() - 1);
+  document.scrollingElement.scrollTop = page * innerHeight;
+  parent.postMessage({ kartanaPage: [page + 1, pageCount()] }, '*');
+};
+addEventListener('message', (e) => {
+  const turn = e.data?.kartanaTurn;
+  if (turn !== undefined) show(page + turn);
+});
+addEventListener('keydown', (e) => {
+  const turn = { ArrowLeft: 1, ArrowDown: 1, PageDown: 1, ' ': 1, ArrowRight: -1, ArrowUp: -1, PageUp: -1 }[e.key];
+  if (turn === undefined) return;
+  e.preventDefault();
+  show(page + turn);
+});
+addEventListener('click', (e) => {
+  if (getSelection().toString()) return;
+  if (e.clientX < innerWidth / 3) show(page + 1);
+  else if (e.clientX > innerWidth * 2 / 3) show(page - 1);
+});
+// A swipe sends many wheel events; it turns one page
+let lastWheel = 0;
+addEventListener('wheel', (e) => {
+  if (e.timeStamp - lastWheel < 400) return;
+  lastWheel = e.timeStamp;
+  show(page + Math.sign(e.deltaY || -e.deltaX));
+}, { passive: true });
+addEventListener('resize', () => show(page));
+addEventListener('load', () => show(0));
+</script>";
+
+/// In the Reader: passes the page reported by the book on, and turns the pages with the
+/// arrow keys while the focus is outside of it.
+const PAGE_LISTENER_SCRIPT: &str = r#"
+window.kartanaPages?.abort();
+const controller = new AbortController();
+window.kartanaPages = controller;
+addEventListener("message", (e) => {
+    const page = e.data?.kartanaPage;
+    if (page) dioxus.send(page);
+}, { signal: controller.signal });
+document.addEventListener("keydown", (e) => {
+    const turn = { ArrowLeft: 1, ArrowRight: -1 }[e.key];
+    if (turn === undefined) return;
+    document.querySelector(".reader_iframe")?.contentWindow?.postMessage({ kartanaTurn: turn }, "*");
+}, { signal: controller.signal });
+await new Promise(() => {});
+"#;
+
+/// Turn the pages of the book in paged mode; positive towards the end.
+fn turn_page(turn: i32) {
+    document::eval(&format!(
+        r#"document.querySelector(".reader_iframe")?.contentWindow?.postMessage({{ kartanaTurn: {} }}, "*");"#,
+        turn
+    ));
+}
+
+/// `<style>` elements for a book shown in an iframe: the book stylesheet with the app's
+/// colours and typography on top.
+pub fn reader_styles() -> String {
+    // We inject the CSS content inline to avoid path resolution issues in srcdoc iframe
+    // This assumes the assets directory is in the current working directory
+    // "include_str" is not used as requested, using runtime read.
+    let variables_css_content = fs::read_to_string("assets/css/variables.css")
+        .unwrap_or_else(|_| "/* Failed to load variables.css */".to_string());
+
+    let reader_css_content = fs::read_to_string("assets/css/reader.css")
+        .unwrap_or_else(|_| "/* Failed to load reader.css */".to_string());
+
+    format!(
+        "<style>{}</style><style>{}</style><style>{}</style>",
+        aozora_parser::default_css(),
+        variables_css_content,
+        reader_css_content
+    )
+}
 
 #[component]
 pub fn Reader(series_title: String, chapter_title: String) -> Element {
     let navigator = use_navigator();
+    let settings = use_settings();
+    // Current page and number of pages, as reported by the book in paged mode
+    let mut page = use_signal(|| None::<(usize, usize)>);
     let mut xhtml_content = use_signal(|| String::new());
     let mut author_name = use_signal(|| String::new());
+    let mut estimate = use_signal(|| None::<ReadingEstimate>);
 
     // Helper to get file path
     let file_path = {
@@ -19,43 +133,39 @@ pub fn Reader(series_title: String, chapter_title: String) -> Element {
     };
 
     use_effect(move || {
+        let paged = settings.read().reader_paged;
+        page.set(None);
         let path = file_path();
         if path.exists() {
             if let Ok(bytes) = fs::read(path) {
                 let (cow, _, _) = SHIFT_JIS.decode(&bytes);
                 let text = cow.into_owned();
                 
-                // Call text_to_xhtml which now returns XhtmlOutput struct
-                match aozora_parser::text_to_xhtml(text) {
-                    Ok(output) => {
-                        // Inject CSS
-                        let css = aozora_parser::default_css();
-                        let default_style_tag = format!("<style>{}</style>", css);
-                        
-                        // We inject the CSS content inline to avoid path resolution issues in srcdoc iframe
-                        // This assumes the assets directory is in the current working directory
-                        // "include_str" is not used as requested, using runtime read.
-                        let variables_css_content = fs::read_to_string("assets/css/variables.css")
-                            .unwrap_or_else(|_| "/* Failed to load variables.css */".to_string());
-
-                        let reader_css_content = fs::read_to_string("assets/css/reader.css")
-                            .unwrap_or_else(|_| "/* Failed to load reader.css */".to_string());
-                        
-                        let variables_style_tag = format!("<style>{}</style>", variables_css_content);
-                        let custom_style_tag = format!("<style>{}</style>", reader_css_content);
-                        
-                        let replacement = format!("{}{}{}", default_style_tag, variables_style_tag, custom_style_tag);
-
-                        // Replace the external link with inline style + link to reader.css
-                        let final_xhtml = output.xhtml.replace(
-                            r#"<link rel="stylesheet" type="text/css" href="../style/book-style.css"/>"#, 
-                            &replacement
+                let parsed = aozora_parser::parse_aozora(text)
+                    .ok()
+                    .map(aozora_parser::parse)
+                    .and_then(|doc| {
+                        let blocks = aozora_parser::parse_blocks(doc.items).ok()?;
+                        Some((doc.metadata, blocks))
+                    });
+                match parsed {
+                    Some((metadata, blocks)) => {
+                        let fragment = aozora_parser::to_html_fragment(&blocks);
+                        let plain = aozora_parser::to_plain_text(&blocks, aozora_parser::RubyStyle::Strip);
+                        estimate.set(Some(ReadingEstimate::of(&plain)));
+
+                        let document = format!(
+                            "<!DOCTYPE html><html lang=\"ja\" class=\"vrtl{}\"><head><meta charset=\"utf-8\">{}{}</head><body>{}</body></html>",
+                            if paged { " paged" } else { "" },
+                            reader_styles(),
+                            if paged { PAGE_SCRIPT } else { "" },
+                            fragment
                         );
-                        
-                        xhtml_content.set(final_xhtml);
-                        author_name.set(output.metadata.author);
+
+                        xhtml_content.set(document);
+                        author_name.set(metadata.author);
                     },
-                    Err(_) => {
+                    None => {
                         xhtml_content.set("Error parsing Aozora text.".to_string());
                     }
                 }
@@ -67,6 +177,16 @@ pub fn Reader(series_title: String, chapter_title: String) -> Element {
         }
     });
 
+    use_future(move || async move {
+        let mut eval = document::eval(PAGE_LISTENER_SCRIPT);
+        while let Ok(current) = eval.recv::<(usize, usize)>().await {
+            page.set(Some(current));
+        }
+    });
+    use_drop(|| {
+        document::eval("window.kartanaPages?.abort();");
+    });
+
     rsx! {
         div {
             class: "reader_layout",
@@ -89,6 +209,28 @@ pub fn Reader(series_title: String, chapter_title: String) -> Element {
                         "{chapter_title}"
                     }
                 }
+                if let Some(estimate) = estimate() {
+                    span {
+                        class: "reading_estimate",
+                        title: "1分に{READING_SPEED}字、文庫本1ページ{PAGE_COLUMNS}字×{PAGE_ROWS}行として",
+                        "約{estimate.minutes}分・{estimate.pages}ページ"
+                    }
+                }
+                if settings.read().reader_paged {
+                    div {
+                        class: "page_nav",
+                        button { title: "次のページ", onclick: move |_| turn_page(1), "‹" }
+                        if let Some((current, total)) = page() {
+                            span { "{current} / {total}" }
+                        }
+                        button { title: "前のページ", onclick: move |_| turn_page(-1), "›" }
+                    }
+                }
+                ActionIcon {
+                    icon: PAGES_ICON,
+                    class: if settings.read().reader_paged { "active" } else { "" },
+                    onclick: move |_| update_settings(settings, |s| s.reader_paged = !s.reader_paged),
+                }
             }
 
             // Reader Content
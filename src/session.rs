@@ -0,0 +1,84 @@
+use crate::toml_file;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const SESSION_PATH: &str = "data/session.toml";
+/// Chapters kept in the list of recent edits
+const RECENT_LIMIT: usize = 8;
+
+/// Whether the last chapter has been reopened since the app started
+static RESTORED: AtomicBool = AtomicBool::new(false);
+
+#[derive(PartialEq, Eq, Clone, Default, Deserialize, Serialize)]
+pub struct OpenedChapter {
+    pub series_title: String,
+    pub chapter_title: String,
+}
+
+/// Where the editor was in a chapter.
+#[derive(PartialEq, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct ChapterPosition {
+    /// Caret position in UTF-16 code units, as the textarea counts them
+    pub cursor: usize,
+    /// Scroll offset of the textarea in pixels
+    pub scroll_top: f64,
+}
+
+/// State of the last session, restored on the next launch.
+#[derive(PartialEq, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Session {
+    pub last_opened: Option<OpenedChapter>,
+    /// Chapters opened or saved lately, the latest first
+    pub recent: Vec<OpenedChapter>,
+    /// Positions keyed by `series/chapter`
+    pub positions: BTreeMap<String, ChapterPosition>,
+}
+
+impl Session {
+    pub fn load() -> Self {
+        toml_file::load(SESSION_PATH).unwrap_or_default()
+    }
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        toml_file::save(SESSION_PATH, self)
+    }
+
+    fn key(series_title: &str, chapter_title: &str) -> String {
+        format!("{}/{}", series_title, chapter_title)
+    }
+
+    pub fn position(&self, series_title: &str, chapter_title: &str) -> ChapterPosition {
+        self.positions
+            .get(&Self::key(series_title, chapter_title))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Remember that the chapter is open, and where in it the editor is.
+    pub fn record(series_title: &str, chapter_title: &str, position: Option<ChapterPosition>) {
+        let mut session = Self::load();
+        let opened = OpenedChapter {
+            series_title: series_title.to_string(),
+            chapter_title: chapter_title.to_string(),
+        };
+        session.recent.retain(|chapter| *chapter != opened);
+        session.recent.insert(0, opened.clone());
+        session.recent.truncate(RECENT_LIMIT);
+        session.last_opened = Some(opened);
+        if let Some(position) = position {
+            session.positions.insert(Self::key(series_title, chapter_title), position);
+        }
+        if let Err(e) = session.save() {
+            println!("Error saving session: {}", e);
+        }
+    }
+
+    /// The chapter to reopen at launch; `None` after the first call.
+    pub fn take_launch_chapter() -> Option<OpenedChapter> {
+        if RESTORED.swap(true, Ordering::Relaxed) {
+            return None;
+        }
+        Self::load().last_opened
+    }
+}
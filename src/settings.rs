@@ -0,0 +1,134 @@
+use crate::toml_file;
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+
+const SETTINGS_PATH: &str = "data/settings.toml";
+
+/// Direction of the text in the editor's preview.
+#[derive(PartialEq, Eq, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewDirection {
+    /// 縦書き
+    #[default]
+    Vertical,
+    /// 横書き
+    Horizontal,
+}
+
+/// Which modifier the editor's shortcuts use.
+#[derive(PartialEq, Eq, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeybindingProfile {
+    /// Ctrl, with Ctrl+Y for redo
+    #[default]
+    Standard,
+    /// ⌘, with ⌘+Shift+Z for redo
+    Mac,
+}
+
+impl KeybindingProfile {
+    /// Whether the modifier of the profile is held.
+    pub fn command(&self, modifiers: Modifiers) -> bool {
+        match self {
+            KeybindingProfile::Standard => modifiers.ctrl(),
+            KeybindingProfile::Mac => modifiers.meta(),
+        }
+    }
+}
+
+/// Order of the series and the chapters on the top page.
+#[derive(PartialEq, Eq, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    /// Oldest first; the order they were added in when created on the same day
+    #[default]
+    Created,
+    Title,
+    /// Most recently saved first
+    Modified,
+    /// Favourites first, then by title; chapters fall back to `Created`
+    Favourite,
+}
+
+/// Application-wide preferences, stored next to the series data.
+#[derive(PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Seconds without typing before the editor saves on its own; 0 turns autosave off
+    pub autosave_interval: u64,
+    /// Whether the editor shows the vertical preview next to the text
+    pub show_preview: bool,
+    /// Whether the editor shows the outline of the headings beside the text
+    pub show_outline: bool,
+    /// Whether the editor shows the glossary of the series beside the text
+    pub show_glossary: bool,
+    /// Whether the editor shows the notes of the chapter beside the text
+    pub show_notes: bool,
+    /// Versions of each chapter kept on save; 0 keeps none
+    pub snapshot_retention: usize,
+    /// Font of the editor; empty for the novel font of the app
+    pub font_family: String,
+    /// Font size of the editor in pixels
+    pub font_size: u32,
+    /// Line height of the editor in percent of the font size
+    pub line_height: u32,
+    pub preview_direction: PreviewDirection,
+    /// Whether the distraction-free mode writes vertically
+    pub focus_vertical: bool,
+    /// Whether the Reader turns pages instead of scrolling
+    pub reader_paged: bool,
+    /// Whether saving by hand also sets short numbers and exclamation marks in 縦中横
+    pub tatechuyoko_on_save: bool,
+    pub keybindings: KeybindingProfile,
+    pub series_sort: SortKey,
+    pub chapter_sort: SortKey,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            autosave_interval: 0,
+            show_preview: false,
+            show_outline: false,
+            show_glossary: false,
+            show_notes: false,
+            snapshot_retention: 20,
+            font_family: String::new(),
+            font_size: 18,
+            line_height: 190,
+            preview_direction: PreviewDirection::default(),
+            focus_vertical: false,
+            reader_paged: false,
+            tatechuyoko_on_save: false,
+            keybindings: KeybindingProfile::default(),
+            series_sort: SortKey::default(),
+            chapter_sort: SortKey::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        toml_file::load(SETTINGS_PATH).unwrap_or_default()
+    }
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        toml_file::save(SETTINGS_PATH, self)
+    }
+}
+
+/// Load the settings once at startup and share them with every page.
+pub fn use_settings_provider() -> Signal<Settings> {
+    use_context_provider(|| Signal::new(Settings::load()))
+}
+
+pub fn use_settings() -> Signal<Settings> {
+    use_context()
+}
+
+/// Change the shared settings and write them to disk.
+pub fn update_settings(mut settings: Signal<Settings>, change: impl FnOnce(&mut Settings)) {
+    change(&mut settings.write());
+    if let Err(e) = settings.peek().save() {
+        println!("Error saving settings: {}", e);
+    }
+}
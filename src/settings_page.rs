@@ -0,0 +1,111 @@
+use crate::settings::{update_settings, use_settings, KeybindingProfile, PreviewDirection};
+use crate::top_page::works::ActionIcon;
+use dioxus::prelude::*;
+
+const BACK_ICON: Asset = asset!("/assets/icons/back.svg");
+
+/// Choices of the autosave interval in seconds (0 is off)
+const AUTOSAVE_INTERVALS: [(u64, &str); 4] = [(0, "オフ"), (5, "5秒"), (30, "30秒"), (60, "60秒")];
+
+#[component]
+pub fn SettingsPage() -> Element {
+    let navigator = use_navigator();
+    let settings = use_settings();
+    let current = settings();
+
+    rsx! {
+        div {
+            class: "settings_layout",
+            header {
+                class: "reader_header",
+                ActionIcon {
+                    icon: BACK_ICON,
+                    onclick: move |_| navigator.go_back(),
+                }
+                div {
+                    class: "header_info",
+                    span { class: "chapter_title_display", "設定" }
+                }
+            }
+            div {
+                class: "settings_content",
+                h2 { "エディタ" }
+                label {
+                    span { "フォント" }
+                    input {
+                        value: "{current.font_family}",
+                        placeholder: "既定の明朝体",
+                        onchange: move |evt| update_settings(settings, |s| s.font_family = evt.value().trim().to_string()),
+                    }
+                }
+                label {
+                    span { "文字の大きさ" }
+                    input {
+                        r#type: "number",
+                        min: "10",
+                        max: "48",
+                        value: "{current.font_size}",
+                        onchange: move |evt| {
+                            if let Ok(size) = evt.value().parse::<u32>() {
+                                update_settings(settings, |s| s.font_size = size.clamp(10, 48));
+                            }
+                        },
+                    }
+                    span { "px" }
+                }
+                label {
+                    span { "行の高さ" }
+                    input {
+                        r#type: "number",
+                        min: "100",
+                        max: "300",
+                        step: "10",
+                        value: "{current.line_height}",
+                        onchange: move |evt| {
+                            if let Ok(height) = evt.value().parse::<u32>() {
+                                update_settings(settings, |s| s.line_height = height.clamp(100, 300));
+                            }
+                        },
+                    }
+                    span { "%" }
+                }
+                label {
+                    span { "自動保存" }
+                    select {
+                        onchange: move |evt| {
+                            if let Ok(seconds) = evt.value().parse() {
+                                update_settings(settings, |s| s.autosave_interval = seconds);
+                            }
+                        },
+                        for (seconds, label) in AUTOSAVE_INTERVALS {
+                            option { value: "{seconds}", selected: current.autosave_interval == seconds, "{label}" }
+                        }
+                    }
+                }
+                label {
+                    span { "ショートカット" }
+                    select {
+                        onchange: move |evt| {
+                            let profile = if evt.value() == "mac" { KeybindingProfile::Mac } else { KeybindingProfile::Standard };
+                            update_settings(settings, |s| s.keybindings = profile);
+                        },
+                        option { value: "standard", selected: current.keybindings == KeybindingProfile::Standard, "標準 (Ctrl)" }
+                        option { value: "mac", selected: current.keybindings == KeybindingProfile::Mac, "Mac (⌘)" }
+                    }
+                }
+                h2 { "プレビュー" }
+                label {
+                    span { "組み方向" }
+                    select {
+                        onchange: move |evt| {
+                            let direction = if evt.value() == "horizontal" { PreviewDirection::Horizontal } else { PreviewDirection::Vertical };
+                            update_settings(settings, |s| s.preview_direction = direction);
+                        },
+                        option { value: "vertical", selected: current.preview_direction == PreviewDirection::Vertical, "縦書き" }
+                        option { value: "horizontal", selected: current.preview_direction == PreviewDirection::Horizontal, "横書き" }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use dioxus::prelude::*;
+use std::time::Duration;
+
+/// How long a finished toast stays on screen
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ToastKind {
+    /// Work in progress; stays until it is updated
+    Progress,
+    Success,
+    Error,
+}
+
+impl ToastKind {
+    fn class(&self) -> &'static str {
+        match self {
+            ToastKind::Progress => "toast progress",
+            ToastKind::Success => "toast success",
+            ToastKind::Error => "toast error",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+struct Toast {
+    id: usize,
+    kind: ToastKind,
+    message: String,
+}
+
+/// Notifications shown in the corner of every page, provided by the app root.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Toasts {
+    list: Signal<Vec<Toast>>,
+    next_id: Signal<usize>,
+}
+
+impl Toasts {
+    /// Show a toast, returning its id for [`update`](Self::update).
+    pub fn show(&mut self, kind: ToastKind, message: impl Into<String>) -> usize {
+        let id = (self.next_id)();
+        self.next_id.set(id + 1);
+        self.list.write().push(Toast { id, kind, message: message.into() });
+        self.dismiss_later(id, kind);
+        id
+    }
+
+    /// Replace the message of a toast, e.g. when the work it reports finishes.
+    pub fn update(&mut self, id: usize, kind: ToastKind, message: impl Into<String>) {
+        if let Some(toast) = self.list.write().iter_mut().find(|toast| toast.id == id) {
+            toast.kind = kind;
+            toast.message = message.into();
+        }
+        self.dismiss_later(id, kind);
+    }
+
+    pub fn dismiss(&mut self, id: usize) {
+        self.list.write().retain(|toast| toast.id != id);
+    }
+
+    fn dismiss_later(&self, id: usize, kind: ToastKind) {
+        if kind == ToastKind::Progress {
+            return;
+        }
+        let mut toasts = *self;
+        // Not tied to the page that showed the toast, which may be gone by then
+        spawn_forever(async move {
+            tokio::time::sleep(TOAST_DURATION).await;
+            toasts.dismiss(id);
+        });
+    }
+}
+
+pub fn use_toasts_provider() -> Toasts {
+    let list = use_signal(Vec::new);
+    let next_id = use_signal(|| 0);
+    use_context_provider(|| Toasts { list, next_id })
+}
+
+pub fn use_toasts() -> Toasts {
+    use_context()
+}
+
+// --- Component: ToastList ---
+
+#[component]
+pub fn ToastList() -> Element {
+    let mut toasts = use_toasts();
+
+    rsx! {
+        div {
+            class: "toast_list",
+            for toast in toasts.list.read().clone() {
+                div {
+                    key: "{toast.id}",
+                    class: toast.kind.class(),
+                    onclick: move |_| toasts.dismiss(toast.id),
+                    "{toast.message}"
+                }
+            }
+        }
+    }
+}
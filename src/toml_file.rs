@@ -0,0 +1,18 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fs, path::Path};
+
+/// The value stored at `path`, or `None` when it is missing or does not parse.
+pub fn load<T: DeserializeOwned>(path: impl AsRef<Path>) -> Option<T> {
+    let content = fs::read_to_string(path).ok()?;
+    toml::from_str(&content).ok()
+}
+
+/// Write `value` to `path`, creating its directory first.
+pub fn save<T: Serialize>(path: impl AsRef<Path>, value: &T) -> Result<(), Box<dyn std::error::Error>> {
+    let path = path.as_ref();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, toml::to_string(value)?)?;
+    Ok(())
+}
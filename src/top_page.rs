@@ -1,15 +1,35 @@
+pub mod import;
+pub mod trash;
 pub mod works;
 
 use dioxus::prelude::*;
-use works::{ActionIcon, Chapter, DrawSeries, Series, DELETE_ICON, EDIT_ICON, READ_ICON};
+use crate::export::{export_chapter, export_series};
+use crate::session::Session;
+use crate::settings::{update_settings, use_settings, SortKey};
+use crate::toast::{use_toasts, ToastKind};
+use import::ImportItem;
+use trash::TrashEntry;
+use std::collections::BTreeSet;
+use works::{modified_label, parse_tags, sort_chapters, sort_series, today, ActionIcon, Chapter, DrawSeries, Series, SeriesMetadata, DELETE_ICON, EDIT_ICON, EXPORT_ICON, READ_ICON, RENAME_ICON, SETTINGS_ICON};
 
 
 
+/// Choices of the order of the series and chapters; chapters have no favourites
+const SORT_CHOICES: [(SortKey, &str); 4] = [
+    (SortKey::Created, "作成順"),
+    (SortKey::Title, "タイトル順"),
+    (SortKey::Modified, "更新が新しい順"),
+    (SortKey::Favourite, "お気に入り優先"),
+];
+
 #[derive(Clone, PartialEq)]
 enum DeleteTarget {
     None,
     Series(usize),
     Chapter(usize, usize),
+    /// An item in the trash, deleted for good
+    Purge(usize),
+    EmptyTrash,
 }
 
 #[derive(Clone, PartialEq)]
@@ -18,6 +38,11 @@ enum PanelState {
     Selected(usize),
     CreateSeries,
     CreateChapter(usize),
+    RenameSeries(usize),
+    RenameChapter(usize, usize),
+    SeriesSettings(usize),
+    Import,
+    Trash,
 }
 
 
@@ -30,7 +55,11 @@ fn CreateForm(
     oninput: EventHandler<String>,
     oncreate: EventHandler<MouseEvent>,
     oncancel: EventHandler<MouseEvent>,
+    submit_label: Option<String>,
+    error: Option<String>,
 ) -> Element {
+    let submit_label = submit_label.unwrap_or("作成".to_string());
+
     rsx! {
         div {
             class: "create_form",
@@ -40,11 +69,72 @@ fn CreateForm(
                 oninput: move |evt| oninput.call(evt.value()),
                 placeholder: "{placeholder}",
             }
+            if let Some(error) = error {
+                p { class: "form_error", "{error}" }
+            }
             div {
                 class: "form_actions",
                 button {
                     onclick: move |evt| oncreate.call(evt),
-                    "作成"
+                    "{submit_label}"
+                }
+                button {
+                    onclick: move |evt| oncancel.call(evt),
+                    "キャンセル"
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn SeriesSettingsForm(
+    metadata: SeriesMetadata,
+    tags: Vec<String>,
+    onsave: EventHandler<(SeriesMetadata, Vec<String>)>,
+    oncancel: EventHandler<MouseEvent>,
+) -> Element {
+    let mut author = use_signal(|| metadata.author.clone());
+    let mut publisher = use_signal(|| metadata.publisher.clone());
+    let mut description = use_signal(|| metadata.description.clone());
+    let mut tags = use_signal(|| tags.join(", "));
+
+    rsx! {
+        div {
+            class: "create_form",
+            h2 { "シリーズの設定" }
+            input {
+                value: "{author}",
+                oninput: move |evt| author.set(evt.value()),
+                placeholder: "著者",
+            }
+            input {
+                value: "{publisher}",
+                oninput: move |evt| publisher.set(evt.value()),
+                placeholder: "出版者",
+            }
+            textarea {
+                value: "{description}",
+                oninput: move |evt| description.set(evt.value()),
+                placeholder: "紹介文",
+            }
+            input {
+                value: "{tags}",
+                oninput: move |evt| tags.set(evt.value()),
+                placeholder: "タグ（カンマ区切り）",
+            }
+            div {
+                class: "form_actions",
+                button {
+                    onclick: move |_| onsave.call((
+                        SeriesMetadata {
+                            author: author().trim().to_string(),
+                            publisher: publisher().trim().to_string(),
+                            description: description().trim().to_string(),
+                        },
+                        parse_tags(&tags()),
+                    )),
+                    "保存"
                 }
                 button {
                     onclick: move |evt| oncancel.call(evt),
@@ -95,14 +185,147 @@ pub fn Top() -> Element {
     let mut delete_target = use_signal(|| DeleteTarget::None);
     let mut new_series_title = use_signal(|| String::new());
     let mut new_chapter_title = use_signal(|| String::new());
+    let mut rename_title = use_signal(|| String::new());
+    let mut rename_error = use_signal(|| None::<String>);
+    let mut import_items = use_signal(Vec::<ImportItem>::new);
+    let mut trash = use_signal(TrashEntry::load_trash);
+    let mut selected_tags = use_signal(Vec::<String>::new);
+    let mut favourites_only = use_signal(|| false);
+    let all_tags = use_memo(move || {
+        series.read().iter().flat_map(|s| s.tags.clone()).collect::<BTreeSet<String>>()
+    });
+    // A series is shown when it has every selected tag that still exists
+    let is_shown = move |s: &Series| {
+        (!favourites_only() || s.is_favourite)
+            && selected_tags
+                .read()
+                .iter()
+                .filter(|tag| all_tags.read().contains(*tag))
+                .all(|tag| s.tags.contains(tag))
+    };
     let navigator = use_navigator();
+    let mut toasts = use_toasts();
+    let settings = use_settings();
+    let series_sort = settings.read().series_sort;
+    let chapter_sort = settings.read().chapter_sort;
+    // Chapters that have since been deleted or renamed are left out
+    let recent: Vec<_> = use_hook(|| Session::load().recent)
+        .into_iter()
+        .filter(|recent| {
+            series.read().iter().any(|s| {
+                s.title == recent.series_title && s.chapters.iter().any(|c| c.title == recent.chapter_title)
+            })
+        })
+        .collect();
+
+    // Reopen the chapter that was open when the app was last closed
+    use_effect(move || {
+        if let Some(last) = Session::take_launch_chapter() {
+            let exists = series.peek().iter().any(|s| {
+                s.title == last.series_title && s.chapters.iter().any(|c| c.title == last.chapter_title)
+            });
+            if exists {
+                navigator.push(crate::Route::Editor {
+                    series_title: last.series_title,
+                    chapter_title: last.chapter_title,
+                });
+            }
+        }
+    });
+
+    let pick_imports = move |_| {
+        spawn(async move {
+            let Some(files) = rfd::AsyncFileDialog::new()
+                .add_filter("テキスト", &["txt"])
+                .pick_files()
+                .await
+            else {
+                return;
+            };
+            let mut items = Vec::new();
+            for file in files {
+                match ImportItem::read(file.path()) {
+                    Ok(item) => items.push(item),
+                    Err(e) => {
+                        toasts.show(ToastKind::Error, e);
+                    }
+                }
+            }
+            if !items.is_empty() {
+                import_items.set(items);
+                panel_state.set(PanelState::Import);
+            }
+        });
+    };
 
     rsx! {
         div {
             class: "top_layout",
             div {
                 class: "series_grid",
-                for (i, s) in series.read().clone().into_iter().enumerate() {
+                div {
+                    class: "series_filter",
+                    span {
+                        class: if favourites_only() { "tag_chip filter active" } else { "tag_chip filter" },
+                        onclick: move |_| favourites_only.toggle(),
+                        "お気に入りのみ"
+                    }
+                    for tag in all_tags() {
+                        span {
+                            class: if selected_tags.read().contains(&tag) { "tag_chip filter active" } else { "tag_chip filter" },
+                            onclick: {
+                                let tag = tag.clone();
+                                move |_| {
+                                    let mut selected = selected_tags.write();
+                                    match selected.iter().position(|t| *t == tag) {
+                                        Some(index) => {
+                                            selected.remove(index);
+                                        }
+                                        None => selected.push(tag.clone()),
+                                    }
+                                }
+                            },
+                            "{tag}"
+                        }
+                    }
+                    select {
+                        class: "sort_select",
+                        onchange: move |evt| {
+                            if let Some(&(key, _)) = evt.value().parse().ok().and_then(|i: usize| SORT_CHOICES.get(i)) {
+                                update_settings(settings, |s| s.series_sort = key);
+                            }
+                        },
+                        for (i, (key, label)) in SORT_CHOICES.into_iter().enumerate() {
+                            option { value: "{i}", selected: series_sort == key, "{label}" }
+                        }
+                    }
+                }
+                if !recent.is_empty() {
+                    div {
+                        class: "recent_strip",
+                        span { class: "recent_heading", "最近の編集" }
+                        for chapter in recent {
+                            span {
+                                class: "tag_chip filter",
+                                onclick: {
+                                    let chapter = chapter.clone();
+                                    move |_| {
+                                        navigator.push(crate::Route::Editor {
+                                            series_title: chapter.series_title.clone(),
+                                            chapter_title: chapter.chapter_title.clone(),
+                                        });
+                                    }
+                                },
+                                "{chapter.series_title} / {chapter.chapter_title}"
+                            }
+                        }
+                    }
+                }
+                for (i, s) in sort_series(&series.read(), series_sort)
+                    .into_iter()
+                    .map(|i| (i, series.read()[i].clone()))
+                    .filter(|(_, s)| is_shown(s))
+                {
                     DrawSeries {
                         series: s,
                         on_favorite_click: move |_| {
@@ -113,6 +336,11 @@ pub fn Top() -> Element {
                         on_click: move |_| {
                             panel_state.set(PanelState::Selected(i));
                         },
+                        on_rename_click: move |_| {
+                            rename_title.set(series.read()[i].title.clone());
+                            rename_error.set(None);
+                            panel_state.set(PanelState::RenameSeries(i));
+                        },
                         on_delete_click: move |_| {
                             delete_target.set(DeleteTarget::Series(i));
                         }
@@ -127,19 +355,55 @@ pub fn Top() -> Element {
                     },
                     p { "+" }
                 }
+                ActionIcon {
+                    icon: SETTINGS_ICON,
+                    class: "settings_button",
+                    onclick: move |_| {
+                        navigator.push(crate::Route::SettingsPage {});
+                    },
+                }
+                ActionIcon {
+                    icon: DELETE_ICON,
+                    class: "trash_button",
+                    onclick: move |_| panel_state.set(PanelState::Trash),
+                }
+                // Import Aozora Bunko Text Card
+                div {
+                    class: "series_container create_card import_card",
+                    onclick: pick_imports,
+                    p { "取り込み" }
+                }
             }
             div {
                 class: "chapter_list_panel",
                 match panel_state() {
                     PanelState::Selected(index) => rsx! {
                         h2 { "{series.read()[index].title}" }
+                        select {
+                            class: "sort_select",
+                            onchange: move |evt| {
+                                if let Some(&(key, _)) = evt.value().parse().ok().and_then(|i: usize| SORT_CHOICES.get(i)) {
+                                    update_settings(settings, |s| s.chapter_sort = key);
+                                }
+                            },
+                            for (i, (key, label)) in SORT_CHOICES[..3].iter().copied().enumerate() {
+                                option { value: "{i}", selected: chapter_sort == key, "{label}" }
+                            }
+                        }
                         ul {
-                            for (chapter_idx, chapter) in series.read()[index].chapters.clone().into_iter().enumerate() {
+                            for (chapter_idx, chapter) in sort_chapters(&series.read()[index], chapter_sort)
+                                .into_iter()
+                                .map(|i| (i, series.read()[index].chapters[i].clone()))
+                            {
                                 li {
                                     div {
                                         "{chapter.title}"
                                         br {}
-                                        small { "{chapter.created_at}" }
+                                        if chapter_sort == SortKey::Modified {
+                                            small { "更新 {modified_label(series.read()[index].chapter_modified(chapter_idx))}" }
+                                        } else {
+                                            small { "{chapter.created_at}" }
+                                        }
                                     }
                                     div {
                                         class: "chapter_actions",
@@ -161,6 +425,24 @@ pub fn Top() -> Element {
                                                 });
                                             },
                                         }
+                                        ActionIcon {
+                                            icon: EXPORT_ICON,
+                                            onclick: move |_| {
+                                                export_chapter(
+                                                    toasts,
+                                                    series.read()[index].title.clone(),
+                                                    series.read()[index].chapters[chapter_idx].title.clone(),
+                                                );
+                                            },
+                                        }
+                                        ActionIcon {
+                                            icon: RENAME_ICON,
+                                            onclick: move |_| {
+                                                rename_title.set(series.read()[index].chapters[chapter_idx].title.clone());
+                                                rename_error.set(None);
+                                                panel_state.set(PanelState::RenameChapter(index, chapter_idx));
+                                            },
+                                        }
                                         ActionIcon {
                                             icon: DELETE_ICON,
                                             class: "delete",
@@ -182,6 +464,18 @@ pub fn Top() -> Element {
                                 },
                                 "+"
                             }
+                            button {
+                                class: "export_series_button",
+                                onclick: move |_| panel_state.set(PanelState::SeriesSettings(index)),
+                                "設定"
+                            }
+                            if !series.read()[index].chapters.is_empty() {
+                                button {
+                                    class: "export_series_button",
+                                    onclick: move |_| export_series(toasts, series.read()[index].clone()),
+                                    "書き出し"
+                                }
+                            }
                         }
                     },
                     PanelState::CreateSeries => rsx! {
@@ -196,6 +490,9 @@ pub fn Top() -> Element {
                                         title: new_series_title(),
                                         is_favourite: false,
                                         chapters: vec![],
+                                        created_at: today(),
+                                        metadata: SeriesMetadata::default(),
+                                        tags: vec![],
                                     };
                                     let _ = new_series.save_series();
                                     series.write().push(new_series);
@@ -216,7 +513,7 @@ pub fn Top() -> Element {
                                 if !new_chapter_title().trim().is_empty() {
                                     series.write()[index].chapters.push(Chapter {
                                         title: new_chapter_title(),
-                                        created_at: "2025-01-01".into(),
+                                        created_at: today(),
                                     });
                                     let _ = series.read()[index].save_series();
                                     panel_state.set(PanelState::Selected(index));
@@ -225,6 +522,166 @@ pub fn Top() -> Element {
                             oncancel: move |_| panel_state.set(PanelState::Selected(index)),
                         }
                     },
+                    PanelState::RenameSeries(index) => rsx! {
+                        CreateForm {
+                            title_header: "シリーズの名前を変更",
+                            placeholder: "シリーズタイトル",
+                            value: "{rename_title}",
+                            oninput: move |val: String| rename_title.set(val),
+                            oncreate: move |_| {
+                                let title = rename_title().trim().to_string();
+                                if !title.is_empty() {
+                                    match series.write()[index].rename_series(&title) {
+                                        Ok(()) => panel_state.set(PanelState::Selected(index)),
+                                        Err(e) => rename_error.set(Some(e.to_string())),
+                                    }
+                                }
+                            },
+                            oncancel: move |_| panel_state.set(PanelState::Selected(index)),
+                            submit_label: "変更",
+                            error: rename_error(),
+                        }
+                    },
+                    PanelState::RenameChapter(index, chapter_idx) => rsx! {
+                        CreateForm {
+                            title_header: "チャプターの名前を変更",
+                            placeholder: "チャプタータイトル",
+                            value: "{rename_title}",
+                            oninput: move |val: String| rename_title.set(val),
+                            oncreate: move |_| {
+                                let title = rename_title().trim().to_string();
+                                if !title.is_empty() {
+                                    match series.write()[index].rename_chapter(chapter_idx, &title) {
+                                        Ok(()) => panel_state.set(PanelState::Selected(index)),
+                                        Err(e) => rename_error.set(Some(e.to_string())),
+                                    }
+                                }
+                            },
+                            oncancel: move |_| panel_state.set(PanelState::Selected(index)),
+                            submit_label: "変更",
+                            error: rename_error(),
+                        }
+                    },
+                    PanelState::SeriesSettings(index) => rsx! {
+                        SeriesSettingsForm {
+                            metadata: series.read()[index].metadata.clone(),
+                            tags: series.read()[index].tags.clone(),
+                            onsave: move |(metadata, tags)| {
+                                series.write()[index].metadata = metadata;
+                                series.write()[index].tags = tags;
+                                let _ = series.read()[index].save_series();
+                                panel_state.set(PanelState::Selected(index));
+                            },
+                            oncancel: move |_| panel_state.set(PanelState::Selected(index)),
+                        }
+                    },
+                    PanelState::Import => rsx! {
+                        div {
+                            class: "create_form",
+                            h2 { "テキストを取り込む" }
+                            for (i, item) in import_items().into_iter().enumerate() {
+                                div {
+                                    class: "import_item",
+                                    small { "{item.path.display()}" }
+                                    input {
+                                        value: "{item.series_title}",
+                                        oninput: move |evt| import_items.write()[i].series_title = evt.value(),
+                                        placeholder: "シリーズタイトル",
+                                    }
+                                    input {
+                                        value: "{item.chapter_title}",
+                                        oninput: move |evt| import_items.write()[i].chapter_title = evt.value(),
+                                        placeholder: "チャプタータイトル",
+                                    }
+                                }
+                            }
+                            div {
+                                class: "form_actions",
+                                button {
+                                    onclick: move |_| {
+                                        let mut imported = None;
+                                        for item in import_items() {
+                                            match item.import_into(&mut series.write()) {
+                                                Ok((index, unmappable)) => {
+                                                    imported = Some(index);
+                                                    if unmappable {
+                                                        toasts.show(
+                                                            ToastKind::Error,
+                                                            format!("「{}」にShift_JISで表せない文字があり、文字参照に置き換えました", item.chapter_title),
+                                                        );
+                                                    }
+                                                }
+                                                Err(e) => {
+                                                    toasts.show(ToastKind::Error, e);
+                                                }
+                                            }
+                                        }
+                                        import_items.set(Vec::new());
+                                        panel_state.set(imported.map_or(PanelState::None, PanelState::Selected));
+                                    },
+                                    "取り込む"
+                                }
+                                button {
+                                    onclick: move |_| {
+                                        import_items.set(Vec::new());
+                                        panel_state.set(PanelState::None);
+                                    },
+                                    "キャンセル"
+                                }
+                            }
+                        }
+                    },
+                    PanelState::Trash => rsx! {
+                        h2 { "ゴミ箱" }
+                        if trash.read().is_empty() {
+                            p { "ゴミ箱は空です" }
+                        }
+                        ul {
+                            for (i, entry) in trash().into_iter().enumerate() {
+                                li {
+                                    div {
+                                        "{entry.label()}"
+                                        br {}
+                                        small { "{entry.deleted_at}" }
+                                    }
+                                    div {
+                                        class: "chapter_actions",
+                                        button {
+                                            class: "export_series_button",
+                                            onclick: move |_| {
+                                                let entry = trash.read()[i].clone();
+                                                match entry.restore(&mut series.write()) {
+                                                    Ok(()) => {
+                                                        trash.write().remove(i);
+                                                        toasts.show(ToastKind::Success, format!("「{}」を元に戻しました", entry.label()));
+                                                    }
+                                                    Err(e) => {
+                                                        toasts.show(ToastKind::Error, e.to_string());
+                                                    }
+                                                }
+                                            },
+                                            "元に戻す"
+                                        }
+                                        ActionIcon {
+                                            icon: DELETE_ICON,
+                                            class: "delete",
+                                            onclick: move |_| delete_target.set(DeleteTarget::Purge(i)),
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        if !trash.read().is_empty() {
+                            div {
+                                class: "add_chapter_container",
+                                button {
+                                    class: "export_series_button",
+                                    onclick: move |_| delete_target.set(DeleteTarget::EmptyTrash),
+                                    "ゴミ箱を空にする"
+                                }
+                            }
+                        }
+                    },
                     PanelState::None => rsx! {
                         p { "シリーズが選択されていません" }
                     },
@@ -235,11 +692,16 @@ pub fn Top() -> Element {
         match delete_target() {
             DeleteTarget::Series(i) => rsx! {
                 ConfirmationModal {
-                    message: format!("本当に「{}」を削除しますか？", series.read()[i].title),
+                    message: format!("「{}」をゴミ箱に移動しますか？", series.read()[i].title),
                     onconfirm: move |_| {
-                        {
-                            let s = series.read();
-                            let _ = s[i].delete_series();
+                        delete_target.set(DeleteTarget::None);
+                        let trashed = TrashEntry::trash_series(&series.read()[i]);
+                        match trashed {
+                            Ok(entry) => trash.write().insert(0, entry),
+                            Err(e) => {
+                                toasts.show(ToastKind::Error, format!("ゴミ箱に移動できませんでした: {}", e));
+                                return;
+                            }
                         }
                         series.write().remove(i);
                         match panel_state() {
@@ -256,20 +718,64 @@ pub fn Top() -> Element {
                                     }
                                 }
                             }
+                            // Indices of the form would go stale
+                            PanelState::RenameSeries(_) | PanelState::RenameChapter(..) | PanelState::SeriesSettings(_) => {
+                                panel_state.set(PanelState::None);
+                            }
                             _ => {}
                         }
-                        delete_target.set(DeleteTarget::None);
                     },
                     oncancel: move |_| delete_target.set(DeleteTarget::None),
                 }
             },
             DeleteTarget::Chapter(series_idx, chapter_idx) => rsx! {
                 ConfirmationModal {
-                    message: format!("本当に「{}」を削除しますか？", series.read()[series_idx].chapters[chapter_idx].title),
+                    message: format!("「{}」をゴミ箱に移動しますか？", series.read()[series_idx].chapters[chapter_idx].title),
                     onconfirm: move |_| {
+                        delete_target.set(DeleteTarget::None);
+                        let trashed = TrashEntry::trash_chapter(&series.read()[series_idx], chapter_idx);
+                        match trashed {
+                            Ok(entry) => trash.write().insert(0, entry),
+                            Err(e) => {
+                                toasts.show(ToastKind::Error, format!("ゴミ箱に移動できませんでした: {}", e));
+                                return;
+                            }
+                        }
                         series.write()[series_idx].chapters.remove(chapter_idx);
                         let _ = series.read()[series_idx].save_series();
+                    },
+                    oncancel: move |_| delete_target.set(DeleteTarget::None),
+                }
+            },
+            DeleteTarget::Purge(i) => rsx! {
+                ConfirmationModal {
+                    message: format!("「{}」を完全に削除しますか？元に戻すことはできません。", trash.read()[i].label()),
+                    onconfirm: move |_| {
+                        delete_target.set(DeleteTarget::None);
+                        let purged = trash.read()[i].purge();
+                        match purged {
+                            Ok(()) => {
+                                trash.write().remove(i);
+                            }
+                            Err(e) => {
+                                toasts.show(ToastKind::Error, format!("削除できませんでした: {}", e));
+                            }
+                        }
+                    },
+                    oncancel: move |_| delete_target.set(DeleteTarget::None),
+                }
+            },
+            DeleteTarget::EmptyTrash => rsx! {
+                ConfirmationModal {
+                    message: format!("ゴミ箱の{}件を完全に削除しますか？元に戻すことはできません。", trash.read().len()),
+                    onconfirm: move |_| {
                         delete_target.set(DeleteTarget::None);
+                        // Keep what could not be deleted, so that it can be tried again
+                        let failed = trash.peek().iter().filter(|entry| entry.purge().is_err()).count();
+                        trash.set(TrashEntry::load_trash());
+                        if failed > 0 {
+                            toasts.show(ToastKind::Error, format!("{}件を削除できませんでした", failed));
+                        }
                     },
                     oncancel: move |_| delete_target.set(DeleteTarget::None),
                 }
@@ -0,0 +1,102 @@
+use super::works::{today, Chapter, Series, SeriesMetadata};
+use encoding_rs::{SHIFT_JIS, UTF_8};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A text file about to be imported, with the titles it will be filed under.
+#[derive(PartialEq, Clone)]
+pub struct ImportItem {
+    pub path: PathBuf,
+    /// The text with `\n` line breaks
+    pub text: String,
+    pub author: String,
+    pub series_title: String,
+    pub chapter_title: String,
+}
+
+impl ImportItem {
+    /// Read a file, suggesting titles from its header: the title becomes the series and
+    /// the subtitle (or the file name) the chapter.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let text = decode(&bytes).replace("\r\n", "\n").replace('\r', "\n");
+        let stem = path
+            .file_stem()
+            .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+        let metadata = aozora_parser::parse_aozora(text.clone())
+            .ok()
+            .map(|tokens| aozora_parser::parse(tokens).metadata);
+        let (title, subtitle, author) = metadata.map_or_else(
+            || (String::new(), None, String::new()),
+            |m| (m.title, m.subtitle, m.author),
+        );
+        Ok(Self {
+            path: path.to_path_buf(),
+            text,
+            author,
+            series_title: if title.is_empty() { stem.clone() } else { title },
+            chapter_title: subtitle.filter(|s| !s.is_empty()).unwrap_or(stem),
+        })
+    }
+
+    /// Add the text to `series`, creating the series when there is none with the title.
+    /// Returns the index of the series, and whether some characters could not be
+    /// represented in Shift_JIS and were written as character references.
+    pub fn import_into(&self, series: &mut Vec<Series>) -> Result<(usize, bool), String> {
+        let (series_title, chapter_title) = (self.series_title.trim(), self.chapter_title.trim());
+        if series_title.is_empty() || chapter_title.is_empty() {
+            return Err(format!("{}: タイトルが空です", self.path.display()));
+        }
+        Series::check_title(series_title)
+            .and_then(|()| Series::check_title(chapter_title))
+            .map_err(|e| format!("{}: {}", self.path.display(), e))?;
+        let existing = series.iter().position(|s| s.title == series_title);
+        // A new series joins the list only once its files are written
+        let mut created = None;
+        let target = match existing {
+            Some(index) => &mut series[index],
+            None => created.insert(Series {
+                title: series_title.to_string(),
+                is_favourite: false,
+                chapters: vec![],
+                created_at: today(),
+                metadata: SeriesMetadata { author: self.author.clone(), ..Default::default() },
+                tags: vec![],
+            }),
+        };
+        let file = target.own_path().join(format!("{}.txt", chapter_title));
+        if target.chapters.iter().any(|c| c.title == chapter_title) || file.exists() {
+            return Err(format!("「{}」には既に「{}」があります", series_title, chapter_title));
+        }
+
+        fs::create_dir_all(target.own_path()).map_err(|e| e.to_string())?;
+        let (bytes, unmappable) = encode(&self.text);
+        fs::write(&file, bytes).map_err(|e| e.to_string())?;
+        target.chapters.push(Chapter {
+            title: chapter_title.to_string(),
+            created_at: today(),
+        });
+        target.save_series().map_err(|e| e.to_string())?;
+        let index = existing.unwrap_or(series.len());
+        if let Some(new) = created {
+            series.push(new);
+        }
+        Ok((index, unmappable))
+    }
+}
+
+/// Text of a file in UTF-8 (with or without BOM) or, failing that, Shift_JIS, the
+/// encoding of Aozora Bunko.
+fn decode(bytes: &[u8]) -> String {
+    match UTF_8.decode_without_bom_handling_and_without_replacement(bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes)) {
+        Some(text) => text.into_owned(),
+        None => SHIFT_JIS.decode(bytes).0.into_owned(),
+    }
+}
+
+/// The text as the editor stores it: Shift_JIS with CRLF line breaks.
+fn encode(text: &str) -> (Vec<u8>, bool) {
+    let text_crlf = text.replace('\n', "\r\n");
+    let (bytes, _, unmappable) = SHIFT_JIS.encode(&text_crlf);
+    (bytes.into_owned(), unmappable)
+}
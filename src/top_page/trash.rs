@@ -0,0 +1,165 @@
+use super::works::{Chapter, Series};
+use crate::toml_file;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf};
+
+const TRASH_PATH: &str = "data/trash";
+/// Name of the metadata file of each item in the trash
+const ENTRY_FILE: &str = "trash.toml";
+
+/// What was deleted, with what it takes to put it back.
+#[derive(PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum TrashedItem {
+    /// The whole directory of the series, kept in `series/`
+    Series { series: Series },
+    /// The text file of the chapter with its versions and notes, kept as they were next to the series
+    Chapter { series_title: String, chapter: Chapter },
+}
+
+/// An item in the trash. Each one has a directory of its own under `data/trash`.
+#[derive(PartialEq, Eq, Clone, Deserialize, Serialize)]
+pub struct TrashEntry {
+    pub id: String,
+    pub deleted_at: String,
+    pub item: TrashedItem,
+}
+
+impl TrashEntry {
+    fn entry_dir(id: &str) -> PathBuf {
+        PathBuf::from(TRASH_PATH).join(id)
+    }
+    fn own_path(&self) -> PathBuf {
+        Self::entry_dir(&self.id)
+    }
+    fn chapter_path(&self, chapter_title: &str) -> PathBuf {
+        self.own_path().join(format!("{}.txt", chapter_title))
+    }
+
+    /// Name of the item as shown in the trash.
+    pub fn label(&self) -> String {
+        match &self.item {
+            TrashedItem::Series { series } => series.title.clone(),
+            TrashedItem::Chapter { series_title, chapter } => format!("{} / {}", series_title, chapter.title),
+        }
+    }
+
+    fn new(item: TrashedItem) -> Result<Self, Box<dyn std::error::Error>> {
+        let entry = Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            deleted_at: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            item,
+        };
+        toml_file::save(entry.own_path().join(ENTRY_FILE), &entry)?;
+        Ok(entry)
+    }
+
+    /// Move the directory of `series` into the trash.
+    pub fn trash_series(series: &Series) -> Result<Self, Box<dyn std::error::Error>> {
+        let entry = Self::new(TrashedItem::Series { series: series.clone() })?;
+        if series.own_path().exists()
+            && let Err(e) = fs::rename(series.own_path(), entry.own_path().join("series"))
+        {
+            let _ = fs::remove_dir_all(entry.own_path());
+            return Err(e.into());
+        }
+        Ok(entry)
+    }
+
+    /// Move the text, the versions and the notes of a chapter of `series` into the trash. The chapter
+    /// itself is left in the series for the caller to remove.
+    pub fn trash_chapter(series: &Series, index: usize) -> Result<Self, Box<dyn std::error::Error>> {
+        let chapter = series.chapters[index].clone();
+        let old_path = series.own_path().join(format!("{}.txt", chapter.title));
+        let entry = Self::new(TrashedItem::Chapter {
+            series_title: series.title.clone(),
+            chapter: chapter.clone(),
+        })?;
+        let new_path = entry.chapter_path(&chapter.title);
+        // A chapter that has never been saved has no file yet
+        let moved = old_path.exists();
+        let result = (|| -> std::io::Result<()> {
+            if moved {
+                fs::rename(&old_path, &new_path)?;
+            }
+            crate::editor::move_companions(&old_path, &new_path)
+        })();
+        if let Err(e) = result {
+            if moved && new_path.exists() {
+                let _ = fs::rename(&new_path, &old_path);
+            }
+            let _ = fs::remove_dir_all(entry.own_path());
+            return Err(e.into());
+        }
+        Ok(entry)
+    }
+
+    /// Put the item back into `series_list`. Fails without changing anything when its place
+    /// has been taken, or when the series of a chapter no longer exists.
+    pub fn restore(&self, series_list: &mut Vec<Series>) -> Result<(), Box<dyn std::error::Error>> {
+        match &self.item {
+            TrashedItem::Series { series } => {
+                if series.own_path().exists() || series_list.iter().any(|s| s.title == series.title) {
+                    return Err(format!("「{}」は既に存在します", series.title).into());
+                }
+                let kept = self.own_path().join("series");
+                if kept.exists() {
+                    fs::rename(&kept, series.own_path())?;
+                } else {
+                    series.save_series()?;
+                }
+                series_list.push(series.clone());
+            }
+            TrashedItem::Chapter { series_title, chapter } => {
+                let Some(series) = series_list.iter_mut().find(|s| &s.title == series_title) else {
+                    return Err(format!("シリーズ「{}」が見つかりません", series_title).into());
+                };
+                let new_path = series.own_path().join(format!("{}.txt", chapter.title));
+                if series.chapters.iter().any(|c| c.title == chapter.title) || new_path.exists() {
+                    return Err(format!("「{}」は既に存在します", chapter.title).into());
+                }
+                let old_path = self.chapter_path(&chapter.title);
+                let moved = old_path.exists();
+                if moved {
+                    fs::rename(&old_path, &new_path)?;
+                }
+                if let Err(e) = crate::editor::move_companions(&old_path, &new_path) {
+                    if moved {
+                        let _ = fs::rename(&new_path, &old_path);
+                    }
+                    return Err(e.into());
+                }
+                series.chapters.push(chapter.clone());
+                if let Err(e) = series.save_series() {
+                    series.chapters.pop();
+                    let _ = crate::editor::move_companions(&new_path, &old_path);
+                    if moved {
+                        let _ = fs::rename(&new_path, &old_path);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        fs::remove_dir_all(self.own_path())?;
+        Ok(())
+    }
+
+    /// Delete the item for good.
+    pub fn purge(&self) -> Result<(), Box<dyn std::error::Error>> {
+        fs::remove_dir_all(self.own_path())?;
+        Ok(())
+    }
+
+    /// Items in the trash, the most recently deleted first.
+    pub fn load_trash() -> Vec<Self> {
+        let mut entries: Vec<Self> = fs::read_dir(TRASH_PATH)
+            .map(|dirs| {
+                dirs.flatten()
+                    .filter_map(|dir| toml_file::load(dir.path().join(ENTRY_FILE)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        entries.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at));
+        entries
+    }
+}
@@ -1,9 +1,12 @@
+use crate::settings::SortKey;
 use dioxus::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::{
+    cmp::Reverse,
     fs::{self, File},
     io::Write,
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 
 const SERIES_PATH: &str = "data/series";
@@ -12,6 +15,9 @@ pub const FAVOURITE_ICON: Asset = asset!("assets/icons/bookmark.svg");
 pub const EDIT_ICON: Asset = asset!("assets/icons/edit.svg");
 pub const READ_ICON: Asset = asset!("assets/icons/read.svg");
 pub const DELETE_ICON: Asset = asset!("assets/icons/delete.svg");
+pub const RENAME_ICON: Asset = asset!("assets/icons/rename.svg");
+pub const EXPORT_ICON: Asset = asset!("assets/icons/export.svg");
+pub const SETTINGS_ICON: Asset = asset!("assets/icons/settings.svg");
 
 #[derive(PartialEq, Eq, Clone, Deserialize, Serialize)]
 pub struct Chapter {
@@ -19,11 +25,80 @@ pub struct Chapter {
     pub created_at: String,
 }
 
+/// Book information of a series, used when it is exported.
+#[derive(PartialEq, Eq, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SeriesMetadata {
+    pub author: String,
+    pub publisher: String,
+    pub description: String,
+}
+
 #[derive(PartialEq, Eq, Clone, Deserialize, Serialize)]
 pub struct Series {
     pub title: String,
     pub is_favourite: bool,
     pub chapters: Vec<Chapter>,
+    /// Empty for series created before the date was recorded
+    #[serde(default)]
+    pub created_at: String,
+    #[serde(default)]
+    pub metadata: SeriesMetadata,
+    /// Tags and genres, used to filter the series on the top page
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Today's date, as recorded when a series or a chapter is created.
+pub fn today() -> String {
+    chrono::Local::now().format("%Y-%m-%d").to_string()
+}
+
+/// Last modified time as shown in the chapter list.
+pub fn modified_label(modified: Option<SystemTime>) -> String {
+    modified.map_or("—".to_string(), |time| {
+        chrono::DateTime::<chrono::Local>::from(time).format("%Y-%m-%d %H:%M").to_string()
+    })
+}
+
+/// Indices of `series_list` in the order of `key`.
+pub fn sort_series(series_list: &[Series], key: SortKey) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..series_list.len()).collect();
+    match key {
+        SortKey::Created => order.sort_by(|&a, &b| series_list[a].created_at.cmp(&series_list[b].created_at)),
+        SortKey::Title => order.sort_by(|&a, &b| series_list[a].title.cmp(&series_list[b].title)),
+        SortKey::Modified => order.sort_by_cached_key(|&i| Reverse(series_list[i].modified())),
+        SortKey::Favourite => order.sort_by(|&a, &b| {
+            let (a, b) = (&series_list[a], &series_list[b]);
+            b.is_favourite.cmp(&a.is_favourite).then_with(|| a.title.cmp(&b.title))
+        }),
+    }
+    order
+}
+
+/// Indices of the chapters of `series` in the order of `key`.
+pub fn sort_chapters(series: &Series, key: SortKey) -> Vec<usize> {
+    let chapters = &series.chapters;
+    let mut order: Vec<usize> = (0..chapters.len()).collect();
+    match key {
+        SortKey::Created | SortKey::Favourite => {
+            order.sort_by(|&a, &b| chapters[a].created_at.cmp(&chapters[b].created_at))
+        }
+        SortKey::Title => order.sort_by(|&a, &b| chapters[a].title.cmp(&chapters[b].title)),
+        SortKey::Modified => order.sort_by_cached_key(|&i| Reverse(series.chapter_modified(i))),
+    }
+    order
+}
+
+/// Tags written in `input`, separated by commas; empty and repeated ones are dropped.
+pub fn parse_tags(input: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in input.split([',', '、', '，']).map(str::trim) {
+        if !tag.is_empty() && !tags.iter().any(|t| t == tag) {
+            tags.push(tag.to_string());
+        }
+    }
+    tags
 }
 
 impl Series {
@@ -33,6 +108,15 @@ impl Series {
     pub fn own_path(&self) -> PathBuf {
         Self::series_dir(&self.title)
     }
+    /// When the text of the chapter was last saved; `None` before its first save.
+    pub fn chapter_modified(&self, index: usize) -> Option<SystemTime> {
+        let path = self.own_path().join(format!("{}.txt", self.chapters[index].title));
+        fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+    /// When a chapter of the series was last saved.
+    pub fn modified(&self) -> Option<SystemTime> {
+        (0..self.chapters.len()).filter_map(|i| self.chapter_modified(i)).max()
+    }
     pub fn save_series(&self) -> Result<(), Box<dyn std::error::Error>> {
         let series_dir = self.own_path();
         if !series_dir.exists() {
@@ -43,10 +127,67 @@ impl Series {
         writeln!(file, "{}", &toml::to_string(self).unwrap());
         Ok(())
     }
-    pub fn delete_series(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let series_dir = self.own_path();
-        if series_dir.exists() {
-            fs::remove_dir_all(&series_dir)?;
+    /// Titles name files and directories, so they must not reach outside the series directory.
+    pub(super) fn check_title(title: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if title == "." || title == ".." || title.contains(['/', '\\']) {
+            return Err(format!("「{}」はタイトルに使えません", title).into());
+        }
+        Ok(())
+    }
+    /// Rename the series along with its directory. Fails without changing anything when
+    /// another series already has the title.
+    pub fn rename_series(&mut self, new_title: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if new_title == self.title {
+            return Ok(());
+        }
+        Self::check_title(new_title)?;
+        let old_dir = self.own_path();
+        let new_dir = Self::series_dir(new_title);
+        if new_dir.exists() {
+            return Err(format!("「{}」は既に存在します", new_title).into());
+        }
+        fs::rename(&old_dir, &new_dir)?;
+        let old_title = std::mem::replace(&mut self.title, new_title.to_string());
+        if let Err(e) = self.save_series() {
+            // Put the directory back so that series.toml and its location agree
+            self.title = old_title;
+            let _ = fs::rename(&new_dir, &old_dir);
+            return Err(e);
+        }
+        Ok(())
+    }
+    /// Rename a chapter along with its text file. Fails without changing anything when
+    /// another chapter of the series already has the title.
+    pub fn rename_chapter(&mut self, index: usize, new_title: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let old_title = self.chapters[index].title.clone();
+        if new_title == old_title {
+            return Ok(());
+        }
+        Self::check_title(new_title)?;
+        let old_path = self.own_path().join(format!("{}.txt", old_title));
+        let new_path = self.own_path().join(format!("{}.txt", new_title));
+        if self.chapters.iter().any(|c| c.title == new_title) || new_path.exists() {
+            return Err(format!("「{}」は既に存在します", new_title).into());
+        }
+        // A chapter that has never been saved has no file yet
+        let moved = old_path.exists();
+        if moved {
+            fs::rename(&old_path, &new_path)?;
+        }
+        if let Err(e) = crate::editor::move_companions(&old_path, &new_path) {
+            if moved {
+                let _ = fs::rename(&new_path, &old_path);
+            }
+            return Err(e.into());
+        }
+        self.chapters[index].title = new_title.to_string();
+        if let Err(e) = self.save_series() {
+            self.chapters[index].title = old_title;
+            let _ = crate::editor::move_companions(&new_path, &old_path);
+            if moved {
+                let _ = fs::rename(&new_path, &old_path);
+            }
+            return Err(e);
         }
         Ok(())
     }
@@ -96,6 +237,7 @@ pub fn DrawSeries(
     series: Series,
     on_favorite_click: EventHandler<MouseEvent>,
     on_click: EventHandler<MouseEvent>,
+    on_rename_click: EventHandler<MouseEvent>,
     on_delete_click: EventHandler<MouseEvent>,
 ) -> Element {
     let fav_bg_color = if series.is_favourite {
@@ -112,6 +254,14 @@ pub fn DrawSeries(
                 class: "series_title",
                 "{series.title}"
             }
+            if !series.tags.is_empty() {
+                div {
+                    class: "series_tags",
+                    for tag in series.tags.iter() {
+                        span { class: "tag_chip", "{tag}" }
+                    }
+                }
+            }
             div {
                 class: "series_actions",
                 ActionIcon {
@@ -119,6 +269,10 @@ pub fn DrawSeries(
                     style: "background-color: {fav_bg_color};",
                     onclick: move |evt| on_favorite_click.call(evt),
                 }
+                ActionIcon {
+                    icon: RENAME_ICON,
+                    onclick: move |evt| on_rename_click.call(evt),
+                }
                 ActionIcon {
                     icon: DELETE_ICON,
                     class: "delete",